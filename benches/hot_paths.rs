@@ -0,0 +1,165 @@
+//! Benchmarks for what every room message goes through: the wire codec and the room
+//! encryption. Run with `cargo bench --bench hot_paths`.
+
+use bevy_libp2p::crypto::{DataEncryptor, RoomKey};
+use bevy_libp2p::network::{decode_game_message, encode_game_message};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use libp2p::gossipsub::{DataTransform, RawMessage, TopicHash};
+use serde::{Deserialize, Serialize};
+
+/// Sizes of game payloads, from a single input to a fragment's worth of state.
+const PAYLOAD_SIZES: [usize; 4] = [64, 1024, 16 * 1024, 60 * 1024];
+/// Keys in the ring when decrypting. Messages sealed under the oldest key are the
+/// slowest to open, since the newest is tried first.
+const KEYRING_LENGTHS: [usize; 3] = [1, 4, 16];
+
+/// A single player's input for one tick.
+#[derive(Serialize, Deserialize)]
+struct PlayerInput {
+    tick: u32,
+    buttons: u16,
+    aim: [f32; 2],
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntityState {
+    id: u64,
+    position: [f32; 3],
+    velocity: [f32; 3],
+    health: u16,
+}
+
+/// The host's view of the world, as sent when a player falls too far behind.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    tick: u32,
+    entities: Vec<EntityState>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Chat {
+    text: String,
+}
+
+fn snapshot(entities: u64) -> Snapshot {
+    Snapshot {
+        tick: 1200,
+        entities: (0..entities)
+            .map(|id| EntityState {
+                id,
+                position: [id as f32, 1.0, -(id as f32)],
+                velocity: [0.5, 0.0, 0.25],
+                health: 100,
+            })
+            .collect(),
+    }
+}
+
+fn raw_message(data: Vec<u8>) -> RawMessage {
+    RawMessage {
+        source: None,
+        data,
+        sequence_number: Some(0),
+        topic: TopicHash::from_raw("bench"),
+        signature: None,
+        key: None,
+        validated: true,
+    }
+}
+
+fn bench_codec(c: &mut Criterion) {
+    let mut group = c.benchmark_group("codec");
+    let input = PlayerInput {
+        tick: 1200,
+        buttons: 0b1010,
+        aim: [0.3, -0.7],
+    };
+    let chat = Chat {
+        text: "gg, one more?".repeat(4),
+    };
+    let small = snapshot(16);
+    let large = snapshot(1024);
+
+    group.bench_function("encode/input", |b| {
+        b.iter(|| encode_game_message(&input).unwrap())
+    });
+    group.bench_function("encode/chat", |b| {
+        b.iter(|| encode_game_message(&chat).unwrap())
+    });
+    group.bench_function("encode/snapshot_16", |b| {
+        b.iter(|| encode_game_message(&small).unwrap())
+    });
+    group.bench_function("encode/snapshot_1024", |b| {
+        b.iter(|| encode_game_message(&large).unwrap())
+    });
+
+    let input = encode_game_message(&input).unwrap();
+    let chat = encode_game_message(&chat).unwrap();
+    let small = encode_game_message(&small).unwrap();
+    let large = encode_game_message(&large).unwrap();
+    group.bench_function("decode/input", |b| {
+        b.iter(|| decode_game_message::<PlayerInput>(&input).unwrap())
+    });
+    group.bench_function("decode/chat", |b| {
+        b.iter(|| decode_game_message::<Chat>(&chat).unwrap())
+    });
+    group.bench_function("decode/snapshot_16", |b| {
+        b.iter(|| decode_game_message::<Snapshot>(&small).unwrap())
+    });
+    group.bench_function("decode/snapshot_1024", |b| {
+        b.iter(|| decode_game_message::<Snapshot>(&large).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_outbound_transform(c: &mut Criterion) {
+    let mut group = c.benchmark_group("outbound_transform");
+    let (encryptor, _keys) = DataEncryptor::new();
+    let topic = TopicHash::from_raw("bench");
+    for size in PAYLOAD_SIZES {
+        let data = vec![0x5a; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+            b.iter(|| encryptor.outbound_transform(&topic, data.clone()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_inbound_transform(c: &mut Criterion) {
+    let mut group = c.benchmark_group("inbound_transform");
+    let topic = TopicHash::from_raw("bench");
+    for keys_in_ring in KEYRING_LENGTHS {
+        for size in PAYLOAD_SIZES {
+            let (encryptor, mut keys) = DataEncryptor::new();
+            keys.replace(RoomKey::random());
+            let sealed = encryptor
+                .outbound_transform(&topic, vec![0x5a; size])
+                .unwrap();
+            for _ in 1..keys_in_ring {
+                keys.add_key(RoomKey::random());
+            }
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}_keys", keys_in_ring), size),
+                &sealed,
+                |b, sealed| {
+                    b.iter(|| {
+                        encryptor
+                            .inbound_transform(raw_message(sealed.clone()))
+                            .unwrap()
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_codec,
+    bench_outbound_transform,
+    bench_inbound_transform
+);
+criterion_main!(benches);
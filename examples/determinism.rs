@@ -0,0 +1,202 @@
+//! Runs an input script through a demo lockstep simulation and compares the resulting
+//! state hashes across machines. CI runs it on every OS and compares the traces:
+//!
+//! ```text
+//! cargo run --example determinism -- run --out linux.json
+//! cargo run --example determinism -- compare linux.json windows.json macos.json
+//! ```
+//!
+//! `run` plays a script generated from `--seed` unless given a recorded `--script`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use bevy_libp2p::determinism::{self, HashTrace, InputScript, Simulation, StateHashes};
+use bevy_libp2p::fixed::{Fixed, FixedVec3};
+use clap::{Parser, Subcommand};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+#[derive(Parser)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Step the simulation through a script and write the state hashes to `out`.
+    Run {
+        /// A script as written by `--save-script`.
+        #[arg(long)]
+        script: Option<PathBuf>,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        #[arg(long, default_value_t = 3600)]
+        ticks: usize,
+        /// Write the generated script here, e.g. to replay it elsewhere.
+        #[arg(long)]
+        save_script: Option<PathBuf>,
+        /// What to call this machine in reports. Defaults to the OS and architecture.
+        #[arg(long)]
+        target: Option<String>,
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Compare traces against the first, reporting where each diverges.
+    Compare {
+        #[arg(required = true, num_args = 2..)]
+        traces: Vec<PathBuf>,
+    },
+}
+
+const PLAYERS: usize = 4;
+const ARENA: i32 = 500;
+
+/// Every player's stick for one tick, from -100 to 100 per axis.
+type Input = [[i8; 2]; PLAYERS];
+
+/// Players push their bodies around an arena with bouncy walls, and score a point for
+/// every wall they hit.
+struct Arena {
+    positions: [FixedVec3; PLAYERS],
+    velocities: [FixedVec3; PLAYERS],
+    bounces: [u32; PLAYERS],
+}
+
+impl Arena {
+    fn new() -> Self {
+        let position = |player: usize| {
+            FixedVec3::new(
+                Fixed::from_int(player as i32 * 100 - 150),
+                Fixed::ZERO,
+                Fixed::ZERO,
+            )
+        };
+        Self {
+            positions: [0, 1, 2, 3].map(position),
+            velocities: [FixedVec3::ZERO; PLAYERS],
+            bounces: [0; PLAYERS],
+        }
+    }
+}
+
+impl Simulation for Arena {
+    type Input = Input;
+
+    fn step(&mut self, _tick: u64, input: &Input) {
+        let dt = Fixed::ratio(1, 60);
+        let drag = Fixed::ratio(98, 100);
+        let wall = Fixed::from_int(ARENA);
+        for (player, &[x, y]) in input.iter().enumerate() {
+            let push = FixedVec3::new(
+                Fixed::ratio(x.into(), 10),
+                Fixed::ratio(y.into(), 10),
+                Fixed::ZERO,
+            );
+            let velocity = &mut self.velocities[player];
+            *velocity = (*velocity + push) * drag;
+            let position = &mut self.positions[player];
+            *position += *velocity * dt;
+            for (coordinate, speed) in [
+                (&mut position.x, &mut velocity.x),
+                (&mut position.y, &mut velocity.y),
+            ] {
+                if coordinate.abs() > wall {
+                    *coordinate = if *coordinate > Fixed::ZERO {
+                        wall
+                    } else {
+                        -wall
+                    };
+                    *speed = -*speed;
+                    self.bounces[player] += 1;
+                }
+            }
+        }
+    }
+
+    fn hash_state(&self, hashes: &mut StateHashes) {
+        hashes.add("positions", &self.positions);
+        hashes.add("velocities", &self.velocities);
+        hashes.add("bounces", &self.bounces);
+    }
+}
+
+fn generate_script(seed: u64, ticks: usize) -> InputScript<Input> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut sticks = [[0i8; 2]; PLAYERS];
+    let ticks = (0..ticks)
+        .map(|_| {
+            // Players hold a direction for a while, like real ones.
+            for stick in sticks.iter_mut() {
+                if rng.gen_ratio(1, 20) {
+                    *stick = [rng.gen_range(-100..=100), rng.gen_range(-100..=100)];
+                }
+            }
+            sticks
+        })
+        .collect();
+    InputScript { ticks }
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Could not read {}: {}", path.display(), e))?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+fn main() -> anyhow::Result<ExitCode> {
+    match Args::parse().command {
+        Command::Run {
+            script,
+            seed,
+            ticks,
+            save_script,
+            target,
+            out,
+        } => {
+            let script = match script {
+                Some(path) => read_json(&path)?,
+                None => generate_script(seed, ticks),
+            };
+            if let Some(path) = save_script {
+                fs::write(path, serde_json::to_string(&script)?)?;
+            }
+            let target = target
+                .unwrap_or_else(|| format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH));
+            let trace = determinism::run(Arena::new(), &script, target);
+            fs::write(out, serde_json::to_string(&trace)?)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Compare { traces } => {
+            let traces = traces
+                .iter()
+                .map(PathBuf::as_path)
+                .map(read_json)
+                .collect::<anyhow::Result<Vec<HashTrace>>>()?;
+            let mut diverged = false;
+            for trace in &traces[1..] {
+                match traces[0].first_divergence(trace) {
+                    Some(divergence) => {
+                        diverged = true;
+                        println!("{}", divergence);
+                    }
+                    None => println!(
+                        "{} and {} agree on all {} ticks",
+                        traces[0].target,
+                        trace.target,
+                        trace.ticks.len()
+                    ),
+                }
+            }
+            Ok(if diverged {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            })
+        }
+    }
+}
@@ -41,11 +41,15 @@ pub fn set_movement_actions(
             - get_movement(GameControl::Down, &keyboard_input),
     );
 
-    if let Some(touch_position) = touch_input.first_pressed_position() {
+    // Spectators have no player to steer.
+    let touch = touch_input
+        .first_pressed_position()
+        .zip(player.get_single().ok());
+    if let Some((touch_position, player)) = touch {
         let (camera, camera_transform) = camera.single();
         if let Some(touch_position) = camera.viewport_to_world_2d(camera_transform, touch_position)
         {
-            let diff = touch_position - player.single().translation.xy();
+            let diff = touch_position - player.translation.xy();
             if diff.length() > FOLLOW_EPSILON {
                 player_movement = diff.normalize();
             }
@@ -0,0 +1,223 @@
+//! Optional encryption of the files the game keeps, like the friends and mute lists.
+//! Off by default: insert an [`AtRestEncryption`] before adding the plugins to turn it
+//! on. Files written before that are read as they are and sealed right away.
+//!
+//! A sealed file is a single line: [`SEALED_PREFIX`], where the key came from, then the
+//! salt, nonce and AES-256-GCM ciphertext in hex.
+
+use std::{fmt, io, path::Path};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, OsRng, Payload},
+    Aes256Gcm, KeyInit, Nonce,
+};
+use bevy::prelude::*;
+use zeroize::Zeroizing;
+
+use crate::paths;
+
+const SEALED_PREFIX: &str = "sealed-v1";
+/// Authenticated with every sealed file, so its contents can't pass for anything else.
+const AAD: &[u8] = b"bevy-libp2p at-rest v1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+/// The keychain entry holding the key for [`AtRestEncryption::Keychain`].
+#[cfg(not(target_arch = "wasm32"))]
+const KEYCHAIN_USER: &str = "at-rest-key";
+
+/// How the game's files are protected on disk.
+#[derive(Resource, Clone, Default, PartialEq, Eq)]
+pub enum AtRestEncryption {
+    /// Plain text, readable by anyone who can read the files.
+    #[default]
+    Off,
+    /// A random key kept in the OS keychain, made on first use. Not in the browser.
+    Keychain,
+    /// A key stretched from the passphrase with Argon2, salted per file.
+    Passphrase(String),
+}
+
+impl fmt::Debug for AtRestEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => f.write_str("Off"),
+            Self::Keychain => f.write_str("Keychain"),
+            Self::Passphrase(_) => f.write_str("Passphrase(..)"),
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn from_hex(hex: &str) -> io::Result<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return Err(invalid("odd length hex"));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| invalid(e.to_string())))
+        .collect()
+}
+
+/// Whether `contents` were written by [`AtRestEncryption::seal`].
+pub fn is_sealed(contents: &str) -> bool {
+    contents.starts_with(SEALED_PREFIX)
+}
+
+impl AtRestEncryption {
+    /// How sealed files name where their key came from.
+    fn source(&self) -> &'static str {
+        match self {
+            Self::Off => "none",
+            Self::Keychain => "keychain",
+            Self::Passphrase(_) => "argon2",
+        }
+    }
+
+    fn key(&self, salt: &[u8]) -> io::Result<Zeroizing<[u8; 32]>> {
+        match self {
+            Self::Off => Err(invalid("At rest encryption is off")),
+            Self::Keychain => keychain_key(),
+            Self::Passphrase(passphrase) => {
+                let mut key = Zeroizing::new([0; 32]);
+                argon2::Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), salt, &mut *key)
+                    .map_err(|e| invalid(format!("Could not derive the key: {}", e)))?;
+                Ok(key)
+            }
+        }
+    }
+
+    /// Encrypts `plaintext` for storing.
+    pub fn seal(&self, plaintext: &str) -> io::Result<String> {
+        let salt: [u8; SALT_LEN] = rand::random();
+        let key = self.key(&salt)?;
+        let nonce = Aes256Gcm::generate_nonce(OsRng);
+        let payload = Payload {
+            msg: plaintext.as_bytes(),
+            aad: AAD,
+        };
+        let ciphertext = Aes256Gcm::new(&(*key).into())
+            .encrypt(&nonce, payload)
+            .map_err(|e| invalid(format!("Could not encrypt: {}", e)))?;
+        Ok(format!(
+            "{}:{}:{}:{}:{}\n",
+            SEALED_PREFIX,
+            self.source(),
+            to_hex(&salt),
+            to_hex(&nonce),
+            to_hex(&ciphertext)
+        ))
+    }
+
+    /// Decrypts what [`seal`](Self::seal) wrote, with the same settings.
+    pub fn open(&self, sealed: &str) -> io::Result<String> {
+        let parts: Vec<_> = sealed.trim().split(':').collect();
+        let [SEALED_PREFIX, source, salt, nonce, ciphertext] = parts[..] else {
+            return Err(invalid("Not a sealed file"));
+        };
+        if source != self.source() {
+            return Err(invalid(format!(
+                "Sealed with {}, but at rest encryption is {:?}",
+                source, self
+            )));
+        }
+        let key = self.key(&from_hex(salt)?)?;
+        let nonce = from_hex(nonce)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(invalid("Bad nonce"));
+        }
+        let payload = Payload {
+            msg: &from_hex(ciphertext)?,
+            aad: AAD,
+        };
+        let plaintext = Aes256Gcm::new(&(*key).into())
+            .decrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| invalid("Wrong key, or the file was changed"))?;
+        String::from_utf8(plaintext).map_err(|e| invalid(e.to_string()))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn keychain_key() -> io::Result<Zeroizing<[u8; 32]>> {
+    let keychain = |e: keyring::Error| io::Error::new(io::ErrorKind::Other, e.to_string());
+    let entry = keyring::Entry::new(paths::APPLICATION, KEYCHAIN_USER).map_err(keychain)?;
+    match entry.get_password() {
+        Ok(stored) => {
+            let bytes = Zeroizing::new(from_hex(&Zeroizing::new(stored))?);
+            let mut key = Zeroizing::new([0; 32]);
+            if bytes.len() != key.len() {
+                return Err(invalid("The keychain holds a malformed key"));
+            }
+            key.copy_from_slice(&bytes);
+            Ok(key)
+        }
+        Err(keyring::Error::NoEntry) => {
+            let key = Zeroizing::new(rand::random::<[u8; 32]>());
+            entry
+                .set_password(&Zeroizing::new(to_hex(&*key)))
+                .map_err(keychain)?;
+            Ok(key)
+        }
+        Err(e) => Err(keychain(e)),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn keychain_key() -> io::Result<Zeroizing<[u8; 32]>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "No keychain in the browser",
+    ))
+}
+
+/// Like [`paths::read_to_string`], opening sealed files. A plain file is sealed on the
+/// spot if `encryption` is on.
+pub fn read_to_string(path: &Path, encryption: &AtRestEncryption) -> io::Result<String> {
+    let contents = paths::read_to_string(path)?;
+    match (is_sealed(&contents), encryption) {
+        (true, AtRestEncryption::Off) => Err(invalid(
+            "The file is encrypted, but at rest encryption is off",
+        )),
+        (true, _) => encryption.open(&contents),
+        (false, AtRestEncryption::Off) => Ok(contents),
+        (false, _) => {
+            paths::write(path, &encryption.seal(&contents)?)?;
+            log::info!("Encrypted {}", path.display());
+            Ok(contents)
+        }
+    }
+}
+
+/// Like [`paths::write`], sealing `contents` first if `encryption` is on.
+pub fn write(path: &Path, contents: &str, encryption: &AtRestEncryption) -> io::Result<()> {
+    match encryption {
+        AtRestEncryption::Off => paths::write(path, contents),
+        _ => paths::write(path, &encryption.seal(contents)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passphrase_round_trip() {
+        let encryption = AtRestEncryption::Passphrase("hunter2".to_string());
+        let sealed = encryption.seal("friend list\n").unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("friend"));
+        assert_eq!(encryption.open(&sealed).unwrap(), "friend list\n");
+
+        let wrong = AtRestEncryption::Passphrase("hunter3".to_string());
+        assert!(wrong.open(&sealed).is_err());
+        assert!(AtRestEncryption::Keychain.open(&sealed).is_err());
+        assert!(!is_sealed("friend list\n"));
+    }
+}
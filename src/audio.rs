@@ -1,8 +1,14 @@
+use std::collections::HashSet;
+
 use crate::actions::{set_movement_actions, Actions};
+use crate::chat::ChatMessage;
 use crate::loading::AudioAssets;
+use crate::lobby::AnswerJoinRequest;
+use crate::network::{NetworkAdminEvent, NetworkEvent};
 use crate::GameState;
 use bevy::prelude::*;
 use bevy_kira_audio::prelude::*;
+use libp2p::PeerId;
 
 pub struct InternalAudioPlugin;
 
@@ -10,7 +16,18 @@ pub struct InternalAudioPlugin;
 impl Plugin for InternalAudioPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(AudioPlugin)
+            .add_audio_channel::<CueChannel>()
+            .init_resource::<AudioCues>()
+            .add_event::<PlayCue>()
             .add_systems(OnEnter(GameState::Playing), start_audio)
+            .add_systems(
+                Update,
+                (
+                    cue_room_events,
+                    play_cues.run_if(resource_exists::<AudioAssets>()),
+                )
+                    .chain(),
+            )
             .add_systems(
                 Update,
                 control_flying_sound
@@ -54,3 +71,136 @@ fn control_flying_sound(
         }
     }
 }
+
+/// Short sounds for what happens in the room, so players notice without watching the
+/// screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Cue {
+    /// We got into a room, or someone got into ours.
+    Join,
+    /// A player left the room.
+    Leave,
+    /// A chat message came in from someone we haven't muted.
+    Chat,
+    /// A player dropped out of the room without leaving.
+    Disconnect,
+}
+
+impl Cue {
+    pub(crate) const ALL: [Cue; 4] = [Cue::Join, Cue::Leave, Cue::Chat, Cue::Disconnect];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Cue::Join => "Join",
+            Cue::Leave => "Leave",
+            Cue::Chat => "Chat",
+            Cue::Disconnect => "Drop",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CueSettings {
+    /// From 0 to 1.
+    pub volume: f64,
+    pub muted: bool,
+}
+
+impl Default for CueSettings {
+    fn default() -> Self {
+        Self {
+            volume: 0.5,
+            muted: false,
+        }
+    }
+}
+
+/// How loud each [`Cue`] plays, changed on the settings screen.
+#[derive(Resource, Debug, Clone, Default)]
+pub(crate) struct AudioCues {
+    settings: [CueSettings; Cue::ALL.len()],
+}
+
+impl AudioCues {
+    pub(crate) fn get(&self, cue: Cue) -> CueSettings {
+        self.settings[cue as usize]
+    }
+
+    pub(crate) fn get_mut(&mut self, cue: Cue) -> &mut CueSettings {
+        &mut self.settings[cue as usize]
+    }
+}
+
+/// Plays `0` unless it's muted.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PlayCue(pub Cue);
+
+/// Cues get their own channel, so pausing the flying sound doesn't hold them up.
+#[derive(Resource)]
+struct CueChannel;
+
+fn cue_room_events(
+    mut network_events: EventReader<NetworkEvent<()>>,
+    mut answers: EventReader<AnswerJoinRequest>,
+    mut chat: EventReader<ChatMessage>,
+    mut cues: EventWriter<PlayCue>,
+    mut members: Local<HashSet<PeerId>>,
+) {
+    for _ in chat.iter().filter(|message| !message.local) {
+        cues.send(PlayCue(Cue::Chat));
+    }
+    for answer in answers.iter().filter(|answer| answer.accept) {
+        members.insert(answer.peer_id);
+        cues.send(PlayCue(Cue::Join));
+    }
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        match event {
+            NetworkAdminEvent::HostingStarted { .. } => members.clear(),
+            NetworkAdminEvent::JoinAccepted { .. } => {
+                members.clear();
+                cues.send(PlayCue(Cue::Join));
+            }
+            // Other joiners only show up once they say whether they are ready.
+            NetworkAdminEvent::PeerReady { peer_id, .. } => {
+                if members.insert(*peer_id) {
+                    cues.send(PlayCue(Cue::Join));
+                }
+            }
+            NetworkAdminEvent::PlayerLeft(peer_id) => {
+                if members.remove(peer_id) {
+                    cues.send(PlayCue(Cue::Leave));
+                }
+            }
+            NetworkAdminEvent::Disconnected(peer_id) => {
+                if members.remove(peer_id) {
+                    cues.send(PlayCue(Cue::Disconnect));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn play_cues(
+    settings: Res<AudioCues>,
+    audio_assets: Res<AudioAssets>,
+    channel: Res<AudioChannel<CueChannel>>,
+    mut cues: EventReader<PlayCue>,
+) {
+    for PlayCue(cue) in cues.iter() {
+        let CueSettings { volume, muted } = settings.get(*cue);
+        if muted || volume <= 0.0 {
+            continue;
+        }
+        let sound = match cue {
+            Cue::Join => &audio_assets.join,
+            Cue::Leave => &audio_assets.leave,
+            Cue::Chat => &audio_assets.chat,
+            Cue::Disconnect => &audio_assets.disconnect,
+        };
+        channel.play(sound.clone()).with_volume(volume);
+    }
+}
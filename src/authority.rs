@@ -0,0 +1,255 @@
+//! Who simulates what. Every entity the peers share carries a [`NetworkId`], the same
+//! on all of them, and an [`Authority`] naming the one peer that runs it; the rest only
+//! follow along. Entities start out with the host, and are handed around by asking
+//! the host, e.g. to move an object you picked up without waiting on the round trip.
+//!
+//! The host settles competing requests: whoever asks first gets the entity, and the
+//! host can take it back at any time. Whatever a leaving peer had goes back to the
+//! host.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::network::{
+    AsyncNetworkTasks, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent, NetworkManager,
+};
+
+pub struct AuthorityPlugin;
+
+impl Plugin for AuthorityPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<NetworkId>()
+            .init_resource::<Authorities>()
+            .add_event::<RequestAuthority>()
+            .add_event::<ReleaseAuthority>()
+            .add_event::<TransferAuthority>()
+            .add_event::<AuthorityChanged>()
+            .add_event::<AuthorityDenied>()
+            .add_systems(
+                Update,
+                (
+                    track_authority::<()>,
+                    send_authority_requests,
+                    apply_authority,
+                )
+                    .chain()
+                    .run_if(resource_exists::<AsyncNetworkTasks<()>>()),
+            );
+    }
+}
+
+/// Names an entity across peers. The game picks these, e.g. the host numbers what it
+/// spawns and says so in the game message that spawns it everywhere.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub struct NetworkId(pub u64);
+
+/// The peer simulating this entity. Kept up to date on everything with a
+/// [`NetworkId`] once we are in a room.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Authority(pub PeerId);
+
+/// Who has what in the room we are in, as far as we've heard from its host.
+#[derive(Resource, Debug, Default)]
+pub struct Authorities {
+    local: Option<PeerId>,
+    host: Option<PeerId>,
+    /// Entities someone other than the host has.
+    owners: HashMap<u64, PeerId>,
+}
+
+impl Authorities {
+    /// Who simulates the entity with network id `id`, or `None` outside of a room.
+    pub fn owner(&self, id: NetworkId) -> Option<PeerId> {
+        self.owners.get(&id.0).copied().or(self.host)
+    }
+
+    /// Whether we host the room we are in, and so have the final say in it.
+    pub fn is_host(&self) -> bool {
+        self.host.is_some() && self.host == self.local
+    }
+
+    /// Whether it's up to us to simulate the entity with network id `id`.
+    pub fn is_local(&self, id: NetworkId) -> bool {
+        self.local.is_some() && self.owner(id) == self.local
+    }
+
+    fn set(&mut self, id: u64, owner: PeerId) -> Option<PeerId> {
+        let previous = self.owner(NetworkId(id));
+        if Some(owner) == self.host {
+            self.owners.remove(&id);
+        } else {
+            self.owners.insert(id, owner);
+        }
+        previous
+    }
+
+    fn enter_room(&mut self, host: PeerId) {
+        self.host = Some(host);
+        self.owners.clear();
+    }
+}
+
+/// Ask the host to let us simulate `entity`. Answered with an [`AuthorityChanged`] or
+/// an [`AuthorityDenied`].
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestAuthority {
+    pub entity: Entity,
+}
+
+/// Hand `entity` back to the host, e.g. when dropping it.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReleaseAuthority {
+    pub entity: Entity,
+}
+
+/// Host only: give `entity` to `owner`, whoever has it now.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferAuthority {
+    pub entity: Entity,
+    pub owner: PeerId,
+}
+
+/// `entity` is now simulated by `to` instead of `from`.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthorityChanged {
+    pub entity: Entity,
+    pub from: Option<PeerId>,
+    pub to: PeerId,
+}
+
+/// We didn't get `entity`, because `owner` has it.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthorityDenied {
+    pub entity: Entity,
+    pub owner: PeerId,
+}
+
+fn track_authority<ToGame>(
+    manager: Res<NetworkManager<(), ()>>,
+    mut authorities: ResMut<Authorities>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+    entities: Query<(Entity, &NetworkId)>,
+    mut changed: EventWriter<AuthorityChanged>,
+    mut denied: EventWriter<AuthorityDenied>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    let find = |id: u64| {
+        entities
+            .iter()
+            .find(|(_, network_id)| network_id.0 == id)
+            .map(|(entity, _)| entity)
+    };
+    // Changes when the network restarts with a new identity.
+    if authorities.local != Some(manager.local_peer_id()) {
+        authorities.local = Some(manager.local_peer_id());
+    }
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        match event {
+            NetworkAdminEvent::HostingStarted { .. } => {
+                authorities.enter_room(manager.local_peer_id())
+            }
+            NetworkAdminEvent::JoinAccepted { host, .. } => authorities.enter_room(*host),
+            NetworkAdminEvent::AuthorityChanged { id, owner } => {
+                let from = authorities.set(*id, *owner);
+                if let Some(entity) = find(*id).filter(|_| from != Some(*owner)) {
+                    changed.send(AuthorityChanged {
+                        entity,
+                        from,
+                        to: *owner,
+                    });
+                }
+            }
+            NetworkAdminEvent::AuthorityDenied { id, owner } => {
+                if let Some(entity) = find(*id) {
+                    denied.send(AuthorityDenied {
+                        entity,
+                        owner: *owner,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn send_authority_requests(
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    mut requests: EventReader<RequestAuthority>,
+    mut releases: EventReader<ReleaseAuthority>,
+    mut transfers: EventReader<TransferAuthority>,
+    ids: Query<&NetworkId>,
+) {
+    for RequestAuthority { entity } in requests.iter() {
+        match ids.get(*entity) {
+            Ok(id) => {
+                tasks.send(GameEvent::Admin(GameAdminEvent::RequestAuthority(id.0)));
+            }
+            Err(_) => log::warn!("Can't take {:?}: it has no NetworkId", entity),
+        }
+    }
+    for ReleaseAuthority { entity } in releases.iter() {
+        if let Ok(id) = ids.get(*entity) {
+            tasks.send(GameEvent::Admin(GameAdminEvent::ReleaseAuthority(id.0)));
+        }
+    }
+    for TransferAuthority { entity, owner } in transfers.iter() {
+        match ids.get(*entity) {
+            Ok(id) => {
+                tasks.send(GameEvent::Admin(GameAdminEvent::TransferAuthority {
+                    id: id.0,
+                    owner: *owner,
+                }));
+            }
+            Err(_) => log::warn!("Can't hand out {:?}: it has no NetworkId", entity),
+        }
+    }
+}
+
+/// Keeps every [`Authority`] in line with [`Authorities`], including on entities
+/// spawned after their owner was announced.
+fn apply_authority(
+    mut commands: Commands,
+    authorities: Res<Authorities>,
+    entities: Query<(Entity, Ref<NetworkId>, Option<&Authority>)>,
+) {
+    for (entity, id, authority) in entities.iter() {
+        if !authorities.is_changed() && !id.is_added() {
+            continue;
+        }
+        let Some(owner) = authorities.owner(*id) else {
+            continue;
+        };
+        if authority.map(|authority| authority.0) != Some(owner) {
+            commands.entity(entity).insert(Authority(owner));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entities_are_the_hosts_until_handed_out() {
+        let (host, us) = (PeerId::random(), PeerId::random());
+        let mut authorities = Authorities {
+            local: Some(us),
+            ..Default::default()
+        };
+        assert_eq!(authorities.owner(NetworkId(1)), None);
+
+        authorities.enter_room(host);
+        assert_eq!(authorities.owner(NetworkId(1)), Some(host));
+        assert_eq!(authorities.set(1, us), Some(host));
+        assert!(authorities.is_local(NetworkId(1)));
+        assert_eq!(authorities.set(1, host), Some(us));
+        assert!(!authorities.is_local(NetworkId(1)));
+        assert!(authorities.owners.is_empty());
+    }
+}
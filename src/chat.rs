@@ -0,0 +1,280 @@
+//! The room's text chat, and who we don't want to hear from.
+//!
+//! Muting happens at the receiving end: a [`MuteList`] kept on disk silences peers for
+//! us alone, in every room, and the host can mute a peer for the whole room with
+//! [`RoomMute`]. [`ChatMessage`]s only come from peers neither mutes; anything else
+//! peers say to each other, like voice, should check [`Mutes::is_muted`] the same way.
+
+use std::{collections::HashSet, io, path::PathBuf, str::FromStr};
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::network::{
+    AsyncNetworkTasks, GameAdminEvent, GameEvent, LocalPeerInfo, NetworkAdminEvent, NetworkEvent,
+};
+use crate::{
+    at_rest::{self, AtRestEncryption},
+    paths::Paths,
+};
+
+/// Longest chat line we send. Longer ones are cut.
+pub const MAX_CHAT_LEN: usize = 200;
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Paths>()
+            .init_resource::<AtRestEncryption>()
+            .init_resource::<MuteListFile>()
+            .init_resource::<MuteList>()
+            .init_resource::<RoomMutes>()
+            .init_resource::<ChatEcho>()
+            .add_event::<SendChat>()
+            .add_event::<ChatMessage>()
+            .add_event::<RoomMute>()
+            .add_systems(Startup, load_mute_list)
+            .add_systems(
+                Update,
+                (
+                    (receive_chat::<()>, send_chat, send_room_mutes)
+                        .run_if(resource_exists::<AsyncNetworkTasks<()>>()),
+                    save_mute_list,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Where the [`MuteList`] is stored, one peer id per line. Defaults to `muted.txt` in
+/// the [`Paths::config`] directory.
+#[derive(Resource, Debug, Clone)]
+pub struct MuteListFile(pub PathBuf);
+
+impl FromWorld for MuteListFile {
+    fn from_world(world: &mut World) -> Self {
+        Self(world.resource::<Paths>().config.join("muted.txt"))
+    }
+}
+
+/// Peers we muted ourselves. Kept across rooms and restarts.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MuteList(HashSet<PeerId>);
+
+impl MuteList {
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.0.contains(peer_id)
+    }
+
+    pub fn mute(&mut self, peer_id: PeerId) {
+        self.0.insert(peer_id);
+    }
+
+    pub fn unmute(&mut self, peer_id: &PeerId) {
+        self.0.remove(peer_id);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PeerId> {
+        self.0.iter()
+    }
+
+    /// The list as stored in [`MuteListFile`], sorted so it only changes when the list
+    /// does.
+    fn to_file(&self) -> String {
+        let mut lines: Vec<_> = self
+            .0
+            .iter()
+            .map(|peer_id| format!("{}\n", peer_id))
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+
+    /// Reads [`to_file`](Self::to_file) output, skipping lines that don't parse.
+    fn from_file(contents: &str) -> Self {
+        let mut list = Self::default();
+        for line in contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            match PeerId::from_str(line) {
+                Ok(peer_id) => list.mute(peer_id),
+                Err(e) => log::warn!("Skipping muted peer {:?}: {}", line, e),
+            }
+        }
+        list
+    }
+}
+
+/// Peers the host of our room muted for everyone in it.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct RoomMutes(HashSet<PeerId>);
+
+impl RoomMutes {
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.0.contains(peer_id)
+    }
+}
+
+/// Whether we listen to a peer, by our own [`MuteList`] and the host's [`RoomMutes`].
+#[derive(SystemParam)]
+pub struct Mutes<'w> {
+    list: Res<'w, MuteList>,
+    room: Res<'w, RoomMutes>,
+}
+
+impl Mutes<'_> {
+    pub fn is_muted(&self, peer_id: &PeerId) -> bool {
+        self.list.contains(peer_id) || self.room.contains(peer_id)
+    }
+}
+
+/// Say something in the room's chat.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct SendChat(pub String);
+
+/// `from` said `text`, and isn't muted.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub from: PeerId,
+    pub text: String,
+    /// We said it, and it's shown because of [`ChatEcho`].
+    pub local: bool,
+}
+
+/// Whether our own [`SendChat`] lines come back as [`ChatMessage`]s right away, so a
+/// chat window can show them without waiting for anyone. Off by default: the network
+/// never hands us our own messages.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChatEcho(pub bool);
+
+/// Host only: mute, or unmute, `peer_id` for everyone in the room.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoomMute {
+    pub peer_id: PeerId,
+    pub muted: bool,
+}
+
+fn load_mute_list(
+    file: Res<MuteListFile>,
+    encryption: Res<AtRestEncryption>,
+    mut list: ResMut<MuteList>,
+) {
+    match at_rest::read_to_string(&file.0, &encryption) {
+        Ok(contents) => *list = MuteList::from_file(&contents),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => log::error!("Could not read {}: {}", file.0.display(), e),
+    }
+}
+
+fn save_mute_list(
+    file: Res<MuteListFile>,
+    encryption: Res<AtRestEncryption>,
+    list: Res<MuteList>,
+    mut saved: Local<Option<String>>,
+) {
+    if !list.is_changed() {
+        return;
+    }
+    let contents = list.to_file();
+    match saved.as_ref() {
+        Some(saved) if *saved == contents => return,
+        // The first run sees what load_mute_list just read.
+        None => {
+            *saved = Some(contents);
+            return;
+        }
+        _ => {}
+    }
+    if let Err(e) = at_rest::write(&file.0, &contents, &encryption) {
+        log::error!("Could not write {}: {}", file.0.display(), e);
+    }
+    *saved = Some(contents);
+}
+
+fn receive_chat<ToGame>(
+    list: Res<MuteList>,
+    mut room_mutes: ResMut<RoomMutes>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+    mut messages: EventWriter<ChatMessage>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        match event {
+            NetworkAdminEvent::HostingStarted { .. } | NetworkAdminEvent::JoinAccepted { .. } => {
+                room_mutes.0.clear()
+            }
+            NetworkAdminEvent::RoomMuted { peer_id, muted } => {
+                if *muted {
+                    room_mutes.0.insert(*peer_id);
+                } else {
+                    room_mutes.0.remove(peer_id);
+                }
+            }
+            NetworkAdminEvent::ChatReceived { from, text } => {
+                if list.contains(from) || room_mutes.contains(from) {
+                    continue;
+                }
+                messages.send(ChatMessage {
+                    from: *from,
+                    text: text.chars().take(MAX_CHAT_LEN).collect(),
+                    local: false,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+fn send_chat(
+    echo: Res<ChatEcho>,
+    local: Option<Res<LocalPeerInfo>>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    mut chat: EventReader<SendChat>,
+    mut messages: EventWriter<ChatMessage>,
+) {
+    for SendChat(text) in chat.iter() {
+        let text: String = text.trim().chars().take(MAX_CHAT_LEN).collect();
+        if text.is_empty() {
+            continue;
+        }
+        if let (true, Some(local)) = (echo.0, &local) {
+            messages.send(ChatMessage {
+                from: local.peer_id,
+                text: text.clone(),
+                local: true,
+            });
+        }
+        tasks.send(GameEvent::Admin(GameAdminEvent::Chat(text)));
+    }
+}
+
+fn send_room_mutes(mut tasks: ResMut<AsyncNetworkTasks<()>>, mut mutes: EventReader<RoomMute>) {
+    for RoomMute { peer_id, muted } in mutes.iter() {
+        tasks.send(GameEvent::Admin(GameAdminEvent::RoomMute {
+            peer_id: *peer_id,
+            muted: *muted,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_round_trip() {
+        let mut list = MuteList::default();
+        list.mute(PeerId::random());
+        list.mute(PeerId::random());
+        assert_eq!(MuteList::from_file(&list.to_file()), list);
+        assert_eq!(MuteList::from_file("nope\n\n").iter().count(), 0);
+    }
+}
@@ -0,0 +1,320 @@
+//! A developer console for poking at the network by hand. Toggle it with the
+//! backtick key and type `help` for the commands.
+
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::{bevy_egui::EguiContexts, egui};
+use libp2p::{Multiaddr, PeerId};
+
+use crate::network::{
+    AsyncNetworkTasks, CommandId, ConnectionPath, GameAdminEvent, NetworkAdminEvent, NetworkEvent,
+};
+use crate::paths::{self, Paths};
+use crate::peer::{IsHost, Latency, PeerIdComp, PeerTimelines, StatsHistory};
+
+/// Lines of output kept around.
+const SCROLLBACK: usize = 200;
+
+const HELP: &str = "\
+dial <multiaddr>           dial an address
+providers <key>            ask the DHT who provides a key
+peers                      list the peers we know
+rotate-key                 host only: switch the room to a new key
+rotate-keys-now            host only: replace every room and team key, old ones stop
+                           working within seconds
+drop <peer id>             disconnect a peer and ignore its gossip
+simulate latency <ms>      hold back incoming game messages, 0 turns it off
+tape record                start taping room messages
+tape save <file>           stop taping and save the tape in the replay directory
+tape play <file>           feed a saved tape's incoming messages back in
+send-file <peer id> <file> offer a file to a peer, sent once they accept
+timeline <peer id>         what happened to a peer and when
+timeline export <file>     write every peer's timeline to a JSON file in the log directory
+stats export <file>        write every peer's RTT, loss and path samples to the log
+                           directory, as CSV for a .csv file and JSON otherwise";
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>().add_systems(
+            Update,
+            (
+                toggle_console,
+                (show_console, show_command_answers)
+                    .run_if(resource_exists::<AsyncNetworkTasks<()>>()),
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Resource, Default)]
+struct Console {
+    open: bool,
+    input: String,
+    lines: Vec<String>,
+    /// Commands sent to the network, waiting for their answer.
+    pending: HashMap<CommandId, String>,
+}
+
+impl Console {
+    fn print(&mut self, line: impl Into<String>) {
+        self.lines.push(line.into());
+        let excess = self.lines.len().saturating_sub(SCROLLBACK);
+        self.lines.drain(..excess);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ConsoleCommand {
+    Help,
+    Peers,
+    Timeline(PeerId),
+    ExportTimelines(PathBuf),
+    ExportStats(PathBuf),
+    SaveTape(PathBuf),
+    PlayTape(PathBuf),
+    /// Anything the network thread carries out for us.
+    Network(GameAdminEvent),
+}
+
+fn parse_command(line: &str) -> Result<ConsoleCommand, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let command = match words.as_slice() {
+        ["help"] => ConsoleCommand::Help,
+        ["peers"] => ConsoleCommand::Peers,
+        ["timeline", "export", path] => ConsoleCommand::ExportTimelines(PathBuf::from(path)),
+        ["stats", "export", path] => ConsoleCommand::ExportStats(PathBuf::from(path)),
+        ["timeline", peer_id] => {
+            let peer_id: PeerId = peer_id.parse().map_err(|e| format!("Bad peer id: {}", e))?;
+            ConsoleCommand::Timeline(peer_id)
+        }
+        ["dial", address] => {
+            let address: Multiaddr = address.parse().map_err(|e| format!("Bad address: {}", e))?;
+            ConsoleCommand::Network(GameAdminEvent::Dial(address))
+        }
+        ["providers", key] => {
+            ConsoleCommand::Network(GameAdminEvent::GetProviders(key.to_string()))
+        }
+        ["rotate-key"] => ConsoleCommand::Network(GameAdminEvent::RotateRoomKey),
+        ["rotate-keys-now"] => ConsoleCommand::Network(GameAdminEvent::RotateKeysNow),
+        ["drop", peer_id] => {
+            let peer_id: PeerId = peer_id.parse().map_err(|e| format!("Bad peer id: {}", e))?;
+            ConsoleCommand::Network(GameAdminEvent::Disconnect(peer_id))
+        }
+        ["simulate", "latency", millis] => {
+            let millis: u64 = millis.parse().map_err(|e| format!("Bad latency: {}", e))?;
+            ConsoleCommand::Network(GameAdminEvent::SimulateLatency(Duration::from_millis(
+                millis,
+            )))
+        }
+        ["tape", "record"] => ConsoleCommand::Network(GameAdminEvent::RecordTape),
+        ["tape", "save", path] => ConsoleCommand::SaveTape(PathBuf::from(path)),
+        ["tape", "play", path] => ConsoleCommand::PlayTape(PathBuf::from(path)),
+        ["send-file", peer_id, path] => {
+            let peer_id: PeerId = peer_id.parse().map_err(|e| format!("Bad peer id: {}", e))?;
+            ConsoleCommand::Network(GameAdminEvent::SendFile {
+                peer_id,
+                path: PathBuf::from(path),
+            })
+        }
+        [] => return Err("Nothing to run".to_string()),
+        [name, ..] => return Err(format!("Unknown command {:?}, try help", name)),
+    };
+    Ok(command)
+}
+
+fn toggle_console(keys: Res<Input<KeyCode>>, mut console: ResMut<Console>) {
+    if keys.just_pressed(KeyCode::Grave) {
+        console.open = !console.open;
+    }
+}
+
+fn show_console(
+    mut contexts: EguiContexts,
+    mut console: ResMut<Console>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    timelines: Res<PeerTimelines>,
+    stats: Res<StatsHistory>,
+    paths: Res<Paths>,
+    peers: Query<(
+        &PeerIdComp,
+        &Name,
+        Option<&Latency>,
+        Option<&ConnectionPath>,
+        Option<&IsHost>,
+    )>,
+) {
+    if !console.open {
+        return;
+    }
+    let console = &mut *console;
+    let mut submitted = None;
+    egui::Window::new("Console").show(contexts.ctx_mut(), |ui| {
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for line in &console.lines {
+                    ui.monospace(line);
+                }
+            });
+        // The toggle key shouldn't end up in the command.
+        console.input.retain(|c| c != '`');
+        let response = ui.text_edit_singleline(&mut console.input);
+        if response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+            submitted = Some(std::mem::take(&mut console.input));
+        }
+        response.request_focus();
+    });
+    let Some(line) = submitted.filter(|line| !line.trim().is_empty()) else {
+        return;
+    };
+    console.print(format!("> {}", line));
+    match parse_command(&line) {
+        Ok(ConsoleCommand::Help) => console.print(HELP),
+        Ok(ConsoleCommand::Peers) => {
+            if peers.is_empty() {
+                console.print("No peers");
+            }
+            for (peer_id, name, latency, path, host) in &peers {
+                console.print(format!(
+                    "{} {}{} {} {}",
+                    peer_id.0,
+                    name,
+                    if host.is_some() { " (host)" } else { "" },
+                    latency.map_or("-".to_string(), |latency| {
+                        format!("{} ms", latency.0.as_millis())
+                    }),
+                    path.map_or("-".to_string(), |path| format!("{:?}", path)),
+                ));
+            }
+        }
+        Ok(ConsoleCommand::Timeline(peer_id)) => match timelines.get(&peer_id) {
+            Some(entries) => {
+                for entry in entries {
+                    console.print(format!("{} {:?}", entry.unix_ms, entry.event));
+                }
+            }
+            None => console.print("Nothing recorded for that peer"),
+        },
+        Ok(ConsoleCommand::ExportTimelines(path)) => {
+            // Absolute paths are kept as they are.
+            let path = paths.logs.join(path);
+            let written = timelines
+                .to_json()
+                .map_err(anyhow::Error::from)
+                .and_then(|json| Ok(paths::write(&path, &json)?));
+            match written {
+                Ok(()) => console.print(format!("Wrote {}", path.display())),
+                Err(e) => console.print(format!("Could not write {}: {}", path.display(), e)),
+            }
+        }
+        Ok(ConsoleCommand::ExportStats(path)) => {
+            let path = paths.logs.join(path);
+            let contents = if path.extension().map_or(false, |ext| ext == "csv") {
+                Ok(stats.to_csv())
+            } else {
+                stats.to_json()
+            };
+            let written = contents
+                .map_err(anyhow::Error::from)
+                .and_then(|contents| Ok(paths::write(&path, &contents)?));
+            match written {
+                Ok(()) => console.print(format!("Wrote {}", path.display())),
+                Err(e) => console.print(format!("Could not write {}: {}", path.display(), e)),
+            }
+        }
+        Ok(ConsoleCommand::SaveTape(path)) => {
+            let id = tasks.command(GameAdminEvent::SaveTape(paths.replays.join(path)));
+            console.pending.insert(id, line);
+        }
+        Ok(ConsoleCommand::PlayTape(path)) => {
+            let id = tasks.command(GameAdminEvent::PlayTape(paths.replays.join(path)));
+            console.pending.insert(id, line);
+        }
+        Ok(ConsoleCommand::Network(command)) => {
+            let id = tasks.command(command);
+            console.pending.insert(id, line);
+        }
+        Err(e) => console.print(e),
+    }
+}
+
+fn show_command_answers(
+    mut console: ResMut<Console>,
+    mut network_events: EventReader<NetworkEvent<()>>,
+) {
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        match event {
+            NetworkAdminEvent::CommandCompleted(id) => {
+                if let Some(line) = console.pending.remove(id) {
+                    console.print(format!("ok: {}", line));
+                }
+            }
+            NetworkAdminEvent::CommandFailed { id, reason } => {
+                if let Some(line) = console.pending.remove(id) {
+                    console.print(format!("failed: {}: {}", line, reason));
+                }
+            }
+            NetworkAdminEvent::ProvidersFound { key, providers } => {
+                console.print(format!("{} providers for {}", providers.len(), key));
+                for provider in providers {
+                    console.print(format!("  {}", provider));
+                }
+            }
+            NetworkAdminEvent::RoomKeyRotated => console.print("Room key rotated"),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_parse() {
+        assert_eq!(
+            parse_command("simulate latency 200"),
+            Ok(ConsoleCommand::Network(GameAdminEvent::SimulateLatency(
+                Duration::from_millis(200)
+            )))
+        );
+        assert_eq!(
+            parse_command("  dial /ip4/127.0.0.1/tcp/4001 "),
+            Ok(ConsoleCommand::Network(GameAdminEvent::Dial(
+                "/ip4/127.0.0.1/tcp/4001".parse().unwrap()
+            )))
+        );
+        assert_eq!(parse_command("peers"), Ok(ConsoleCommand::Peers));
+        assert_eq!(
+            parse_command("tape save crash.tape"),
+            Ok(ConsoleCommand::SaveTape("crash.tape".into()))
+        );
+        assert_eq!(
+            parse_command("timeline export drops.json"),
+            Ok(ConsoleCommand::ExportTimelines("drops.json".into()))
+        );
+        assert_eq!(
+            parse_command("stats export tuning.csv"),
+            Ok(ConsoleCommand::ExportStats("tuning.csv".into()))
+        );
+    }
+
+    #[test]
+    fn bad_commands_say_why() {
+        assert!(parse_command("dial nowhere")
+            .unwrap_err()
+            .contains("address"));
+        assert!(parse_command("drop someone")
+            .unwrap_err()
+            .contains("peer id"));
+        assert!(parse_command("frobnicate").unwrap_err().contains("Unknown"));
+    }
+}
@@ -0,0 +1,407 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, OsRng, Payload},
+    Aes256Gcm, KeyInit, Nonce,
+};
+use bevy::prelude::Resource;
+use generic_array::typenum::Unsigned;
+use libp2p::gossipsub::{DataTransform, TopicHash};
+
+mod peer_secrets;
+mod room_key;
+mod unicast;
+#[cfg(test)]
+mod vectors;
+
+pub use peer_secrets::{PeerCiphertext, PeerSecrets};
+pub use room_key::{MasterSecret, RoomKey};
+pub use unicast::{identity_public_key, open, seal, x25519_public, x25519_secret, SealedBox};
+
+/// How long a key still decrypts after a newer one took over, for messages that were
+/// in flight during the rotation. Then it is dropped, and wiped with it.
+pub const RETIRED_KEY_GRACE: Duration = Duration::from_secs(30);
+
+/// The grace of keys retired because someone who held them can't be trusted anymore,
+/// just long enough for messages already in flight.
+pub const COMPROMISED_KEY_GRACE: Duration = Duration::from_secs(5);
+
+/// Shared list of keys for the current room. The newest key encrypts, every key is
+/// tried when decrypting.
+///
+/// Topics inside the room, like a team's, can have keys of their own. Messages on them
+/// only use those, so peers without them can't read the topic.
+pub struct KeyRing {
+    room: Arc<RwLock<Vec<KeyEntry>>>,
+    topics: Arc<RwLock<HashMap<TopicHash, Vec<KeyEntry>>>>,
+}
+
+struct KeyEntry {
+    key: RoomKey,
+    cipher: Aes256Gcm,
+    /// How many keys the ring had before this one, ever.
+    generation: u64,
+    added: Instant,
+    /// How long the keys before this one still decrypt once it's added.
+    grace: Duration,
+    added_at: SystemTime,
+    /// Messages encrypted with this key.
+    sealed: AtomicU64,
+}
+
+impl KeyEntry {
+    fn new(key: RoomKey, generation: u64, grace: Duration) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(&key.key.into()),
+            key,
+            generation,
+            added: Instant::now(),
+            grace,
+            added_at: SystemTime::now(),
+            sealed: AtomicU64::new(0),
+        }
+    }
+}
+
+/// What the [`KeyRing`] holds, without the keys themselves. Kept up to date by
+/// [`NetworkPlugin`](crate::network::NetworkPlugin) for overlays and for deciding when
+/// to send [`GameAdminEvent::RotateRoomKey`](crate::network::GameAdminEvent::RotateRoomKey).
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct CryptoStatus {
+    /// Keys messages are accepted under, the current one included.
+    pub keys: usize,
+    /// Goes up by one with every new key, across rooms.
+    pub generation: u64,
+    /// When the current key came in, by rotation or by entering a room.
+    pub rotated_at: SystemTime,
+    /// Messages we encrypted with the current key.
+    pub encrypted_with_current: u64,
+    /// The current key's [`RoomKey::fingerprint`], to compare with peers.
+    pub fingerprint: [u8; 8],
+}
+
+/// Gossipsub [`DataTransform`] that encrypts every message with the [`KeyRing`].
+pub struct DataEncryptor {
+    keys: KeyRing,
+}
+
+impl DataEncryptor {
+    pub fn new() -> (Self, KeyRing) {
+        let keys = KeyRing {
+            room: Arc::new(RwLock::new(vec![KeyEntry::new(
+                RoomKey::random(),
+                0,
+                RETIRED_KEY_GRACE,
+            )])),
+            topics: Arc::default(),
+        };
+        (Self { keys: keys.clone() }, keys)
+    }
+}
+
+impl Clone for KeyRing {
+    fn clone(&self) -> Self {
+        Self {
+            room: Arc::clone(&self.room),
+            topics: Arc::clone(&self.topics),
+        }
+    }
+}
+
+impl KeyRing {
+    /// Starts encrypting with `key`, still accepting messages under the older keys.
+    pub fn add_key(&mut self, key: RoomKey) {
+        self.add_key_with_grace(key, RETIRED_KEY_GRACE);
+    }
+
+    /// Starts encrypting with `key`, accepting messages under the older keys for only
+    /// `grace` more, e.g. [`COMPROMISED_KEY_GRACE`]. Cuts the grace of keys retired
+    /// earlier short too.
+    pub fn add_key_with_grace(&mut self, key: RoomKey, grace: Duration) {
+        let mut entries = self.room.write().unwrap();
+        let generation = Self::next_generation(&entries);
+        entries.push(KeyEntry::new(key, generation, grace));
+    }
+
+    /// Swaps every key for `key`, so nothing sent in a previous room decrypts anymore.
+    /// Topic keys are dropped too.
+    pub fn replace(&mut self, key: RoomKey) {
+        let mut entries = self.room.write().unwrap();
+        let generation = Self::next_generation(&entries);
+        *entries = vec![KeyEntry::new(key, generation, RETIRED_KEY_GRACE)];
+        self.topics.write().unwrap().clear();
+    }
+
+    /// Starts encrypting `topic` with `key` instead of the room key, still accepting
+    /// messages under its older keys.
+    pub fn add_topic_key(&mut self, topic: TopicHash, key: RoomKey) {
+        let mut topics = self.topics.write().unwrap();
+        let entries = topics.entry(topic).or_default();
+        let generation = Self::next_generation(entries);
+        entries.push(KeyEntry::new(key, generation, RETIRED_KEY_GRACE));
+    }
+
+    /// Forgets `topic`'s keys, so it's under the room key again.
+    pub fn remove_topic_key(&mut self, topic: &TopicHash) {
+        self.topics.write().unwrap().remove(topic);
+    }
+
+    /// When the oldest retired key's grace runs out, if there is one.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        let room = self.room.read().expect("key read lock poisoned");
+        let topics = self.topics.read().expect("key read lock poisoned");
+        std::iter::once(&*room)
+            .chain(topics.values())
+            .filter_map(|entries| expiry(entries.get(1..)?))
+            .min()
+    }
+
+    /// Drops the retired keys whose grace ran out by `now`, returning how many.
+    pub fn prune(&mut self, now: Instant) -> usize {
+        let mut expired = prune_entries(&mut self.room.write().unwrap(), now);
+        for entries in self.topics.write().unwrap().values_mut() {
+            expired += prune_entries(entries, now);
+        }
+        expired
+    }
+
+    fn next_generation(entries: &[KeyEntry]) -> u64 {
+        entries.last().map_or(0, |entry| entry.generation + 1)
+    }
+
+    pub fn status(&self) -> CryptoStatus {
+        let entries = self.room.read().expect("key read lock poisoned");
+        let current = entries.last().expect("key ring is never empty");
+        CryptoStatus {
+            keys: entries.len(),
+            generation: current.generation,
+            rotated_at: current.added_at,
+            encrypted_with_current: current.sealed.load(Ordering::Relaxed),
+            fingerprint: current.key.fingerprint(),
+        }
+    }
+
+    /// The key currently used to encrypt, for sharing with peers we admit.
+    pub fn current_key(&self) -> RoomKey {
+        self.room
+            .read()
+            .expect("key read lock poisoned")
+            .last()
+            .expect("key ring is never empty")
+            .key
+            .clone()
+    }
+}
+
+/// When a key stops decrypting, given the keys added after it: at the end of the
+/// shortest grace any of them gave. `None` if none were.
+fn expiry(newer: &[KeyEntry]) -> Option<Instant> {
+    newer.iter().map(|entry| entry.added + entry.grace).min()
+}
+
+/// Drops the keys in `entries` whose grace ran out by `now`, returning how many. Older
+/// keys never outlive newer ones, so those are always the first few.
+fn prune_entries(entries: &mut Vec<KeyEntry>, now: Instant) -> usize {
+    let expired = (0..entries.len())
+        .take_while(|i| expiry(&entries[i + 1..]).map_or(false, |expiry| expiry <= now))
+        .count();
+    entries.drain(..expired);
+    expired
+}
+
+/// Encrypts `data` into the wire format every room message uses: the AES-256-GCM
+/// ciphertext with its tag, then the nonce. The room's AAD is authenticated but not
+/// sent. Changing any of this breaks compatibility with peers on older versions; see
+/// the test vectors.
+fn seal_envelope(
+    entry: &KeyEntry,
+    nonce: &Nonce<<Aes256Gcm as AeadCore>::NonceSize>,
+    data: &[u8],
+) -> Result<Vec<u8>, aes_gcm::Error> {
+    let payload = Payload {
+        msg: data,
+        aad: &entry.key.aad,
+    };
+    let mut sealed = entry.cipher.encrypt(nonce, payload)?;
+    sealed.extend(nonce.as_slice());
+    Ok(sealed)
+}
+
+/// Opens an envelope made by [`seal_envelope`] with whichever of `entries` sealed it,
+/// trying the newest first.
+fn open_envelope(entries: &[KeyEntry], sealed: &[u8]) -> Option<Vec<u8>> {
+    let data_size = sealed
+        .len()
+        .checked_sub(<Aes256Gcm as AeadCore>::NonceSize::to_usize())?;
+    let (ciphertext, nonce) = sealed.split_at(data_size);
+    entries.iter().rev().find_map(|entry| {
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &entry.key.aad,
+        };
+        entry.cipher.decrypt(nonce.into(), payload).ok()
+    })
+}
+
+impl DataTransform for DataEncryptor {
+    fn inbound_transform(
+        &self,
+        raw_message: libp2p::gossipsub::RawMessage,
+    ) -> Result<libp2p::gossipsub::Message, std::io::Error> {
+        let room = self.keys.room.read().expect("key read lock poisoned");
+        let topics = self.keys.topics.read().expect("key read lock poisoned");
+        let entries = topics.get(&raw_message.topic).unwrap_or(&room);
+        let data = open_envelope(entries, &raw_message.data).ok_or(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "Encryption failed: No corresponding key",
+        ))?;
+        Ok(libp2p::gossipsub::Message {
+            data,
+            source: raw_message.source,
+            sequence_number: raw_message.sequence_number,
+            topic: raw_message.topic,
+        })
+    }
+
+    fn outbound_transform(
+        &self,
+        topic: &libp2p::gossipsub::TopicHash,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>, std::io::Error> {
+        let room = self.keys.room.read().expect("key read lock poisoned");
+        let topics = self.keys.topics.read().expect("key read lock poisoned");
+        let entry = topics
+            .get(topic)
+            .unwrap_or(&room)
+            .last()
+            .expect("key ring is never empty");
+        let nonce = Aes256Gcm::generate_nonce(OsRng);
+        let sealed = seal_envelope(entry, &nonce, &data).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Decryption failed: {}", e),
+            )
+        })?;
+        entry.sealed.fetch_add(1, Ordering::Relaxed);
+        Ok(sealed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_works() {
+        let (encryptor, _keys) = DataEncryptor::new();
+        let data = b"Hello, world!";
+        let encrypted = encryptor
+            .outbound_transform(
+                &libp2p::gossipsub::TopicHash::from_raw("test"),
+                data.to_vec(),
+            )
+            .unwrap();
+        let raw_message = libp2p::gossipsub::RawMessage {
+            data: encrypted,
+            source: None,
+            sequence_number: Some(0),
+            topic: libp2p::gossipsub::TopicHash::from_raw("test"),
+            key: None,
+            signature: None,
+            validated: true,
+        };
+        let decrypted_msg = encryptor.inbound_transform(raw_message).unwrap();
+        assert_eq!(decrypted_msg.data, data);
+    }
+
+    #[test]
+    fn status_follows_rotations() {
+        let (encryptor, mut keys) = DataEncryptor::new();
+        let topic = libp2p::gossipsub::TopicHash::from_raw("test");
+        encryptor.outbound_transform(&topic, vec![1]).unwrap();
+        let status = keys.status();
+        assert_eq!((status.keys, status.generation), (1, 0));
+        assert_eq!(status.encrypted_with_current, 1);
+
+        let key = RoomKey::random();
+        keys.add_key(key.clone());
+        let status = keys.status();
+        assert_eq!((status.keys, status.generation), (2, 1));
+        assert_eq!(status.encrypted_with_current, 0);
+        assert_eq!(status.fingerprint, key.fingerprint());
+
+        keys.replace(RoomKey::random());
+        let status = keys.status();
+        assert_eq!((status.keys, status.generation), (1, 2));
+    }
+
+    #[test]
+    fn retired_keys_are_pruned_after_their_grace() {
+        let (_, mut keys) = DataEncryptor::new();
+        assert_eq!(keys.next_expiry(), None);
+        keys.add_key(RoomKey::random());
+        keys.add_key(RoomKey::random());
+        let expiry = keys.next_expiry().unwrap();
+        assert_eq!(keys.prune(expiry - Duration::from_secs(1)), 0);
+        let current = keys.current_key();
+        assert_eq!(keys.prune(expiry + RETIRED_KEY_GRACE), 2);
+        assert_eq!(keys.status().keys, 1);
+        assert!(keys.current_key() == current);
+        assert_eq!(keys.next_expiry(), None);
+    }
+
+    #[test]
+    fn compromised_keys_cut_every_grace_short() {
+        let (_, mut keys) = DataEncryptor::new();
+        keys.add_key(RoomKey::random());
+        let retired = keys.next_expiry().unwrap();
+        keys.add_key_with_grace(RoomKey::random(), COMPROMISED_KEY_GRACE);
+        let expiry = keys.next_expiry().unwrap();
+        assert!(expiry < retired);
+        assert_eq!(keys.prune(expiry - Duration::from_secs(1)), 0);
+        assert_eq!(keys.prune(expiry), 2);
+        assert_eq!(keys.status().keys, 1);
+    }
+
+    #[test]
+    fn topic_keys_only_cover_their_topic() {
+        let (encryptor, mut keys) = DataEncryptor::new();
+        let (room, team) = (TopicHash::from_raw("room"), TopicHash::from_raw("team"));
+        keys.add_topic_key(team.clone(), RoomKey::random());
+        let raw_message = |topic: &TopicHash, data: Vec<u8>| libp2p::gossipsub::RawMessage {
+            data,
+            source: None,
+            sequence_number: Some(0),
+            topic: topic.clone(),
+            key: None,
+            signature: None,
+            validated: true,
+        };
+
+        let sealed = encryptor.outbound_transform(&team, vec![1]).unwrap();
+        assert!(encryptor
+            .inbound_transform(raw_message(&room, sealed.clone()))
+            .is_err());
+        assert_eq!(
+            encryptor
+                .inbound_transform(raw_message(&team, sealed))
+                .unwrap()
+                .data,
+            vec![1]
+        );
+
+        // Without the key, a team member's messages can't be read.
+        let sealed = encryptor.outbound_transform(&team, vec![2]).unwrap();
+        keys.remove_topic_key(&team);
+        assert!(encryptor
+            .inbound_transform(raw_message(&team, sealed))
+            .is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, OsRng, Payload},
+    Aes256Gcm, Key, KeyInit,
+};
+use hkdf::Hkdf;
+use libp2p::{identity, PeerId};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::StaticSecret;
+use zeroize::Zeroizing;
+
+use super::{x25519_public, x25519_secret};
+
+const PEER_SECRET_INFO: &[u8] = b"bevy-p2p-demo peer secret v1";
+
+/// A payload encrypted with the secret shared by exactly two peers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerCiphertext {
+    pub nonce: [u8; 12],
+    pub ciphertext: Vec<u8>,
+}
+
+/// Symmetric keys shared with every peer we talk to.
+///
+/// Each key comes from a static X25519 exchange between the two libp2p identities,
+/// so both sides derive it on their own without a handshake, and a message that
+/// opens under it can only have come from the other peer.
+pub struct PeerSecrets {
+    local_peer_id: PeerId,
+    secret: StaticSecret,
+    ciphers: HashMap<PeerId, Aes256Gcm>,
+}
+
+impl PeerSecrets {
+    pub fn new(keypair: &identity::Keypair) -> anyhow::Result<Self> {
+        Ok(Self {
+            local_peer_id: keypair.public().to_peer_id(),
+            secret: x25519_secret(keypair)?,
+            ciphers: HashMap::new(),
+        })
+    }
+
+    fn cipher(&mut self, peer_id: &PeerId) -> anyhow::Result<&Aes256Gcm> {
+        if !self.ciphers.contains_key(peer_id) {
+            let shared = self.secret.diffie_hellman(&x25519_public(peer_id)?);
+            // Order the ids so both ends use the same salt.
+            let (low, high) = if self.local_peer_id < *peer_id {
+                (self.local_peer_id, *peer_id)
+            } else {
+                (*peer_id, self.local_peer_id)
+            };
+            let salt = [low.to_bytes(), high.to_bytes()].concat();
+            let mut key = Zeroizing::new(Key::<Aes256Gcm>::default());
+            Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+                .expand(PEER_SECRET_INFO, &mut key)
+                .expect("32 bytes is a valid HKDF-SHA256 output length");
+            self.ciphers.insert(*peer_id, Aes256Gcm::new(&key));
+        }
+        Ok(&self.ciphers[peer_id])
+    }
+
+    /// Encrypts `plaintext` for `peer_id`.
+    pub fn encrypt(
+        &mut self,
+        peer_id: &PeerId,
+        plaintext: &[u8],
+    ) -> anyhow::Result<PeerCiphertext> {
+        // Bind the direction so a message can't be reflected back at its sender.
+        let aad = [self.local_peer_id.to_bytes(), peer_id.to_bytes()].concat();
+        let nonce = Aes256Gcm::generate_nonce(OsRng);
+        let ciphertext = self
+            .cipher(peer_id)?
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|e| anyhow::anyhow!("Encrypting for {} failed: {}", peer_id, e))?;
+        Ok(PeerCiphertext {
+            nonce: nonce.into(),
+            ciphertext,
+        })
+    }
+
+    /// Decrypts a message `peer_id` encrypted for us.
+    pub fn decrypt(
+        &mut self,
+        peer_id: &PeerId,
+        message: &PeerCiphertext,
+    ) -> anyhow::Result<Vec<u8>> {
+        let aad = [peer_id.to_bytes(), self.local_peer_id.to_bytes()].concat();
+        self.cipher(peer_id)?
+            .decrypt(
+                (&message.nonce).into(),
+                Payload {
+                    msg: &message.ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|e| anyhow::anyhow!("Decrypting from {} failed: {}", peer_id, e))
+    }
+
+    /// Drops the cached key for a peer we no longer talk to.
+    pub fn forget(&mut self, peer_id: &PeerId) {
+        self.ciphers.remove(peer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_peers_derive_the_same_key() {
+        let alice = identity::Keypair::generate_ed25519();
+        let bob = identity::Keypair::generate_ed25519();
+        let eve = identity::Keypair::generate_ed25519();
+        let alice_id = alice.public().to_peer_id();
+        let bob_id = bob.public().to_peer_id();
+
+        let mut alice_secrets = PeerSecrets::new(&alice).unwrap();
+        let mut bob_secrets = PeerSecrets::new(&bob).unwrap();
+        let mut eve_secrets = PeerSecrets::new(&eve).unwrap();
+
+        let message = alice_secrets.encrypt(&bob_id, b"hello bob").unwrap();
+        assert_eq!(
+            bob_secrets.decrypt(&alice_id, &message).unwrap(),
+            b"hello bob"
+        );
+        assert!(eve_secrets.decrypt(&alice_id, &message).is_err());
+        // Reflected back at the sender, the direction no longer matches.
+        assert!(alice_secrets.decrypt(&bob_id, &message).is_err());
+    }
+}
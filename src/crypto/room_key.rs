@@ -0,0 +1,133 @@
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+const ROOM_KEY_INFO: &[u8] = b"bevy-p2p-demo room key v1";
+const ROOM_AAD_INFO: &[u8] = b"bevy-p2p-demo room aad v1";
+const TEAM_KEY_INFO: &[u8] = b"bevy-p2p-demo team key v1";
+const TEAM_AAD_INFO: &[u8] = b"bevy-p2p-demo team aad v1";
+const FINGERPRINT_INFO: &[u8] = b"bevy-p2p-demo room key fingerprint v1";
+
+/// Secret a host keeps for the whole session and derives every room's key from.
+///
+/// Hosting the same room code again yields the same [`RoomKey`], so peers coming back
+/// to a re-hosted room still read its gossip, while a different code never does.
+#[derive(Zeroize, ZeroizeOnDrop)]
+pub struct MasterSecret(pub(super) [u8; 32]);
+
+impl MasterSecret {
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    /// The key for `room_code`, from HKDF-SHA256 salted with the code.
+    pub fn room_key(&self, room_code: &str) -> RoomKey {
+        self.derive(room_code, &[ROOM_KEY_INFO], &[ROOM_AAD_INFO])
+    }
+
+    /// The key for `team` in `room_code`. Every change of members bumps `generation`,
+    /// so peers who left the team can't read it anymore.
+    pub fn team_key(&self, room_code: &str, team: &str, generation: u64) -> RoomKey {
+        let generation = generation.to_be_bytes();
+        // The name is followed by a zero byte, so no name can run into the generation.
+        let context: [&[u8]; 3] = [team.as_bytes(), &[0], &generation];
+        self.derive(
+            room_code,
+            &[&[TEAM_KEY_INFO][..], &context].concat(),
+            &[&[TEAM_AAD_INFO][..], &context].concat(),
+        )
+    }
+
+    /// HKDF-SHA256 salted with the room code, expanded with `key_info` and `aad_info`.
+    fn derive(&self, room_code: &str, key_info: &[&[u8]], aad_info: &[&[u8]]) -> RoomKey {
+        let hkdf = Hkdf::<Sha256>::new(Some(room_code.as_bytes()), &self.0);
+        let mut key = RoomKey {
+            key: [0; 32],
+            aad: [0; 16],
+        };
+        hkdf.expand_multi_info(key_info, &mut key.key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        hkdf.expand_multi_info(aad_info, &mut key.aad)
+            .expect("16 bytes is a valid HKDF-SHA256 output length");
+        key
+    }
+}
+
+/// The AES key and associated data protecting one room's gossip. Wiped from memory
+/// when dropped.
+#[derive(Clone, PartialEq, Eq, Zeroize, ZeroizeOnDrop)]
+pub struct RoomKey {
+    pub key: [u8; 32],
+    pub aad: [u8; 16],
+}
+
+impl RoomKey {
+    /// Length of [`to_bytes`](Self::to_bytes).
+    pub const LEN: usize = 48;
+
+    /// A key belonging to no room, for while we are not in one.
+    pub fn random() -> Self {
+        Self {
+            key: rand::random(),
+            aad: rand::random(),
+        }
+    }
+
+    /// The key as sent to peers, wiped once dropped.
+    pub fn to_bytes(&self) -> Zeroizing<[u8; Self::LEN]> {
+        let mut bytes = Zeroizing::new([0; Self::LEN]);
+        bytes[..32].copy_from_slice(&self.key);
+        bytes[32..].copy_from_slice(&self.aad);
+        bytes
+    }
+
+    /// Names the key without giving it away, so peers can confirm which one they use.
+    pub fn fingerprint(&self) -> [u8; 8] {
+        let digest = Sha256::new_with_prefix(FINGERPRINT_INFO)
+            .chain_update(self.to_bytes().as_slice())
+            .finalize();
+        digest[..8]
+            .try_into()
+            .expect("SHA-256 digests are longer than 8 bytes")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LEN {
+            return None;
+        }
+        let mut key = Self {
+            key: [0; 32],
+            aad: [0; 16],
+        };
+        key.key.copy_from_slice(&bytes[..32]);
+        key.aad.copy_from_slice(&bytes[32..]);
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_per_room_and_repeatable() {
+        let master = MasterSecret::generate();
+        let room = master.room_key("ABC-DEFG");
+        assert!(room == master.room_key("ABC-DEFG"));
+        let other = master.room_key("ABC-DEFH");
+        assert_ne!(room.key, other.key);
+        assert_ne!(room.aad, other.aad);
+        assert!(MasterSecret::generate().room_key("ABC-DEFG") != room);
+        assert!(RoomKey::from_bytes(room.to_bytes().as_slice()) == Some(room));
+    }
+
+    #[test]
+    fn team_keys_change_with_every_generation() {
+        let master = MasterSecret::generate();
+        let red = master.team_key("ABC-DEFG", "red", 0);
+        assert!(red == master.team_key("ABC-DEFG", "red", 0));
+        assert!(red != master.team_key("ABC-DEFG", "red", 1));
+        assert!(red != master.team_key("ABC-DEFG", "blue", 0));
+        assert!(red != master.room_key("ABC-DEFG"));
+    }
+}
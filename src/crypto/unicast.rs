@@ -0,0 +1,122 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, OsRng, Payload},
+    Aes256Gcm, Key, KeyInit,
+};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use hkdf::Hkdf;
+use libp2p::{identity, PeerId};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+const UNICAST_INFO: &[u8] = b"bevy-p2p-demo unicast v1";
+
+/// A payload only the peer it was sealed for can open.
+///
+/// The sender uses a fresh X25519 key per message, so the box carries no long term
+/// secret of its own and only the recipient's identity key can open it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedBox {
+    pub ephemeral: [u8; 32],
+    pub nonce: [u8; 12],
+    pub ciphertext: Vec<u8>,
+}
+
+/// The identity key embedded in `peer_id`.
+pub fn identity_public_key(peer_id: &PeerId) -> anyhow::Result<identity::PublicKey> {
+    let multihash = peer_id.as_ref();
+    // Only identity multihashes carry the key itself; ed25519 ids always do.
+    anyhow::ensure!(multihash.code() == 0, "{} does not embed its key", peer_id);
+    Ok(identity::PublicKey::try_decode_protobuf(
+        multihash.digest(),
+    )?)
+}
+
+/// The X25519 public key matching the ed25519 identity embedded in `peer_id`.
+pub fn x25519_public(peer_id: &PeerId) -> anyhow::Result<PublicKey> {
+    let public = identity_public_key(peer_id)?.try_into_ed25519()?;
+    let edwards = CompressedEdwardsY(public.to_bytes())
+        .decompress()
+        .ok_or_else(|| anyhow::anyhow!("{} has an invalid ed25519 key", peer_id))?;
+    Ok(PublicKey::from(edwards.to_montgomery().to_bytes()))
+}
+
+/// The X25519 secret matching our ed25519 identity, derived the same way as the
+/// ed25519 scalar so it pairs with [`x25519_public`].
+pub fn x25519_secret(keypair: &identity::Keypair) -> anyhow::Result<StaticSecret> {
+    let keypair = keypair.clone().try_into_ed25519()?;
+    let hash = Sha512::digest(keypair.secret().as_ref());
+    let mut bytes = Zeroizing::new([0u8; 32]);
+    bytes.copy_from_slice(&hash[..32]);
+    Ok(StaticSecret::from(*bytes))
+}
+
+fn box_key(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> Key<Aes256Gcm> {
+    let salt = Sha256::new()
+        .chain_update(ephemeral.as_bytes())
+        .chain_update(recipient.as_bytes())
+        .finalize();
+    let mut key = Key::<Aes256Gcm>::default();
+    Hkdf::<Sha256>::new(Some(salt.as_slice()), shared)
+        .expand(UNICAST_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+/// Encrypts `plaintext` so only `recipient` can read it.
+pub fn seal(recipient: &PeerId, plaintext: &[u8]) -> anyhow::Result<SealedBox> {
+    let recipient = x25519_public(recipient)?;
+    let secret = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral = PublicKey::from(&secret);
+    let shared = secret.diffie_hellman(&recipient);
+    let key = box_key(shared.as_bytes(), &ephemeral, &recipient);
+
+    let nonce = Aes256Gcm::generate_nonce(OsRng);
+    let ciphertext = Aes256Gcm::new(&key)
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: ephemeral.as_bytes(),
+            },
+        )
+        .map_err(|e| anyhow::anyhow!("Sealing failed: {}", e))?;
+    Ok(SealedBox {
+        ephemeral: ephemeral.to_bytes(),
+        nonce: nonce.into(),
+        ciphertext,
+    })
+}
+
+/// Decrypts a box sealed for the owner of `secret`.
+pub fn open(secret: &StaticSecret, sealed: &SealedBox) -> anyhow::Result<Vec<u8>> {
+    let ephemeral = PublicKey::from(sealed.ephemeral);
+    let shared = secret.diffie_hellman(&ephemeral);
+    let key = box_key(shared.as_bytes(), &ephemeral, &PublicKey::from(secret));
+    Aes256Gcm::new(&key)
+        .decrypt(
+            (&sealed.nonce).into(),
+            Payload {
+                msg: &sealed.ciphertext,
+                aad: &sealed.ephemeral,
+            },
+        )
+        .map_err(|e| anyhow::anyhow!("Opening sealed box failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_recipient_can_open() {
+        let recipient = identity::Keypair::generate_ed25519();
+        let other = identity::Keypair::generate_ed25519();
+        let sealed = seal(&recipient.public().to_peer_id(), b"your hand").unwrap();
+
+        let opened = open(&x25519_secret(&recipient).unwrap(), &sealed).unwrap();
+        assert_eq!(opened, b"your hand");
+        assert!(open(&x25519_secret(&other).unwrap(), &sealed).is_err());
+    }
+}
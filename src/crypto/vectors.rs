@@ -0,0 +1,116 @@
+//! Fixed test vectors for the room message envelope and room key derivation.
+//!
+//! These pin the wire format: peers on different versions of the crate have to agree
+//! on every byte. A failure here after a refactor means old and new peers can no
+//! longer read each other's gossip. Don't update the vectors to make a test pass
+//! unless the format is meant to change, and then bump the `v1` in the HKDF infos.
+//!
+//! The vectors were computed independently of this crate, with Python's
+//! `cryptography` package.
+
+use super::*;
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).expect("Vectors are valid hex"))
+        .collect()
+}
+
+/// Key `00..1f`, AAD `20..2f`.
+fn vector_key() -> RoomKey {
+    RoomKey {
+        key: std::array::from_fn(|i| i as u8),
+        aad: std::array::from_fn(|i| 0x20 + i as u8),
+    }
+}
+
+const NONCE: [u8; 12] = [
+    0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xab,
+];
+const PLAINTEXT: &[u8] = b"bevy-p2p-demo envelope v1";
+/// Ciphertext, tag, then nonce.
+const ENVELOPE: &str = "847d0a5468bb30cf4f01e2be685aa5b006c9357fe2d2621aad42537b17e60e9e\
+                        1d402ad76b10975d44a0a1a2a3a4a5a6a7a8a9aaab";
+
+fn raw_message(data: Vec<u8>) -> libp2p::gossipsub::RawMessage {
+    libp2p::gossipsub::RawMessage {
+        data,
+        source: None,
+        sequence_number: Some(0),
+        topic: libp2p::gossipsub::TopicHash::from_raw("test"),
+        key: None,
+        signature: None,
+        validated: true,
+    }
+}
+
+#[test]
+fn envelope_matches_vector() {
+    let entry = KeyEntry::new(vector_key(), 0, RETIRED_KEY_GRACE);
+    let sealed = seal_envelope(&entry, Nonce::from_slice(&NONCE), PLAINTEXT).unwrap();
+    assert_eq!(sealed, hex(ENVELOPE));
+}
+
+#[test]
+fn vector_opens_through_gossipsub_transform() {
+    let (encryptor, mut keys) = DataEncryptor::new();
+    keys.replace(vector_key());
+    let message = encryptor
+        .inbound_transform(raw_message(hex(ENVELOPE)))
+        .unwrap();
+    assert_eq!(message.data, PLAINTEXT);
+}
+
+#[test]
+fn vector_opens_after_key_rotation() {
+    // Messages sealed before a rotation still arrive for a while afterwards.
+    let (encryptor, mut keys) = DataEncryptor::new();
+    keys.replace(vector_key());
+    keys.add_key(RoomKey::random());
+    let message = encryptor
+        .inbound_transform(raw_message(hex(ENVELOPE)))
+        .unwrap();
+    assert_eq!(message.data, PLAINTEXT);
+}
+
+#[test]
+fn aad_is_bound() {
+    let mut other_aad = vector_key();
+    other_aad.aad[0] ^= 1;
+    assert_eq!(
+        open_envelope(
+            &[KeyEntry::new(other_aad, 0, RETIRED_KEY_GRACE)],
+            &hex(ENVELOPE)
+        ),
+        None
+    );
+}
+
+#[test]
+fn tampering_and_truncation_are_rejected() {
+    let entries = [KeyEntry::new(vector_key(), 0, RETIRED_KEY_GRACE)];
+    let mut tampered = hex(ENVELOPE);
+    tampered[0] ^= 1;
+    assert_eq!(open_envelope(&entries, &tampered), None);
+    assert_eq!(open_envelope(&entries, &hex(ENVELOPE)[..8]), None);
+    assert_eq!(open_envelope(&entries, &[]), None);
+}
+
+#[test]
+fn room_key_derivation_matches_vector() {
+    let master = MasterSecret([0x42; 32]);
+    let room = master.room_key("ABC-DEFG");
+    assert_eq!(
+        room.key.to_vec(),
+        hex("c74db10f244dc1ecaced3f5c9b24eab9c6e7f290174fc5409756399c1ed5bc2c")
+    );
+    assert_eq!(room.aad.to_vec(), hex("59d9715f64bdb0771a604ad3779e6dbc"));
+}
+
+#[test]
+fn room_key_bytes_are_key_then_aad() {
+    let bytes = vector_key().to_bytes();
+    assert_eq!(bytes[..32], vector_key().key);
+    assert_eq!(bytes[32..], vector_key().aad);
+}
@@ -0,0 +1,164 @@
+//! Host-only mode: this machine hosts a room and referees the games in it, without a
+//! player of its own. Meant for a community server running the headless build.
+//!
+//! The room stays open for good. Joins are let in without asking, and a game starts
+//! by itself once enough players are in and all of them are ready. This peer is the
+//! room host, so its seeds and rounds are the ones everyone plays by.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::game_mode::GameModes;
+use crate::lobby::{JoinApproval, Readiness, StartCountdown};
+use crate::network::{
+    AsyncNetworkTasks, CommandId, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent,
+};
+use crate::rng::RequestStartGame;
+use crate::room_code::generate_room_code;
+
+/// Added by [`GamePlugin`](crate::GamePlugin) instead of the menu and the local player
+/// when a [`DedicatedHost`] resource is inserted before it.
+pub struct DedicatedHostPlugin;
+
+impl Plugin for DedicatedHostPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DedicatedHost>()
+            .insert_resource(JoinApproval::AutoAccept)
+            .add_systems(
+                Update,
+                (host_room::<()>, start_when_ready)
+                    .chain()
+                    .run_if(resource_exists::<AsyncNetworkTasks<()>>()),
+            );
+    }
+}
+
+/// How the dedicated host runs its room.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct DedicatedHost {
+    /// The code to host under, so players can keep it bookmarked. A random one when
+    /// `None`, or when the chosen one turns out to be taken.
+    pub room_code: Option<String>,
+    /// Players needed, all ready, before a game starts.
+    pub min_players: usize,
+    pub countdown: Duration,
+}
+
+impl Default for DedicatedHost {
+    fn default() -> Self {
+        Self {
+            room_code: None,
+            min_players: 2,
+            countdown: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Wait before hosting again after the first breakdown; doubles with every further one.
+const HOST_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_HOST_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+fn retry_backoff(failures: u32) -> Duration {
+    HOST_RETRY_BACKOFF
+        .saturating_mul(1 << failures.saturating_sub(1).min(16))
+        .min(MAX_HOST_RETRY_BACKOFF)
+}
+
+/// Breakdowns since hosting last started, and the wait before the next attempt.
+#[derive(Default)]
+struct HostRetry {
+    failures: u32,
+    timer: Option<Timer>,
+}
+
+/// Hosts the room at startup, again under a new code if ours is taken, and again
+/// after a backoff if hosting breaks down.
+fn host_room<ToGame>(
+    time: Res<Time>,
+    config: Res<DedicatedHost>,
+    modes: Option<Res<GameModes>>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+    mut command: Local<Option<CommandId>>,
+    mut retry: Local<HostRetry>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    if command.is_none() {
+        if let Some(modes) = modes {
+            tasks.send(GameEvent::Admin(modes.describe_room()));
+        }
+        let room_code = config.room_code.clone().unwrap_or_else(generate_room_code);
+        *command = Some(tasks.command(GameAdminEvent::Host { room_code }));
+    }
+    if let Some(timer) = retry.timer.as_mut() {
+        if timer.tick(time.delta()).just_finished() {
+            retry.timer = None;
+            let room_code = config.room_code.clone().unwrap_or_else(generate_room_code);
+            *command = Some(tasks.command(GameAdminEvent::Host { room_code }));
+        }
+    }
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        match event {
+            NetworkAdminEvent::HostingStarted { room_code } => {
+                log::info!("Hosting room {}", room_code);
+                retry.failures = 0;
+            }
+            NetworkAdminEvent::RoomCodeTaken { room_code } => {
+                let replacement = generate_room_code();
+                log::warn!(
+                    "Room code {} is taken, hosting {} instead",
+                    room_code,
+                    replacement
+                );
+                *command = Some(tasks.command(GameAdminEvent::Host {
+                    room_code: replacement,
+                }));
+            }
+            NetworkAdminEvent::CommandFailed { id, reason } if Some(*id) == *command => {
+                log::error!("Could not host: {}", reason)
+            }
+            NetworkAdminEvent::HostingFailed { reason } => {
+                retry.failures += 1;
+                let wait = retry_backoff(retry.failures);
+                log::error!(
+                    "Hosting broke down, hosting again in {:?}: {}",
+                    wait,
+                    reason
+                );
+                retry.timer = Some(Timer::new(wait, TimerMode::Once));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Starts a game once [`DedicatedHost::min_players`] are in and ready. The next one
+/// can start after everyone has left.
+fn start_when_ready(
+    config: Res<DedicatedHost>,
+    readiness: Res<Readiness>,
+    countdown: Res<StartCountdown>,
+    mut start_requests: EventWriter<RequestStartGame>,
+    mut started: Local<bool>,
+) {
+    if readiness.players() == 0 {
+        *started = false;
+    }
+    if *started
+        || countdown.remaining().is_some()
+        || readiness.players() < config.min_players
+        || !readiness.all_ready()
+    {
+        return;
+    }
+    log::info!("All {} players are ready, starting", readiness.players());
+    start_requests.send(RequestStartGame {
+        countdown: config.countdown,
+    });
+    *started = true;
+}
@@ -0,0 +1,282 @@
+//! Checks that a lockstep simulation steps the same on every platform.
+//!
+//! Record the inputs of a session as an [`InputScript`], [`run`] it through the
+//! [`Simulation`] on each target to get a [`HashTrace`], and compare the traces with
+//! [`HashTrace::first_divergence`]. Simulations hash their state in named parts, so
+//! the report says which part went wrong first, e.g. `"bodies"` but not `"scores"`,
+//! which narrows down the system at fault. `cargo run --example determinism` does this
+//! for a demo simulation, as CI does on every OS.
+
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Something stepped in lockstep: the same inputs must always lead to the same state.
+pub trait Simulation {
+    /// All players' input for one tick.
+    type Input;
+
+    fn step(&mut self, tick: u64, input: &Self::Input);
+
+    /// Feed the state into `hashes`, split into parts along the systems that own them.
+    fn hash_state(&self, hashes: &mut StateHashes);
+}
+
+/// The inputs of a session, one entry per tick.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputScript<I> {
+    pub ticks: Vec<I>,
+}
+
+/// Steps `simulation` through `script`, hashing its state after every tick. `target`
+/// names where this ran, for the report.
+pub fn run<S: Simulation>(
+    mut simulation: S,
+    script: &InputScript<S::Input>,
+    target: impl Into<String>,
+) -> HashTrace {
+    let ticks = script
+        .ticks
+        .iter()
+        .zip(0..)
+        .map(|(input, tick)| {
+            simulation.step(tick, input);
+            let mut hashes = StateHashes::default();
+            simulation.hash_state(&mut hashes);
+            hashes.parts
+        })
+        .collect();
+    HashTrace {
+        target: target.into(),
+        ticks,
+    }
+}
+
+/// The state hashes of one tick, by part.
+#[derive(Debug, Default)]
+pub struct StateHashes {
+    parts: Vec<(String, u64)>,
+}
+
+impl StateHashes {
+    pub fn add(&mut self, part: &str, state: &impl Hash) {
+        let mut hasher = StableHasher::default();
+        state.hash(&mut hasher);
+        self.parts.push((part.to_string(), hasher.finish()));
+    }
+}
+
+/// FNV-1a, with every integer written little endian. `DefaultHasher` may change
+/// between Rust versions and the default `Hasher` methods hash integers in native byte
+/// order, either of which would make equal states hash differently across targets.
+#[derive(Debug)]
+struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    /// As a `u64`, so 32 and 64 bit targets agree.
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64);
+    }
+}
+
+/// The state hashes after every tick of one [`run`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashTrace {
+    pub target: String,
+    /// For every tick, each part's hash.
+    pub ticks: Vec<Vec<(String, u64)>>,
+}
+
+impl HashTrace {
+    /// The first tick at which `other` disagrees with this trace, if any. Traces of
+    /// different lengths diverge where the shorter one ends.
+    pub fn first_divergence<'a>(&'a self, other: &'a HashTrace) -> Option<Divergence<'a>> {
+        let tick = (0..self.ticks.len().max(other.ticks.len()))
+            .find(|tick| self.ticks.get(*tick) != other.ticks.get(*tick))?;
+        Some(Divergence {
+            tick: tick as u64,
+            left: self,
+            right: other,
+        })
+    }
+}
+
+/// Where two [`HashTrace`]s first disagree. Its `Display` is the report.
+#[derive(Debug, Clone, Copy)]
+pub struct Divergence<'a> {
+    pub tick: u64,
+    left: &'a HashTrace,
+    right: &'a HashTrace,
+}
+
+impl Divergence<'_> {
+    /// The parts whose hashes differ at [`tick`](Self::tick), in the order the
+    /// simulation hashed them.
+    pub fn parts(&self) -> Vec<&str> {
+        let tick = self.tick as usize;
+        let (Some(left), Some(right)) = (self.left.ticks.get(tick), self.right.ticks.get(tick))
+        else {
+            return Vec::new();
+        };
+        let hash = |ticks: &[(String, u64)], part: &str| {
+            ticks
+                .iter()
+                .find(|(name, _)| name == part)
+                .map(|(_, hash)| *hash)
+        };
+        let mut parts: Vec<&str> = Vec::new();
+        for (part, _) in left.iter().chain(right) {
+            let part = part.as_str();
+            if hash(left, part) != hash(right, part) && !parts.contains(&part) {
+                parts.push(part);
+            }
+        }
+        parts
+    }
+}
+
+impl fmt::Display for Divergence<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (left, right) = (&self.left.target, &self.right.target);
+        write!(f, "{} and {} diverge at tick {}", left, right, self.tick)?;
+        let tick = self.tick as usize;
+        for (name, trace) in [(left, self.left), (right, self.right)] {
+            if trace.ticks.len() <= tick {
+                return write!(f, ": {} stops after {} ticks", name, trace.ticks.len());
+            }
+        }
+        write!(f, ", in {}", self.parts().join(", "))?;
+        for part in self.parts() {
+            let hash = |trace: &HashTrace| {
+                trace.ticks[tick]
+                    .iter()
+                    .find(|(name, _)| name == part)
+                    .map_or("missing".to_string(), |(_, hash)| format!("{:016x}", hash))
+            };
+            write!(
+                f,
+                "\n  {}: {} {}, {} {}",
+                part,
+                left,
+                hash(self.left),
+                right,
+                hash(self.right)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts up by its inputs, except that `faulty` goes wrong at tick 3.
+    struct Counter {
+        value: i64,
+        steps: u64,
+        faulty: bool,
+    }
+
+    impl Simulation for Counter {
+        type Input = i64;
+
+        fn step(&mut self, tick: u64, input: &i64) {
+            self.value += input;
+            if self.faulty && tick == 3 {
+                self.value += 1;
+            }
+            self.steps += 1;
+        }
+
+        fn hash_state(&self, hashes: &mut StateHashes) {
+            hashes.add("value", &self.value);
+            hashes.add("steps", &self.steps);
+        }
+    }
+
+    #[test]
+    fn reports_the_first_diverging_tick_and_part() {
+        let script = InputScript {
+            ticks: vec![1, 2, 3, 4, 5],
+        };
+        let counter = |faulty| Counter {
+            value: 0,
+            steps: 0,
+            faulty,
+        };
+        let good = run(counter(false), &script, "good");
+        assert_eq!(
+            good.first_divergence(&run(counter(false), &script, "again"))
+                .map(|d| d.tick),
+            None
+        );
+
+        let bad = run(counter(true), &script, "bad");
+        let divergence = good.first_divergence(&bad).unwrap();
+        assert_eq!(divergence.tick, 3);
+        assert_eq!(divergence.parts(), ["value"]);
+        assert!(divergence
+            .to_string()
+            .starts_with("good and bad diverge at tick 3, in value"));
+
+        let short = run(counter(false), &InputScript { ticks: vec![1, 2] }, "short");
+        assert_eq!(good.first_divergence(&short).unwrap().tick, 2);
+    }
+}
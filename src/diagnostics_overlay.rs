@@ -0,0 +1,103 @@
+//! An egui window summarizing the state of the network: our external addresses, the
+//! room key and the link to every peer.
+
+use std::time::{Duration, SystemTime};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::{bevy_egui::EguiContexts, egui};
+
+use crate::crypto::CryptoStatus;
+use crate::network::{ConnectionPath, ExternalAddresses};
+use crate::peer::{Latency, PeerIdComp, PeerStats, SendRate};
+
+pub struct DiagnosticsOverlayPlugin;
+
+impl Plugin for DiagnosticsOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, show_diagnostics_overlay);
+    }
+}
+
+fn show_diagnostics_overlay(
+    mut contexts: EguiContexts,
+    addresses: Res<ExternalAddresses>,
+    crypto: Option<Res<CryptoStatus>>,
+    peers: Query<(
+        &PeerIdComp,
+        Option<&ConnectionPath>,
+        Option<&Latency>,
+        &PeerStats,
+        &SendRate,
+    )>,
+) {
+    egui::Window::new("Network diagnostics")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.heading("External addresses");
+            if addresses.is_empty() {
+                ui.label("No peer has reported our address yet");
+            }
+            egui::Grid::new("external_addresses")
+                .striped(true)
+                .show(ui, |ui| {
+                    let mut addresses: Vec<_> = addresses.iter().collect();
+                    addresses.sort_by(|a, b| b.1.confirmations.cmp(&a.1.confirmations));
+                    for (address, external) in addresses {
+                        ui.label(address.to_string());
+                        ui.label(format!("{} peers", external.confirmations));
+                        ui.label(if external.confirmed {
+                            "advertised"
+                        } else {
+                            "candidate"
+                        });
+                        ui.end_row();
+                    }
+                });
+
+            if let Some(crypto) = crypto {
+                ui.separator();
+                ui.heading("Room key");
+                let age = SystemTime::now()
+                    .duration_since(crypto.rotated_at)
+                    .unwrap_or_default();
+                let fingerprint: String = crypto
+                    .fingerprint
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+                ui.label(format!(
+                    "Generation {} ({}), {} keys accepted",
+                    crypto.generation, fingerprint, crypto.keys
+                ));
+                ui.label(format!(
+                    "{} messages in {} s",
+                    crypto.encrypted_with_current,
+                    age.as_secs()
+                ));
+            }
+
+            ui.separator();
+            ui.heading("Peers");
+            egui::Grid::new("peers").striped(true).show(ui, |ui| {
+                for label in ["Peer", "Path", "RTT", "p50", "p95", "p99", "Sending"] {
+                    ui.strong(label);
+                }
+                ui.end_row();
+                for (peer_id, path, latency, stats, send_rate) in &peers {
+                    let peer = peer_id.0.to_base58();
+                    ui.label(&peer[peer.len().saturating_sub(6)..]);
+                    ui.label(path.map_or("-".to_string(), |path| format!("{:?}", path)));
+                    ui.label(latency.map_or("-".to_string(), |latency| millis(latency.0)));
+                    for rtt in [stats.rtt.p50(), stats.rtt.p95(), stats.rtt.p99()] {
+                        ui.label(rtt.map_or("-".to_string(), millis));
+                    }
+                    ui.label(format!("{:.1}/s", send_rate.rate));
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{} ms", duration.as_millis())
+}
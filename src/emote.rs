@@ -0,0 +1,151 @@
+//! Quick canned [`Emote`]s, a lighter way to talk than [`chat`](crate::chat).
+//!
+//! Both ends hold emotes to [`EmoteLimits`]: ours are dropped before they go out, and a
+//! peer that sends more than that anyway only gets the first few through. Emotes from
+//! muted peers are dropped like their chat.
+
+use std::{collections::HashMap, hash::Hash, time::Duration};
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::chat::Mutes;
+use crate::network::{
+    AsyncNetworkTasks, Emote, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent,
+};
+
+pub struct EmotePlugin;
+
+impl Plugin for EmotePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EmoteLimits>()
+            .add_event::<SendEmote>()
+            .add_event::<EmoteReceived>()
+            .add_systems(
+                Update,
+                (receive_emotes::<()>, send_emotes)
+                    .run_if(resource_exists::<AsyncNetworkTasks<()>>()),
+            );
+    }
+}
+
+/// How many emotes anyone gets to send per [`window`](Self::window).
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct EmoteLimits {
+    pub per_window: u32,
+    pub window: Duration,
+}
+
+impl Default for EmoteLimits {
+    fn default() -> Self {
+        Self {
+            per_window: 3,
+            window: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Show the room an emote, unless we already sent our share for now.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendEmote(pub Emote);
+
+/// `from` sent `emote`, within its limits and unmuted.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmoteReceived {
+    pub from: PeerId,
+    pub emote: Emote,
+}
+
+/// Counts emotes per sender over fixed windows.
+#[derive(Debug)]
+struct EmoteLimiter<K> {
+    /// When each sender's window started, in seconds since startup, and how many
+    /// emotes it sent in it.
+    windows: HashMap<K, (f64, u32)>,
+}
+
+// Derived, this would want `K: Default`, which `PeerId` isn't.
+impl<K> Default for EmoteLimiter<K> {
+    fn default() -> Self {
+        Self {
+            windows: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq> EmoteLimiter<K> {
+    /// Records an emote from `sender` at `now` and returns whether it is within `limits`.
+    fn allow(&mut self, sender: K, now: f64, limits: &EmoteLimits) -> bool {
+        let window = limits.window.as_secs_f64();
+        // Forget senders whose window is over so the map can't grow without bound.
+        self.windows
+            .retain(|_, (started, _)| now - *started < window);
+        let (_, sent) = self.windows.entry(sender).or_insert((now, 0));
+        *sent += 1;
+        *sent <= limits.per_window
+    }
+}
+
+fn receive_emotes<ToGame>(
+    time: Res<Time>,
+    limits: Res<EmoteLimits>,
+    mutes: Mutes,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+    mut emotes: EventWriter<EmoteReceived>,
+    mut limiter: Local<EmoteLimiter<PeerId>>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(NetworkAdminEvent::EmoteReceived { from, emote }) = event else {
+            continue;
+        };
+        if mutes.is_muted(from) {
+            continue;
+        }
+        if !limiter.allow(*from, time.elapsed_seconds_f64(), &limits) {
+            log::debug!("Dropping emote from {}: over the limit", from);
+            continue;
+        }
+        emotes.send(EmoteReceived {
+            from: *from,
+            emote: *emote,
+        });
+    }
+}
+
+fn send_emotes(
+    time: Res<Time>,
+    limits: Res<EmoteLimits>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    mut send: EventReader<SendEmote>,
+    mut limiter: Local<EmoteLimiter<()>>,
+) {
+    for SendEmote(emote) in send.iter() {
+        if !limiter.allow((), time.elapsed_seconds_f64(), &limits) {
+            log::info!("Not sending {:?}: too many emotes, wait a moment", emote);
+            continue;
+        }
+        tasks.send(GameEvent::Admin(GameAdminEvent::Emote(*emote)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emotes_are_limited_per_sender_and_window() {
+        let limits = EmoteLimits {
+            per_window: 2,
+            window: Duration::from_secs(5),
+        };
+        let mut limiter = EmoteLimiter::default();
+        let peer = PeerId::random();
+        assert!(limiter.allow(peer, 0.0, &limits));
+        assert!(limiter.allow(peer, 1.0, &limits));
+        assert!(!limiter.allow(peer, 2.0, &limits));
+        assert!(limiter.allow(PeerId::random(), 2.0, &limits));
+        assert!(limiter.allow(peer, 5.0, &limits));
+    }
+}
@@ -0,0 +1,110 @@
+//! Shows [`EmoteReceived`] as a bubble above the sender's [`RemotePlayer`] for a couple
+//! of seconds, and sends emotes from the number keys while playing.
+
+use bevy::prelude::*;
+
+use crate::emote::{EmoteReceived, SendEmote};
+use crate::loading::FontAssets;
+use crate::network::Emote;
+use crate::peer::RemotePlayer;
+use crate::GameState;
+
+/// How long a bubble stays up; it fades out over the last third.
+const BUBBLE_SECONDS: f32 = 2.0;
+/// Above the link indicator.
+const BUBBLE_HEIGHT: f32 = 72.0;
+const BUBBLE_COLOR: Color = Color::rgb(1.0, 0.95, 0.6);
+
+pub struct EmoteBubblePlugin;
+
+impl Plugin for EmoteBubblePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                emote_keys.run_if(in_state(GameState::Playing)),
+                show_bubbles.run_if(resource_exists::<FontAssets>()),
+                fade_bubbles,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Seconds a bubble has been up.
+#[derive(Component, Default)]
+struct EmoteBubble(f32);
+
+fn emote_key(emote: Emote) -> KeyCode {
+    match emote {
+        Emote::ThumbsUp => KeyCode::Key1,
+        Emote::GoodGame => KeyCode::Key2,
+        Emote::Help => KeyCode::Key3,
+    }
+}
+
+fn emote_keys(keys: Res<Input<KeyCode>>, mut send: EventWriter<SendEmote>) {
+    for emote in Emote::ALL {
+        if keys.just_pressed(emote_key(emote)) {
+            send.send(SendEmote(emote));
+        }
+    }
+}
+
+fn show_bubbles(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    mut emotes: EventReader<EmoteReceived>,
+    players: Query<(Entity, &RemotePlayer, Option<&Children>)>,
+    bubbles: Query<(), With<EmoteBubble>>,
+) {
+    for emote in emotes.iter() {
+        let Some((player, _, children)) =
+            players.iter().find(|(_, remote, _)| remote.0 == emote.from)
+        else {
+            continue;
+        };
+        // A new emote replaces the one still showing.
+        for child in children.into_iter().flatten() {
+            if bubbles.contains(*child) {
+                commands.entity(*child).despawn_recursive();
+            }
+        }
+        commands.entity(player).with_children(|parent| {
+            parent.spawn((
+                Text2dBundle {
+                    text: Text::from_section(
+                        emote.emote.text(),
+                        TextStyle {
+                            font: font_assets.fira_sans.clone(),
+                            font_size: 24.0,
+                            color: BUBBLE_COLOR,
+                        },
+                    ),
+                    transform: Transform::from_xyz(0.0, BUBBLE_HEIGHT, 10.0),
+                    ..default()
+                },
+                EmoteBubble::default(),
+            ));
+        });
+    }
+}
+
+fn fade_bubbles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut bubbles: Query<(Entity, &mut EmoteBubble, &mut Text)>,
+) {
+    for (entity, mut bubble, mut text) in bubbles.iter_mut() {
+        bubble.0 += time.delta_seconds();
+        if bubble.0 >= BUBBLE_SECONDS {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let fade_from = BUBBLE_SECONDS * 2.0 / 3.0;
+        let alpha = 1.0 - ((bubble.0 - fade_from) / (BUBBLE_SECONDS - fade_from)).max(0.0);
+        for section in text.sections.iter_mut() {
+            section.style.color.set_a(alpha);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Files other players offer us in the lobby, waiting for our player to accept or
+//! decline them. Sending one is a [`GameAdminEvent::SendFile`]; accepted files end up
+//! in [`FileDropConfig::directory`](crate::network::FileDropConfig::directory).
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::network::{
+    AsyncNetworkTasks, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent,
+};
+
+pub struct FileDropPlugin;
+
+impl Plugin for FileDropPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FileOffers>()
+            .add_event::<AnswerFileOffer>()
+            .add_systems(
+                Update,
+                (collect_file_offers::<()>, answer_file_offers).chain(),
+            );
+    }
+}
+
+/// A file a peer wants to send us.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOffer {
+    pub peer_id: PeerId,
+    pub transfer: u64,
+    pub name: String,
+    pub size: u64,
+}
+
+/// File offers waiting for an answer, oldest first.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct FileOffers(Vec<FileOffer>);
+
+impl FileOffers {
+    pub fn iter(&self) -> impl Iterator<Item = &FileOffer> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Accept or decline one of the [`FileOffers`].
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnswerFileOffer {
+    pub peer_id: PeerId,
+    pub transfer: u64,
+    pub accept: bool,
+}
+
+fn collect_file_offers<ToGame>(
+    mut offers: ResMut<FileOffers>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        match event {
+            NetworkEvent::Admin(NetworkAdminEvent::FileOffered {
+                peer_id,
+                transfer,
+                name,
+                size,
+            }) => offers.0.push(FileOffer {
+                peer_id: *peer_id,
+                transfer: *transfer,
+                name: name.clone(),
+                size: *size,
+            }),
+            NetworkEvent::Admin(NetworkAdminEvent::Disconnected(peer_id)) => {
+                offers.0.retain(|offer| offer.peer_id != *peer_id);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn answer_file_offers(
+    mut offers: ResMut<FileOffers>,
+    mut answers: EventReader<AnswerFileOffer>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+) {
+    for answer in answers.iter() {
+        offers
+            .0
+            .retain(|offer| (offer.peer_id, offer.transfer) != (answer.peer_id, answer.transfer));
+        tasks.send(GameEvent::Admin(GameAdminEvent::AnswerFileOffer {
+            peer_id: answer.peer_id,
+            transfer: answer.transfer,
+            accept: answer.accept,
+        }));
+    }
+}
@@ -0,0 +1,349 @@
+//! Fixed-point math for lockstep simulations.
+//!
+//! Floats round differently across CPUs, compilers and optimization levels, so peers
+//! stepping the same inputs with `f32` drift apart and desync. [`Fixed`] only does
+//! integer arithmetic, which every platform agrees on bit for bit.
+//!
+//! Simulate with a [`FixedTransform`] instead of writing the `Transform`; the
+//! `Transform` follows it for rendering, and may be smoothed or otherwise touched up
+//! without affecting the simulation. Only convert floats in at the edges, e.g. for
+//! tuning values, and never feed a float computed from simulation state back in.
+
+use std::{
+    fmt,
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign},
+};
+
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use serde::{Deserialize, Serialize};
+
+pub struct FixedMathPlugin;
+
+impl Plugin for FixedMathPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            sync_transforms.before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// Bits after the binary point.
+const FRACTION_BITS: u32 = 16;
+const ONE: i64 = 1 << FRACTION_BITS;
+
+/// A number with 16 fractional bits, i.e. steps of about 0.000015, and a range far
+/// beyond any game world.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub struct Fixed(i64);
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(ONE);
+
+    pub const fn from_int(value: i32) -> Self {
+        Self((value as i64) << FRACTION_BITS)
+    }
+
+    /// `numerator / denominator`, e.g. `Fixed::ratio(1, 60)` for a tick length.
+    pub fn ratio(numerator: i32, denominator: i32) -> Self {
+        Self::from_int(numerator) / Self::from_int(denominator)
+    }
+
+    /// Rounds `value` to the nearest step. The same float always gives the same
+    /// `Fixed`, so this is safe for constants and inputs.
+    pub fn from_f32(value: f32) -> Self {
+        Self((value as f64 * ONE as f64).round() as i64)
+    }
+
+    /// For rendering and display only; see the [module docs](self).
+    pub fn to_f32(self) -> f32 {
+        (self.0 as f64 / ONE as f64) as f32
+    }
+
+    pub const fn from_bits(bits: i64) -> Self {
+        Self(bits)
+    }
+
+    pub const fn to_bits(self) -> i64 {
+        self.0
+    }
+
+    pub fn abs(self) -> Self {
+        Self(self.0.abs())
+    }
+
+    /// Rounds towards negative infinity.
+    pub fn floor(self) -> i64 {
+        self.0 >> FRACTION_BITS
+    }
+
+    /// Zero for negative numbers.
+    pub fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Self::ZERO;
+        }
+        // sqrt(x / 2^16) * 2^16 == sqrt(x * 2^16)
+        Self(isqrt((self.0 as u128) << FRACTION_BITS) as i64)
+    }
+}
+
+/// The largest integer whose square is at most `value`, by Newton's method.
+fn isqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    let mut x = 1u128 << ((128 - value.leading_zeros()) / 2 + 1);
+    loop {
+        let next = (x + value / x) / 2;
+        if next >= x {
+            return x;
+        }
+        x = next;
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_f32())
+    }
+}
+
+impl Add for Fixed {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self(((self.0 as i128 * rhs.0 as i128) >> FRACTION_BITS) as i64)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Self;
+
+    /// Panics on division by zero, like integers.
+    fn div(self, rhs: Self) -> Self {
+        Self((((self.0 as i128) << FRACTION_BITS) / rhs.0 as i128) as i64)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+/// A 3D vector of [`Fixed`]. 2D games leave `z` alone, or use it for draw order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct FixedVec3 {
+    pub x: Fixed,
+    pub y: Fixed,
+    pub z: Fixed,
+}
+
+impl FixedVec3 {
+    pub const ZERO: FixedVec3 = FixedVec3::new(Fixed::ZERO, Fixed::ZERO, Fixed::ZERO);
+
+    pub const fn new(x: Fixed, y: Fixed, z: Fixed) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Rounds every component; see [`Fixed::from_f32`].
+    pub fn from_vec3(value: Vec3) -> Self {
+        Self::new(
+            Fixed::from_f32(value.x),
+            Fixed::from_f32(value.y),
+            Fixed::from_f32(value.z),
+        )
+    }
+
+    pub fn to_vec3(self) -> Vec3 {
+        Vec3::new(self.x.to_f32(), self.y.to_f32(), self.z.to_f32())
+    }
+
+    pub fn dot(self, rhs: Self) -> Fixed {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    pub fn length(self) -> Fixed {
+        self.dot(self).sqrt()
+    }
+
+    /// The vector scaled to length one, or zero if it has no length.
+    pub fn normalize_or_zero(self) -> Self {
+        let length = self.length();
+        if length == Fixed::ZERO {
+            Self::ZERO
+        } else {
+            self / length
+        }
+    }
+}
+
+impl Add for FixedVec3 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl Sub for FixedVec3 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl Mul<Fixed> for FixedVec3 {
+    type Output = Self;
+
+    fn mul(self, rhs: Fixed) -> Self {
+        Self::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl Div<Fixed> for FixedVec3 {
+    type Output = Self;
+
+    fn div(self, rhs: Fixed) -> Self {
+        Self::new(self.x / rhs, self.y / rhs, self.z / rhs)
+    }
+}
+
+impl Neg for FixedVec3 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl AddAssign for FixedVec3 {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for FixedVec3 {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+/// The simulated position and scale of an entity. Its `Transform` is overwritten from
+/// this whenever it changes. Rotation isn't covered: it needs trigonometry, which
+/// differs across platforms too, so keep angles as [`Fixed`] in the game's own state
+/// and set the `Transform`'s rotation from them for rendering.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FixedTransform {
+    pub translation: FixedVec3,
+    pub scale: FixedVec3,
+}
+
+impl Default for FixedTransform {
+    fn default() -> Self {
+        Self {
+            translation: FixedVec3::ZERO,
+            scale: FixedVec3::new(Fixed::ONE, Fixed::ONE, Fixed::ONE),
+        }
+    }
+}
+
+impl FixedTransform {
+    pub fn from_translation(translation: FixedVec3) -> Self {
+        Self {
+            translation,
+            ..default()
+        }
+    }
+
+    /// Rounds `transform` onto the fixed-point grid, dropping its rotation. For
+    /// spawning; every peer has to start from the same values.
+    pub fn from_transform(transform: &Transform) -> Self {
+        Self {
+            translation: FixedVec3::from_vec3(transform.translation),
+            scale: FixedVec3::from_vec3(transform.scale),
+        }
+    }
+
+    /// Writes position and scale into `transform`, keeping its rotation.
+    pub fn apply_to(&self, transform: &mut Transform) {
+        transform.translation = self.translation.to_vec3();
+        transform.scale = self.scale.to_vec3();
+    }
+}
+
+fn sync_transforms(
+    mut entities: Query<(&FixedTransform, &mut Transform), Changed<FixedTransform>>,
+) {
+    for (fixed, mut transform) in entities.iter_mut() {
+        fixed.apply_to(&mut transform);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic_is_exact_on_the_grid() {
+        let half = Fixed::ratio(1, 2);
+        assert_eq!(half + half, Fixed::ONE);
+        assert_eq!(Fixed::from_int(3) * half, Fixed::from_f32(1.5));
+        assert_eq!(
+            Fixed::from_int(-3) / Fixed::from_int(2),
+            Fixed::from_f32(-1.5)
+        );
+        assert_eq!(Fixed::from_int(9).sqrt(), Fixed::from_int(3));
+        assert_eq!(Fixed::from_f32(-1.25).floor(), -2);
+
+        let v = FixedVec3::new(Fixed::from_int(3), Fixed::from_int(4), Fixed::ZERO);
+        assert_eq!(v.length(), Fixed::from_int(5));
+        // 0.6 isn't on the grid; division truncates, rounding may go either way.
+        let x = v.normalize_or_zero().x;
+        assert!((x - Fixed::from_f32(0.6)).abs() <= Fixed::from_bits(1));
+    }
+
+    #[test]
+    fn transforms_round_trip() {
+        let transform = Transform::from_xyz(12.5, -3.25, 1.0);
+        let fixed = FixedTransform::from_transform(&transform);
+        let mut back = Transform::default();
+        fixed.apply_to(&mut back);
+        assert_eq!(back, transform);
+    }
+}
@@ -0,0 +1,269 @@
+//! A friends list kept on disk: who is online, and invites straight to their client.
+//!
+//! Friends are pinned by [`PeerId`], so the name shown is always the one we gave them,
+//! whatever they call themselves. Invites from anyone else are dropped.
+//!
+//! Friends who are offline get their invite in their DHT mailbox instead, and see it
+//! next time they start the game.
+
+use std::{io, path::PathBuf, str::FromStr, time::Duration};
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::network::{
+    AsyncNetworkTasks, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent,
+};
+use crate::{
+    at_rest::{self, AtRestEncryption},
+    paths::Paths,
+};
+
+/// How often offline friends are looked up on the DHT again.
+const LOOKUP_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct FriendsPlugin;
+
+impl Plugin for FriendsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Paths>()
+            .init_resource::<AtRestEncryption>()
+            .init_resource::<FriendsFile>()
+            .init_resource::<Friends>()
+            .add_event::<InviteFriend>()
+            .add_event::<FriendInvite>()
+            .add_systems(Startup, load_friends)
+            .add_systems(
+                Update,
+                (
+                    track_friends::<()>,
+                    look_for_friends,
+                    send_invites,
+                    save_friends,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Where the friends list is stored, one `<peer id> <name>` per line. Defaults to
+/// `friends.txt` in the [`Paths::config`] directory.
+#[derive(Resource, Debug, Clone)]
+pub struct FriendsFile(pub PathBuf);
+
+impl FromWorld for FriendsFile {
+    fn from_world(world: &mut World) -> Self {
+        Self(world.resource::<Paths>().config.join("friends.txt"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Friend {
+    pub peer_id: PeerId,
+    pub name: String,
+    /// Connected to us, or last found on the DHT.
+    pub online: bool,
+}
+
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Friends(Vec<Friend>);
+
+impl Friends {
+    pub fn iter(&self) -> impl Iterator<Item = &Friend> {
+        self.0.iter()
+    }
+
+    pub fn get(&self, peer_id: &PeerId) -> Option<&Friend> {
+        self.0.iter().find(|friend| friend.peer_id == *peer_id)
+    }
+
+    /// Adds `peer_id`, or renames it if it's already a friend.
+    pub fn add(&mut self, peer_id: PeerId, name: impl Into<String>) {
+        let name = name.into();
+        match self.0.iter_mut().find(|friend| friend.peer_id == peer_id) {
+            Some(friend) => friend.name = name,
+            None => self.0.push(Friend {
+                peer_id,
+                name,
+                online: false,
+            }),
+        }
+    }
+
+    pub fn remove(&mut self, peer_id: &PeerId) {
+        self.0.retain(|friend| friend.peer_id != *peer_id);
+    }
+
+    fn set_online(&mut self, peer_id: &PeerId, online: bool) {
+        if let Some(friend) = self.0.iter_mut().find(|friend| friend.peer_id == *peer_id) {
+            friend.online = online;
+        }
+    }
+
+    /// The list as stored in [`FriendsFile`].
+    fn to_file(&self) -> String {
+        self.0
+            .iter()
+            .map(|friend| format!("{} {}\n", friend.peer_id, friend.name))
+            .collect()
+    }
+
+    /// Reads [`to_file`](Self::to_file) output, skipping lines that don't parse.
+    fn from_file(contents: &str) -> Self {
+        let mut friends = Self::default();
+        for line in contents.lines() {
+            let (peer_id, name) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            match PeerId::from_str(peer_id) {
+                Ok(peer_id) => friends.add(peer_id, name.trim()),
+                Err(_) if line.trim().is_empty() => {}
+                Err(e) => log::warn!("Skipping friend {:?}: {}", line, e),
+            }
+        }
+        friends
+    }
+}
+
+/// Send a friend a direct invite to `room_code`.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct InviteFriend {
+    pub peer_id: PeerId,
+    pub room_code: String,
+}
+
+/// A friend invited us to `room_code`.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct FriendInvite {
+    pub peer_id: PeerId,
+    /// Our name for them, from [`Friends`].
+    pub name: String,
+    pub room_code: String,
+}
+
+fn load_friends(
+    file: Res<FriendsFile>,
+    encryption: Res<AtRestEncryption>,
+    mut friends: ResMut<Friends>,
+) {
+    match at_rest::read_to_string(&file.0, &encryption) {
+        Ok(contents) => *friends = Friends::from_file(&contents),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => log::error!("Could not read {}: {}", file.0.display(), e),
+    }
+}
+
+/// Writes the list whenever a friend is added, renamed or removed. Online status
+/// isn't stored, so it changing doesn't cause a write.
+fn save_friends(
+    file: Res<FriendsFile>,
+    encryption: Res<AtRestEncryption>,
+    friends: Res<Friends>,
+    mut saved: Local<Option<String>>,
+) {
+    if !friends.is_changed() {
+        return;
+    }
+    let contents = friends.to_file();
+    match saved.as_ref() {
+        Some(saved) if *saved == contents => return,
+        // The first run sees what load_friends just read.
+        None => {
+            *saved = Some(contents);
+            return;
+        }
+        _ => {}
+    }
+    if let Err(e) = at_rest::write(&file.0, &contents, &encryption) {
+        log::error!("Could not write {}: {}", file.0.display(), e);
+    }
+    *saved = Some(contents);
+}
+
+fn track_friends<ToGame>(
+    mut friends: ResMut<Friends>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+    mut invites: EventWriter<FriendInvite>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        match event {
+            NetworkEvent::Admin(
+                NetworkAdminEvent::Connected(peer_id) | NetworkAdminEvent::PeerFound(peer_id),
+            ) => friends.set_online(peer_id, true),
+            NetworkEvent::Admin(
+                NetworkAdminEvent::Disconnected(peer_id) | NetworkAdminEvent::PeerNotFound(peer_id),
+            ) => friends.set_online(peer_id, false),
+            NetworkEvent::Admin(
+                NetworkAdminEvent::InviteReceived { from, room_code }
+                | NetworkAdminEvent::JoinInvitation {
+                    from, room_code, ..
+                },
+            ) => match friends.get(from) {
+                Some(friend) => invites.send(FriendInvite {
+                    peer_id: *from,
+                    name: friend.name.clone(),
+                    room_code: room_code.clone(),
+                }),
+                None => log::info!("Ignoring invite from stranger {}", from),
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Looks every offline friend up on the DHT at startup and every [`LOOKUP_INTERVAL`].
+fn look_for_friends(
+    time: Res<Time>,
+    friends: Res<Friends>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    mut timer: Local<Option<Timer>>,
+) {
+    let due = match timer.as_mut() {
+        Some(timer) => timer.tick(time.delta()).just_finished(),
+        None => {
+            *timer = Some(Timer::new(LOOKUP_INTERVAL, TimerMode::Repeating));
+            true
+        }
+    };
+    if !due {
+        return;
+    }
+    for friend in friends.iter().filter(|friend| !friend.online) {
+        tasks.send(GameEvent::Admin(GameAdminEvent::FindPeer(friend.peer_id)));
+    }
+}
+
+/// Sends online friends their invite directly and leaves it in everyone else's mailbox.
+fn send_invites(
+    friends: Res<Friends>,
+    mut requests: EventReader<InviteFriend>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+) {
+    for request in requests.iter() {
+        let peer_id = request.peer_id;
+        let room_code = request.room_code.clone();
+        let invite = match friends.get(&peer_id) {
+            Some(friend) if !friend.online => GameAdminEvent::PostInvite { peer_id, room_code },
+            _ => GameAdminEvent::Invite { peer_id, room_code },
+        };
+        tasks.send(GameEvent::Admin(invite));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_round_trip() {
+        let mut friends = Friends::default();
+        friends.add(PeerId::random(), "Ada Lovelace");
+        friends.add(PeerId::random(), "Bob");
+        let loaded = Friends::from_file(&friends.to_file());
+        assert_eq!(loaded, friends);
+        assert_eq!(
+            Friends::from_file("not-a-peer-id Eve\n\n").iter().count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Game modes a game can play, each registered with a [`GameModePlugin`] declaring
+//! what it needs: its message types, tick rate, how its state is kept in sync and how
+//! many players it takes.
+//!
+//! The host publishes the mode it picked as the room's
+//! [`RoomInfo::mode`](crate::network::RoomInfo::mode), and joins skip rooms playing a
+//! mode we don't have registered. Hosts turn such joiners away too, as an older
+//! client may not have looked at the room info.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+
+use crate::messages::{MessagePlugin, MessageRegistry};
+use crate::network::{
+    AsyncNetworkTasks, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent,
+};
+
+/// How a mode keeps every peer's game state the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyncStrategy {
+    /// Every peer runs the whole simulation on the same inputs.
+    Lockstep,
+    /// The host runs the simulation and the others follow its
+    /// [`Authority`](crate::authority::Authority).
+    Authoritative,
+    /// Each peer runs its own entities and the rest take its word for them.
+    ClientAuthority,
+}
+
+/// What a game mode needs to be played.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameMode {
+    /// Shown to players and published in the room info, so it has to be the same on
+    /// every client.
+    pub name: String,
+    /// Simulation ticks per second, applied to Bevy's [`FixedTime`].
+    pub tick_rate: u32,
+    pub sync: SyncStrategy,
+    /// Players, including the host, needed before a game can start.
+    pub min_players: usize,
+    /// `None` if there is no limit.
+    pub max_players: Option<u32>,
+    /// Ids of the message types the mode sends.
+    pub messages: Vec<u16>,
+}
+
+impl GameMode {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            tick_rate: 60,
+            sync: SyncStrategy::ClientAuthority,
+            min_players: 1,
+            max_players: None,
+            messages: Vec::new(),
+        }
+    }
+
+    pub fn with_tick_rate(mut self, tick_rate: u32) -> Self {
+        self.tick_rate = tick_rate;
+        self
+    }
+
+    pub fn with_sync(mut self, sync: SyncStrategy) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    pub fn with_players(mut self, min_players: usize, max_players: Option<u32>) -> Self {
+        self.min_players = min_players;
+        self.max_players = max_players;
+        self
+    }
+
+    fn tick(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / f64::from(self.tick_rate.max(1)))
+    }
+}
+
+/// The game modes registered with [`GameModePlugin`]s, in registration order, and the
+/// one we play.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct GameModes {
+    modes: Vec<GameMode>,
+    selected: Option<String>,
+}
+
+impl GameModes {
+    pub fn iter(&self) -> impl Iterator<Item = &GameMode> {
+        self.modes.iter()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&GameMode> {
+        self.modes.iter().find(|mode| mode.name == name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.modes.iter().map(|mode| mode.name.clone()).collect()
+    }
+
+    /// The mode we host or play: the one last selected, else the first registered.
+    pub fn selected(&self) -> Option<&GameMode> {
+        self.selected
+            .as_deref()
+            .and_then(|name| self.get(name))
+            .or_else(|| self.modes.first())
+    }
+
+    /// Plays `name` from now on. Returns whether it's registered.
+    pub fn select(&mut self, name: &str) -> bool {
+        let known = self.get(name).is_some();
+        if known {
+            self.selected = Some(name.to_string());
+        }
+        known
+    }
+
+    /// What the host publishes about the room for the selected mode.
+    pub fn describe_room(&self) -> GameAdminEvent {
+        let (mode, max_players) = self.selected().map_or((String::new(), None), |mode| {
+            (mode.name.clone(), mode.max_players)
+        });
+        GameAdminEvent::DescribeRoom { mode, max_players }
+    }
+
+    fn register(&mut self, mode: GameMode) {
+        match self.modes.iter_mut().find(|known| known.name == mode.name) {
+            Some(known) => *known = mode,
+            None => self.modes.push(mode),
+        }
+    }
+}
+
+type RegisterMessage = Box<dyn Fn(&mut App) + Send + Sync>;
+
+/// Registers a [`GameMode`] and the message types it sends. Add one per mode.
+///
+/// # Panics
+///
+/// When built, if a message id or type is already registered otherwise, as with
+/// [`MessagePlugin`].
+pub struct GameModePlugin {
+    mode: GameMode,
+    register_messages: Vec<RegisterMessage>,
+}
+
+impl GameModePlugin {
+    pub fn new(mode: GameMode) -> Self {
+        Self {
+            mode,
+            register_messages: Vec::new(),
+        }
+    }
+
+    /// Sends `T` under `id`. Modes may share message types, as long as they agree on
+    /// their ids.
+    pub fn with_message<T>(mut self, id: u16) -> Self
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+    {
+        self.mode.messages.push(id);
+        self.register_messages.push(Box::new(move |app: &mut App| {
+            let registered = app
+                .world
+                .get_resource::<MessageRegistry>()
+                .and_then(MessageRegistry::id_of::<T>);
+            if registered != Some(id) {
+                app.add_plugins(MessagePlugin::<T>::new(id));
+            }
+        }));
+        self
+    }
+}
+
+impl Plugin for GameModePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<GameModes>() {
+            app.init_resource::<GameModes>().add_systems(
+                Update,
+                (
+                    follow_room_mode::<()>,
+                    announce_modes,
+                    apply_tick_rate.run_if(resource_changed::<GameModes>()),
+                )
+                    .chain(),
+            );
+        }
+        for register in &self.register_messages {
+            register(app);
+        }
+        app.world
+            .resource_mut::<GameModes>()
+            .register(self.mode.clone());
+    }
+
+    fn is_unique(&self) -> bool {
+        false
+    }
+}
+
+/// Joiners play whichever of our modes the room they are joining does.
+fn follow_room_mode<ToGame>(
+    mut modes: ResMut<GameModes>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        if let NetworkEvent::Admin(NetworkAdminEvent::RoomInfoFound { info, .. }) = event {
+            if modes.selected().map(|mode| &mode.name) != Some(&info.mode) {
+                modes.select(&info.mode);
+            }
+        }
+    }
+}
+
+/// Tells the network thread our modes once it's up, and again when they change.
+fn announce_modes(
+    modes: Res<GameModes>,
+    tasks: Option<ResMut<AsyncNetworkTasks<()>>>,
+    mut announced: Local<bool>,
+) {
+    let Some(mut tasks) = tasks else {
+        return;
+    };
+    if *announced && !modes.is_changed() {
+        return;
+    }
+    tasks.send(GameEvent::Admin(GameAdminEvent::SetModes(modes.names())));
+    *announced = true;
+}
+
+fn apply_tick_rate(modes: Res<GameModes>, fixed_time: Option<ResMut<FixedTime>>) {
+    if let (Some(mode), Some(mut fixed_time)) = (modes.selected(), fixed_time) {
+        if fixed_time.period != mode.tick() {
+            fixed_time.period = mode.tick();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_only_registered_modes() {
+        let mut modes = GameModes::default();
+        assert_eq!(modes.selected(), None);
+
+        modes.register(GameMode::new("Free for all"));
+        modes.register(GameMode::new("Capture the flag").with_players(4, Some(8)));
+        assert_eq!(modes.selected().unwrap().name, "Free for all");
+
+        assert!(!modes.select("Racing"));
+        assert!(modes.select("Capture the flag"));
+        assert_eq!(
+            modes.describe_room(),
+            GameAdminEvent::DescribeRoom {
+                mode: "Capture the flag".to_string(),
+                max_players: Some(8),
+            }
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! Running without a window, for servers and scripted sessions. Nobody is there to
+//! click through the menus, so a [`LaunchJoin`] is carried out directly and we get
+//! ready as soon as the host lets us in.
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+use crate::lobby::{LaunchJoin, PlayerSettings, SetReady};
+use crate::network::{
+    AsyncNetworkTasks, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent,
+};
+use crate::room_code::{parse_invite_token, parse_room_code};
+
+/// Added by [`GamePlugin`](crate::GamePlugin) when
+/// [`GameOptions::headless`](crate::GameOptions::headless) is set.
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                join_at_launch.run_if(resource_exists::<LaunchJoin>()),
+                follow_session::<()>,
+            )
+                .run_if(resource_exists::<AsyncNetworkTasks<()>>()),
+        );
+    }
+}
+
+fn join_at_launch(
+    mut commands: Commands,
+    launch: Res<LaunchJoin>,
+    settings: Res<PlayerSettings>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    commands.remove_resource::<LaunchJoin>();
+    let room_code = match parse_room_code(&launch.0) {
+        Ok(room_code) => room_code,
+        Err(e) => match parse_invite_token(&launch.0) {
+            Ok(invite) => invite.to_string(),
+            Err(_) => {
+                log::error!("Can't join {:?}: {}", launch.0, e);
+                exit.send(AppExit);
+                return;
+            }
+        },
+    };
+    log::info!("Joining {} as {}", room_code, settings.name);
+    tasks.send(GameEvent::Admin(GameAdminEvent::Join {
+        room_code,
+        name: settings.name.clone(),
+    }));
+}
+
+/// Logs how the session goes and readies up once in a room. A join that can't succeed
+/// ends the app, so a script running it notices.
+fn follow_session<ToGame>(
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+    mut ready: EventWriter<SetReady>,
+    mut exit: EventWriter<AppExit>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        match event {
+            NetworkAdminEvent::JoinAccepted { host, .. } => {
+                log::info!("Joined the room of {}", host);
+                ready.send(SetReady(true));
+            }
+            NetworkAdminEvent::JoinFailed(error) => {
+                log::error!("Could not join: {}", error);
+                exit.send(AppExit);
+            }
+            NetworkAdminEvent::GameStarted {
+                seed, countdown, ..
+            } => {
+                log::info!("Game starting in {:?} with seed {:#x}", countdown, seed)
+            }
+            _ => {}
+        }
+    }
+}
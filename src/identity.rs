@@ -0,0 +1,32 @@
+//! Keeping our peer id across runs. A client that crashes and comes back as the same
+//! peer can reclaim its place in the room it was in, see
+//! [`JoinLimits::reclaim_window`](crate::network::JoinLimits::reclaim_window).
+//!
+//! Off by default, as two copies of the game on one machine would share the identity.
+//! Load one into [`NetworkConfig::identity`](crate::network::NetworkConfig::identity)
+//! to turn it on.
+
+use std::{io, path::Path};
+
+use libp2p::identity::Keypair;
+
+use crate::at_rest::{self, from_hex, to_hex, AtRestEncryption};
+
+/// Reads the identity kept in `path`, or makes up a new one and keeps it there. It's
+/// the secret half of our peer id, so it's sealed if `encryption` is on.
+pub fn load_or_create(path: &Path, encryption: &AtRestEncryption) -> io::Result<Keypair> {
+    match at_rest::read_to_string(path, encryption) {
+        Ok(contents) => Keypair::from_protobuf_encoding(&from_hex(contents.trim())?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let keypair = Keypair::generate_ed25519();
+            let encoded = keypair
+                .to_protobuf_encoding()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            at_rest::write(path, &to_hex(&encoded), encryption)?;
+            log::info!("Created a new identity in {}", path.display());
+            Ok(keypair)
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,247 @@
+//! Hit detection against the world as the shooter saw it. By the time a shot reaches
+//! the host, its targets have moved on by about half the shooter's round trip, so the
+//! host keeps the last few ticks of every [`Hitbox`] and checks the shot against the
+//! tick the shooter was looking at.
+//!
+//! The game advances [`SimulationTick`] once per simulation step; the host records a
+//! frame of hitboxes whenever it does. Shots then go through
+//! [`RewindBuffer::rewind_to`], e.g. with the tick from
+//! [`LagCompensation::shooter_tick`] for the shooter's [`Latency`](crate::peer::Latency).
+
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+
+use crate::authority::Authorities;
+
+pub struct LagCompensationPlugin;
+
+impl Plugin for LagCompensationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Hitbox>()
+            .init_resource::<LagCompensation>()
+            .init_resource::<SimulationTick>()
+            .init_resource::<RewindBuffer>()
+            .add_systems(
+                PostUpdate,
+                record_hitboxes.run_if(resource_changed::<SimulationTick>()),
+            );
+    }
+}
+
+/// How far back the host can rewind.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct LagCompensation {
+    /// Shooters with a worse round trip get hit checked as if they had this one.
+    pub max_rtt: Duration,
+    /// How long one [`SimulationTick`] lasts.
+    pub tick_duration: Duration,
+}
+
+impl Default for LagCompensation {
+    fn default() -> Self {
+        Self {
+            max_rtt: Duration::from_millis(300),
+            tick_duration: Duration::from_secs_f64(1.0 / 60.0),
+        }
+    }
+}
+
+impl LagCompensation {
+    /// Frames kept: enough to cover half of [`max_rtt`](Self::max_rtt), plus the
+    /// current one.
+    pub fn history_len(&self) -> usize {
+        self.ticks_behind(self.max_rtt) as usize + 1
+    }
+
+    /// How many ticks behind the host a shooter with round trip `rtt` sees the world.
+    pub fn ticks_behind(&self, rtt: Duration) -> u64 {
+        let one_way = (rtt.min(self.max_rtt) / 2).as_nanos();
+        let tick = self.tick_duration.as_nanos().max(1);
+        ((one_way + tick - 1) / tick) as u64
+    }
+
+    /// The tick a shooter with round trip `rtt` was looking at when the host is at `now`.
+    pub fn shooter_tick(&self, now: SimulationTick, rtt: Duration) -> u64 {
+        now.0.saturating_sub(self.ticks_behind(rtt))
+    }
+}
+
+/// The game's simulation step. Advancing it records a frame of hitboxes on the host.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct SimulationTick(pub u64);
+
+/// Something that can be hit: an axis aligned box of `half_size` around the entity's
+/// translation.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect)]
+pub struct Hitbox {
+    pub half_size: Vec2,
+}
+
+/// Where a [`Hitbox`] was in a past tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HitboxState {
+    pub entity: Entity,
+    pub center: Vec2,
+    pub half_size: Vec2,
+}
+
+impl HitboxState {
+    pub fn contains(&self, point: Vec2) -> bool {
+        let offset = (point - self.center).abs();
+        offset.x <= self.half_size.x && offset.y <= self.half_size.y
+    }
+}
+
+/// Host only: the hitboxes of the last [`LagCompensation::history_len`] ticks.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct RewindBuffer {
+    frames: VecDeque<(u64, Vec<HitboxState>)>,
+}
+
+impl RewindBuffer {
+    /// Stores the hitboxes of `tick`, forgetting the oldest frames beyond `capacity`.
+    pub fn record(&mut self, tick: u64, hitboxes: Vec<HitboxState>, capacity: usize) {
+        match self.frames.back() {
+            Some((last, _)) if *last == tick => {
+                self.frames.pop_back();
+            }
+            // The tick went backwards, e.g. a new round: the history is useless now.
+            Some((last, _)) if *last > tick => self.frames.clear(),
+            _ => {}
+        }
+        self.frames.push_back((tick, hitboxes));
+        while self.frames.len() > capacity.max(1) {
+            self.frames.pop_front();
+        }
+    }
+
+    /// The world as of `tick`, or the oldest we still have if it's further back than
+    /// that. `None` until something was recorded.
+    pub fn rewind_to(&self, tick: u64) -> Option<Rewound<'_>> {
+        let index = self
+            .frames
+            .iter()
+            .rposition(|(recorded, _)| *recorded <= tick)
+            .unwrap_or(0);
+        self.frames.get(index).map(|(tick, hitboxes)| Rewound {
+            tick: *tick,
+            hitboxes,
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+/// The hitboxes of one past tick.
+#[derive(Debug, Clone, Copy)]
+pub struct Rewound<'a> {
+    /// The tick actually rewound to, which is older than asked for when the history
+    /// doesn't reach back far enough.
+    pub tick: u64,
+    hitboxes: &'a [HitboxState],
+}
+
+impl<'a> Rewound<'a> {
+    pub fn get(&self, entity: Entity) -> Option<&'a HitboxState> {
+        self.hitboxes.iter().find(|state| state.entity == entity)
+    }
+
+    /// Every entity whose hitbox covered `point`.
+    pub fn hits(&self, point: Vec2) -> impl Iterator<Item = Entity> + 'a {
+        self.hitboxes
+            .iter()
+            .filter(move |state| state.contains(point))
+            .map(|state| state.entity)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a HitboxState> {
+        self.hitboxes.iter()
+    }
+}
+
+fn record_hitboxes(
+    tick: Res<SimulationTick>,
+    config: Res<LagCompensation>,
+    authorities: Res<Authorities>,
+    mut buffer: ResMut<RewindBuffer>,
+    hitboxes: Query<(Entity, &GlobalTransform, &Hitbox)>,
+) {
+    if !authorities.is_host() {
+        buffer.clear();
+        return;
+    }
+    let frame = hitboxes
+        .iter()
+        .map(|(entity, transform, hitbox)| HitboxState {
+            entity,
+            center: transform.translation().truncate(),
+            half_size: hitbox.half_size,
+        })
+        .collect();
+    buffer.record(tick.0, frame, config.history_len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(entity: Entity, x: f32) -> Vec<HitboxState> {
+        vec![HitboxState {
+            entity,
+            center: Vec2::new(x, 0.0),
+            half_size: Vec2::splat(1.0),
+        }]
+    }
+
+    #[test]
+    fn rewinds_to_the_tick_the_shooter_saw() {
+        let target = Entity::from_raw(1);
+        let mut buffer = RewindBuffer::default();
+        assert!(buffer.rewind_to(0).is_none());
+        for tick in 0..10 {
+            buffer.record(tick, frame(target, tick as f32 * 10.0), 4);
+        }
+
+        let rewound = buffer.rewind_to(7).unwrap();
+        assert_eq!(rewound.tick, 7);
+        assert_eq!(
+            rewound.hits(Vec2::new(70.5, 0.0)).collect::<Vec<_>>(),
+            [target]
+        );
+        assert_eq!(rewound.hits(Vec2::new(90.0, 0.0)).count(), 0);
+        // Older than the history goes: the oldest frame left stands in.
+        assert_eq!(buffer.rewind_to(2).unwrap().tick, 6);
+    }
+
+    #[test]
+    fn history_covers_half_the_worst_round_trip() {
+        let config = LagCompensation {
+            max_rtt: Duration::from_millis(200),
+            tick_duration: Duration::from_millis(10),
+        };
+        assert_eq!(config.history_len(), 11);
+        assert_eq!(
+            config.shooter_tick(SimulationTick(100), Duration::from_millis(60)),
+            97
+        );
+        assert_eq!(
+            config.shooter_tick(SimulationTick(100), Duration::from_secs(5)),
+            90
+        );
+    }
+
+    #[test]
+    fn rounds_up_only_past_a_tick_boundary() {
+        let config = LagCompensation::default();
+        let tick = config.tick_duration;
+        assert_eq!(config.ticks_behind(Duration::ZERO), 0);
+        // Exactly on a boundary, which floating point division could push over.
+        assert_eq!(config.ticks_behind(tick * 2), 1);
+        assert_eq!(config.ticks_behind(tick * 6), 3);
+        assert_eq!(config.ticks_behind(tick * 6 + Duration::from_nanos(2)), 4);
+        assert_eq!(config.ticks_behind(Duration::from_nanos(2)), 1);
+    }
+}
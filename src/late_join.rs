@@ -0,0 +1,210 @@
+//! Catching up with a game that started before we joined.
+//!
+//! Right after a join is accepted, [`LateJoinPlugin`] asks the host for a
+//! [`Snapshot`] of its game. The host's game gets a [`SnapshotWanted`] and answers
+//! with a [`ProvideSnapshot`] taken at some tick. Game messages keep coming in
+//! meanwhile; a [`DeltaBuffer`] holds them, then drops the ones the snapshot already
+//! covers and hands back the rest to apply on top of it.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::network::{
+    AsyncNetworkTasks, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent, Snapshot,
+};
+
+/// Messages a [`DeltaBuffer`] holds at most while syncing. The oldest go first.
+const MAX_BUFFERED: usize = 4096;
+
+pub struct LateJoinPlugin;
+
+impl Plugin for LateJoinPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LateJoin>()
+            .add_event::<SnapshotWanted>()
+            .add_event::<ProvideSnapshot>()
+            .add_systems(
+                Update,
+                (track_late_join::<()>, send_snapshots)
+                    .run_if(resource_exists::<AsyncNetworkTasks<()>>()),
+            );
+    }
+}
+
+/// Where we are in catching up with the room we joined.
+#[derive(Resource, Debug, Clone, PartialEq, Eq, Default)]
+pub enum LateJoin {
+    /// Not in someone else's room.
+    #[default]
+    Idle,
+    /// Waiting for the host's snapshot.
+    Syncing,
+    /// Caught up. `tick` is the snapshot's, or `None` when the game hadn't started and
+    /// there was nothing to catch up with.
+    Synced { tick: Option<u64> },
+    /// The host didn't send its state. Send [`GameAdminEvent::RequestSnapshot`] to try
+    /// again.
+    Failed { reason: String },
+}
+
+/// Host only: `peer_id` joined the game in progress. Answer with a
+/// [`ProvideSnapshot`].
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotWanted {
+    pub peer_id: PeerId,
+}
+
+/// Host only: the game state for a [`SnapshotWanted`], as of `tick`.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct ProvideSnapshot {
+    pub peer_id: PeerId,
+    pub tick: u64,
+    pub state: Vec<u8>,
+}
+
+/// Game messages for a joiner that isn't caught up yet, by the tick they apply to.
+#[derive(Debug, Clone)]
+pub struct DeltaBuffer<T> {
+    syncing: bool,
+    /// Ticks up to this one are in the snapshot.
+    covered: Option<u64>,
+    pending: VecDeque<(u64, T)>,
+}
+
+impl<T> Default for DeltaBuffer<T> {
+    fn default() -> Self {
+        Self {
+            syncing: false,
+            covered: None,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> DeltaBuffer<T> {
+    /// Holds everything from now until [`synced`](Self::synced), e.g. once
+    /// [`LateJoin::Syncing`].
+    pub fn start_syncing(&mut self) {
+        self.syncing = true;
+        self.covered = None;
+        self.pending.clear();
+    }
+
+    /// Takes the message for `tick`, returning it if it should be applied now. While
+    /// syncing it's held instead, and once synced it's dropped if the snapshot
+    /// already covers it.
+    pub fn receive(&mut self, tick: u64, message: T) -> Option<T> {
+        if self.syncing {
+            if self.pending.len() == MAX_BUFFERED {
+                self.pending.pop_front();
+            }
+            self.pending.push_back((tick, message));
+            return None;
+        }
+        match self.covered {
+            Some(covered) if tick <= covered => None,
+            _ => Some(message),
+        }
+    }
+
+    /// The snapshot at `tick` is applied, or there was none. Returns the held messages
+    /// that come after it, by tick and then in the order they arrived.
+    pub fn synced(&mut self, tick: Option<u64>) -> Vec<T> {
+        self.syncing = false;
+        self.covered = tick;
+        let mut pending: Vec<_> = self
+            .pending
+            .drain(..)
+            .filter(|(held, _)| tick.map_or(true, |tick| *held > tick))
+            .collect();
+        pending.sort_by_key(|(held, _)| *held);
+        pending.into_iter().map(|(_, message)| message).collect()
+    }
+}
+
+fn track_late_join<ToGame>(
+    mut late_join: ResMut<LateJoin>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+    mut wanted: EventWriter<SnapshotWanted>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        match event {
+            NetworkAdminEvent::JoinAccepted { .. } => {
+                *late_join = LateJoin::Syncing;
+                tasks.send(GameEvent::Admin(GameAdminEvent::RequestSnapshot));
+            }
+            NetworkAdminEvent::SnapshotReceived(snapshot) => {
+                *late_join = LateJoin::Synced {
+                    tick: snapshot.as_ref().map(|snapshot| snapshot.tick),
+                };
+            }
+            NetworkAdminEvent::SnapshotFailed { reason } => {
+                log::warn!("Could not catch up with the game: {}", reason);
+                *late_join = LateJoin::Failed {
+                    reason: reason.clone(),
+                };
+            }
+            NetworkAdminEvent::HostingStarted { .. } => *late_join = LateJoin::Idle,
+            NetworkAdminEvent::SnapshotRequested(peer_id) => {
+                wanted.send(SnapshotWanted { peer_id: *peer_id })
+            }
+            _ => {}
+        }
+    }
+}
+
+fn send_snapshots(
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    mut snapshots: EventReader<ProvideSnapshot>,
+) {
+    for ProvideSnapshot {
+        peer_id,
+        tick,
+        state,
+    } in snapshots.iter()
+    {
+        tasks.send(GameEvent::Admin(GameAdminEvent::AnswerSnapshot {
+            peer_id: *peer_id,
+            snapshot: Some(Snapshot {
+                tick: *tick,
+                state: state.clone(),
+            }),
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffered_messages_apply_after_the_snapshot_tick() {
+        let mut buffer = DeltaBuffer::default();
+        assert_eq!(buffer.receive(1, "live"), Some("live"));
+        buffer.start_syncing();
+        for (tick, message) in [(5, "b"), (3, "old"), (4, "a"), (5, "c")] {
+            assert_eq!(buffer.receive(tick, message), None);
+        }
+        assert_eq!(buffer.synced(Some(3)), ["a", "b", "c"]);
+        // Stragglers from before the snapshot are still covered by it.
+        assert_eq!(buffer.receive(2, "late"), None);
+        assert_eq!(buffer.receive(6, "d"), Some("d"));
+    }
+
+    #[test]
+    fn nothing_is_dropped_without_a_snapshot() {
+        let mut buffer = DeltaBuffer::default();
+        buffer.start_syncing();
+        buffer.receive(0, "first");
+        assert_eq!(buffer.synced(None), ["first"]);
+        assert_eq!(buffer.receive(0, "second"), Some("second"));
+    }
+}
@@ -1,27 +1,185 @@
 #![allow(clippy::type_complexity)]
 
+#[cfg(feature = "windowed")]
 mod actions;
+pub mod at_rest;
+#[cfg(feature = "audio")]
 mod audio;
+pub mod authority;
+pub mod chat;
+#[cfg(feature = "inspector")]
+mod console;
 pub mod crypto;
+pub mod dedicated;
+pub mod determinism;
+#[cfg(feature = "inspector")]
+mod diagnostics_overlay;
+pub mod emote;
+#[cfg(feature = "windowed")]
+mod emote_bubbles;
+pub mod file_drop;
+pub mod fixed;
+pub mod friends;
+pub mod game_mode;
+mod headless;
+pub mod identity;
+pub mod lag_compensation;
+pub mod late_join;
+#[cfg(feature = "windowed")]
+mod link_indicator;
+#[cfg(feature = "windowed")]
 mod loading;
+pub mod lobby;
+#[cfg(feature = "inspector")]
+mod log_panel;
+#[cfg(feature = "windowed")]
 mod menu;
+pub mod messages;
 pub mod network;
-mod peer;
+#[cfg(feature = "windowed")]
+mod notifications;
+pub mod paths;
+pub mod peer;
+#[cfg(feature = "windowed")]
 mod player;
+pub mod rng;
+pub mod room_code;
+pub mod shutdown;
+pub mod smoothing;
+#[cfg(feature = "windowed")]
+mod spectator_camera;
+pub mod teams;
+#[cfg(feature = "inspector")]
+pub mod test_client;
+#[cfg(feature = "windowed")]
+mod text_input;
+pub mod tick_negotiation;
+#[cfg(feature = "inspector")]
+mod topology_panel;
+pub mod validation;
 
+/// Everything needed to use the networking layer from another Bevy game.
+pub mod prelude {
+    pub use crate::at_rest::AtRestEncryption;
+    pub use crate::authority::{
+        Authorities, Authority, AuthorityChanged, AuthorityDenied, AuthorityPlugin, NetworkId,
+        ReleaseAuthority, RequestAuthority, TransferAuthority,
+    };
+    pub use crate::chat::{
+        ChatEcho, ChatMessage, ChatPlugin, MuteList, MuteListFile, Mutes, RoomMute, RoomMutes,
+        SendChat,
+    };
+    pub use crate::crypto::{CryptoStatus, DataEncryptor, KeyRing};
+    pub use crate::dedicated::{DedicatedHost, DedicatedHostPlugin};
+    pub use crate::emote::{EmoteLimits, EmotePlugin, EmoteReceived, SendEmote};
+    pub use crate::file_drop::{AnswerFileOffer, FileDropPlugin, FileOffer, FileOffers};
+    pub use crate::fixed::{Fixed, FixedMathPlugin, FixedTransform, FixedVec3};
+    pub use crate::friends::{
+        Friend, FriendInvite, Friends, FriendsFile, FriendsPlugin, InviteFriend,
+    };
+    pub use crate::game_mode::{GameMode, GameModePlugin, GameModes, SyncStrategy};
+    pub use crate::lag_compensation::{
+        Hitbox, HitboxState, LagCompensation, LagCompensationPlugin, RewindBuffer, Rewound,
+        SimulationTick,
+    };
+    pub use crate::late_join::{
+        DeltaBuffer, LateJoin, LateJoinPlugin, ProvideSnapshot, SnapshotWanted,
+    };
+    pub use crate::lobby::{
+        AnswerJoinRequest, CountdownFinished, JoinApproval, JoinRequest, JoinRequests, LaunchHost,
+        LaunchJoin, LobbyPlugin, PlayerSettings, Readiness, SetReady, StartCountdown,
+    };
+    pub use crate::messages::{
+        Envelope, MessageError, MessagePlugin, MessageReceived, MessageRegistry,
+    };
+    pub use crate::network::{
+        setup_network, setup_network_with_behaviour, AsyncNetworkTasks, Behaviour, CommandId,
+        ConnectionPath, CurrentRoom, CustomBehaviour, CustomBehaviourEvent, CustomBehaviourPlugin,
+        DefaultBehaviour, DiscoveryMethod, DnsConfig, DnsLookup, DnsProvider, Emote,
+        ExternalAddress, ExternalAddresses, ExtraSessionPlugin, FileDropConfig, GameAdminEvent,
+        GameEvent, IpVersions, JoinLimits, KeepAlivePolicy, LinkConditions, ListenStrategy,
+        LocalPeerInfo, LogEntry, LogFilter, LogSubscription, MeshHealth, NetworkAdminEvent,
+        NetworkConfig, NetworkDiagnosticsPlugin, NetworkEvent, NetworkEventBudget,
+        NetworkFailurePolicy, NetworkLog, NetworkManager, NetworkMetrics, NetworkPlugin,
+        NetworkSetup, NetworkSetupError, NetworkTaskFinished, NetworkTaskId, NetworkWatchdog,
+        PeerProfile, Priority, ProtocolNames, RelaySelection, RelayUsageLimits, SessionState,
+        Severity, TapeConfig, TickSettings,
+    };
+    pub use crate::paths::Paths;
+    pub use crate::peer::{
+        HolePunch, IsHost, Latency, Misbehaviour, PeerAvatar, PeerConnected, PeerEntities,
+        PeerIdComp, PeerMisbehaved, PeerNameComp, PeerPlugin, PeerReputations, PeerStats, PeerTeam,
+        PeerTimelines, RelayedVia, RemotePlayer, ReputationConfig, ReputationEvent, SendRate,
+        SendRateConfig, Spectator, StatsHistory, StatsSample, TimelineEntry, TimelineEvent,
+    };
+    pub use crate::rng::{RequestNewRound, RequestStartGame, SessionRng, SessionRngPlugin};
+    pub use crate::room_code::{generate_room_code, parse_room_code, InvalidRoomCode};
+    pub use crate::shutdown::{Shutdown, ShutdownPlugin};
+    pub use crate::smoothing::{
+        ErrorSmoothing, ErrorSmoothingPlugin, RenderOffset, SnapCorrection,
+    };
+    pub use crate::teams::{MoveToTeam, Teams, TeamsPlugin};
+    pub use crate::tick_negotiation::{RoomTick, TickNegotiation, TickNegotiationPlugin};
+    pub use crate::validation::{
+        InputRejected, InputValidationPlugin, InputValidator, ValidInput, ValidationConfig,
+    };
+}
+
+#[cfg(feature = "windowed")]
 use crate::actions::ActionsPlugin;
+#[cfg(feature = "audio")]
 use crate::audio::InternalAudioPlugin;
+use crate::authority::AuthorityPlugin;
+use crate::chat::ChatPlugin;
+#[cfg(feature = "inspector")]
+use crate::console::ConsolePlugin;
+use crate::dedicated::{DedicatedHost, DedicatedHostPlugin};
+#[cfg(feature = "inspector")]
+use crate::diagnostics_overlay::DiagnosticsOverlayPlugin;
+use crate::emote::EmotePlugin;
+#[cfg(feature = "windowed")]
+use crate::emote_bubbles::EmoteBubblePlugin;
+use crate::file_drop::FileDropPlugin;
+use crate::fixed::FixedMathPlugin;
+use crate::friends::FriendsPlugin;
+use crate::game_mode::{GameMode, GameModePlugin};
+use crate::headless::HeadlessPlugin;
+use crate::lag_compensation::LagCompensationPlugin;
+use crate::late_join::LateJoinPlugin;
+#[cfg(feature = "windowed")]
+use crate::link_indicator::LinkIndicatorPlugin;
+#[cfg(feature = "windowed")]
 use crate::loading::LoadingPlugin;
+#[cfg(feature = "windowed")]
+use crate::lobby::JoinApproval;
+use crate::lobby::LobbyPlugin;
+#[cfg(feature = "inspector")]
+use crate::log_panel::LogPanelPlugin;
+#[cfg(feature = "windowed")]
 use crate::menu::MenuPlugin;
-use crate::network::{GameAdminEvent, GameEvent, NetworkManager, NetworkPlugin};
+use crate::network::{NetworkDiagnosticsPlugin, NetworkPlugin};
+#[cfg(feature = "windowed")]
+use crate::notifications::NotificationPlugin;
 use crate::peer::PeerPlugin;
+#[cfg(feature = "windowed")]
 use crate::player::PlayerPlugin;
+use crate::rng::SessionRngPlugin;
+use crate::shutdown::ShutdownPlugin;
+use crate::smoothing::ErrorSmoothingPlugin;
+#[cfg(feature = "windowed")]
+use crate::spectator_camera::SpectatorCameraPlugin;
+use crate::teams::TeamsPlugin;
+#[cfg(feature = "windowed")]
+use crate::text_input::TextInputPlugin;
+use crate::tick_negotiation::TickNegotiationPlugin;
+#[cfg(feature = "inspector")]
+use crate::topology_panel::TopologyPanelPlugin;
 
-use async_std::task;
+use bevy::app::App;
 #[cfg(debug_assertions)]
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 use bevy::prelude::*;
-use bevy::{app::App, window::WindowCloseRequested};
+#[cfg(feature = "inspector")]
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
 // This example game uses States to separate logic
@@ -42,24 +200,103 @@ enum GameState {
 
     // Here the join menu is drawn
     JoinMenu,
+
+    // Here the settings screen is drawn
+    Settings,
+}
+
+/// How [`GamePlugin`] sets the game up. Insert it before adding the plugin; without
+/// it the game runs with everything the enabled features allow.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct GameOptions {
+    /// No window, menus or local player. Always set without the `windowed` feature.
+    pub headless: bool,
+    /// Has no effect without the `audio` feature.
+    pub audio: bool,
 }
 
+impl Default for GameOptions {
+    fn default() -> Self {
+        Self {
+            headless: !cfg!(feature = "windowed"),
+            audio: true,
+        }
+    }
+}
+
+/// The whole game, set up by [`GameOptions`]. Insert a [`DedicatedHost`] before adding
+/// it to run host-only: the room is hosted at startup and there is no menu or local
+/// player.
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_state::<GameState>()
-            .add_plugins((
+        let options = app
+            .world
+            .get_resource::<GameOptions>()
+            .cloned()
+            .unwrap_or_default();
+        app.add_state::<GameState>().add_plugins((
+            NetworkPlugin,
+            PeerPlugin,
+            SessionRngPlugin,
+            LobbyPlugin,
+            LateJoinPlugin,
+            AuthorityPlugin,
+            LagCompensationPlugin,
+            ErrorSmoothingPlugin,
+            FriendsPlugin,
+            FileDropPlugin,
+            TeamsPlugin,
+            ChatPlugin,
+            EmotePlugin,
+            FixedMathPlugin,
+            NetworkDiagnosticsPlugin,
+        ));
+        app.add_plugins((TickNegotiationPlugin, ShutdownPlugin));
+        // Games register their own modes the same way and select the one to host.
+        app.add_plugins(GameModePlugin::new(GameMode::new("Free for all")));
+        let host_only = app.world.contains_resource::<DedicatedHost>();
+        if host_only {
+            app.add_plugins(DedicatedHostPlugin);
+        }
+        if options.headless {
+            app.add_plugins(HeadlessPlugin);
+        }
+
+        #[cfg(feature = "windowed")]
+        if !options.headless {
+            app.add_plugins((
                 LoadingPlugin,
-                MenuPlugin,
+                TextInputPlugin,
                 ActionsPlugin,
-                InternalAudioPlugin,
-                PlayerPlugin,
-                NetworkPlugin,
-                PeerPlugin,
-            ))
-            .add_plugins(WorldInspectorPlugin::new())
-            .add_systems(Update, send_quit_on_close);
+                NotificationPlugin,
+                LinkIndicatorPlugin,
+                EmoteBubblePlugin,
+            ));
+            // Without a local player the window only shows the room, if anything.
+            if !host_only {
+                app.add_plugins((MenuPlugin, PlayerPlugin, SpectatorCameraPlugin))
+                    // The host menu lets the player accept or reject every join.
+                    .insert_resource(JoinApproval::Manual);
+            }
+        }
+
+        #[cfg(feature = "audio")]
+        if options.audio && !options.headless {
+            app.add_plugins(InternalAudioPlugin);
+        }
+
+        #[cfg(feature = "inspector")]
+        if !options.headless {
+            app.add_plugins((
+                WorldInspectorPlugin::new(),
+                LogPanelPlugin,
+                DiagnosticsOverlayPlugin,
+                TopologyPanelPlugin,
+                ConsolePlugin,
+            ));
+        }
 
         #[cfg(debug_assertions)]
         {
@@ -72,20 +309,3 @@ impl Plugin for GamePlugin {
         }
     }
 }
-
-fn send_quit_on_close(
-    mut commands: Commands,
-    mut manager: ResMut<NetworkManager<(), ()>>,
-    mut events: EventReader<WindowCloseRequested>,
-) {
-    for event in events.iter() {
-        log::info!("Window Closing");
-        task::block_on(
-            manager
-                .as_mut()
-                .send_to_network(GameEvent::Admin(GameAdminEvent::Quit)),
-        )
-        .expect("Send to open channel should succeed");
-        commands.entity(event.window).despawn_recursive();
-    }
-}
@@ -0,0 +1,214 @@
+//! A signal-strength icon above every [`RemotePlayer`], so players can tell who is
+//! lagging. Three bars, fewer and redder the more of our latest-wins messages the peer
+//! loses (see [`PeerStats`]) and the longer it has been since we last heard from it.
+//! Players whose game stopped responding are greyed out as well.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::network::NetworkEvent;
+use crate::peer::{PeerEntities, PeerStats, RemotePlayer};
+
+const BAR_WIDTH: f32 = 4.0;
+const BAR_GAP: f32 = 2.0;
+/// Height of the shortest bar; each next one is this much taller.
+const BAR_STEP: f32 = 4.0;
+/// How far above the player's origin the icon sits.
+const ICON_HEIGHT: f32 = 48.0;
+const UNLIT: Color = Color::rgba(1.0, 1.0, 1.0, 0.2);
+/// What an unresponsive player's sprite turns into.
+const UNRESPONSIVE: Color = Color::rgba(0.5, 0.5, 0.5, 0.6);
+
+pub struct LinkIndicatorPlugin;
+
+impl Plugin for LinkIndicatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                track_last_heard,
+                add_indicators,
+                update_indicators,
+                grey_out_unresponsive,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// How a peer's link looks from here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkQuality {
+    Good,
+    Fair,
+    Poor,
+    /// Nothing from the peer in a while; whatever we show of it is out of date.
+    Stale,
+}
+
+impl LinkQuality {
+    /// `loss` is the share of our messages the peer lost, `silence` the seconds since
+    /// its last update.
+    fn assess(loss: f32, silence: f32) -> Self {
+        if silence > 2.0 {
+            LinkQuality::Stale
+        } else if loss > 0.15 || silence > 0.5 {
+            LinkQuality::Poor
+        } else if loss > 0.03 || silence > 0.2 {
+            LinkQuality::Fair
+        } else {
+            LinkQuality::Good
+        }
+    }
+
+    fn bars(self) -> usize {
+        match self {
+            LinkQuality::Good => 3,
+            LinkQuality::Fair => 2,
+            LinkQuality::Poor | LinkQuality::Stale => 1,
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            LinkQuality::Good => Color::rgb(0.3, 0.9, 0.3),
+            LinkQuality::Fair => Color::rgb(0.95, 0.8, 0.2),
+            LinkQuality::Poor => Color::rgb(0.95, 0.3, 0.2),
+            LinkQuality::Stale => Color::GRAY,
+        }
+    }
+}
+
+/// When we last got a game message from each peer, in seconds since startup.
+#[derive(Debug, Default)]
+struct LastHeard(HashMap<PeerId, f64>);
+
+/// One bar of a player's icon; the lowest is 0.
+#[derive(Component)]
+struct SignalBar(usize);
+
+/// Marks a [`RemotePlayer`] that got its icon.
+#[derive(Component)]
+struct HasIndicator;
+
+/// An unresponsive player's own sprite color, to restore once it responds again.
+#[derive(Component)]
+struct GreyedOut(Color);
+
+/// Seconds since the player's peer last sent a game message.
+#[derive(Component, Default)]
+struct Silence(f32);
+
+fn track_last_heard(
+    time: Res<Time>,
+    mut network_events: EventReader<NetworkEvent<()>>,
+    mut last_heard: Local<LastHeard>,
+    mut players: Query<(&RemotePlayer, &mut Silence)>,
+) {
+    for event in network_events.iter() {
+        if let NetworkEvent::Game { source, .. } = event {
+            last_heard.0.insert(*source, time.elapsed_seconds_f64());
+        }
+    }
+    for (player, mut silence) in players.iter_mut() {
+        silence.0 = last_heard.0.get(&player.0).map_or(f32::INFINITY, |heard| {
+            (time.elapsed_seconds_f64() - heard) as f32
+        });
+    }
+}
+
+fn add_indicators(
+    mut commands: Commands,
+    players: Query<Entity, (With<RemotePlayer>, Without<HasIndicator>)>,
+) {
+    for player in players.iter() {
+        commands
+            .entity(player)
+            .insert((HasIndicator, Silence::default()))
+            .with_children(|parent| {
+                for bar in 0..3 {
+                    let height = BAR_STEP * (bar + 1) as f32;
+                    let x = (bar as f32 - 1.0) * (BAR_WIDTH + BAR_GAP);
+                    parent.spawn((
+                        SpriteBundle {
+                            sprite: Sprite {
+                                color: UNLIT,
+                                custom_size: Some(Vec2::new(BAR_WIDTH, height)),
+                                ..default()
+                            },
+                            transform: Transform::from_xyz(x, ICON_HEIGHT + height / 2.0, 10.0),
+                            ..default()
+                        },
+                        SignalBar(bar),
+                    ));
+                }
+            });
+    }
+}
+
+fn update_indicators(
+    peers: Res<PeerEntities>,
+    stats: Query<&PeerStats>,
+    players: Query<(&RemotePlayer, &Silence, &Children)>,
+    mut bars: Query<(&SignalBar, &mut Sprite)>,
+) {
+    for (player, silence, children) in players.iter() {
+        let loss = peers
+            .get(&player.0)
+            .and_then(|entity| stats.get(entity).ok())
+            .map_or(0.0, PeerStats::loss);
+        let quality = LinkQuality::assess(loss, silence.0);
+        for child in children.iter() {
+            if let Ok((bar, mut sprite)) = bars.get_mut(*child) {
+                let color = if bar.0 < quality.bars() {
+                    quality.color()
+                } else {
+                    UNLIT
+                };
+                if sprite.color != color {
+                    sprite.color = color;
+                }
+            }
+        }
+    }
+}
+
+fn grey_out_unresponsive(
+    mut commands: Commands,
+    peers: Res<PeerEntities>,
+    stats: Query<&PeerStats>,
+    mut players: Query<(Entity, &RemotePlayer, &mut Sprite, Option<&GreyedOut>)>,
+) {
+    for (entity, player, mut sprite, greyed_out) in players.iter_mut() {
+        let unresponsive = peers
+            .get(&player.0)
+            .and_then(|entity| stats.get(entity).ok())
+            .map_or(false, |stats| stats.unresponsive);
+        match (unresponsive, greyed_out) {
+            (true, None) => {
+                commands.entity(entity).insert(GreyedOut(sprite.color));
+                sprite.color = UNRESPONSIVE;
+            }
+            (false, Some(GreyedOut(color))) => {
+                sprite.color = *color;
+                commands.entity(entity).remove::<GreyedOut>();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silence_outweighs_a_clean_record() {
+        assert_eq!(LinkQuality::assess(0.0, 0.05), LinkQuality::Good);
+        assert_eq!(LinkQuality::assess(0.05, 0.05), LinkQuality::Fair);
+        assert_eq!(LinkQuality::assess(0.5, 0.05), LinkQuality::Poor);
+        assert_eq!(LinkQuality::assess(0.0, f32::INFINITY), LinkQuality::Stale);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::GameState;
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
+#[cfg(feature = "audio")]
 use bevy_kira_audio::AudioSource;
 
 pub struct LoadingPlugin;
@@ -14,8 +15,10 @@ impl Plugin for LoadingPlugin {
             LoadingState::new(GameState::Loading).continue_to_state(GameState::Menu),
         )
         .add_collection_to_loading_state::<_, FontAssets>(GameState::Loading)
-        .add_collection_to_loading_state::<_, AudioAssets>(GameState::Loading)
         .add_collection_to_loading_state::<_, TextureAssets>(GameState::Loading);
+
+        #[cfg(feature = "audio")]
+        app.add_collection_to_loading_state::<_, AudioAssets>(GameState::Loading);
     }
 }
 
@@ -28,10 +31,19 @@ pub struct FontAssets {
     pub fira_sans: Handle<Font>,
 }
 
+#[cfg(feature = "audio")]
 #[derive(AssetCollection, Resource)]
 pub struct AudioAssets {
     #[asset(path = "audio/flying.ogg")]
     pub flying: Handle<AudioSource>,
+    #[asset(path = "audio/join.wav")]
+    pub join: Handle<AudioSource>,
+    #[asset(path = "audio/leave.wav")]
+    pub leave: Handle<AudioSource>,
+    #[asset(path = "audio/chat.wav")]
+    pub chat: Handle<AudioSource>,
+    #[asset(path = "audio/disconnect.wav")]
+    pub disconnect: Handle<AudioSource>,
 }
 
 #[derive(AssetCollection, Resource)]
@@ -0,0 +1,316 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::network::{
+    AsyncNetworkTasks, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent, PeerProfile,
+};
+use crate::peer::{IsHost, Latency};
+
+/// Decides who gets into the room we host, tracks who is ready to play, and counts
+/// down to the start once the host starts the game.
+pub struct LobbyPlugin;
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerSettings>()
+            .init_resource::<JoinApproval>()
+            .init_resource::<JoinRequests>()
+            .init_resource::<Readiness>()
+            .init_resource::<StartCountdown>()
+            .add_event::<AnswerJoinRequest>()
+            .add_event::<SetReady>()
+            .add_event::<CountdownFinished>()
+            .add_systems(
+                Update,
+                (
+                    (
+                        collect_join_requests::<()>,
+                        answer_join_requests,
+                        track_readiness::<()>,
+                        send_ready,
+                    )
+                        .chain(),
+                    (send_spectating, send_profile).run_if(resource_changed::<PlayerSettings>()),
+                    (start_countdown::<()>, tick_countdown).chain(),
+                ),
+            );
+    }
+}
+
+/// Choices the player makes on the settings screen.
+#[derive(Resource, Debug, Clone)]
+pub struct PlayerSettings {
+    /// Shown to the host when we ask to join their room, and to the room in our signed
+    /// profile.
+    pub name: String,
+    /// Our avatar, by a name the game knows. `None` for its default one.
+    pub avatar: Option<String>,
+    /// Only watch: no local player, and none of our game messages reach the room.
+    pub spectator: bool,
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        Self {
+            name: "Player".to_string(),
+            avatar: None,
+            spectator: false,
+        }
+    }
+}
+
+/// A room code or invite token to join as soon as the game has loaded, skipping the
+/// main menu. Comes from `--join` or an invite link on the command line.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct LaunchJoin(pub String);
+
+/// Host a room as soon as the game has loaded, under the given room code or a fresh
+/// one. Comes from `--host` on the command line.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct LaunchHost(pub Option<String>);
+
+/// Whether the host lets everyone in or asks the player first.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinApproval {
+    #[default]
+    AutoAccept,
+    /// Requests wait in [`JoinRequests`] until answered with [`AnswerJoinRequest`].
+    Manual,
+}
+
+/// A peer waiting for the host to let it in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JoinRequest {
+    pub peer_id: PeerId,
+    pub name: String,
+}
+
+/// Join requests waiting for an answer, oldest first.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct JoinRequests(Vec<JoinRequest>);
+
+impl JoinRequests {
+    pub fn iter(&self) -> impl Iterator<Item = &JoinRequest> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Accept or reject one of the [`JoinRequests`]. Only accepted peers get the room key.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnswerJoinRequest {
+    pub peer_id: PeerId,
+    pub accept: bool,
+}
+
+fn collect_join_requests<ToGame>(
+    approval: Res<JoinApproval>,
+    mut requests: ResMut<JoinRequests>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+    mut answers: EventWriter<AnswerJoinRequest>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        match event {
+            NetworkEvent::Admin(NetworkAdminEvent::JoinRequested { peer_id, name }) => {
+                match *approval {
+                    JoinApproval::AutoAccept => answers.send(AnswerJoinRequest {
+                        peer_id: *peer_id,
+                        accept: true,
+                    }),
+                    JoinApproval::Manual => {
+                        requests.0.retain(|r| r.peer_id != *peer_id);
+                        requests.0.push(JoinRequest {
+                            peer_id: *peer_id,
+                            name: name.clone(),
+                        });
+                    }
+                }
+            }
+            NetworkEvent::Admin(NetworkAdminEvent::Disconnected(peer_id)) => {
+                requests.0.retain(|r| r.peer_id != *peer_id);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn answer_join_requests(
+    mut requests: ResMut<JoinRequests>,
+    mut readiness: ResMut<Readiness>,
+    mut answers: EventReader<AnswerJoinRequest>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+) {
+    for answer in answers.iter() {
+        requests.0.retain(|r| r.peer_id != answer.peer_id);
+        if answer.accept {
+            readiness.players.insert(answer.peer_id, false);
+        }
+        tasks.send(GameEvent::Admin(GameAdminEvent::AnswerJoin {
+            peer_id: answer.peer_id,
+            accept: answer.accept,
+        }));
+    }
+}
+
+/// Who in our room is ready for the game to start. The host counts everyone it let in;
+/// other peers only know about those that said whether they are ready.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Readiness {
+    players: HashMap<PeerId, bool>,
+    local: bool,
+}
+
+impl Readiness {
+    pub fn is_ready(&self, peer_id: &PeerId) -> bool {
+        self.players.get(peer_id).copied().unwrap_or(false)
+    }
+
+    /// Whether we said we are ready.
+    pub fn local(&self) -> bool {
+        self.local
+    }
+
+    pub fn players(&self) -> usize {
+        self.players.len()
+    }
+
+    /// Players that aren't ready yet.
+    pub fn waiting_for(&self) -> usize {
+        self.players.values().filter(|ready| !**ready).count()
+    }
+
+    /// Whether the game can start. The host can't start it until this holds.
+    pub fn all_ready(&self) -> bool {
+        self.waiting_for() == 0
+    }
+}
+
+/// Tell the room whether we are ready for the game to start.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetReady(pub bool);
+
+/// Time left until the game the host started begins, if it started.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct StartCountdown(Option<Timer>);
+
+impl StartCountdown {
+    pub fn remaining(&self) -> Option<Duration> {
+        self.0.as_ref().map(Timer::remaining)
+    }
+}
+
+/// The [`StartCountdown`] ran out: time to play. Every peer gets this at about the same
+/// moment.
+#[derive(Event, Debug, Clone, Copy, Default)]
+pub struct CountdownFinished;
+
+fn track_readiness<ToGame>(
+    mut readiness: ResMut<Readiness>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        match event {
+            // A new room, with nobody ready yet.
+            NetworkEvent::Admin(
+                NetworkAdminEvent::HostingStarted { .. } | NetworkAdminEvent::JoinAccepted { .. },
+            ) => *readiness = Readiness::default(),
+            NetworkEvent::Admin(NetworkAdminEvent::PeerReady { peer_id, ready }) => {
+                readiness.players.insert(*peer_id, *ready);
+            }
+            NetworkEvent::Admin(
+                NetworkAdminEvent::Disconnected(peer_id) | NetworkAdminEvent::PlayerLeft(peer_id),
+            ) => {
+                readiness.players.remove(peer_id);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn send_ready(
+    mut readiness: ResMut<Readiness>,
+    mut requests: EventReader<SetReady>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+) {
+    for SetReady(ready) in requests.iter() {
+        readiness.local = *ready;
+        tasks.send(GameEvent::Admin(GameAdminEvent::SetReady(*ready)));
+    }
+}
+
+/// Tells the network thread, and through it the room, whether we only watch.
+fn send_spectating(
+    settings: Res<PlayerSettings>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    mut sent: Local<Option<bool>>,
+) {
+    if *sent != Some(settings.spectator) {
+        *sent = Some(settings.spectator);
+        tasks.send(GameEvent::Admin(GameAdminEvent::SetSpectating(
+            settings.spectator,
+        )));
+    }
+}
+
+/// Signs our name and avatar into a profile for the room, again whenever they change.
+fn send_profile(
+    settings: Res<PlayerSettings>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    mut sent: Local<Option<PeerProfile>>,
+) {
+    let profile = PeerProfile {
+        name: settings.name.clone(),
+        avatar: settings.avatar.clone(),
+    };
+    if sent.as_ref() != Some(&profile) {
+        *sent = Some(profile.clone());
+        tasks.send(GameEvent::Admin(GameAdminEvent::SetProfile(profile)));
+    }
+}
+
+/// Starts the [`StartCountdown`]. The host's `StartGame` reaches us about half a round
+/// trip after the host sent it, so that much is taken off to finish together.
+fn start_countdown<ToGame>(
+    mut countdown: ResMut<StartCountdown>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+    host: Query<&Latency, With<IsHost>>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        if let NetworkEvent::Admin(NetworkAdminEvent::GameStarted {
+            countdown: duration,
+            ..
+        }) = event
+        {
+            let in_flight = host.get_single().map_or(Duration::ZERO, |rtt| rtt.0 / 2);
+            countdown.0 = Some(Timer::new(
+                duration.saturating_sub(in_flight),
+                TimerMode::Once,
+            ));
+        }
+    }
+}
+
+fn tick_countdown(
+    time: Res<Time>,
+    mut countdown: ResMut<StartCountdown>,
+    mut finished: EventWriter<CountdownFinished>,
+) {
+    let Some(timer) = countdown.0.as_mut() else {
+        return;
+    };
+    if timer.tick(time.delta()).finished() {
+        countdown.0 = None;
+        finished.send(CountdownFinished);
+    }
+}
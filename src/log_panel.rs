@@ -0,0 +1,82 @@
+//! An egui window for browsing the [`NetworkLog`].
+
+use bevy::prelude::*;
+use bevy_inspector_egui::{bevy_egui::EguiContexts, egui};
+
+use crate::network::{LogFilter, NetworkLog, Severity};
+
+pub struct LogPanelPlugin;
+
+impl Plugin for LogPanelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, show_log_panel);
+    }
+}
+
+/// Every target the network thread logs under.
+const TARGETS: [&str; 8] = [
+    "swarm", "relay", "dcutr", "kad", "gossip", "ping", "identify", "join",
+];
+
+fn show_log_panel(
+    mut contexts: EguiContexts,
+    network_log: Res<NetworkLog>,
+    mut filter: Local<LogFilter>,
+) {
+    egui::Window::new("Network log")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_label("Severity")
+                    .selected_text(filter.min_severity.to_string())
+                    .show_ui(ui, |ui| {
+                        for severity in Severity::ALL {
+                            ui.selectable_value(
+                                &mut filter.min_severity,
+                                severity,
+                                severity.to_string(),
+                            );
+                        }
+                    });
+                egui::ComboBox::from_label("Target")
+                    .selected_text(filter.target.unwrap_or("all"))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut filter.target, None, "all");
+                        for target in TARGETS {
+                            ui.selectable_value(&mut filter.target, Some(target), target);
+                        }
+                    });
+            });
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for entry in network_log.filtered(&filter) {
+                        let color = match entry.severity {
+                            Severity::Error => egui::Color32::RED,
+                            Severity::Warn => egui::Color32::YELLOW,
+                            Severity::Info => egui::Color32::LIGHT_GRAY,
+                            Severity::Debug | Severity::Trace => egui::Color32::GRAY,
+                        };
+                        let peer = entry
+                            .peer
+                            .map(|peer| {
+                                let peer = peer.to_base58();
+                                format!(" {}", &peer[peer.len().saturating_sub(6)..])
+                            })
+                            .unwrap_or_default();
+                        let at = entry
+                            .at
+                            .saturating_duration_since(network_log.started())
+                            .as_secs_f32();
+                        ui.colored_label(
+                            color,
+                            format!(
+                                "{:>8.2} {:>5} [{}]{} {}",
+                                at, entry.severity, entry.target, peer, entry.message
+                            ),
+                        );
+                    }
+                });
+        });
+}
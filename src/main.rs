@@ -1,53 +1,203 @@
 // disable console on windows for release builds
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+#[cfg(feature = "inspector")]
 use async_std::task;
 use bevy::{
+    diagnostic::DiagnosticsPlugin,
     log::{Level, LogPlugin},
     prelude::*,
-    window::PrimaryWindow,
-    winit::WinitWindows,
-    DefaultPlugins,
 };
-use bevy_libp2p::{network::setup_network, GamePlugin};
+#[cfg(feature = "windowed")]
+use bevy::{window::PrimaryWindow, winit::WinitWindows, DefaultPlugins};
+use bevy_libp2p::{
+    at_rest::AtRestEncryption,
+    dedicated::DedicatedHost,
+    identity,
+    lobby::{LaunchHost, LaunchJoin, PlayerSettings},
+    network::{NetworkConfig, NetworkFailurePolicy, NetworkSetup, RelaySelection},
+    paths::Paths,
+    room_code::parse_invite_uri,
+    GameOptions, GamePlugin,
+};
+#[cfg(feature = "inspector")]
+use bevy_libp2p::{
+    network::setup_network,
+    test_client::{TestClient, TestClientPlugin},
+};
+use clap::Parser;
+use libp2p::Multiaddr;
+#[cfg(feature = "windowed")]
 use std::io::Cursor;
+#[cfg(feature = "windowed")]
 use winit::window::Icon;
 
+const LOG_FILTER: &str = "wgpu=error,bevy_render=info,bevy_ecs=trace,naga=warn,naga_oil=warn,\
+    bevy_app=info,yamux=warn,multistream_select=warn,libp2p_kad=info,libp2p=info";
+
+/// Bevy libp2p demo. Every option can be used to script a session, e.g. from CI.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    /// Host a room at startup, under CODE if given. Without a window this hosts for
+    /// others without playing.
+    #[arg(long, value_name = "CODE", num_args = 0..=1)]
+    host: Option<Option<String>>,
+    /// Host for others without a local player, even with a window.
+    #[arg(long)]
+    host_only: bool,
+    /// Join the room with this code or invite token at startup.
+    #[arg(long, value_name = "CODE", conflicts_with_all = ["host", "host_only"])]
+    join: Option<String>,
+    /// An invite link like `bevy-p2p://join/K7P-XQ2M`, as passed when one is clicked.
+    #[arg(value_name = "INVITE", conflicts_with_all = ["host", "host_only", "join"])]
+    invite: Option<String>,
+    /// The name the host sees when we ask to join.
+    #[arg(long)]
+    name: Option<String>,
+    /// Watch the room without playing.
+    #[arg(long, conflicts_with_all = ["host", "host_only"])]
+    spectate: bool,
+    /// Relay to listen on while hosting, ending in `/p2p/<peer id>`.
+    #[arg(long, value_name = "ADDR")]
+    relay: Option<Multiaddr>,
+    #[arg(long)]
+    no_audio: bool,
+    /// Run without a window. Always the case without the `windowed` feature.
+    #[arg(long)]
+    headless: bool,
+    /// Exit if the network thread dies, e.g. so a supervisor restarts a dedicated host.
+    #[arg(long)]
+    exit_on_network_failure: bool,
+    /// Keep our peer id across runs, so after a crash we can take our place in the room
+    /// back. Don't use it for two copies of the game on one machine.
+    #[arg(long)]
+    persistent_identity: bool,
+    /// Also run a second player with its own identity in this process, which joins the
+    /// rooms we host. For testing both sides on one machine.
+    #[cfg(feature = "inspector")]
+    #[arg(long)]
+    test_client: bool,
+}
+
+impl Args {
+    /// Inserts the resources [`GamePlugin`] and the menus pick the options up from.
+    fn apply(&self, app: &mut App) -> anyhow::Result<()> {
+        let options = GameOptions {
+            headless: self.headless || GameOptions::default().headless,
+            audio: !self.no_audio,
+        };
+        if self.name.is_some() || self.spectate {
+            let defaults = PlayerSettings::default();
+            app.insert_resource(PlayerSettings {
+                name: self.name.clone().unwrap_or(defaults.name),
+                avatar: defaults.avatar,
+                spectator: self.spectate,
+            });
+        }
+        if self.host_only || (options.headless && self.host.is_some()) {
+            app.insert_resource(DedicatedHost {
+                room_code: self.host.clone().flatten(),
+                ..default()
+            });
+        } else if let Some(room_code) = &self.host {
+            app.insert_resource(LaunchHost(room_code.clone()));
+        }
+        let join = match &self.invite {
+            Some(invite) => Some(
+                parse_invite_uri(invite)
+                    .ok_or_else(|| anyhow::anyhow!("Not an invite link: {}", invite))?
+                    .to_string(),
+            ),
+            None => self.join.clone(),
+        };
+        if let Some(room_code) = join {
+            app.insert_resource(LaunchJoin(room_code));
+        }
+        if self.exit_on_network_failure {
+            app.insert_resource(NetworkFailurePolicy::ExitApp);
+        }
+        app.insert_resource(options);
+        Ok(())
+    }
+
+    fn network_config(&self) -> anyhow::Result<NetworkConfig> {
+        let mut config = NetworkConfig::default();
+        config.file_drop.directory = Paths::default().received;
+        if let Some(relay) = &self.relay {
+            config.relay = relay.clone();
+            config.relay_selection = RelaySelection::Manual;
+        }
+        if self.persistent_identity {
+            let path = Paths::default().data.join("identity");
+            config.identity = Some(identity::load_or_create(
+                &path,
+                &AtRestEncryption::default(),
+            )?);
+        }
+        Ok(config)
+    }
+}
+
+fn log_plugin() -> LogPlugin {
+    LogPlugin {
+        level: Level::INFO,
+        filter: LOG_FILTER.to_string(),
+    }
+}
+
 fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
     let mut app = App::new();
-    app.insert_resource(Msaa::Off)
-        .insert_resource(ClearColor(Color::rgb(0.4, 0.4, 0.4)))
-        .add_plugins(
-            DefaultPlugins
-                .set(WindowPlugin {
-                    primary_window: Some(Window {
-                        title: "Bevy libP2P demo".to_string(), // ToDo
-                        resolution: (800., 600.).into(),
-                        // Bind to canvas included in `index.html`
-                        canvas: Some("#bevy".to_owned()),
-                        // Tells wasm not to override default event handling, like F5 and Ctrl+R
-                        prevent_default_event_handling: false,
+    args.apply(&mut app)?;
+
+    #[cfg(feature = "windowed")]
+    if !app.world.resource::<GameOptions>().headless {
+        app.insert_resource(Msaa::Off)
+            .insert_resource(ClearColor(Color::rgb(0.4, 0.4, 0.4)))
+            .add_plugins(
+                DefaultPlugins
+                    .set(WindowPlugin {
+                        primary_window: Some(Window {
+                            title: "Bevy libP2P demo".to_string(), // ToDo
+                            resolution: (800., 600.).into(),
+                            // Bind to canvas included in `index.html`
+                            canvas: Some("#bevy".to_owned()),
+                            // Tells wasm not to override default event handling, like F5 and Ctrl+R
+                            prevent_default_event_handling: false,
+                            ..default()
+                        }),
+                        close_when_requested: false,
                         ..default()
-                    }),
-                    close_when_requested: false,
-                    ..default()
-                })
-                .set(LogPlugin {
-                    level: Level::INFO,
-                    filter: "wgpu=error,bevy_render=info,bevy_ecs=trace,naga=warn,naga_oil=warn,\
-                        bevy_app=info,yamux=warn,multistream_select=warn,libp2p_kad=info,libp2p=info".to_string(),
-                }),
-        )
-        .add_plugins(GamePlugin)
-        .add_systems(Startup, set_window_icon);
-    let network_manager = task::block_on(setup_network::<(), ()>())?;
-    app.insert_resource(network_manager);
+                    })
+                    .set(log_plugin()),
+            )
+            .add_systems(Startup, set_window_icon);
+    }
+
+    // Without a window we only need the scheduler, time and logging.
+    if app.world.resource::<GameOptions>().headless {
+        app.add_plugins((MinimalPlugins, DiagnosticsPlugin, log_plugin()));
+    }
+
+    app.add_plugins(GamePlugin);
+    app.insert_resource(NetworkSetup(args.network_config()?));
+    #[cfg(feature = "inspector")]
+    if args.test_client {
+        let config = NetworkConfig {
+            identity: None,
+            ..args.network_config()?
+        };
+        let client = task::block_on(setup_network::<TestClient, TestClient>(config))?;
+        app.add_plugins(TestClientPlugin).insert_resource(client);
+    }
     app.run();
 
     Ok(())
 }
 
 // Sets the icon on windows and X11
+#[cfg(feature = "windowed")]
 fn set_window_icon(
     windows: NonSend<WinitWindows>,
     primary_window: Query<Entity, With<PrimaryWindow>>,
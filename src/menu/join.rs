@@ -0,0 +1,449 @@
+use std::cmp::Ordering;
+
+use bevy::prelude::*;
+
+use super::{
+    spawn_button, spawn_countdown_text, ButtonColors, FileOfferList, LeaveRoomButton, MenuLink,
+};
+use crate::late_join::LateJoin;
+use crate::loading::FontAssets;
+use crate::lobby::{LaunchJoin, PlayerSettings, Readiness, SetReady};
+use crate::network::{
+    AsyncNetworkTasks, GameAdminEvent, GameEvent, JoinError, JoinStage, NetworkAdminEvent,
+    NetworkEvent,
+};
+use crate::room_code::{parse_invite_token, parse_room_code, InvalidRoomCode};
+use crate::text_input::{spawn_text_input, TextInput, TextInputSubmitted};
+use crate::GameState;
+
+pub(super) struct JoinMenuPlugin;
+
+impl Plugin for JoinMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameState::Menu),
+            skip_to_join_menu.run_if(resource_exists::<LaunchJoin>()),
+        )
+        .init_resource::<JoinProgress>()
+        .add_systems(OnEnter(GameState::JoinMenu), setup_join_menu)
+        .add_systems(
+            Update,
+            (
+                submit_launch_join.run_if(resource_exists::<LaunchJoin>()),
+                submit_join,
+                show_join_status,
+                track_join_progress,
+                show_join_progress,
+                click_cancel_join_button,
+                click_ready_button,
+            )
+                .chain()
+                .run_if(in_state(GameState::JoinMenu)),
+        )
+        .add_systems(OnExit(GameState::JoinMenu), cleanup_join_menu);
+    }
+}
+
+#[derive(Component)]
+struct JoinMenu;
+
+#[derive(Component)]
+struct RoomCodeInput;
+
+#[derive(Component)]
+struct JoinRoomButton;
+
+/// Toggles whether we are ready for the host to start the game.
+#[derive(Component)]
+struct ReadyButton;
+
+/// Tells the player how their join is going.
+#[derive(Component)]
+struct JoinStatus;
+
+/// Lists the [`JoinStage`]s, the one we are in highlighted.
+#[derive(Component)]
+struct JoinStages;
+
+/// Stops the join in progress.
+#[derive(Component)]
+struct CancelJoinButton;
+
+/// The stage of the join in progress, and when it began in [`Time::elapsed_seconds`].
+#[derive(Resource, Debug, Default)]
+struct JoinProgress(Option<(JoinStage, f32)>);
+
+const STAGE_DONE_COLOR: Color = Color::rgb(0.5, 0.8, 0.5);
+const STAGE_CURRENT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const STAGE_AHEAD_COLOR: Color = Color::rgb(0.4, 0.4, 0.4);
+const STAGE_SEPARATOR: &str = "  >  ";
+
+fn skip_to_join_menu(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::JoinMenu);
+}
+
+fn setup_join_menu(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    button_colors: Res<ButtonColors>,
+    launch_join: Option<Res<LaunchJoin>>,
+) {
+    let text_style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: 40.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(20.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            JoinMenu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Room Code or Invite",
+                text_style.clone(),
+            ));
+            spawn_text_input(
+                parent,
+                // Long enough for an invite token with its dashes.
+                TextInput::new(29)
+                    .with_filter(|c| c.is_ascii_alphanumeric() || c == '-')
+                    .with_placeholder("XXX-XXXX")
+                    .with_value(launch_join.map_or(String::new(), |join| join.0.clone())),
+                text_style.clone(),
+                button_colors.normal,
+                RoomCodeInput,
+            );
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        column_gap: Val::Px(20.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|row| {
+                    spawn_button(
+                        row,
+                        "Join",
+                        font_assets.fira_sans.clone(),
+                        &button_colors,
+                        JoinRoomButton,
+                    );
+                    spawn_button(
+                        row,
+                        "Cancel",
+                        font_assets.fira_sans.clone(),
+                        &button_colors,
+                        CancelJoinButton,
+                    );
+                    spawn_button(
+                        row,
+                        "Ready",
+                        font_assets.fira_sans.clone(),
+                        &button_colors,
+                        ReadyButton,
+                    );
+                    spawn_button(
+                        row,
+                        "Back",
+                        font_assets.fira_sans.clone(),
+                        &button_colors,
+                        (MenuLink(GameState::Menu), LeaveRoomButton),
+                    );
+                });
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 24.0,
+                        ..text_style.clone()
+                    },
+                ),
+                JoinStatus,
+            ));
+            parent.spawn((
+                TextBundle::from_sections(JoinStage::ALL.map(|_| {
+                    TextSection::new(
+                        "",
+                        TextStyle {
+                            font_size: 20.0,
+                            ..text_style.clone()
+                        },
+                    )
+                }))
+                .with_style(Style {
+                    display: Display::None,
+                    ..Default::default()
+                }),
+                JoinStages,
+            ));
+            spawn_countdown_text(parent, font_assets.fira_sans.clone());
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                FileOfferList,
+            ));
+        });
+}
+
+/// Joins the [`LaunchJoin`] code as if it had been typed in, once.
+fn submit_launch_join(
+    mut commands: Commands,
+    code_input: Query<Entity, With<RoomCodeInput>>,
+    mut submitted: EventWriter<TextInputSubmitted>,
+) {
+    if let Ok(entity) = code_input.get_single() {
+        submitted.send(TextInputSubmitted { entity });
+        commands.remove_resource::<LaunchJoin>();
+    }
+}
+
+/// Sends the join once the Join button is pressed or Enter is hit in the code field.
+fn submit_join(
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    settings: Res<PlayerSettings>,
+    mut submitted: EventReader<TextInputSubmitted>,
+    buttons: Query<&Interaction, (Changed<Interaction>, With<JoinRoomButton>)>,
+    code_input: Query<(Entity, &TextInput), With<RoomCodeInput>>,
+    mut status: Query<&mut Text, With<JoinStatus>>,
+) {
+    let Ok((input_entity, input)) = code_input.get_single() else {
+        return;
+    };
+    let entered = submitted.iter().any(|event| event.entity == input_entity);
+    let pressed = buttons
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed);
+    if !entered && !pressed {
+        return;
+    }
+    if input.value.is_empty() {
+        return;
+    }
+    let Ok(mut status) = status.get_single_mut() else {
+        return;
+    };
+    // Catch typos here rather than after a DHT lookup that can never succeed.
+    let (room_code, looking_for) = match parse_room_code(&input.value) {
+        Ok(room_code) => (room_code.clone(), format!("room {}", room_code)),
+        Err(e) => match parse_invite_token(&input.value) {
+            Ok(invite) => (invite.to_string(), "the private match".to_string()),
+            Err(invite_error) => {
+                // Past the room code's length, the invite's complaint is the useful one.
+                let e = if e == InvalidRoomCode::Length {
+                    invite_error
+                } else {
+                    e
+                };
+                status.sections[0].value = format!("Invalid code: {}", e);
+                return;
+            }
+        },
+    };
+    status.sections[0].value = format!("Looking for {}...", looking_for);
+    tasks.send(GameEvent::Admin(GameAdminEvent::Join {
+        room_code,
+        name: settings.name.clone(),
+    }));
+}
+
+/// What the player can do about a failed join.
+fn join_failed_message(error: &JoinError) -> String {
+    match error {
+        JoinError::RoomNotFound => "No room with that code. Check it with the host".to_string(),
+        JoinError::RoomFull => "The room is full. Ask the host for a spot".to_string(),
+        JoinError::VersionMismatch { host } => format!(
+            "The host runs version {}, update the game so you both run the same",
+            host
+        ),
+        JoinError::WrongPassword => {
+            "That invite is for another match, ask for a new one".to_string()
+        }
+        JoinError::Timeout => "Nobody answered in time. Try again".to_string(),
+        JoinError::HostRejected { reason } => format!("Rejected: {}", reason),
+        JoinError::TransportFailure { reason } => {
+            format!(
+                "Could not reach the host ({}). Check your connection",
+                reason
+            )
+        }
+        JoinError::Throttled { retry_in } => {
+            format!("Slow down! Try again in {}s", retry_in.as_secs() + 1)
+        }
+        JoinError::UnknownMode { mode } => {
+            format!("The room plays {}, which this game doesn't have", mode)
+        }
+    }
+}
+
+fn show_join_status(
+    mut network_events: EventReader<NetworkEvent<()>>,
+    mut status: Query<&mut Text, With<JoinStatus>>,
+) {
+    for event in network_events.iter() {
+        let message = match event {
+            NetworkEvent::Admin(NetworkAdminEvent::JoinAccepted { .. }) => {
+                "Joined! Waiting for the host to start".to_string()
+            }
+            NetworkEvent::Admin(NetworkAdminEvent::JoinFailed(error)) => join_failed_message(error),
+            NetworkEvent::Admin(NetworkAdminEvent::DhtBusy { queued }) => {
+                format!("Network busy, {} lookups ahead of yours", queued - 1)
+            }
+            NetworkEvent::Admin(NetworkAdminEvent::RoomInfoFound { info, .. }) => {
+                let players = match info.max_players {
+                    Some(max) => format!("{}/{}", info.players, max),
+                    None => info.players.to_string(),
+                };
+                format!("Found a {} game with {} players", info.mode, players)
+            }
+            _ => continue,
+        };
+        if let Ok(mut status) = status.get_single_mut() {
+            status.sections[0].value = message;
+        }
+    }
+}
+
+fn track_join_progress(
+    time: Res<Time>,
+    late_join: Res<LateJoin>,
+    mut progress: ResMut<JoinProgress>,
+    mut network_events: EventReader<NetworkEvent<()>>,
+) {
+    for event in network_events.iter() {
+        match event {
+            NetworkEvent::Admin(NetworkAdminEvent::JoinProgress(stage)) => {
+                progress.0 = Some((*stage, time.elapsed_seconds()));
+            }
+            NetworkEvent::Admin(NetworkAdminEvent::JoinFailed(_) | NetworkAdminEvent::LeftRoom) => {
+                progress.0 = None;
+            }
+            _ => {}
+        }
+    }
+    // Syncing ends on our side, once the host's snapshot is in or didn't come.
+    let syncing = matches!(progress.0, Some((JoinStage::SyncingState, _)));
+    if syncing
+        && matches!(
+            *late_join,
+            LateJoin::Synced { .. } | LateJoin::Failed { .. }
+        )
+    {
+        progress.0 = None;
+    }
+}
+
+/// Highlights the stage the join is in, with how long it's been there, and offers to
+/// cancel it.
+fn show_join_progress(
+    time: Res<Time>,
+    progress: Res<JoinProgress>,
+    mut stages: Query<(&mut Text, &mut Style), With<JoinStages>>,
+    mut cancel: Query<&mut Style, (With<CancelJoinButton>, Without<JoinStages>)>,
+) {
+    let display = if progress.0.is_some() {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    if let Ok(mut style) = cancel.get_single_mut() {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+    let Ok((mut text, mut style)) = stages.get_single_mut() else {
+        return;
+    };
+    if style.display != display {
+        style.display = display;
+    }
+    let Some((current, since)) = progress.0 else {
+        return;
+    };
+    let elapsed = (time.elapsed_seconds() - since) as u32;
+    for (index, (section, stage)) in text.sections.iter_mut().zip(JoinStage::ALL).enumerate() {
+        let separator = if index == 0 { "" } else { STAGE_SEPARATOR };
+        let (label, color) = match stage.cmp(&current) {
+            Ordering::Less => (stage.text().to_string(), STAGE_DONE_COLOR),
+            Ordering::Equal => (
+                format!("{} ({}s)", stage.text(), elapsed),
+                STAGE_CURRENT_COLOR,
+            ),
+            Ordering::Greater => (stage.text().to_string(), STAGE_AHEAD_COLOR),
+        };
+        let value = format!("{}{}", separator, label);
+        if section.value != value {
+            section.value = value;
+        }
+        section.style.color = color;
+    }
+}
+
+/// Stops the join wherever it is, the same way leaving a room does.
+fn click_cancel_join_button(
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    mut progress: ResMut<JoinProgress>,
+    buttons: Query<&Interaction, (Changed<Interaction>, With<CancelJoinButton>)>,
+    mut status: Query<&mut Text, With<JoinStatus>>,
+) {
+    if !buttons
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed)
+    {
+        return;
+    }
+    tasks.send(GameEvent::Admin(GameAdminEvent::Leave));
+    progress.0 = None;
+    if let Ok(mut status) = status.get_single_mut() {
+        status.sections[0].value = "Join cancelled".to_string();
+    }
+}
+
+fn click_ready_button(
+    readiness: Res<Readiness>,
+    mut set_ready: EventWriter<SetReady>,
+    buttons: Query<(&Interaction, &Children), (Changed<Interaction>, With<ReadyButton>)>,
+    mut labels: Query<&mut Text>,
+) {
+    for (interaction, children) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let ready = !readiness.local();
+        set_ready.send(SetReady(ready));
+        if let Some(mut label) = children.first().and_then(|c| labels.get_mut(*c).ok()) {
+            label.sections[0].value = if ready { "Not ready" } else { "Ready" }.to_string();
+        }
+    }
+}
+
+fn cleanup_join_menu(
+    mut commands: Commands,
+    mut progress: ResMut<JoinProgress>,
+    menus: Query<Entity, With<JoinMenu>>,
+) {
+    // Going back leaves the room, so whatever the join was up to is over.
+    progress.0 = None;
+    for menu in &menus {
+        commands.entity(menu).despawn_recursive();
+    }
+}
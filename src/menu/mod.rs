@@ -0,0 +1,1103 @@
+use crate::file_drop::{AnswerFileOffer, FileOffers};
+use crate::friends::{Friends, InviteFriend};
+use crate::game_mode::GameModes;
+use crate::loading::FontAssets;
+use crate::lobby::{
+    AnswerJoinRequest, CountdownFinished, JoinRequests, LaunchHost, Readiness, StartCountdown,
+};
+use crate::network::{
+    AsyncNetworkTasks, CommandId, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent,
+};
+use crate::rng::RequestStartGame;
+use crate::room_code::{generate_room_code, parse_room_code, InviteToken};
+use crate::GameState;
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+mod join;
+mod settings;
+
+pub struct MenuPlugin;
+
+/// This plugin is responsible for the game menu (containing only one button...)
+/// The menu is only drawn during the State `GameState::Menu` and is removed when that state is exited
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ButtonColors>()
+            .init_resource::<PrivateMatch>()
+            .add_plugins((join::JoinMenuPlugin, settings::SettingsMenuPlugin))
+            .add_systems(OnEnter(GameState::Menu), setup_menu)
+            .add_systems(
+                OnEnter(GameState::Menu),
+                skip_to_host_menu.run_if(resource_exists::<LaunchHost>()),
+            )
+            .add_systems(OnEnter(GameState::HostMenu), setup_host_menu)
+            .add_systems(
+                Update,
+                (hover_button, follow_menu_links).run_if(
+                    in_state(GameState::Menu)
+                        .or_else(in_state(GameState::HostMenu))
+                        .or_else(in_state(GameState::JoinMenu))
+                        .or_else(in_state(GameState::Settings)),
+                ),
+            )
+            .add_systems(Update, click_host_button.run_if(in_state(GameState::Menu)))
+            .add_systems(
+                Update,
+                (
+                    show_join_requests,
+                    click_join_request_button,
+                    // A taken code is replaced before its failure could offer a retry.
+                    (replace_taken_room_code, show_hosting_status).chain(),
+                    click_retry_host_button,
+                    show_friends,
+                    click_invite_friend_button,
+                    click_befriend_button,
+                    click_start_game_button,
+                    show_ready_count,
+                )
+                    .run_if(in_state(GameState::HostMenu)),
+            )
+            .add_systems(OnExit(GameState::Menu), cleanup_menu)
+            .add_systems(
+                Update,
+                (
+                    click_leave_room_button,
+                    show_countdown,
+                    enter_playing,
+                    show_file_offers,
+                    click_file_offer_button,
+                )
+                    .run_if(in_state(GameState::HostMenu).or_else(in_state(GameState::JoinMenu))),
+            )
+            .add_systems(OnExit(GameState::HostMenu), cleanup_host_menu);
+    }
+}
+
+#[derive(Resource)]
+pub(super) struct ButtonColors {
+    normal: Color,
+    hovered: Color,
+}
+
+impl Default for ButtonColors {
+    fn default() -> Self {
+        ButtonColors {
+            normal: Color::rgb(0.15, 0.15, 0.15),
+            hovered: Color::rgb(0.25, 0.25, 0.25),
+        }
+    }
+}
+
+#[derive(Component)]
+struct Menu;
+
+#[derive(Component)]
+struct HostMenu;
+
+#[derive(Component)]
+struct HostButton;
+
+/// Hosts a private match behind an [`InviteToken`] instead of a room code.
+#[derive(Component)]
+struct PrivateHostButton;
+
+/// Whether the host menu should start a private match.
+#[derive(Resource, Default)]
+struct PrivateMatch(bool);
+
+#[derive(Component)]
+struct JoinButton;
+
+#[derive(Component)]
+struct SettingsButton;
+
+/// Leaves the current room when pressed, telling its peers.
+#[derive(Component)]
+pub(super) struct LeaveRoomButton;
+
+/// Switches to the given state when the button it's on is pressed.
+#[derive(Component)]
+pub(super) struct MenuLink(pub GameState);
+
+/// The code of the room we host, kept around to retry hosting with it.
+#[derive(Resource)]
+struct HostedRoom(String);
+
+/// Shows the code or invite of the room we host.
+#[derive(Component)]
+struct RoomCodeText;
+
+/// The [`GameAdminEvent::Host`] command for the room we host, answered once hosting
+/// is up or failed.
+#[derive(Resource)]
+struct HostCommand(CommandId);
+
+/// Says whether hosting is up, or why it failed.
+#[derive(Component)]
+struct HostingStatus;
+
+const STATUS_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const FAILURE_COLOR: Color = Color::rgb(0.9, 0.4, 0.4);
+
+/// Only shown after hosting failed.
+#[derive(Component)]
+struct RetryHostButton;
+
+/// Container the pending join requests are listed in.
+/// Starts the game once every player is ready.
+#[derive(Component)]
+struct StartGameButton;
+
+/// How many players are ready.
+#[derive(Component)]
+struct ReadyCountText;
+
+/// Seconds left until the game starts, in the host and join menus.
+#[derive(Component)]
+pub(super) struct CountdownText;
+
+#[derive(Component)]
+struct JoinRequestList;
+
+#[derive(Component)]
+struct JoinRequestButton {
+    peer_id: PeerId,
+    accept: bool,
+}
+
+/// Adds whoever asked to join to our [`Friends`].
+#[derive(Component)]
+struct BefriendButton {
+    peer_id: PeerId,
+    name: String,
+}
+
+/// Container our friends are listed in, each with an invite button.
+#[derive(Component)]
+struct FriendList;
+
+#[derive(Component)]
+struct InviteFriendButton(PeerId);
+
+/// Container files offered to us are listed in, in the host and join menus.
+#[derive(Component)]
+pub(super) struct FileOfferList;
+
+#[derive(Component)]
+struct FileOfferButton {
+    peer_id: PeerId,
+    transfer: u64,
+    accept: bool,
+}
+
+fn setup_menu(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    button_colors: Res<ButtonColors>,
+    cameras: Query<(), With<Camera>>,
+) {
+    // We come back here from the other menus, which share the same camera.
+    if cameras.is_empty() {
+        commands.spawn(Camera2dBundle::default());
+    }
+    commands
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(120.0),
+                    height: Val::Px(50.0),
+                    margin: UiRect::all(Val::Auto),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: button_colors.normal.into(),
+                ..Default::default()
+            },
+            HostButton,
+            Menu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Host",
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ));
+        });
+    commands
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(160.0),
+                    height: Val::Px(50.0),
+                    margin: UiRect::all(Val::Auto),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: button_colors.normal.into(),
+                ..Default::default()
+            },
+            PrivateHostButton,
+            Menu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Private",
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ));
+        });
+    commands
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(120.0),
+                    height: Val::Px(50.0),
+                    margin: UiRect::all(Val::Auto),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: button_colors.normal.into(),
+                ..Default::default()
+            },
+            JoinButton,
+            MenuLink(GameState::JoinMenu),
+            Menu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Join",
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ));
+        });
+    commands
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(160.0),
+                    height: Val::Px(50.0),
+                    margin: UiRect::all(Val::Auto),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: button_colors.normal.into(),
+                ..Default::default()
+            },
+            SettingsButton,
+            MenuLink(GameState::Settings),
+            Menu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Settings",
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ));
+        });
+}
+
+/// Spawns a text button as a child of `parent`.
+pub(super) fn spawn_button(
+    parent: &mut ChildBuilder,
+    label: &str,
+    font: Handle<Font>,
+    button_colors: &ButtonColors,
+    marker: impl Bundle,
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(120.0),
+                    height: Val::Px(50.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: button_colors.normal.into(),
+                ..Default::default()
+            },
+            marker,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font,
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ));
+        });
+}
+
+fn follow_menu_links(
+    mut state: ResMut<NextState<GameState>>,
+    interaction_query: Query<(&Interaction, &MenuLink), Changed<Interaction>>,
+) {
+    for (interaction, link) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            state.set(link.0.clone());
+        }
+    }
+}
+
+fn click_host_button(
+    mut state: ResMut<NextState<GameState>>,
+    mut private_match: ResMut<PrivateMatch>,
+    mut interaction_query: Query<
+        (&Interaction, Option<&PrivateHostButton>),
+        (
+            Changed<Interaction>,
+            Or<(With<HostButton>, With<PrivateHostButton>)>,
+        ),
+    >,
+) {
+    for (interaction, private) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                private_match.0 = private.is_some();
+                state.set(GameState::HostMenu);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn hover_button(
+    button_colors: Res<ButtonColors>,
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<Button>),
+    >,
+) {
+    for (interaction, mut color) in &mut interaction_query {
+        match *interaction {
+            Interaction::Hovered => {
+                *color = button_colors.hovered.into();
+            }
+            Interaction::None => {
+                *color = button_colors.normal.into();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn cleanup_menu(mut commands: Commands, buttons: Query<Entity, (With<Button>, With<Menu>)>) {
+    for button in &buttons {
+        commands.entity(button).despawn_recursive();
+    }
+}
+
+fn skip_to_host_menu(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::HostMenu);
+}
+
+fn setup_host_menu(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    button_colors: Res<ButtonColors>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    private_match: Res<PrivateMatch>,
+    launch_host: Option<Res<LaunchHost>>,
+    modes: Res<GameModes>,
+) {
+    // A code from the command line is only used for the first room.
+    let launch_code = launch_host.and_then(|launch| launch.0.clone());
+    commands.remove_resource::<LaunchHost>();
+    // TODO: Add textbox for setting options eventually.
+    let (room_code, room_code_text, font_size) = if private_match.0 {
+        let invite = InviteToken::generate().to_string();
+        let text = format!("Invite: {}", invite);
+        (invite, text, 24.0)
+    } else {
+        let room_code = launch_code
+            .and_then(|code| match parse_room_code(&code) {
+                Ok(code) => Some(code),
+                Err(e) => {
+                    log::warn!("Not hosting {:?}, a fresh code instead: {}", code, e);
+                    None
+                }
+            })
+            .unwrap_or_else(generate_room_code);
+        let text = format!("Room Code: {}", room_code);
+        (room_code, text, 40.0)
+    };
+    commands.insert_resource(HostedRoom(room_code.clone()));
+    // Described first, so the room info published once hosting starts is complete.
+    tasks.send(GameEvent::Admin(modes.describe_room()));
+    let command = tasks.command(GameAdminEvent::Host { room_code });
+    commands.insert_resource(HostCommand(command));
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Px(150.0),
+                    height: Val::Px(50.0),
+                    margin: UiRect::all(Val::Auto),
+                    justify_content: JustifyContent::FlexStart,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            HostMenu,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    &room_code_text,
+                    TextStyle {
+                        font: font_assets.fira_sans.clone(),
+                        font_size,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    },
+                ),
+                RoomCodeText,
+            ));
+        });
+    // For now we'll just show the room code, and start hosting.
+
+    commands
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(120.0),
+                    height: Val::Px(50.0),
+                    margin: UiRect::all(Val::Auto),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: button_colors.normal.into(),
+                ..Default::default()
+            },
+            HostButton,
+            HostMenu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Host",
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ));
+        });
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                margin: UiRect::all(Val::Auto),
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        JoinRequestList,
+        HostMenu,
+    ));
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                margin: UiRect::all(Val::Auto),
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        FriendList,
+        HostMenu,
+    ));
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                margin: UiRect::all(Val::Auto),
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        FileOfferList,
+        HostMenu,
+    ));
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    margin: UiRect::all(Val::Auto),
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            HostMenu,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Starting...",
+                    TextStyle {
+                        font: font_assets.fira_sans.clone(),
+                        font_size: 24.0,
+                        color: STATUS_COLOR,
+                    },
+                ),
+                HostingStatus,
+            ));
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: font_assets.fira_sans.clone(),
+                        font_size: 24.0,
+                        color: STATUS_COLOR,
+                    },
+                ),
+                ReadyCountText,
+            ));
+            spawn_countdown_text(parent, font_assets.fira_sans.clone());
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(120.0),
+                            height: Val::Px(50.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            display: Display::None,
+                            ..Default::default()
+                        },
+                        background_color: button_colors.normal.into(),
+                        ..Default::default()
+                    },
+                    RetryHostButton,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Retry",
+                        TextStyle {
+                            font: font_assets.fira_sans.clone(),
+                            font_size: 40.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                    ));
+                });
+            spawn_button(
+                parent,
+                "Start",
+                font_assets.fira_sans.clone(),
+                &button_colors,
+                StartGameButton,
+            );
+            spawn_button(
+                parent,
+                "Back",
+                font_assets.fira_sans.clone(),
+                &button_colors,
+                (MenuLink(GameState::Menu), LeaveRoomButton),
+            );
+        });
+}
+
+pub(super) fn spawn_countdown_text(parent: &mut ChildBuilder, font: Handle<Font>) {
+    parent.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font,
+                font_size: 40.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+            },
+        ),
+        CountdownText,
+    ));
+}
+
+fn cleanup_host_menu(mut commands: Commands, menus: Query<Entity, With<HostMenu>>) {
+    for menu in &menus {
+        commands.entity(menu).despawn_recursive();
+    }
+    commands.remove_resource::<HostedRoom>();
+    commands.remove_resource::<HostCommand>();
+}
+
+/// Leaves the room we host or joined when a [`LeaveRoomButton`] is pressed.
+fn click_leave_room_button(
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<LeaveRoomButton>)>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            tasks.send(GameEvent::Admin(GameAdminEvent::Leave));
+        }
+    }
+}
+
+/// Follows the host command until hosting is up, and offers a retry if it fails then
+/// or later.
+fn show_hosting_status(
+    mut network_events: EventReader<NetworkEvent<()>>,
+    command: Res<HostCommand>,
+    mut status: Query<&mut Text, With<HostingStatus>>,
+    mut retry: Query<&mut Style, With<RetryHostButton>>,
+) {
+    for event in network_events.iter() {
+        let reason = match event {
+            NetworkEvent::Admin(NetworkAdminEvent::CommandCompleted(id)) if *id == command.0 => {
+                if let Ok(mut status) = status.get_single_mut() {
+                    status.sections[0].value = "Waiting for players".to_string();
+                    status.sections[0].style.color = STATUS_COLOR;
+                }
+                continue;
+            }
+            NetworkEvent::Admin(NetworkAdminEvent::CommandFailed { id, reason })
+                if *id == command.0 =>
+            {
+                reason
+            }
+            NetworkEvent::Admin(NetworkAdminEvent::HostingFailed { reason }) => reason,
+            _ => continue,
+        };
+        if let Ok(mut status) = status.get_single_mut() {
+            status.sections[0].value = format!("Hosting failed: {}", reason);
+            status.sections[0].style.color = FAILURE_COLOR;
+        }
+        if let Ok(mut style) = retry.get_single_mut() {
+            style.display = Display::Flex;
+        }
+    }
+}
+
+/// Someone else already hosts the code we picked, so host under a new one.
+fn replace_taken_room_code(
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    mut network_events: EventReader<NetworkEvent<()>>,
+    mut room: ResMut<HostedRoom>,
+    mut command: ResMut<HostCommand>,
+    mut text: Query<&mut Text, With<RoomCodeText>>,
+) {
+    for event in network_events.iter() {
+        if !matches!(
+            event,
+            NetworkEvent::Admin(NetworkAdminEvent::RoomCodeTaken { .. })
+        ) {
+            continue;
+        }
+        let room_code = generate_room_code();
+        log::info!(
+            "Room code {} is taken, hosting {} instead",
+            room.0,
+            room_code
+        );
+        if let Ok(mut text) = text.get_single_mut() {
+            text.sections[0].value = format!("Room Code: {}", room_code);
+        }
+        room.0 = room_code.clone();
+        command.0 = tasks.command(GameAdminEvent::Host { room_code });
+    }
+}
+
+fn click_retry_host_button(
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    room: Res<HostedRoom>,
+    mut command: ResMut<HostCommand>,
+    mut interaction_query: Query<
+        (&Interaction, &mut Style),
+        (Changed<Interaction>, With<RetryHostButton>),
+    >,
+    mut status: Query<&mut Text, With<HostingStatus>>,
+) {
+    for (interaction, mut style) in &mut interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        style.display = Display::None;
+        if let Ok(mut status) = status.get_single_mut() {
+            status.sections[0].value = "Starting...".to_string();
+            status.sections[0].style.color = STATUS_COLOR;
+        }
+        command.0 = tasks.command(GameAdminEvent::Host {
+            room_code: room.0.clone(),
+        });
+    }
+}
+
+fn show_join_requests(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    button_colors: Res<ButtonColors>,
+    requests: Res<JoinRequests>,
+    list: Query<Entity, With<JoinRequestList>>,
+) {
+    if !requests.is_changed() {
+        return;
+    }
+    let Ok(list) = list.get_single() else {
+        return;
+    };
+    let text_style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: 24.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .entity(list)
+        .despawn_descendants()
+        .with_children(|parent| {
+            for request in requests.iter() {
+                let peer = request.peer_id.to_base58();
+                let label = format!(
+                    "{} ({}) wants to join",
+                    request.name,
+                    &peer[peer.len().saturating_sub(6)..]
+                );
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(10.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .with_children(|row| {
+                        row.spawn(TextBundle::from_section(label, text_style.clone()));
+                        for (text, accept) in [("Accept", true), ("Reject", false)] {
+                            row.spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        width: Val::Px(90.0),
+                                        height: Val::Px(32.0),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..Default::default()
+                                    },
+                                    background_color: button_colors.normal.into(),
+                                    ..Default::default()
+                                },
+                                JoinRequestButton {
+                                    peer_id: request.peer_id,
+                                    accept,
+                                },
+                            ))
+                            .with_children(|button| {
+                                button.spawn(TextBundle::from_section(text, text_style.clone()));
+                            });
+                        }
+                        row.spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    width: Val::Px(90.0),
+                                    height: Val::Px(32.0),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..Default::default()
+                                },
+                                background_color: button_colors.normal.into(),
+                                ..Default::default()
+                            },
+                            BefriendButton {
+                                peer_id: request.peer_id,
+                                name: request.name.clone(),
+                            },
+                        ))
+                        .with_children(|button| {
+                            button.spawn(TextBundle::from_section("Friend", text_style.clone()));
+                        });
+                    });
+            }
+        });
+}
+
+fn click_join_request_button(
+    mut answers: EventWriter<AnswerJoinRequest>,
+    interaction_query: Query<(&Interaction, &JoinRequestButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            answers.send(AnswerJoinRequest {
+                peer_id: button.peer_id,
+                accept: button.accept,
+            });
+        }
+    }
+}
+
+fn click_befriend_button(
+    mut friends: ResMut<Friends>,
+    interaction_query: Query<(&Interaction, &BefriendButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            friends.add(button.peer_id, button.name.clone());
+        }
+    }
+}
+
+/// Lists the files offered to us, each with accept and decline buttons.
+fn show_file_offers(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    button_colors: Res<ButtonColors>,
+    offers: Res<FileOffers>,
+    list: Query<Entity, Added<FileOfferList>>,
+    all_lists: Query<Entity, With<FileOfferList>>,
+) {
+    // Draw a freshly spawned list even if nothing changed since the last one.
+    let Some(list) = list.iter().next().or_else(|| {
+        offers
+            .is_changed()
+            .then(|| all_lists.get_single().ok())
+            .flatten()
+    }) else {
+        return;
+    };
+    let text_style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: 24.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .entity(list)
+        .despawn_descendants()
+        .with_children(|parent| {
+            for offer in offers.iter() {
+                let peer = offer.peer_id.to_base58();
+                let label = format!(
+                    "{} wants to send you {} ({:.1} KB)",
+                    &peer[peer.len().saturating_sub(6)..],
+                    offer.name,
+                    offer.size as f64 / 1024.0
+                );
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(10.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .with_children(|row| {
+                        row.spawn(TextBundle::from_section(label, text_style.clone()));
+                        for (text, accept) in [("Accept", true), ("Decline", false)] {
+                            row.spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        width: Val::Px(90.0),
+                                        height: Val::Px(32.0),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..Default::default()
+                                    },
+                                    background_color: button_colors.normal.into(),
+                                    ..Default::default()
+                                },
+                                FileOfferButton {
+                                    peer_id: offer.peer_id,
+                                    transfer: offer.transfer,
+                                    accept,
+                                },
+                            ))
+                            .with_children(|button| {
+                                button.spawn(TextBundle::from_section(text, text_style.clone()));
+                            });
+                        }
+                    });
+            }
+        });
+}
+
+fn click_file_offer_button(
+    mut answers: EventWriter<AnswerFileOffer>,
+    interaction_query: Query<(&Interaction, &FileOfferButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            answers.send(AnswerFileOffer {
+                peer_id: button.peer_id,
+                transfer: button.transfer,
+                accept: button.accept,
+            });
+        }
+    }
+}
+
+/// Lists our friends and whether they are online, redrawn whenever [`Friends`] changes.
+fn show_friends(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    button_colors: Res<ButtonColors>,
+    friends: Res<Friends>,
+    list: Query<Entity, Added<FriendList>>,
+    all_lists: Query<Entity, With<FriendList>>,
+) {
+    // Draw a freshly spawned list even if nothing changed since the last one.
+    let Some(list) = list.iter().next().or_else(|| {
+        friends
+            .is_changed()
+            .then(|| all_lists.get_single().ok())
+            .flatten()
+    }) else {
+        return;
+    };
+    let text_style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: 24.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .entity(list)
+        .despawn_descendants()
+        .with_children(|parent| {
+            for friend in friends.iter() {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(10.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .with_children(|row| {
+                        row.spawn(TextBundle::from_section(
+                            format!(
+                                "{} is {}",
+                                friend.name,
+                                if friend.online { "online" } else { "offline" }
+                            ),
+                            text_style.clone(),
+                        ));
+                        row.spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    width: Val::Px(150.0),
+                                    height: Val::Px(32.0),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..Default::default()
+                                },
+                                background_color: button_colors.normal.into(),
+                                ..Default::default()
+                            },
+                            InviteFriendButton(friend.peer_id),
+                        ))
+                        .with_children(|button| {
+                            // Offline friends find the invite in their mailbox later.
+                            let label = if friend.online {
+                                "Invite"
+                            } else {
+                                "Leave invite"
+                            };
+                            button.spawn(TextBundle::from_section(label, text_style.clone()));
+                        });
+                    });
+            }
+        });
+}
+
+fn click_invite_friend_button(
+    room: Res<HostedRoom>,
+    mut invites: EventWriter<InviteFriend>,
+    interaction_query: Query<(&Interaction, &InviteFriendButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            invites.send(InviteFriend {
+                peer_id: button.0,
+                room_code: room.0.clone(),
+            });
+        }
+    }
+}
+
+/// Asks to start the game. The request is dropped with a warning until everyone is
+/// ready, which [`show_ready_count`] makes visible.
+fn click_start_game_button(
+    mut start_requests: EventWriter<RequestStartGame>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<StartGameButton>)>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            start_requests.send(RequestStartGame::default());
+        }
+    }
+}
+
+fn show_ready_count(
+    readiness: Res<Readiness>,
+    mut text_query: Query<&mut Text, With<ReadyCountText>>,
+) {
+    if !readiness.is_changed() {
+        return;
+    }
+    let Ok(mut text) = text_query.get_single_mut() else {
+        return;
+    };
+    text.sections[0].value = if readiness.players() == 0 {
+        String::new()
+    } else if readiness.all_ready() {
+        "Everyone is ready".to_string()
+    } else {
+        format!(
+            "{}/{} players ready",
+            readiness.players() - readiness.waiting_for(),
+            readiness.players()
+        )
+    };
+}
+
+fn show_countdown(
+    countdown: Res<StartCountdown>,
+    mut text_query: Query<&mut Text, With<CountdownText>>,
+) {
+    let Ok(mut text) = text_query.get_single_mut() else {
+        return;
+    };
+    text.sections[0].value = match countdown.remaining() {
+        Some(remaining) => format!("Starting in {}", remaining.as_secs() + 1),
+        None => String::new(),
+    };
+}
+
+/// Everyone in the room starts playing together once the host's countdown is over.
+fn enter_playing(
+    mut finished: EventReader<CountdownFinished>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    if finished.iter().next().is_some() {
+        state.set(GameState::Playing);
+    }
+}
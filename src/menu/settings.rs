@@ -0,0 +1,216 @@
+use bevy::prelude::*;
+
+use super::{spawn_button, ButtonColors, MenuLink};
+#[cfg(feature = "audio")]
+use crate::audio::{AudioCues, Cue};
+use crate::loading::FontAssets;
+use crate::lobby::PlayerSettings;
+use crate::text_input::{spawn_text_input, TextInput};
+use crate::GameState;
+
+pub(super) struct SettingsMenuPlugin;
+
+impl Plugin for SettingsMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Settings), setup_settings_menu)
+            .add_systems(
+                Update,
+                store_player_name.run_if(in_state(GameState::Settings)),
+            )
+            .add_systems(OnExit(GameState::Settings), cleanup_settings_menu);
+        #[cfg(feature = "audio")]
+        app.add_systems(
+            Update,
+            (click_cue_buttons, show_cue_settings)
+                .chain()
+                .run_if(in_state(GameState::Settings).and_then(resource_exists::<AudioCues>())),
+        );
+    }
+}
+
+/// Volume change per click of a cue's - and + buttons.
+#[cfg(feature = "audio")]
+const VOLUME_STEP: f64 = 0.1;
+
+#[derive(Component)]
+struct SettingsMenu;
+
+#[derive(Component)]
+struct PlayerNameInput;
+
+/// Shows how loud a cue is.
+#[cfg(feature = "audio")]
+#[derive(Component)]
+struct CueLabel(Cue);
+
+/// Turns a cue's volume up or down by this many steps.
+#[cfg(feature = "audio")]
+#[derive(Component)]
+struct CueVolumeButton(Cue, f64);
+
+#[cfg(feature = "audio")]
+#[derive(Component)]
+struct CueMuteButton(Cue);
+
+fn setup_settings_menu(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    button_colors: Res<ButtonColors>,
+    settings: Res<PlayerSettings>,
+    #[cfg(feature = "audio")] cues: Option<Res<AudioCues>>,
+) {
+    let text_style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: 40.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(20.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            SettingsMenu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("Name", text_style.clone()));
+            spawn_text_input(
+                parent,
+                TextInput::new(16)
+                    .with_filter(|c| c.is_alphanumeric() || " -_".contains(c))
+                    .with_value(settings.name.clone()),
+                text_style.clone(),
+                button_colors.normal,
+                PlayerNameInput,
+            );
+            #[cfg(feature = "audio")]
+            if let Some(cues) = &cues {
+                for cue in Cue::ALL {
+                    spawn_cue_row(parent, cue, cues, &text_style, &font_assets, &button_colors);
+                }
+            }
+            spawn_button(
+                parent,
+                "Back",
+                font_assets.fira_sans.clone(),
+                &button_colors,
+                MenuLink(GameState::Menu),
+            );
+        });
+}
+
+#[cfg(feature = "audio")]
+fn spawn_cue_row(
+    parent: &mut ChildBuilder,
+    cue: Cue,
+    cues: &AudioCues,
+    text_style: &TextStyle,
+    font_assets: &FontAssets,
+    button_colors: &ButtonColors,
+) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(10.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|row| {
+            row.spawn((
+                TextBundle {
+                    text: Text::from_section(cue_text(cue, cues), text_style.clone()),
+                    style: Style {
+                        width: Val::Px(220.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                CueLabel(cue),
+            ));
+            let font = font_assets.fira_sans.clone();
+            spawn_button(
+                row,
+                "-",
+                font.clone(),
+                button_colors,
+                CueVolumeButton(cue, -1.0),
+            );
+            spawn_button(
+                row,
+                "+",
+                font.clone(),
+                button_colors,
+                CueVolumeButton(cue, 1.0),
+            );
+            spawn_button(row, "Mute", font, button_colors, CueMuteButton(cue));
+        });
+}
+
+#[cfg(feature = "audio")]
+fn cue_text(cue: Cue, cues: &AudioCues) -> String {
+    let settings = cues.get(cue);
+    if settings.muted {
+        format!("{} muted", cue.label())
+    } else {
+        format!("{} {:.0}%", cue.label(), settings.volume * 100.0)
+    }
+}
+
+#[cfg(feature = "audio")]
+fn click_cue_buttons(
+    mut cues: ResMut<AudioCues>,
+    volume_buttons: Query<(&Interaction, &CueVolumeButton), Changed<Interaction>>,
+    mute_buttons: Query<(&Interaction, &CueMuteButton), Changed<Interaction>>,
+) {
+    for (interaction, CueVolumeButton(cue, steps)) in &volume_buttons {
+        if *interaction == Interaction::Pressed {
+            let settings = cues.get_mut(*cue);
+            settings.volume = (settings.volume + steps * VOLUME_STEP).clamp(0.0, 1.0);
+            settings.muted = false;
+        }
+    }
+    for (interaction, CueMuteButton(cue)) in &mute_buttons {
+        if *interaction == Interaction::Pressed {
+            let settings = cues.get_mut(*cue);
+            settings.muted = !settings.muted;
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+fn show_cue_settings(cues: Res<AudioCues>, mut labels: Query<(&mut Text, &CueLabel)>) {
+    if !cues.is_changed() {
+        return;
+    }
+    for (mut text, CueLabel(cue)) in &mut labels {
+        text.sections[0].value = cue_text(*cue, &cues);
+    }
+}
+
+fn store_player_name(
+    mut settings: ResMut<PlayerSettings>,
+    inputs: Query<&TextInput, (Changed<TextInput>, With<PlayerNameInput>)>,
+) {
+    for input in &inputs {
+        let name = input.value.trim();
+        if !name.is_empty() {
+            settings.name = name.to_string();
+        }
+    }
+}
+
+fn cleanup_settings_menu(mut commands: Commands, menus: Query<Entity, With<SettingsMenu>>) {
+    for menu in &menus {
+        commands.entity(menu).despawn_recursive();
+    }
+}
@@ -0,0 +1,264 @@
+//! Game messages identified by stable numeric ids rather than by their place in one
+//! big enum. Games and the plugins they use each register their own types with a
+//! [`MessagePlugin`], send them as an [`Envelope`] from
+//! [`MessageRegistry::encode`], and read [`MessageReceived`] events back. An
+//! envelope whose id nobody registered, e.g. from a newer version of the game, is
+//! skipped instead of failing to decode.
+//!
+//! Using this means the network layer carries [`Envelope`]s, i.e. the game's
+//! `FromGame` and `ToGame` are both [`Envelope`].
+
+use std::{
+    any::{type_name, TypeId},
+    collections::{HashMap, HashSet},
+    fmt,
+    marker::PhantomData,
+};
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::network::NetworkEvent;
+
+/// A game message as it goes over the network: the id its type was registered with,
+/// and the message itself encoded with bincode.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Envelope {
+    pub type_id: u16,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum MessageError {
+    /// The type was never registered.
+    Unregistered(&'static str),
+    /// The id is already taken by another type.
+    IdTaken {
+        id: u16,
+        by: &'static str,
+    },
+    /// The type is already registered under another id.
+    AlreadyRegistered {
+        name: &'static str,
+        id: u16,
+    },
+    Encoding(bincode::Error),
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unregistered(name) => write!(f, "{} is not a registered message", name),
+            Self::IdTaken { id, by } => write!(f, "message id {} is taken by {}", id, by),
+            Self::AlreadyRegistered { name, id } => {
+                write!(f, "{} is already registered as message {}", name, id)
+            }
+            Self::Encoding(e) => write!(f, "could not encode the message: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
+/// Which id every message type goes by. Filled in by the [`MessagePlugin`]s.
+#[derive(Resource, Debug, Default)]
+pub struct MessageRegistry {
+    ids: HashMap<TypeId, u16>,
+    names: HashMap<u16, &'static str>,
+}
+
+impl MessageRegistry {
+    /// Gives `T` the id `id`. Ids are part of the protocol, so they must never change
+    /// or be reused once released.
+    pub fn register<T: 'static>(&mut self, id: u16) -> Result<(), MessageError> {
+        let name = type_name::<T>();
+        if let Some(&registered) = self.ids.get(&TypeId::of::<T>()) {
+            return if registered == id {
+                Ok(())
+            } else {
+                Err(MessageError::AlreadyRegistered {
+                    name,
+                    id: registered,
+                })
+            };
+        }
+        if let Some(&by) = self.names.get(&id) {
+            return Err(MessageError::IdTaken { id, by });
+        }
+        self.ids.insert(TypeId::of::<T>(), id);
+        self.names.insert(id, name);
+        Ok(())
+    }
+
+    /// The id `T` was registered with.
+    pub fn id_of<T: 'static>(&self) -> Option<u16> {
+        self.ids.get(&TypeId::of::<T>()).copied()
+    }
+
+    pub fn is_registered(&self, id: u16) -> bool {
+        self.names.contains_key(&id)
+    }
+
+    /// Wraps `message` for sending, e.g. with
+    /// [`GameEvent::Game`](crate::network::GameEvent::Game).
+    pub fn encode<T: Serialize + 'static>(&self, message: &T) -> Result<Envelope, MessageError> {
+        let type_id = self
+            .id_of::<T>()
+            .ok_or(MessageError::Unregistered(type_name::<T>()))?;
+        let payload = bincode::serialize(message).map_err(MessageError::Encoding)?;
+        Ok(Envelope { type_id, payload })
+    }
+
+    /// Unwraps `envelope` if it holds a `T`.
+    pub fn decode<T: DeserializeOwned + 'static>(
+        &self,
+        envelope: &Envelope,
+    ) -> Option<bincode::Result<T>> {
+        (self.id_of::<T>() == Some(envelope.type_id))
+            .then(|| bincode::deserialize(&envelope.payload))
+    }
+}
+
+/// A `T` from `source`.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct MessageReceived<T> {
+    pub source: PeerId,
+    pub message: T,
+}
+
+/// Registers `T` under a stable id and turns the [`Envelope`]s carrying it into
+/// [`MessageReceived<T>`] events.
+///
+/// # Panics
+///
+/// When built, if the id or `T` are already registered otherwise.
+pub struct MessagePlugin<T> {
+    id: u16,
+    marker: PhantomData<T>,
+}
+
+impl<T> MessagePlugin<T> {
+    pub fn new(id: u16) -> Self {
+        Self {
+            id,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Plugin for MessagePlugin<T>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+{
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<MessageRegistry>() {
+            app.init_resource::<MessageRegistry>()
+                .add_event::<NetworkEvent<Envelope>>()
+                .add_systems(Update, skip_unknown_messages);
+        }
+        let mut registry = app.world.resource_mut::<MessageRegistry>();
+        if let Err(e) = registry.register::<T>(self.id) {
+            panic!("Could not register {}: {}", type_name::<T>(), e);
+        }
+        app.add_event::<MessageReceived<T>>()
+            .add_systems(Update, receive_messages::<T>);
+    }
+}
+
+fn receive_messages<T>(
+    registry: Res<MessageRegistry>,
+    mut network_events: EventReader<NetworkEvent<Envelope>>,
+    mut received: EventWriter<MessageReceived<T>>,
+) where
+    T: DeserializeOwned + Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        let NetworkEvent::Game { source, event } = event else {
+            continue;
+        };
+        match registry.decode::<T>(event) {
+            Some(Ok(message)) => received.send(MessageReceived {
+                source: *source,
+                message,
+            }),
+            Some(Err(e)) => log::warn!(
+                "Dropping a malformed {} from {}: {}",
+                type_name::<T>(),
+                source,
+                e
+            ),
+            None => {}
+        }
+    }
+}
+
+/// Logs each id we got messages for but don't know, once.
+fn skip_unknown_messages(
+    registry: Res<MessageRegistry>,
+    mut network_events: EventReader<NetworkEvent<Envelope>>,
+    mut reported: Local<HashSet<u16>>,
+) {
+    for event in network_events.iter() {
+        if let NetworkEvent::Game { source, event } = event {
+            if !registry.is_registered(event.type_id) && reported.insert(event.type_id) {
+                log::info!(
+                    "Skipping messages with unknown id {}, first from {}",
+                    event.type_id,
+                    source
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Move(i32, i32);
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Fire;
+
+    #[test]
+    fn ids_and_types_register_once() {
+        let mut registry = MessageRegistry::default();
+        registry.register::<Move>(1).unwrap();
+        registry.register::<Move>(1).unwrap();
+        assert!(matches!(
+            registry.register::<Fire>(1),
+            Err(MessageError::IdTaken { id: 1, .. })
+        ));
+        assert!(matches!(
+            registry.register::<Move>(2),
+            Err(MessageError::AlreadyRegistered { id: 1, .. })
+        ));
+        assert!(matches!(
+            registry.encode(&Fire),
+            Err(MessageError::Unregistered(_))
+        ));
+    }
+
+    #[test]
+    fn messages_decode_only_as_their_own_type() {
+        let mut registry = MessageRegistry::default();
+        registry.register::<Move>(1).unwrap();
+        registry.register::<Fire>(2).unwrap();
+        let envelope = registry.encode(&Move(3, -4)).unwrap();
+        assert_eq!(envelope.type_id, 1);
+        assert_eq!(
+            registry.decode::<Move>(&envelope).unwrap().unwrap(),
+            Move(3, -4)
+        );
+        assert!(registry.decode::<Fire>(&envelope).is_none());
+
+        let unknown = Envelope {
+            type_id: 7,
+            payload: vec![1, 2, 3],
+        };
+        assert!(!registry.is_registered(unknown.type_id));
+        assert!(registry.decode::<Move>(&unknown).is_none());
+    }
+}
@@ -0,0 +1,186 @@
+//! Acknowledgments for the latest-wins channel, [`GameEvent::Latest`](super::GameEvent::Latest).
+//!
+//! Gossip doesn't tell a sender what arrived, so every latest-wins message carries,
+//! for each peer we hear latest-wins messages from, the newest sequence number we got
+//! from it plus a bitfield of the [`ACK_BITS`] before that. Senders match those
+//! against what they sent to learn, per peer, which messages made it and which are
+//! lost, e.g. to pick a baseline for delta compression.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+/// Sequence numbers before the newest covered by an [`AckField`].
+pub(super) const ACK_BITS: u32 = 32;
+/// Sent sequence numbers remembered while waiting for their acks.
+const SENT_HISTORY: usize = 64;
+
+/// What one peer received from another: `latest`, and `latest - 1 - i` for every bit
+/// `i` set in `bits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct AckField {
+    pub latest: u32,
+    pub bits: u32,
+}
+
+impl AckField {
+    fn covers(&self, seq: u32) -> bool {
+        match self.latest.checked_sub(seq) {
+            Some(0) => true,
+            Some(behind) if behind <= ACK_BITS => self.bits & (1 << (behind - 1)) != 0,
+            _ => false,
+        }
+    }
+}
+
+/// The latest-wins messages received from one peer.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct ReceivedWindow {
+    field: Option<AckField>,
+}
+
+impl ReceivedWindow {
+    /// Records `seq`, returning whether it's the newest yet and should reach the game.
+    /// Older ones are only acknowledged.
+    pub(super) fn receive(&mut self, seq: u32) -> bool {
+        let Some(field) = &mut self.field else {
+            self.field = Some(AckField {
+                latest: seq,
+                bits: 0,
+            });
+            return true;
+        };
+        if seq > field.latest {
+            let ahead = seq - field.latest;
+            field.bits = if ahead > ACK_BITS {
+                0
+            } else {
+                ((u64::from(field.bits) << ahead) | (1 << (ahead - 1))) as u32
+            };
+            field.latest = seq;
+            true
+        } else {
+            let behind = field.latest - seq;
+            if (1..=ACK_BITS).contains(&behind) {
+                field.bits |= 1 << (behind - 1);
+            }
+            false
+        }
+    }
+
+    pub(super) fn field(&self) -> Option<AckField> {
+        self.field
+    }
+}
+
+/// Both sides of the bookkeeping for one room.
+#[derive(Debug, Default)]
+pub(super) struct Acks {
+    received: HashMap<PeerId, ReceivedWindow>,
+    /// Our last [`SENT_HISTORY`] sequence numbers, oldest first.
+    sent: VecDeque<u32>,
+    /// Per peer, which of `sent` were already reported acked or lost.
+    settled: HashMap<PeerId, HashSet<u32>>,
+}
+
+impl Acks {
+    /// Records `seq` from `peer`. See [`ReceivedWindow::receive`].
+    pub(super) fn receive(&mut self, peer: PeerId, seq: u32) -> bool {
+        self.received.entry(peer).or_default().receive(seq)
+    }
+
+    /// What to piggyback on our next latest-wins message.
+    pub(super) fn fields(&self) -> Vec<(PeerId, AckField)> {
+        self.received
+            .iter()
+            .filter_map(|(peer, window)| Some((*peer, window.field()?)))
+            .collect()
+    }
+
+    pub(super) fn sent(&mut self, seq: u32) {
+        if self.sent.len() == SENT_HISTORY {
+            self.sent.pop_front();
+        }
+        self.sent.push_back(seq);
+        let oldest = self.sent.front().copied().unwrap_or(seq);
+        for settled in self.settled.values_mut() {
+            settled.retain(|settled| *settled >= oldest);
+        }
+    }
+
+    /// Takes `peer`'s word on what it got from us. Returns the sequence numbers that
+    /// are newly known to have arrived, and those that now count as lost because they
+    /// fell out of the ack window unacknowledged.
+    pub(super) fn acknowledge(&mut self, peer: PeerId, field: AckField) -> (Vec<u32>, Vec<u32>) {
+        let settled = self.settled.entry(peer).or_default();
+        let (mut acked, mut lost) = (Vec::new(), Vec::new());
+        for seq in self.sent.iter().copied() {
+            if seq > field.latest || settled.contains(&seq) {
+                continue;
+            }
+            if field.covers(seq) {
+                acked.push(seq);
+            } else if field.latest - seq > ACK_BITS {
+                lost.push(seq);
+            } else {
+                continue;
+            }
+            settled.insert(seq);
+        }
+        (acked, lost)
+    }
+
+    pub(super) fn forget(&mut self, peer: &PeerId) {
+        self.received.remove(peer);
+        self.settled.remove(peer);
+    }
+
+    pub(super) fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_newer_messages_get_through_but_all_are_acked() {
+        let mut window = ReceivedWindow::default();
+        assert!(window.receive(10));
+        assert!(window.receive(12));
+        assert!(!window.receive(11));
+        assert!(!window.receive(12));
+        let field = window.field().unwrap();
+        assert_eq!(field.latest, 12);
+        assert!(field.covers(12) && field.covers(11) && field.covers(10));
+        assert!(!field.covers(9));
+
+        assert!(window.receive(100));
+        assert_eq!(window.field().unwrap().bits, 0);
+    }
+
+    #[test]
+    fn senders_learn_what_arrived_and_what_was_lost() {
+        let peer = PeerId::random();
+        let mut acks = Acks::default();
+        for seq in 1..=3 {
+            acks.sent(seq);
+        }
+        let mut window = ReceivedWindow::default();
+        window.receive(1);
+        window.receive(3);
+        assert_eq!(
+            acks.acknowledge(peer, window.field().unwrap()),
+            (vec![1, 3], vec![])
+        );
+        // Nothing is reported twice, and 2 is lost once it's out of the window.
+        window.receive(3 + ACK_BITS);
+        acks.sent(3 + ACK_BITS);
+        assert_eq!(
+            acks.acknowledge(peer, window.field().unwrap()),
+            (vec![3 + ACK_BITS], vec![2])
+        );
+    }
+}
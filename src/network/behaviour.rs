@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use libp2p::{
+    dcutr, gossipsub, identify, identity,
+    kad::{self, store::MemoryStore},
+    mdns, ping, relay, rendezvous,
+    request_response::{self, ProtocolSupport},
+    swarm::{behaviour::toggle::Toggle, dummy, NetworkBehaviour},
+    PeerId,
+};
+
+use super::{
+    config::{IpVersions, NetworkConfig},
+    custom::{CustomBehaviour, CustomSlot},
+    discovery::DiscoveryMethod,
+    fragment::MAX_TRANSMIT_SIZE,
+    keep_alive,
+    protocol::{
+        FileRequest, FileResponse, InviteAck, JoinRequest, JoinResponse, RoomInvite,
+        SnapshotRequest, SnapshotResponse, FILE_PROTOCOL, INVITE_PROTOCOL, JOIN_PROTOCOL,
+        SNAPSHOT_PROTOCOL,
+    },
+};
+use crate::crypto::{DataEncryptor, KeyRing};
+
+/// Protocol version advertised over identify, after
+/// [`ProtocolNames::prefix`](super::ProtocolNames::prefix), so peers running the same
+/// game can find each other.
+pub const IDENTIFY_PROTOCOL: &str = "/v1";
+/// How long a joiner waits for the host to answer, which includes the host deciding.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(120);
+/// How long a file offer waits for an answer, which includes the player deciding.
+const FILE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Protocol advertised by peers that can act as a circuit relay.
+pub const RELAY_PROTOCOL: &str = "/libp2p/circuit/relay/0.2.0/hop";
+
+/// The full libp2p stack used by the game, with the game's own `Custom` behaviour
+/// next to it, see [`setup_network_with_behaviour`](super::setup_network_with_behaviour).
+///
+/// Gossip payloads are encrypted with [`DataEncryptor`], so only peers holding a
+/// key from the shared [`KeyRing`] can read them.
+#[derive(NetworkBehaviour)]
+pub struct Behaviour<Custom: CustomBehaviour> {
+    pub relay: relay::client::Behaviour,
+    pub dcutr: dcutr::Behaviour,
+    pub kad: kad::Kademlia<MemoryStore>,
+    pub gossip: gossipsub::Behaviour<DataEncryptor, gossipsub::AllowAllSubscriptionFilter>,
+    pub ping: ping::Behaviour,
+    pub identify: identify::Behaviour,
+    pub join: request_response::cbor::Behaviour<JoinRequest, JoinResponse>,
+    pub invite: request_response::cbor::Behaviour<RoomInvite, InviteAck>,
+    pub snapshot: request_response::cbor::Behaviour<SnapshotRequest, SnapshotResponse>,
+    pub file: request_response::cbor::Behaviour<FileRequest, FileResponse>,
+    pub keep_alive: keep_alive::Behaviour,
+    /// Only enabled when [`NetworkConfig::discovery`] asks for mDNS.
+    pub mdns: Toggle<mdns::async_io::Behaviour>,
+    /// Only enabled when [`NetworkConfig::discovery`] names a rendezvous server.
+    pub rendezvous: Toggle<rendezvous::client::Behaviour>,
+    custom: CustomSlot<Custom>,
+}
+
+/// The stack [`setup_network`](super::setup_network) runs, with no behaviour of the
+/// game's own.
+pub type DefaultBehaviour = Behaviour<dummy::Behaviour>;
+
+impl<Custom: CustomBehaviour> Behaviour<Custom> {
+    /// Builds the behaviour for `id_keys`, returning the [`KeyRing`] that backs gossip
+    /// encryption so callers can add room keys to it later.
+    pub fn new(
+        id_keys: &identity::Keypair,
+        relay: relay::client::Behaviour,
+        config: &NetworkConfig,
+        custom: Custom,
+    ) -> Result<(Self, KeyRing), anyhow::Error> {
+        let local_peer_id = PeerId::from(id_keys.public());
+
+        let mut kad = kad::Kademlia::new(local_peer_id, MemoryStore::new(local_peer_id));
+        for (peer_id, address) in config.bootstrap_peers()? {
+            kad.add_address(&peer_id, address);
+        }
+        // Only hosts serve DHT records; see `SwarmTask::set_kad_mode`.
+        kad.set_mode(Some(kad::Mode::Client));
+        let gossip_config = gossipsub::ConfigBuilder::default()
+            .idle_timeout(config.gossip_idle_timeout)
+            .max_transmit_size(MAX_TRANSMIT_SIZE)
+            .build()
+            .map_err(|e| anyhow::anyhow!("Invalid gossipsub config: {}", e))?;
+        let (data_encryptor, keys) = DataEncryptor::new();
+        let gossip = gossipsub::Behaviour::new_with_transform(
+            gossipsub::MessageAuthenticity::Signed(id_keys.clone()),
+            gossip_config,
+            None,
+            data_encryptor,
+        )
+        .map_err(|s: &str| anyhow::anyhow!(s))?;
+        let dcutr = dcutr::Behaviour::new(local_peer_id);
+        let ping = ping::Behaviour::default();
+        let identify = identify::Behaviour::new(identify::Config::new(
+            config.protocol.protocol(IDENTIFY_PROTOCOL),
+            id_keys.public(),
+        ));
+        let mut join_config = request_response::Config::default();
+        join_config.set_request_timeout(JOIN_TIMEOUT);
+        let join = request_response::cbor::Behaviour::new(
+            [(
+                config.protocol.stream_protocol(JOIN_PROTOCOL)?,
+                ProtocolSupport::Full,
+            )],
+            join_config,
+        );
+        let invite = request_response::cbor::Behaviour::new(
+            [(
+                config.protocol.stream_protocol(INVITE_PROTOCOL)?,
+                ProtocolSupport::Full,
+            )],
+            request_response::Config::default(),
+        );
+        let snapshot = request_response::cbor::Behaviour::new(
+            [(
+                config.protocol.stream_protocol(SNAPSHOT_PROTOCOL)?,
+                ProtocolSupport::Full,
+            )],
+            request_response::Config::default(),
+        );
+        let mut file_config = request_response::Config::default();
+        file_config.set_request_timeout(FILE_TIMEOUT);
+        let file = request_response::cbor::Behaviour::new(
+            [(
+                config.protocol.stream_protocol(FILE_PROTOCOL)?,
+                ProtocolSupport::Full,
+            )],
+            file_config,
+        );
+        let mdns = if config.discovery.contains(&DiscoveryMethod::Mdns) {
+            // mDNS runs over one IP version, so only use IPv6 when that's all we listen on.
+            let mdns_config = mdns::Config {
+                enable_ipv6: config.listen.ip == IpVersions::V6,
+                ..Default::default()
+            };
+            Some(mdns::async_io::Behaviour::new(mdns_config, local_peer_id)?)
+        } else {
+            None
+        };
+        let rendezvous = config
+            .discovery
+            .iter()
+            .any(|method| matches!(method, DiscoveryMethod::Rendezvous(_)))
+            .then(|| rendezvous::client::Behaviour::new(id_keys.clone()));
+        Ok((
+            Self {
+                relay,
+                dcutr,
+                kad,
+                gossip,
+                ping,
+                identify,
+                join,
+                invite,
+                snapshot,
+                file,
+                keep_alive: keep_alive::Behaviour::new(
+                    config.game_peers,
+                    config.dht_peers,
+                    config.idle_connection_timeout,
+                ),
+                mdns: mdns.into(),
+                rendezvous: rendezvous.into(),
+                custom: CustomSlot(custom),
+            },
+            keys,
+        ))
+    }
+
+    /// The game's own behaviour.
+    pub fn custom(&mut self) -> &mut Custom {
+        &mut self.custom.0
+    }
+}
@@ -0,0 +1,561 @@
+use std::{fmt, net::SocketAddr, path::PathBuf, time::Duration};
+
+use libp2p::{dns, identity::Keypair, multiaddr::Protocol, Multiaddr, PeerId, StreamProtocol};
+use trust_dns_resolver::{
+    config::{LookupIpStrategy, NameServerConfigGroup},
+    system_conf,
+};
+
+use super::{
+    discovery::{split_peer_id, DiscoveryMethod},
+    JoinStage,
+};
+use crate::at_rest::AtRestEncryption;
+
+/// The public IPFS bootstrap nodes, used unless the game brings its own.
+const IPFS_BOOTNODES: [&str; 4] = [
+    "QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN",
+    "QmQCU2EcMqAqQPR2i9bChDtGNJchTbq5TbXJJ16u19uLTa",
+    "QmbLHAnMoJPWSCR5Zhtx6BHJX9KiKNN6tpvbUcqanj75Nb",
+    "QmcZf59bWwK5XFi76CZX8cbJ4BhTzzA3gU1ZjYZcYW3dwt",
+];
+
+/// The relay run for this demo.
+const DEFAULT_RELAY: &str =
+    "/dns/p2p.favil.org/tcp/4001/p2p/12D3KooWJAmx46jdsLbvsEJmUAnQ44Yj4iHmgdsDD4BEYvALnFy8";
+
+/// Whether a class of connections is held open while nothing is using it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAlivePolicy {
+    /// Never close for being idle. Turn-based games can go quiet for minutes.
+    Always,
+    /// Close once idle for [`NetworkConfig::idle_connection_timeout`].
+    IdleTimeout,
+}
+
+/// Which IP versions a host listens on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpVersions {
+    V4,
+    V6,
+    /// Both, so joiners on IPv6 only networks (common on mobile) can reach us too.
+    DualStack,
+}
+
+/// Which addresses a `/dns/` name resolves to. Names in `/dns4/` and `/dns6/` addresses
+/// always resolve to their own IP version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsLookup {
+    Ipv4Only,
+    Ipv6Only,
+    /// Both, so whichever version the network actually routes gets dialed.
+    Ipv4AndIpv6,
+}
+
+/// A public DNS service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsProvider {
+    Google,
+    Cloudflare,
+    Quad9,
+}
+
+/// Who resolves the host names in the addresses we dial, e.g. the relay's and the
+/// bootstrap nodes'.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsConfig {
+    /// Whatever the operating system is set up with. Where that can't be read, e.g. on
+    /// Android, falls back to Google's.
+    System,
+    Provider(DnsProvider),
+    /// These name servers, asked over UDP and TCP.
+    Custom(Vec<SocketAddr>),
+    /// Don't resolve anything: addresses with host names fail to dial, so every
+    /// address we need must be given as an IP.
+    Disabled,
+}
+
+/// Where a host listens for joiners, besides the relay.
+///
+/// Each address is opened on its own. Hosting goes ahead as long as one of them, or
+/// the relay circuit, works, and every failure is reported as a
+/// [`NetworkAdminEvent::ListenFailed`](super::NetworkAdminEvent::ListenFailed).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenStrategy {
+    pub ip: IpVersions,
+    /// TCP port to listen on, or `None` for whichever the OS picks. A fixed port can
+    /// be forwarded on the router.
+    pub tcp_port: Option<u16>,
+    /// Also accept websocket connections, e.g. from browsers.
+    pub websocket: bool,
+    /// Like [`tcp_port`](Self::tcp_port), for websockets. Must differ from it when
+    /// both are fixed.
+    pub websocket_port: Option<u16>,
+}
+
+impl Default for ListenStrategy {
+    fn default() -> Self {
+        Self {
+            ip: IpVersions::DualStack,
+            tcp_port: None,
+            websocket: true,
+            websocket_port: None,
+        }
+    }
+}
+
+impl ListenStrategy {
+    /// The addresses to listen on, not counting the relay circuit.
+    pub(super) fn addresses(&self) -> Vec<Multiaddr> {
+        let ips = match self.ip {
+            IpVersions::V4 => vec![Protocol::Ip4([0, 0, 0, 0].into())],
+            IpVersions::V6 => vec![Protocol::Ip6([0; 16].into())],
+            IpVersions::DualStack => vec![
+                Protocol::Ip4([0, 0, 0, 0].into()),
+                Protocol::Ip6([0; 16].into()),
+            ],
+        };
+        let mut addresses = Vec::new();
+        for ip in ips {
+            let tcp = Multiaddr::empty()
+                .with(ip)
+                .with(Protocol::Tcp(self.tcp_port.unwrap_or(0)));
+            addresses.push(tcp);
+        }
+        if self.websocket {
+            let websockets: Vec<_> = addresses
+                .iter()
+                .map(|tcp| {
+                    let mut ws: Multiaddr = tcp.iter().take(1).collect();
+                    ws.push(Protocol::Tcp(self.websocket_port.unwrap_or(0)));
+                    ws.push(Protocol::Ws("/".into()));
+                    ws
+                })
+                .collect();
+            addresses.extend(websockets);
+        }
+        addresses
+    }
+}
+
+/// Keeps a confused or misbehaving client from hammering the DHT, and hosts from
+/// being flooded with join handshakes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JoinLimits {
+    /// Least time between two of our joins. Joins sent sooner fail with
+    /// [`JoinError::Throttled`](super::JoinError::Throttled).
+    pub cooldown: Duration,
+    /// How long a join may take, from looking for the room to being let in.
+    pub timeout: Duration,
+    /// How long a join may take in each of its stages.
+    pub stages: JoinStageTimeouts,
+    /// DHT queries we run at once. Joins and lookups the game asks for past that wait
+    /// until one finishes.
+    pub max_dht_queries: usize,
+    /// Host only: join handshakes a single peer may start per
+    /// [`handshake_window`](Self::handshake_window). The rest are rejected.
+    pub handshakes_per_peer: u32,
+    pub handshake_window: Duration,
+    /// Host only: how long a peer that dropped without saying goodbye keeps its place
+    /// in the room. Coming back as the same peer within it lets it in without asking
+    /// the game, full room or not, and gives it back the entities it simulated.
+    pub reclaim_window: Duration,
+}
+
+impl Default for JoinLimits {
+    fn default() -> Self {
+        Self {
+            cooldown: Duration::from_secs(2),
+            timeout: Duration::from_secs(60),
+            stages: JoinStageTimeouts::default(),
+            max_dht_queries: 4,
+            handshakes_per_peer: 3,
+            handshake_window: Duration::from_secs(60),
+            reclaim_window: Duration::from_secs(60),
+        }
+    }
+}
+
+/// How long a join may stay in each [`JoinStage`]. A candidate host
+/// that takes too long is skipped for the next one; finding no host in time fails the
+/// join with [`JoinError::Timeout`](super::JoinError::Timeout). Syncing state is up to
+/// the game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JoinStageTimeouts {
+    pub resolving_room: Duration,
+    pub dialing_host: Duration,
+    pub hole_punching: Duration,
+    pub exchanging_keys: Duration,
+}
+
+impl Default for JoinStageTimeouts {
+    fn default() -> Self {
+        Self {
+            resolving_room: Duration::from_secs(30),
+            dialing_host: Duration::from_secs(15),
+            hole_punching: Duration::from_secs(15),
+            exchanging_keys: Duration::from_secs(15),
+        }
+    }
+}
+
+impl JoinStageTimeouts {
+    /// How long a join may stay in `stage`, if there's a limit.
+    pub fn of(&self, stage: JoinStage) -> Option<Duration> {
+        match stage {
+            JoinStage::ResolvingRoom => Some(self.resolving_room),
+            JoinStage::DialingHost => Some(self.dialing_host),
+            JoinStage::HolePunching => Some(self.hole_punching),
+            JoinStage::ExchangingKeys => Some(self.exchanging_keys),
+            JoinStage::SyncingState => None,
+        }
+    }
+}
+
+/// Which relay hosts listen on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelaySelection {
+    /// Always [`NetworkConfig::relay`].
+    Manual,
+    /// The relay with the lowest round trip among the DHT peers that offer one, or
+    /// [`NetworkConfig::relay`] until one of them answered a ping.
+    Discover,
+}
+
+/// When to raise a [`NetworkAdminEvent::RelayUsageWarning`](super::NetworkAdminEvent::RelayUsageWarning)
+/// about a peer we only reach through the relay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayUsageLimits {
+    /// Time on the relay before warning.
+    pub duration: Duration,
+    /// Room traffic with the peer over the relay before warning.
+    pub bytes: u64,
+}
+
+impl Default for RelayUsageLimits {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(120),
+            bytes: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// How [`GameAdminEvent::RecordTape`](super::GameAdminEvent::RecordTape) records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TapeConfig {
+    /// Messages kept on a tape. Past that the oldest make room for new ones.
+    pub max_entries: usize,
+    /// How saved tapes are kept on disk. They hold every room message in the clear.
+    pub encryption: AtRestEncryption,
+}
+
+impl Default for TapeConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 100_000,
+            encryption: AtRestEncryption::Off,
+        }
+    }
+}
+
+/// Limits on [`GameAdminEvent::SendFile`](super::GameAdminEvent::SendFile) transfers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDropConfig {
+    /// Larger files are neither sent nor accepted.
+    pub max_size: u64,
+    /// Where accepted files are written, created if missing.
+    pub directory: PathBuf,
+}
+
+impl Default for FileDropConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 8 * 1024 * 1024,
+            directory: PathBuf::from("received"),
+        }
+    }
+}
+
+/// What our protocols, gossip topics and DHT records are called. Games built on this
+/// crate should each pick their own, so they neither find each other's rooms on the
+/// public DHT nor take each other's peers for players.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolNames {
+    /// Starts the identify protocol version and every protocol we speak, e.g.
+    /// `/my-game`.
+    pub prefix: String,
+    /// Starts every gossip topic, DHT key and rendezvous namespace, e.g. `my-game`.
+    /// Must not contain a `/`.
+    pub namespace: String,
+}
+
+impl ProtocolNames {
+    /// Names under `game`, which should be unique to the game and contain no `/`.
+    pub fn new(game: &str) -> Self {
+        Self {
+            prefix: format!("/{}", game),
+            namespace: game.to_string(),
+        }
+    }
+
+    /// `suffix`, e.g. [`JOIN_PROTOCOL`](super::JOIN_PROTOCOL), under our prefix.
+    pub fn protocol(&self, suffix: &str) -> String {
+        format!("{}{}", self.prefix, suffix)
+    }
+
+    pub(super) fn stream_protocol(&self, suffix: &str) -> anyhow::Result<StreamProtocol> {
+        StreamProtocol::try_from_owned(self.protocol(suffix))
+            .map_err(|e| anyhow::anyhow!("Invalid protocol prefix {:?}: {}", self.prefix, e))
+    }
+
+    /// The topic or DHT key of the `kind` of thing called `name`, e.g. a room's topic.
+    pub(super) fn key(&self, kind: &str, name: impl fmt::Display) -> String {
+        format!("/{}/{}/{}", self.namespace, kind, name)
+    }
+}
+
+impl Default for ProtocolNames {
+    /// The names of this demo, which older clients use too.
+    fn default() -> Self {
+        Self {
+            prefix: "/bevy-p2p-demo".to_string(),
+            namespace: "bevy-libp2p-demo".to_string(),
+        }
+    }
+}
+
+/// Tunables for [`setup_network`](super::setup_network).
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    /// How long a new connection is kept while no behaviour needs it, for peers under
+    /// [`KeepAlivePolicy::IdleTimeout`].
+    pub idle_connection_timeout: Duration,
+    /// Gossipsub keeps a connection open this long after its last message, unless the
+    /// peer is in our mesh.
+    pub gossip_idle_timeout: Duration,
+    /// Connections to peers running this game.
+    pub game_peers: KeepAlivePolicy,
+    /// Connections to everyone else, mostly DHT and relay nodes.
+    pub dht_peers: KeepAlivePolicy,
+    /// DHT entry points, each ending in `/p2p/<peer id>`. May be empty.
+    pub bootstrap_peers: Vec<Multiaddr>,
+    /// Relay every host listens on so joiners can reach it behind NAT, ending in
+    /// `/p2p/<peer id>`. Only a fallback under [`RelaySelection::Discover`].
+    pub relay: Multiaddr,
+    pub relay_selection: RelaySelection,
+    /// Stay off the public DHT: [`bootstrap_peers`](Self::bootstrap_peers) are ignored
+    /// and no bootstrap is attempted.
+    pub lan_only: bool,
+    /// How rooms are announced and found. Every method is used at once, so e.g.
+    /// `[Dht, Mdns]` finds hosts on the LAN even when the DHT is unreachable.
+    pub discovery: Vec<DiscoveryMethod>,
+    /// Where we listen while hosting.
+    pub listen: ListenStrategy,
+    /// Which DNS servers resolve host names in the addresses we dial.
+    pub dns: DnsConfig,
+    /// How host names in the addresses we dial are resolved.
+    pub dns_lookup: DnsLookup,
+    pub join_limits: JoinLimits,
+    pub relay_usage: RelayUsageLimits,
+    /// Who we are. `None` makes up a new identity every run; keep one with
+    /// [`identity::load_or_create`](crate::identity::load_or_create) to come back as
+    /// the same peer after a crash.
+    pub identity: Option<Keypair>,
+    pub tape: TapeConfig,
+    /// Game messages older than this when they reach us are dropped instead of handed
+    /// to the game, so a peer unfreezing doesn't replay a burst of old state. Measured
+    /// against the sender's clock, so keep it well above the clock skew between
+    /// players. `None` keeps every message.
+    pub message_ttl: Option<Duration>,
+    pub file_drop: FileDropConfig,
+    pub protocol: ProtocolNames,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            idle_connection_timeout: Duration::from_secs(30),
+            gossip_idle_timeout: Duration::from_secs(120),
+            game_peers: KeepAlivePolicy::Always,
+            dht_peers: KeepAlivePolicy::IdleTimeout,
+            bootstrap_peers: IPFS_BOOTNODES
+                .iter()
+                .map(|peer| {
+                    format!("/dnsaddr/bootstrap.libp2p.io/p2p/{}", peer)
+                        .parse()
+                        .expect("Bootnode addresses should always parse")
+                })
+                .collect(),
+            relay: DEFAULT_RELAY
+                .parse()
+                .expect("Relay address should always parse"),
+            relay_selection: RelaySelection::Discover,
+            lan_only: false,
+            discovery: vec![DiscoveryMethod::Dht],
+            listen: ListenStrategy::default(),
+            dns: DnsConfig::System,
+            dns_lookup: DnsLookup::Ipv4AndIpv6,
+            join_limits: JoinLimits::default(),
+            relay_usage: RelayUsageLimits::default(),
+            identity: None,
+            tape: TapeConfig::default(),
+            message_ttl: Some(Duration::from_secs(10)),
+            file_drop: FileDropConfig::default(),
+            protocol: ProtocolNames::default(),
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// The bootstrap peers to use, split into peer id and address.
+    pub(super) fn bootstrap_peers(&self) -> anyhow::Result<Vec<(PeerId, Multiaddr)>> {
+        if self.lan_only {
+            return Ok(Vec::new());
+        }
+        self.bootstrap_peers.iter().map(split_peer_id).collect()
+    }
+
+    /// The resolver every transport dials through, per [`dns`](Self::dns) and
+    /// [`dns_lookup`](Self::dns_lookup).
+    pub(super) fn resolver(&self) -> (dns::ResolverConfig, dns::ResolverOpts) {
+        let (config, mut opts) = match &self.dns {
+            DnsConfig::System => system_conf::read_system_conf().unwrap_or_else(|e| {
+                log::warn!(
+                    "Could not read the system's DNS setup, using Google's: {}",
+                    e
+                );
+                (dns::ResolverConfig::google(), dns::ResolverOpts::default())
+            }),
+            DnsConfig::Provider(DnsProvider::Google) => {
+                (dns::ResolverConfig::google(), dns::ResolverOpts::default())
+            }
+            DnsConfig::Provider(DnsProvider::Cloudflare) => (
+                dns::ResolverConfig::cloudflare(),
+                dns::ResolverOpts::default(),
+            ),
+            DnsConfig::Provider(DnsProvider::Quad9) => {
+                (dns::ResolverConfig::quad9(), dns::ResolverOpts::default())
+            }
+            DnsConfig::Custom(servers) => {
+                let mut group = NameServerConfigGroup::new();
+                for server in servers {
+                    group.merge(NameServerConfigGroup::from_ips_clear(
+                        &[server.ip()],
+                        server.port(),
+                        true,
+                    ));
+                }
+                let config = dns::ResolverConfig::from_parts(None, Vec::new(), group);
+                (config, dns::ResolverOpts::default())
+            }
+            // Without name servers every lookup fails right away.
+            DnsConfig::Disabled => (dns::ResolverConfig::new(), dns::ResolverOpts::default()),
+        };
+        opts.ip_strategy = match self.dns_lookup {
+            DnsLookup::Ipv4Only => LookupIpStrategy::Ipv4Only,
+            DnsLookup::Ipv6Only => LookupIpStrategy::Ipv6Only,
+            DnsLookup::Ipv4AndIpv6 => LookupIpStrategy::Ipv4AndIpv6,
+        };
+        (config, opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_listens_on_both_ip_versions_and_websockets() {
+        let addresses: Vec<String> = ListenStrategy::default()
+            .addresses()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            addresses,
+            [
+                "/ip4/0.0.0.0/tcp/0",
+                "/ip6/::/tcp/0",
+                "/ip4/0.0.0.0/tcp/0/ws",
+                "/ip6/::/tcp/0/ws",
+            ]
+        );
+    }
+
+    #[test]
+    fn ipv6_listens_on_the_unspecified_address() {
+        let strategy = ListenStrategy {
+            ip: IpVersions::V6,
+            websocket: false,
+            ..ListenStrategy::default()
+        };
+        assert_eq!(
+            strategy.addresses(),
+            ["/ip6/::/tcp/0".parse::<Multiaddr>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn fixed_ports_are_used() {
+        let strategy = ListenStrategy {
+            ip: IpVersions::V4,
+            tcp_port: Some(4001),
+            websocket: false,
+            websocket_port: Some(4002),
+        };
+        assert_eq!(
+            strategy.addresses(),
+            ["/ip4/0.0.0.0/tcp/4001".parse::<Multiaddr>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn custom_dns_asks_every_server_over_udp_and_tcp() {
+        let config = NetworkConfig {
+            dns: DnsConfig::Custom(vec![
+                "10.0.0.53:53".parse().unwrap(),
+                "[fd00::53]:5353".parse().unwrap(),
+            ]),
+            dns_lookup: DnsLookup::Ipv4Only,
+            ..NetworkConfig::default()
+        };
+        let (resolver, opts) = config.resolver();
+        let servers: Vec<_> = resolver
+            .name_servers()
+            .iter()
+            .map(|server| server.socket_addr)
+            .collect();
+        assert_eq!(servers.len(), 4);
+        assert!(servers.contains(&"[fd00::53]:5353".parse().unwrap()));
+        assert_eq!(opts.ip_strategy, LookupIpStrategy::Ipv4Only);
+
+        let disabled = NetworkConfig {
+            dns: DnsConfig::Disabled,
+            ..NetworkConfig::default()
+        };
+        assert!(disabled.resolver().0.name_servers().is_empty());
+    }
+
+    #[test]
+    fn games_get_their_own_names() {
+        let demo = ProtocolNames::default();
+        assert_eq!(
+            demo.stream_protocol(crate::network::JOIN_PROTOCOL)
+                .unwrap()
+                .as_ref(),
+            "/bevy-p2p-demo/join/6"
+        );
+        assert_eq!(demo.key("room", "ABCD"), "/bevy-libp2p-demo/room/ABCD");
+
+        let game = ProtocolNames::new("my-game");
+        assert_eq!(game.protocol("/v1"), "/my-game/v1");
+        assert_eq!(game.key("room", "ABCD"), "/my-game/room/ABCD");
+
+        let broken = ProtocolNames {
+            prefix: "my-game".to_string(),
+            ..game
+        };
+        assert!(broken.stream_protocol("/v1").is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::prelude::{Component, Reflect};
+use libp2p::{core::ConnectedPoint, multiaddr::Protocol, swarm::ConnectionId, PeerId};
+use serde::{Deserialize, Serialize};
+
+/// How a connection to a peer is routed. Also a component of peer entities.
+#[derive(
+    Component,
+    Reflect,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum ConnectionPath {
+    /// Straight to the peer, possibly after hole punching.
+    Direct,
+    /// Through a circuit relay.
+    Relayed,
+}
+
+impl ConnectionPath {
+    pub fn of(endpoint: &ConnectedPoint) -> Self {
+        if endpoint
+            .get_remote_address()
+            .iter()
+            .any(|p| matches!(p, Protocol::P2pCircuit))
+        {
+            ConnectionPath::Relayed
+        } else {
+            ConnectionPath::Direct
+        }
+    }
+}
+
+/// The relay a relayed connection goes through, named in its address before the circuit.
+pub(super) fn relay_of(endpoint: &ConnectedPoint) -> Option<PeerId> {
+    let mut relay = None;
+    for protocol in endpoint.get_remote_address().iter() {
+        match protocol {
+            Protocol::P2p(peer_id) => relay = Some(peer_id),
+            Protocol::P2pCircuit => return relay,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A connection only replaces another on the same path if its RTT is this much better.
+const RTT_MARGIN: f32 = 0.8;
+
+#[derive(Debug, Clone)]
+struct Connection {
+    id: ConnectionId,
+    path: ConnectionPath,
+    relay: Option<PeerId>,
+    rtt: Option<Duration>,
+    /// Opened before our addresses last changed, so it may be dead without knowing.
+    stale: bool,
+}
+
+impl Connection {
+    /// Whether this connection is clearly better than `other`.
+    ///
+    /// Both ends run this independently, so it only answers `true` when they are
+    /// likely to agree, otherwise both sides could close different connections.
+    fn beats(&self, other: &Connection) -> bool {
+        if self.stale != other.stale {
+            return other.stale;
+        }
+        if self.path != other.path {
+            return self.path < other.path;
+        }
+        match (self.rtt, other.rtt) {
+            (Some(ours), Some(theirs)) => ours.as_secs_f32() < theirs.as_secs_f32() * RTT_MARGIN,
+            _ => false,
+        }
+    }
+}
+
+/// Every open connection per peer, used to close redundant ones.
+#[derive(Debug, Default)]
+pub(super) struct Connections(HashMap<PeerId, Vec<Connection>>);
+
+impl Connections {
+    pub(super) fn established(
+        &mut self,
+        peer_id: PeerId,
+        id: ConnectionId,
+        endpoint: &ConnectedPoint,
+    ) {
+        self.0.entry(peer_id).or_default().push(Connection {
+            id,
+            path: ConnectionPath::of(endpoint),
+            relay: relay_of(endpoint),
+            rtt: None,
+            stale: false,
+        });
+    }
+
+    pub(super) fn closed(&mut self, peer_id: &PeerId, id: ConnectionId) {
+        if let Some(connections) = self.0.get_mut(peer_id) {
+            connections.retain(|c| c.id != id);
+            if connections.is_empty() {
+                self.0.remove(peer_id);
+            }
+        }
+    }
+
+    pub(super) fn record_rtt(&mut self, peer_id: &PeerId, id: ConnectionId, rtt: Duration) {
+        if let Some(connection) = self
+            .0
+            .get_mut(peer_id)
+            .and_then(|connections| connections.iter_mut().find(|c| c.id == id))
+        {
+            connection.rtt = Some(rtt);
+        }
+    }
+
+    /// Marks every open connection as stale after a network change. Any connection
+    /// opened afterwards beats them, so they are closed as soon as it comes up.
+    pub(super) fn mark_stale(&mut self) {
+        for connection in self.0.values_mut().flatten() {
+            connection.stale = true;
+        }
+    }
+
+    /// The path of the best connection we currently have to `peer_id`.
+    pub(super) fn preferred_path(&self, peer_id: &PeerId) -> Option<ConnectionPath> {
+        self.preferred(peer_id).map(|c| c.path)
+    }
+
+    /// The relay the best connection to `peer_id` goes through, if it is relayed.
+    pub(super) fn relay(&self, peer_id: &PeerId) -> Option<PeerId> {
+        self.preferred(peer_id).and_then(|c| c.relay)
+    }
+
+    /// Round trip time over the best connection to `peer_id`, once measured.
+    pub(super) fn rtt(&self, peer_id: &PeerId) -> Option<Duration> {
+        self.preferred(peer_id).and_then(|c| c.rtt)
+    }
+
+    /// How many peers we only have relayed connections to.
+    pub(super) fn relayed(&self) -> usize {
+        self.0
+            .keys()
+            .filter(|peer_id| self.preferred_path(peer_id) == Some(ConnectionPath::Relayed))
+            .count()
+    }
+
+    /// Connections to `peer_id` that are beaten by the preferred one and should be closed.
+    pub(super) fn redundant(&self, peer_id: &PeerId) -> Vec<ConnectionId> {
+        let Some(best) = self.preferred(peer_id) else {
+            return Vec::new();
+        };
+        self.0[peer_id]
+            .iter()
+            .filter(|c| c.id != best.id && best.beats(c))
+            .map(|c| c.id)
+            .collect()
+    }
+
+    fn preferred(&self, peer_id: &PeerId) -> Option<&Connection> {
+        self.0
+            .get(peer_id)?
+            .iter()
+            .min_by_key(|c| (c.stale, c.path, c.rtt.unwrap_or(Duration::MAX)))
+    }
+}
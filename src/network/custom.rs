@@ -0,0 +1,172 @@
+//! A behaviour of the game's own, running in our swarm next to the stack in
+//! [`Behaviour`](super::Behaviour). Pass it to
+//! [`setup_network_with_behaviour`](super::setup_network_with_behaviour), reach it with
+//! [`NetworkManager::with_custom_behaviour`](super::NetworkManager::with_custom_behaviour)
+//! and read its events as [`CustomBehaviourEvent`]s after adding a
+//! [`CustomBehaviourPlugin`] for their type. Without the plugin they pile up unread.
+
+use std::{
+    any::{type_name, Any},
+    fmt,
+    marker::PhantomData,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use bevy::prelude::*;
+use libp2p::{
+    core::Endpoint,
+    identity,
+    swarm::{
+        ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, PollParameters, THandler,
+        THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId,
+};
+
+use super::NetworkManager;
+
+/// What the game can add to the swarm: any [`NetworkBehaviour`] that can move to the
+/// network thread. Combine several with `#[derive(NetworkBehaviour)]`.
+pub trait CustomBehaviour: NetworkBehaviour + Send {}
+
+impl<B: NetworkBehaviour + Send> CustomBehaviour for B {}
+
+/// Builds the custom behaviour for our identity, again on every network restart.
+pub(super) type CustomFactory<Custom> =
+    Arc<dyn Fn(&identity::Keypair) -> Custom + Send + Sync + 'static>;
+
+/// Work the game wants done on its custom behaviour, see
+/// [`NetworkManager::with_custom_behaviour`].
+pub(super) type CustomCall = Box<dyn FnOnce(&mut dyn Any) + Send>;
+
+/// An event of the custom behaviour, with its type erased so the rest of the stack
+/// doesn't depend on it.
+pub struct CustomEvent(pub(super) Box<dyn Any + Send>);
+
+impl fmt::Debug for CustomEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomEvent")
+    }
+}
+
+/// Holds the custom behaviour in [`Behaviour`](super::Behaviour), passing everything
+/// through but wrapping its events in [`CustomEvent`]s.
+pub struct CustomSlot<Custom>(pub(super) Custom);
+
+impl<Custom: CustomBehaviour> NetworkBehaviour for CustomSlot<Custom> {
+    type ConnectionHandler = Custom::ConnectionHandler;
+    type ToSwarm = CustomEvent;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        self.0
+            .handle_pending_inbound_connection(connection_id, local_addr, remote_addr)
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.0
+            .handle_established_inbound_connection(connection_id, peer, local_addr, remote_addr)
+    }
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        addresses: &[Multiaddr],
+        effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        self.0.handle_pending_outbound_connection(
+            connection_id,
+            maybe_peer,
+            addresses,
+            effective_role,
+        )
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        addr: &Multiaddr,
+        role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.0
+            .handle_established_outbound_connection(connection_id, peer, addr, role_override)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        self.0.on_swarm_event(event)
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        self.0
+            .on_connection_handler_event(peer_id, connection_id, event)
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        params: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        self.0
+            .poll(cx, params)
+            .map(|action| action.map_out(|event| CustomEvent(Box::new(event))))
+    }
+}
+
+/// An event of the custom behaviour, for a [`CustomBehaviourPlugin`] of its type.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct CustomBehaviourEvent<E>(pub E);
+
+/// Turns the custom behaviour's events, of type `E`, into [`CustomBehaviourEvent`]s.
+pub struct CustomBehaviourPlugin<E>(PhantomData<E>);
+
+impl<E> Default for CustomBehaviourPlugin<E> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<E: Send + Sync + 'static> Plugin for CustomBehaviourPlugin<E> {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CustomBehaviourEvent<E>>().add_systems(
+            PreUpdate,
+            forward_custom_events::<E, (), ()>.run_if(resource_exists::<NetworkManager<(), ()>>()),
+        );
+    }
+}
+
+fn forward_custom_events<E, FromGame, ToGame>(
+    manager: Res<NetworkManager<FromGame, ToGame>>,
+    mut events: EventWriter<CustomBehaviourEvent<E>>,
+) where
+    E: Send + Sync + 'static,
+    FromGame: Send + Sync + 'static,
+    ToGame: Send + Sync + 'static,
+{
+    while let Ok(CustomEvent(event)) = manager.custom_events.try_recv() {
+        match event.downcast::<E>() {
+            Ok(event) => events.send(CustomBehaviourEvent(*event)),
+            Err(_) => log::error!(
+                "Dropping a custom behaviour event: it is not a {}",
+                type_name::<E>()
+            ),
+        }
+    }
+}
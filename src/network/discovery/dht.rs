@@ -0,0 +1,116 @@
+use libp2p::{
+    kad::{self, QueryId, RecordKey},
+    Swarm,
+};
+
+use super::{DiscoveryUpdate, RoomDiscovery};
+use crate::network::{Behaviour, BehaviourEvent, CustomBehaviour, ProtocolNames};
+
+/// Hosts put a provider record for the room on the DHT and joiners look it up.
+pub(super) struct DhtDiscovery {
+    names: ProtocolNames,
+    announcing: Option<QueryId>,
+    finding: Option<QueryId>,
+}
+
+impl DhtDiscovery {
+    pub(super) fn new(names: ProtocolNames) -> Self {
+        Self {
+            names,
+            announcing: None,
+            finding: None,
+        }
+    }
+
+    /// DHT key the host of `room_code` provides.
+    fn room_key(&self, room_code: &str) -> RecordKey {
+        RecordKey::new(&self.names.key("room", room_code).as_bytes())
+    }
+}
+
+impl<Custom: CustomBehaviour> RoomDiscovery<Custom> for DhtDiscovery {
+    fn name(&self) -> &'static str {
+        "dht"
+    }
+
+    fn announce(
+        &mut self,
+        swarm: &mut Swarm<Behaviour<Custom>>,
+        room_code: &str,
+    ) -> anyhow::Result<()> {
+        let query = swarm
+            .behaviour_mut()
+            .kad
+            .start_providing(self.room_key(room_code))
+            .map_err(|e| anyhow::anyhow!("Could not store the provider record: {:?}", e))?;
+        self.announcing = Some(query);
+        Ok(())
+    }
+
+    fn withdraw(&mut self, swarm: &mut Swarm<Behaviour<Custom>>, room_code: &str) {
+        self.announcing = None;
+        swarm
+            .behaviour_mut()
+            .kad
+            .stop_providing(&self.room_key(room_code));
+    }
+
+    fn find(
+        &mut self,
+        swarm: &mut Swarm<Behaviour<Custom>>,
+        room_code: &str,
+    ) -> Vec<DiscoveryUpdate> {
+        let key = self.room_key(room_code);
+        self.finding = Some(swarm.behaviour_mut().kad.get_providers(key));
+        Vec::new()
+    }
+
+    fn on_event(&mut self, event: &BehaviourEvent<Custom>) -> Vec<DiscoveryUpdate> {
+        let BehaviourEvent::Kad(kad::KademliaEvent::OutboundQueryProgressed {
+            id,
+            result,
+            step,
+            ..
+        }) = event
+        else {
+            return Vec::new();
+        };
+        match result {
+            kad::QueryResult::StartProviding(result) if self.announcing == Some(*id) => {
+                self.announcing = None;
+                match result {
+                    Ok(kad::AddProviderOk { key }) => {
+                        log::info!("Started providing for our room: {:?}", key);
+                        Vec::new()
+                    }
+                    Err(e) => vec![DiscoveryUpdate::AnnounceFailed(format!(
+                        "Could not advertise the room: {}",
+                        e
+                    ))],
+                }
+            }
+            kad::QueryResult::GetProviders(result) if self.finding == Some(*id) => {
+                let mut updates = match result {
+                    Ok(kad::GetProvidersOk::FoundProviders { providers, .. }) => providers
+                        .iter()
+                        .map(|&peer_id| DiscoveryUpdate::Candidate {
+                            peer_id,
+                            addresses: Vec::new(),
+                        })
+                        .collect(),
+                    Ok(_) => Vec::new(),
+                    Err(e) => {
+                        log::warn!("Room lookup failed: {}", e);
+                        Vec::new()
+                    }
+                };
+                if step.last {
+                    self.finding = None;
+                    updates.push(DiscoveryUpdate::Finished);
+                }
+                updates
+            }
+            _ => Vec::new(),
+        }
+    }
+}
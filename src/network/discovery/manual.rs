@@ -0,0 +1,40 @@
+use libp2p::{Multiaddr, PeerId, Swarm};
+
+use super::{DiscoveryUpdate, RoomDiscovery};
+use crate::network::{Behaviour, CustomBehaviour};
+
+/// A host address the player typed in or got out of band, tried for any room code.
+pub(super) struct ManualDiscovery {
+    host: PeerId,
+    address: Multiaddr,
+}
+
+impl ManualDiscovery {
+    pub(super) fn new(host: PeerId, address: Multiaddr) -> Self {
+        Self { host, address }
+    }
+}
+
+impl<Custom: CustomBehaviour> RoomDiscovery<Custom> for ManualDiscovery {
+    fn name(&self) -> &'static str {
+        "manual"
+    }
+
+    fn finds_hosts_only(&self) -> bool {
+        false
+    }
+
+    fn find(
+        &mut self,
+        _swarm: &mut Swarm<Behaviour<Custom>>,
+        _room_code: &str,
+    ) -> Vec<DiscoveryUpdate> {
+        vec![
+            DiscoveryUpdate::Candidate {
+                peer_id: self.host,
+                addresses: vec![self.address.clone()],
+            },
+            DiscoveryUpdate::Finished,
+        ]
+    }
+}
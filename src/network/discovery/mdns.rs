@@ -0,0 +1,50 @@
+use libp2p::{mdns, Swarm};
+
+use super::{DiscoveryUpdate, RoomDiscovery};
+use crate::network::{Behaviour, BehaviourEvent, CustomBehaviour};
+
+/// Every peer mDNS finds on the local network is a candidate; the ones not hosting
+/// our room answer [`JoinResponse::UnknownRoom`](crate::network::JoinResponse::UnknownRoom)
+/// and get skipped. Hosts are announced by mDNS itself as soon as they listen.
+pub(super) struct MdnsDiscovery;
+
+impl<Custom: CustomBehaviour> RoomDiscovery<Custom> for MdnsDiscovery {
+    fn name(&self) -> &'static str {
+        "mdns"
+    }
+
+    fn finds_hosts_only(&self) -> bool {
+        false
+    }
+
+    fn find(
+        &mut self,
+        swarm: &mut Swarm<Behaviour<Custom>>,
+        _room_code: &str,
+    ) -> Vec<DiscoveryUpdate> {
+        let Some(mdns) = swarm.behaviour().mdns.as_ref() else {
+            return vec![DiscoveryUpdate::Finished];
+        };
+        // mDNS keeps the addresses, so the swarm finds them when dialing by peer id.
+        mdns.discovered_nodes()
+            .map(|&peer_id| DiscoveryUpdate::Candidate {
+                peer_id,
+                addresses: Vec::new(),
+            })
+            .chain([DiscoveryUpdate::Finished])
+            .collect()
+    }
+
+    fn on_event(&mut self, event: &BehaviourEvent<Custom>) -> Vec<DiscoveryUpdate> {
+        let BehaviourEvent::Mdns(mdns::Event::Discovered(peers)) = event else {
+            return Vec::new();
+        };
+        peers
+            .iter()
+            .map(|(peer_id, address)| DiscoveryUpdate::Candidate {
+                peer_id: *peer_id,
+                addresses: vec![address.clone()],
+            })
+            .collect()
+    }
+}
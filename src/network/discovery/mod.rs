@@ -0,0 +1,171 @@
+//! Ways to advertise a hosted room and to find the host of a room we want to join.
+//!
+//! Every [`DiscoveryMethod`] in [`NetworkConfig::discovery`] becomes one
+//! [`RoomDiscovery`], and they all run side by side: a host announces its room through
+//! each of them and a joiner tries candidates from whichever answers.
+
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId, Swarm};
+
+use super::{config::NetworkConfig, Behaviour, BehaviourEvent, CustomBehaviour};
+
+mod dht;
+mod manual;
+mod mdns;
+mod rendezvous;
+
+use dht::DhtDiscovery;
+use manual::ManualDiscovery;
+use mdns::MdnsDiscovery;
+use rendezvous::RendezvousDiscovery;
+
+/// One entry in [`NetworkConfig::discovery`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiscoveryMethod {
+    /// Provider records on the Kademlia DHT. Needs a bootstrapped routing table.
+    Dht,
+    /// A rendezvous server at this address, which must end in `/p2p/<peer id>`.
+    Rendezvous(Multiaddr),
+    /// Multicast DNS, for hosts on the same local network.
+    Mdns,
+    /// Skip the lookup and go straight to this host, whose address must end in
+    /// `/p2p/<peer id>`. Hosts have nothing to announce.
+    Manual(Multiaddr),
+}
+
+/// What a [`RoomDiscovery`] learned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum DiscoveryUpdate {
+    /// A peer that may host the room we are looking for. `addresses` may be empty if
+    /// the swarm can find them on its own.
+    Candidate {
+        peer_id: PeerId,
+        addresses: Vec<Multiaddr>,
+    },
+    /// The last [`RoomDiscovery::find`] has nothing more to report. Late candidates
+    /// are still welcome.
+    Finished,
+    /// Our room stopped being findable after [`RoomDiscovery::announce`] returned.
+    AnnounceFailed(String),
+}
+
+/// Advertises rooms and looks them up through one mechanism.
+///
+/// Calls only start the work; results arrive from [`on_event`](Self::on_event) as the
+/// swarm reports back.
+pub(super) trait RoomDiscovery<Custom: CustomBehaviour>: Send {
+    /// Short name used in logs and errors.
+    fn name(&self) -> &'static str;
+
+    /// Whether anyone can list the rooms announced here. Private matches skip these.
+    fn browsable(&self) -> bool {
+        false
+    }
+
+    /// Whether every candidate [`find`](Self::find) reports really announced the room,
+    /// rather than being any peer we know of. Only these can tell a host its room
+    /// code is already taken.
+    fn finds_hosts_only(&self) -> bool {
+        true
+    }
+
+    /// Makes us findable as the host of `room_code`.
+    fn announce(
+        &mut self,
+        _swarm: &mut Swarm<Behaviour<Custom>>,
+        _room_code: &str,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Stops advertising `room_code`.
+    fn withdraw(&mut self, _swarm: &mut Swarm<Behaviour<Custom>>, _room_code: &str) {}
+
+    /// Starts looking for the host of `room_code`, returning anything already known.
+    fn find(
+        &mut self,
+        swarm: &mut Swarm<Behaviour<Custom>>,
+        room_code: &str,
+    ) -> Vec<DiscoveryUpdate>;
+
+    /// Picks the results of earlier calls out of a behaviour event.
+    fn on_event(&mut self, _event: &BehaviourEvent<Custom>) -> Vec<DiscoveryUpdate> {
+        Vec::new()
+    }
+}
+
+/// Builds the discovery mechanisms `config` asks for, in order. The DHT is left out in
+/// [LAN-only](NetworkConfig::lan_only) mode since we never bootstrap into it.
+pub(super) fn from_config<Custom: CustomBehaviour>(
+    config: &NetworkConfig,
+) -> anyhow::Result<Vec<Box<dyn RoomDiscovery<Custom>>>> {
+    config
+        .discovery
+        .iter()
+        .filter(|method| !(config.lan_only && **method == DiscoveryMethod::Dht))
+        .map(|method| {
+            Ok(match method {
+                DiscoveryMethod::Dht => Box::new(DhtDiscovery::new(config.protocol.clone()))
+                    as Box<dyn RoomDiscovery<Custom>>,
+                DiscoveryMethod::Rendezvous(address) => {
+                    let (server, address) = split_peer_id(address)?;
+                    Box::new(RendezvousDiscovery::new(
+                        server,
+                        address,
+                        config.protocol.clone(),
+                    ))
+                }
+                DiscoveryMethod::Mdns => Box::new(MdnsDiscovery),
+                DiscoveryMethod::Manual(address) => {
+                    let (host, address) = split_peer_id(address)?;
+                    Box::new(ManualDiscovery::new(host, address))
+                }
+            })
+        })
+        .collect()
+}
+
+/// Splits the trailing `/p2p/<peer id>` off `address`.
+pub(super) fn split_peer_id(address: &Multiaddr) -> anyhow::Result<(PeerId, Multiaddr)> {
+    let mut stripped = address.clone();
+    match stripped.pop() {
+        Some(Protocol::P2p(peer_id)) => Ok((peer_id, stripped)),
+        _ => Err(anyhow::anyhow!(
+            "Address {} does not end in /p2p/<peer id>",
+            address
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::swarm::dummy;
+
+    use super::*;
+
+    #[test]
+    fn splits_ipv6_addresses() {
+        let peer_id = PeerId::random();
+        let address: Multiaddr = format!("/ip6/2001:db8::1/tcp/4001/p2p/{}", peer_id)
+            .parse()
+            .unwrap();
+        let (split, rest) = split_peer_id(&address).unwrap();
+        assert_eq!(split, peer_id);
+        assert_eq!(
+            rest,
+            "/ip6/2001:db8::1/tcp/4001".parse::<Multiaddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn manual_discovery_takes_ipv6_hosts() {
+        let config = NetworkConfig {
+            discovery: vec![DiscoveryMethod::Manual(
+                format!("/ip6/::1/tcp/4001/p2p/{}", PeerId::random())
+                    .parse()
+                    .unwrap(),
+            )],
+            ..NetworkConfig::default()
+        };
+        assert_eq!(from_config::<dummy::Behaviour>(&config).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,142 @@
+use libp2p::{
+    rendezvous::{self, Namespace},
+    swarm::dial_opts::DialOpts,
+    Multiaddr, PeerId, Swarm,
+};
+
+use super::{DiscoveryUpdate, RoomDiscovery};
+use crate::network::{Behaviour, BehaviourEvent, CustomBehaviour, ProtocolNames};
+
+/// Hosts register with a rendezvous server under the room code and joiners ask the
+/// server who registered.
+pub(super) struct RendezvousDiscovery {
+    server: PeerId,
+    address: Multiaddr,
+    names: ProtocolNames,
+}
+
+impl RendezvousDiscovery {
+    pub(super) fn new(server: PeerId, address: Multiaddr, names: ProtocolNames) -> Self {
+        Self {
+            server,
+            address,
+            names,
+        }
+    }
+
+    /// Rendezvous namespace the host of `room_code` registers under.
+    fn namespace(&self, room_code: &str) -> anyhow::Result<Namespace> {
+        Namespace::new(format!("{}/{}", self.names.namespace, room_code))
+            .map_err(|e| anyhow::anyhow!("Room code makes an invalid namespace: {}", e))
+    }
+
+    /// Requests to the server only go out once we are connected to it.
+    fn connect<Custom: CustomBehaviour>(&self, swarm: &mut Swarm<Behaviour<Custom>>) {
+        if swarm.is_connected(&self.server) {
+            return;
+        }
+        let opts = DialOpts::peer_id(self.server)
+            .addresses(vec![self.address.clone()])
+            .build();
+        if let Err(e) = swarm.dial(opts) {
+            log::warn!("Dialing rendezvous server {} failed: {}", self.server, e);
+        }
+    }
+}
+
+fn client<Custom: CustomBehaviour>(
+    swarm: &mut Swarm<Behaviour<Custom>>,
+) -> anyhow::Result<&mut rendezvous::client::Behaviour> {
+    swarm
+        .behaviour_mut()
+        .rendezvous
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("The rendezvous client is disabled"))
+}
+
+impl<Custom: CustomBehaviour> RoomDiscovery<Custom> for RendezvousDiscovery {
+    fn name(&self) -> &'static str {
+        "rendezvous"
+    }
+
+    /// Clients can discover without a namespace and get every registration.
+    fn browsable(&self) -> bool {
+        true
+    }
+
+    fn announce(
+        &mut self,
+        swarm: &mut Swarm<Behaviour<Custom>>,
+        room_code: &str,
+    ) -> anyhow::Result<()> {
+        let namespace = self.namespace(room_code)?;
+        self.connect(swarm);
+        client(swarm)?
+            .register(namespace, self.server, None)
+            .map_err(|e| anyhow::anyhow!("Could not register the room: {:?}", e))
+    }
+
+    fn withdraw(&mut self, swarm: &mut Swarm<Behaviour<Custom>>, room_code: &str) {
+        if let (Ok(namespace), Ok(client)) = (self.namespace(room_code), client(swarm)) {
+            client.unregister(namespace, self.server);
+        }
+    }
+
+    fn find(
+        &mut self,
+        swarm: &mut Swarm<Behaviour<Custom>>,
+        room_code: &str,
+    ) -> Vec<DiscoveryUpdate> {
+        let namespace = match self.namespace(room_code) {
+            Ok(namespace) => namespace,
+            Err(e) => {
+                log::warn!("{}", e);
+                return vec![DiscoveryUpdate::Finished];
+            }
+        };
+        self.connect(swarm);
+        match client(swarm) {
+            Ok(client) => {
+                client.discover(Some(namespace), None, None, self.server);
+                Vec::new()
+            }
+            Err(_) => vec![DiscoveryUpdate::Finished],
+        }
+    }
+
+    fn on_event(&mut self, event: &BehaviourEvent<Custom>) -> Vec<DiscoveryUpdate> {
+        match event {
+            BehaviourEvent::Rendezvous(rendezvous::client::Event::Discovered {
+                rendezvous_node,
+                registrations,
+                ..
+            }) if *rendezvous_node == self.server => registrations
+                .iter()
+                .map(|registration| DiscoveryUpdate::Candidate {
+                    peer_id: registration.record.peer_id(),
+                    addresses: registration.record.addresses().to_vec(),
+                })
+                .chain([DiscoveryUpdate::Finished])
+                .collect(),
+            BehaviourEvent::Rendezvous(rendezvous::client::Event::DiscoverFailed {
+                rendezvous_node,
+                error,
+                ..
+            }) if *rendezvous_node == self.server => {
+                log::warn!("Rendezvous lookup failed: {:?}", error);
+                vec![DiscoveryUpdate::Finished]
+            }
+            BehaviourEvent::Rendezvous(rendezvous::client::Event::RegisterFailed {
+                rendezvous_node,
+                error,
+                ..
+            }) if *rendezvous_node == self.server => {
+                vec![DiscoveryUpdate::AnnounceFailed(format!(
+                    "Rendezvous server refused the room: {:?}",
+                    error
+                ))]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
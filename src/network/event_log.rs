@@ -0,0 +1,208 @@
+use std::{collections::VecDeque, fmt, time::Instant};
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+/// How much a [`LogEntry`] matters, from least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 5] = [
+        Severity::Trace,
+        Severity::Debug,
+        Severity::Info,
+        Severity::Warn,
+        Severity::Error,
+    ];
+
+    pub(super) fn level(self) -> log::Level {
+        match self {
+            Severity::Trace => log::Level::Trace,
+            Severity::Debug => log::Level::Debug,
+            Severity::Info => log::Level::Info,
+            Severity::Warn => log::Level::Warn,
+            Severity::Error => log::Level::Error,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Something the network thread noticed.
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    /// Position in the log, increasing by one per entry.
+    pub seq: u64,
+    pub at: Instant,
+    pub severity: Severity,
+    /// The part of the stack that produced it, e.g. `"swarm"` or `"kad"`.
+    pub target: &'static str,
+    pub peer: Option<PeerId>,
+    pub message: String,
+}
+
+/// Which entries a reader cares about. The default lets everything through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    pub min_severity: Severity,
+    pub target: Option<&'static str>,
+    pub peer: Option<PeerId>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            min_severity: Severity::Trace,
+            target: None,
+            peer: None,
+        }
+    }
+}
+
+impl LogFilter {
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        entry.severity >= self.min_severity
+            && self.target.map_or(true, |target| target == entry.target)
+            && self.peer.map_or(true, |peer| entry.peer == Some(peer))
+    }
+}
+
+/// A reader's position in the [`NetworkLog`], like an `EventReader` for log entries.
+#[derive(Debug, Clone, Default)]
+pub struct LogSubscription {
+    pub filter: LogFilter,
+    next_seq: u64,
+}
+
+impl LogSubscription {
+    pub fn new(filter: LogFilter) -> Self {
+        Self {
+            filter,
+            next_seq: 0,
+        }
+    }
+}
+
+/// The most recent swarm and behaviour events, oldest first.
+#[derive(Resource, Debug)]
+pub struct NetworkLog {
+    entries: VecDeque<LogEntry>,
+    capacity: usize,
+    started: Instant,
+}
+
+impl Default for NetworkLog {
+    fn default() -> Self {
+        Self::with_capacity(1000)
+    }
+}
+
+impl NetworkLog {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            started: Instant::now(),
+        }
+    }
+
+    /// When the log was created, for showing entry times relative to it.
+    pub fn started(&self) -> Instant {
+        self.started
+    }
+
+    /// Appends `entry`, dropping the oldest one once full.
+    pub fn push(&mut self, entry: LogEntry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &LogEntry> {
+        self.entries.iter()
+    }
+
+    /// Entries still in the log that pass `filter`.
+    pub fn filtered<'a>(&'a self, filter: &'a LogFilter) -> impl Iterator<Item = &'a LogEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| filter.matches(entry))
+    }
+
+    /// Entries matching the subscription's filter that were added since its last read.
+    /// Entries that fell off the end of the log before being read are skipped.
+    pub fn read<'a>(
+        &'a self,
+        subscription: &'a mut LogSubscription,
+    ) -> impl Iterator<Item = &'a LogEntry> {
+        let from = subscription.next_seq;
+        if let Some(last) = self.entries.back() {
+            subscription.next_seq = last.seq + 1;
+        }
+        let filter = &subscription.filter;
+        self.entries
+            .iter()
+            .filter(move |entry| entry.seq >= from && filter.matches(entry))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(seq: u64, severity: Severity) -> LogEntry {
+        LogEntry {
+            seq,
+            at: Instant::now(),
+            severity,
+            target: "swarm",
+            peer: None,
+            message: seq.to_string(),
+        }
+    }
+
+    #[test]
+    fn subscriptions_only_see_new_matching_entries() {
+        let mut log = NetworkLog::with_capacity(3);
+        let mut warnings = LogSubscription::new(LogFilter {
+            min_severity: Severity::Warn,
+            ..Default::default()
+        });
+        log.push(entry(0, Severity::Info));
+        log.push(entry(1, Severity::Error));
+        let seen: Vec<u64> = log.read(&mut warnings).map(|e| e.seq).collect();
+        assert_eq!(seen, [1]);
+
+        for seq in 2..6 {
+            log.push(entry(seq, Severity::Warn));
+        }
+        assert_eq!(log.len(), 3, "oldest entries are dropped");
+        let seen: Vec<u64> = log.read(&mut warnings).map(|e| e.seq).collect();
+        assert_eq!(seen, [3, 4, 5]);
+        assert_eq!(log.read(&mut warnings).count(), 0);
+    }
+}
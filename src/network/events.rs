@@ -0,0 +1,538 @@
+use std::{net::IpAddr, path::PathBuf, time::Duration};
+
+use bevy::prelude::*;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ConnectionPath, JoinError, LinkConditions, NetworkSetupError, PeerProfile, Priority, RoomInfo,
+    Snapshot, TickSettings,
+};
+
+/// Messages sent from the game into the network thread via
+/// [`NetworkManager::send_to_network`](super::NetworkManager::send_to_network).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameEvent<FromGame> {
+    /// Control of the network stack itself.
+    Admin(GameAdminEvent),
+    /// A game specific payload to hand to the other peers, at [`Priority::Normal`].
+    Game(FromGame),
+    /// Like [`GameEvent::Game`], but goes out ahead of or behind other game messages
+    /// when the network thread has a backlog.
+    Prioritized { priority: Priority, event: FromGame },
+    /// A game specific payload on the latest-wins channel, e.g. a state snapshot. Peers
+    /// only get it if they haven't had a higher `seq` from us yet, and tell us whether
+    /// it arrived with [`NetworkAdminEvent::Delivery`]. `seq` has to go up with every
+    /// message, like a tick. Sent at [`Priority::Normal`].
+    Latest { seq: u32, event: FromGame },
+    /// A game specific payload only `to` can read, e.g. their hand of cards. Sent at
+    /// [`Priority::Normal`].
+    Unicast { to: PeerId, event: FromGame },
+    /// A game specific payload only members of `team` can read, e.g. team chat or the
+    /// team's fog of war. Sent at [`Priority::Normal`], and dropped unless we are on the
+    /// team.
+    Team { team: String, event: FromGame },
+    /// Like [`GameEvent::Admin`], but answered with [`NetworkAdminEvent::CommandCompleted`]
+    /// or [`NetworkAdminEvent::CommandFailed`] carrying `id`, once `command` took effect
+    /// or couldn't.
+    Command {
+        id: CommandId,
+        command: GameAdminEvent,
+    },
+}
+
+/// A quick canned message, for when there's no time to type. See
+/// [`GameAdminEvent::Emote`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Emote {
+    ThumbsUp,
+    GoodGame,
+    Help,
+}
+
+impl Emote {
+    pub const ALL: [Emote; 3] = [Emote::ThumbsUp, Emote::GoodGame, Emote::Help];
+
+    /// How the emote reads on screen.
+    pub fn text(self) -> &'static str {
+        match self {
+            Emote::ThumbsUp => "+1",
+            Emote::GoodGame => "GG",
+            Emote::Help => "Help!",
+        }
+    }
+}
+
+/// How far a join got, see [`NetworkAdminEvent::JoinProgress`]. Each stage but the
+/// last has a timeout in [`JoinStageTimeouts`](super::JoinStageTimeouts).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum JoinStage {
+    /// Asking discovery who hosts the room.
+    ResolvingRoom,
+    /// Connecting to a candidate host and sending it our hello.
+    DialingHost,
+    /// Only a relay connects us to the host so far, and we try to reach it directly.
+    HolePunching,
+    /// Proving we know the room code, and getting the room key in return.
+    ExchangingKeys,
+    /// Let in. Catching up with the room, see [`LateJoin`](crate::late_join::LateJoin).
+    SyncingState,
+}
+
+impl JoinStage {
+    /// In the order joins go through them. Hole punching is skipped when the host is
+    /// reachable directly.
+    pub const ALL: [JoinStage; 5] = [
+        JoinStage::ResolvingRoom,
+        JoinStage::DialingHost,
+        JoinStage::HolePunching,
+        JoinStage::ExchangingKeys,
+        JoinStage::SyncingState,
+    ];
+
+    /// How the stage reads on screen.
+    pub fn text(self) -> &'static str {
+        match self {
+            JoinStage::ResolvingRoom => "Resolving room",
+            JoinStage::DialingHost => "Dialing host",
+            JoinStage::HolePunching => "Hole punching",
+            JoinStage::ExchangingKeys => "Exchanging keys",
+            JoinStage::SyncingState => "Syncing state",
+        }
+    }
+}
+
+/// Ties a [`GameEvent::Command`] to its answer. Handed out by
+/// [`AsyncNetworkTasks::command`](super::AsyncNetworkTasks::command).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CommandId(pub(super) u64);
+
+/// For things like killing the swarm and replacing it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameAdminEvent {
+    /// Start listening and announce `room_code` through discovery. A private match
+    /// passes an [`InviteToken`](crate::room_code::InviteToken) instead, which is only
+    /// announced as a hash and never where it can be browsed.
+    Host { room_code: String },
+    /// Look `room_code` (or an invite token) up and ask its host to let us in as
+    /// `name`.
+    Join { room_code: String, name: String },
+    /// Host only: what to publish about the room in its [`RoomInfo`]. The player count
+    /// and started flag are kept up to date by the network thread.
+    DescribeRoom {
+        mode: String,
+        max_players: Option<u32>,
+    },
+    /// The game modes we can play, by [`RoomInfo::mode`]. Joins skip rooms playing any
+    /// other mode and hosts turn away joiners without the room's. Empty, the default,
+    /// plays anything.
+    SetModes(Vec<String>),
+    /// Host only: put `members` on `team`, creating it if needed. The team gets a
+    /// topic of its own under a key only its members are given, and a new key whenever
+    /// its members change. No members disbands the team. Teams last until the room
+    /// is left.
+    SetTeam { team: String, members: Vec<PeerId> },
+    /// Host only: take `peer_id` off its team and put it on `team`, if any. Both teams
+    /// switch to new keys, so it can't read its old team anymore.
+    MoveToTeam {
+        peer_id: PeerId,
+        team: Option<String>,
+    },
+    /// Host only: answer a [`NetworkAdminEvent::JoinRequested`].
+    AnswerJoin { peer_id: PeerId, accept: bool },
+    /// Leave the room we are in, telling its peers, and keep the network running.
+    /// Answered with [`NetworkAdminEvent::LeftRoom`].
+    Leave,
+    /// Tell the room we are leaving and stop the network thread. Answered with
+    /// [`NetworkAdminEvent::ShutdownComplete`].
+    Quit,
+    /// Tear the swarm down and build a fresh one, e.g. after the relay went bad or to
+    /// shed a peer id. Game state is untouched: the room we were hosting is hosted
+    /// again, or the room we were in rejoined, and settings like spectating and
+    /// throttles carry over. `new_identity` gets us a new peer id, and `relay`
+    /// replaces [`NetworkConfig::relay`](super::NetworkConfig::relay). Answered with
+    /// [`NetworkAdminEvent::NetworkRestarted`] or
+    /// [`NetworkAdminEvent::NetworkRestartFailed`].
+    RestartNetwork {
+        new_identity: bool,
+        relay: Option<Multiaddr>,
+    },
+    /// Limit how many gossip messages from a peer reach the game.
+    Throttle { peer_id: PeerId, throttled: bool },
+    /// Drop every connection to a peer and ignore its gossip from now on.
+    Disconnect(PeerId),
+    /// Host only: start the game, sharing `seed` with every peer. Everyone starts
+    /// playing once `countdown` has run out, at `tick` if given.
+    StartGame {
+        seed: u64,
+        countdown: Duration,
+        tick: Option<TickSettings>,
+    },
+    /// Tell the host what we measured about our links and machine, so it can pick
+    /// [`TickSettings`] that suit us. Hosts keep their own.
+    ReportConditions(LinkConditions),
+    /// Host only: switch the game in progress to new `TickSettings`, e.g. because a
+    /// link got worse. Everyone, us included, gets a
+    /// [`NetworkAdminEvent::TickSettingsChanged`].
+    ChangeTickSettings(TickSettings),
+    /// Tell the room whether we are ready for the game to start.
+    SetReady(bool),
+    /// Only watch the game: game messages from now on are dropped instead of sent, and
+    /// the room is told we are a spectator.
+    SetSpectating(bool),
+    /// Sign `profile` with our identity key and show it to the room, now and to
+    /// whoever joins later. Send it again to change name mid-session.
+    SetProfile(PeerProfile),
+    /// Host only: start round `round` with a fresh `seed`.
+    NewRound { round: u32, seed: u64 },
+    /// Look a peer up on the DHT and connect to it if it's out there. Answered with
+    /// [`NetworkAdminEvent::PeerFound`] or [`NetworkAdminEvent::PeerNotFound`].
+    FindPeer(PeerId),
+    /// Send `peer_id` an invite straight to its client, whether or not it's in a room.
+    Invite { peer_id: PeerId, room_code: String },
+    /// Leave an invite in `peer_id`'s DHT mailbox, for a friend who isn't online right
+    /// now. They find it the next time they start up. Answered with
+    /// [`NetworkAdminEvent::InvitePosted`] or [`NetworkAdminEvent::InviteFailed`].
+    PostInvite { peer_id: PeerId, room_code: String },
+    /// Look in our DHT mailbox for invites, as is done after every bootstrap.
+    CheckMailbox,
+    /// Dial `address` directly, for testing connectivity by hand.
+    Dial(Multiaddr),
+    /// Ask the DHT who provides `key`. Answered with
+    /// [`NetworkAdminEvent::ProvidersFound`].
+    GetProviders(String),
+    /// Host only: hand every peer in the room a fresh room key and switch to it.
+    /// Messages under the old key are still accepted.
+    RotateRoomKey,
+    /// Host only: like [`RotateRoomKey`](Self::RotateRoomKey), for when a key may be in
+    /// the wrong hands. Team keys are replaced too, and old keys stop decrypting after
+    /// [`COMPROMISED_KEY_GRACE`](crate::crypto::COMPROMISED_KEY_GRACE). Also done
+    /// whenever the host [disconnects](Self::Disconnect) a player in its room.
+    RotateKeysNow,
+    /// Late joiner: ask the host for the state of the game in progress. Answered with
+    /// [`NetworkAdminEvent::SnapshotReceived`] or
+    /// [`NetworkAdminEvent::SnapshotFailed`].
+    RequestSnapshot,
+    /// Host only: answer a [`NetworkAdminEvent::SnapshotRequested`]. `None` refuses.
+    AnswerSnapshot {
+        peer_id: PeerId,
+        snapshot: Option<Snapshot>,
+    },
+    /// Hold back game messages from other peers by this much before handing them to
+    /// the game, to try out how it copes with lag. Zero turns it off.
+    SimulateLatency(Duration),
+    /// Start taping every room message we send or get, dropping any tape not saved
+    /// yet. See [`TapeConfig`](super::TapeConfig).
+    RecordTape,
+    /// Stop taping and write the tape to `path`. Answered with
+    /// [`NetworkAdminEvent::TapeSaved`] or [`NetworkAdminEvent::TapeFailed`].
+    SaveTape(PathBuf),
+    /// Feed the messages we got on the tape at `path` back in as if they just came in,
+    /// at the pace they did. Best done outside a room, as anything we answer goes out.
+    /// Answered with [`NetworkAdminEvent::TapePlayed`] or
+    /// [`NetworkAdminEvent::TapeFailed`].
+    PlayTape(PathBuf),
+    /// Offer the file at `path` to `peer_id`, sending it once their player accepts.
+    /// Answered with [`NetworkAdminEvent::FileSent`], [`NetworkAdminEvent::FileDeclined`]
+    /// or [`NetworkAdminEvent::FileFailed`]. See [`FileDropConfig`](super::FileDropConfig).
+    SendFile { peer_id: PeerId, path: PathBuf },
+    /// Accept or decline a [`NetworkAdminEvent::FileOffered`].
+    AnswerFileOffer {
+        peer_id: PeerId,
+        transfer: u64,
+        accept: bool,
+    },
+    /// Say `0` in the room's chat.
+    Chat(String),
+    /// Host only: mute, or unmute, `peer_id` for everyone in the room.
+    RoomMute { peer_id: PeerId, muted: bool },
+    /// Show the room an [`Emote`]. Not rate limited here; the game has to.
+    Emote(Emote),
+    /// Ask the host to let us simulate the entity with network id `id`. Answered with
+    /// [`NetworkAdminEvent::AuthorityChanged`] or [`NetworkAdminEvent::AuthorityDenied`].
+    RequestAuthority(u64),
+    /// Hand the entity with network id `id` back to the host.
+    ReleaseAuthority(u64),
+    /// Host only: give the entity with network id `id` to `owner`, whoever has it now.
+    TransferAuthority { id: u64, owner: PeerId },
+    /// Tell the room our game is still running, as opposed to just the network thread.
+    /// [`PeerPlugin`](crate::peer::PeerPlugin) sends one every second; dropped outside
+    /// a room.
+    Heartbeat,
+}
+
+/// Events coming out of the network thread, surfaced as a Bevy [`Event`] by
+/// [`NetworkPlugin`](super::NetworkPlugin).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Event)]
+pub enum NetworkEvent<ToGame> {
+    /// Something happened to the network stack or one of our peers.
+    Admin(NetworkAdminEvent),
+    /// A game specific payload received from another peer.
+    Game { source: PeerId, event: ToGame },
+    /// A game specific payload `source` sent to `team`, which we are on.
+    Team {
+        team: String,
+        source: PeerId,
+        event: ToGame,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NetworkAdminEvent {
+    /// A peer speaking our identify protocol was found.
+    Connected(PeerId),
+    /// The connection to a peer was closed.
+    Disconnected(PeerId),
+    /// A peer announced it is leaving our room. Its connection may linger a bit, but it
+    /// is gone from the game's point of view.
+    PlayerLeft(PeerId),
+    /// The DHT bootstrap finished with `peers` in our routing table.
+    Bootstrapped { peers: usize },
+    /// The DHT bootstrap left our routing table empty, so rooms can't be found or
+    /// announced over the DHT.
+    BootstrapFailed { reason: String },
+    /// We are now reachable on a new address.
+    NewNetworkAddress(Multiaddr),
+    /// Host only: we could not listen on `address`, or stopped listening on it. Hosting
+    /// carries on over the addresses that work, see
+    /// [`ListenStrategy`](super::ListenStrategy).
+    ListenFailed { address: Multiaddr, reason: String },
+    /// A peer reported seeing us at `address`. Once `confirmations` reaches a few
+    /// distinct peers it is `confirmed` and advertised as an external address.
+    ExternalAddressObserved {
+        address: Multiaddr,
+        confirmations: usize,
+        confirmed: bool,
+    },
+    /// Our addresses changed, e.g. we moved from Wi-Fi to Ethernet. Listeners were
+    /// reopened and the peers in `redialed` are being reconnected; connections from
+    /// before the change close once their replacements are up.
+    NetworkMigrated {
+        gained: Vec<IpAddr>,
+        lost: Vec<IpAddr>,
+        redialed: Vec<PeerId>,
+    },
+    /// The machine slept for about `slept`, e.g. a laptop lid was closed. Our room
+    /// announcements and relay reservation were renewed, the DHT bootstrapped again,
+    /// and the peers in `redialed` are being reconnected.
+    Resumed {
+        slept: Duration,
+        redialed: Vec<PeerId>,
+    },
+    /// A ping round trip to a peer completed.
+    Latency { peer_id: PeerId, rtt: Duration },
+    /// A ping to a peer failed or timed out.
+    PingFailed(PeerId),
+    /// A gossip message from a peer could not be decoded.
+    DecodeFailed(PeerId),
+    /// Another host already announced `room_code`, so we didn't. Host again with a
+    /// fresh code.
+    RoomCodeTaken { room_code: String },
+    /// We are listening and `room_code` is announced, so joiners can find us.
+    HostingStarted { room_code: String },
+    /// Hosting could not be set up or broke down, and has been torn down. Sending
+    /// [`GameAdminEvent::Host`] again retries.
+    HostingFailed { reason: String },
+    /// Host only: a peer asks to join our room. Answer with [`GameAdminEvent::AnswerJoin`].
+    JoinRequested { peer_id: PeerId, name: String },
+    /// Host only: `peer_id` dropped without saying goodbye and came back in time to
+    /// take its slot again, see [`JoinLimits::reclaim_window`](super::JoinLimits::reclaim_window).
+    /// It was let in without a [`JoinRequested`](Self::JoinRequested): treat it as the
+    /// player it was, not a new one.
+    SlotReclaimed { peer_id: PeerId, name: String },
+    /// Host only: `peer_id` didn't come back in time, and its slot is free for others.
+    SlotReleased(PeerId),
+    /// While joining: `host` describes the room as `info`. Hosts whose room is full or
+    /// started are skipped without dialing them.
+    RoomInfoFound { host: PeerId, info: RoomInfo },
+    /// Our join moved on to `JoinStage`. Sent before the join is accepted or fails.
+    /// [`GameAdminEvent::Leave`] cancels it.
+    JoinProgress(JoinStage),
+    /// The host admitted us to `room_code` and we can now talk in the room.
+    JoinAccepted { host: PeerId, room_code: String },
+    /// Answer to [`GameAdminEvent::Leave`]: we said goodbye and are out of the room.
+    LeftRoom,
+    /// Our join is over without getting us in, for the reason given.
+    JoinFailed(JoinError),
+    /// Too many DHT queries are running, so our join or lookup waits in line behind
+    /// `queued - 1` others.
+    DhtBusy { queued: usize },
+    /// The host started the game, which begins once `countdown` runs out, at `tick`
+    /// if the host picked one. Also sent to the host itself.
+    GameStarted {
+        seed: u64,
+        countdown: Duration,
+        tick: Option<TickSettings>,
+    },
+    /// Host only: `peer_id` reported what it measured, see
+    /// [`GameAdminEvent::ReportConditions`].
+    ConditionsReported {
+        peer_id: PeerId,
+        conditions: LinkConditions,
+    },
+    /// The host switched the game in progress to `TickSettings`. Also sent to the host
+    /// itself.
+    TickSettingsChanged(TickSettings),
+    /// A peer in our room is, or no longer is, ready for the game to start.
+    PeerReady { peer_id: PeerId, ready: bool },
+    /// `peer_id` only watches the game, or stopped doing so.
+    PeerSpectating { peer_id: PeerId, spectating: bool },
+    /// `peer_id` goes by `profile`. Only sent once its signature checks out, and never
+    /// for a profile older than one we already got from it.
+    ProfileReceived {
+        peer_id: PeerId,
+        profile: PeerProfile,
+    },
+    /// Host only: [`GameAdminEvent::StartGame`] is held back until `waiting` are in our
+    /// gossip mesh and hold the current room key. It goes out by itself once they do,
+    /// or once they disconnect.
+    StartDelayed { waiting: Vec<PeerId> },
+    /// `peer_id`'s game sent a [`GameAdminEvent::Heartbeat`]. See
+    /// [`PeerStats::unresponsive`](crate::peer::PeerStats::unresponsive).
+    Heartbeat(PeerId),
+    /// The host started a new round. Also sent to the host itself.
+    RoundStarted { round: u32, seed: u64 },
+    /// Answer to [`GameAdminEvent::Quit`]: peers were told and the network thread is
+    /// done. Nothing else is sent after this.
+    ShutdownComplete,
+    /// The network thread stopped without being asked to, e.g. it panicked. Raised on
+    /// the game's side; nothing is sent or received after this. See
+    /// [`NetworkFailurePolicy`](super::NetworkFailurePolicy).
+    NetworkDied,
+    /// The network thread hasn't come around its loop for `stalled_for`. Raised on the
+    /// game's side, see [`NetworkWatchdog`](super::NetworkWatchdog), which may replace
+    /// the thread and follow up with a `NetworkRestarted`.
+    NetworkStalled { stalled_for: Duration },
+    /// Answer to [`GameAdminEvent::RestartNetwork`], or a stalled network thread was
+    /// replaced: the new swarm is up as `local_peer_id`, and getting back into our room.
+    NetworkRestarted { local_peer_id: PeerId },
+    /// Answer to [`GameAdminEvent::RestartNetwork`]: the new swarm could not be built,
+    /// so the old one carries on.
+    NetworkRestartFailed { reason: String },
+    /// The network a [`NetworkSetup`](super::NetworkSetup) asked for could not be
+    /// started, so there is none. Raised on the game's side.
+    NetworkSetupFailed(NetworkSetupError),
+    /// Answer to [`GameAdminEvent::FindPeer`]: the peer is reachable and we are
+    /// dialing it.
+    PeerFound(PeerId),
+    /// Answer to [`GameAdminEvent::FindPeer`]: nobody on the DHT knows the peer.
+    PeerNotFound(PeerId),
+    /// `peer_id` subscribed to gossip `topic`. See [`MeshHealth`](super::MeshHealth).
+    PeerSubscribed { peer_id: PeerId, topic: String },
+    /// `peer_id` unsubscribed from gossip `topic`.
+    PeerUnsubscribed { peer_id: PeerId, topic: String },
+    /// `peer_id` joined our mesh for `topic`, so our messages on it now go there.
+    MeshGrafted { peer_id: PeerId, topic: String },
+    /// `peer_id` left our mesh for `topic`.
+    MeshPruned { peer_id: PeerId, topic: String },
+    /// We gave up dialing a peer, or a bare address when `peer_id` is unknown, after
+    /// retrying with backoff. `addresses` are the ones we were told to try.
+    DialFailed {
+        peer_id: Option<PeerId>,
+        addresses: Vec<Multiaddr>,
+        reason: String,
+    },
+    /// Answer to [`GameAdminEvent::GetProviders`].
+    ProvidersFound { key: String, providers: Vec<PeerId> },
+    /// Answer to [`GameAdminEvent::SaveTape`].
+    TapeSaved { path: PathBuf, entries: usize },
+    /// A [`GameAdminEvent::PlayTape`] ran out after feeding `entries` messages back in.
+    TapePlayed { path: PathBuf, entries: usize },
+    /// Saving or playing the tape at `path` failed.
+    TapeFailed { path: PathBuf, reason: String },
+    /// `peer_id` wants to send us a file. Answer with
+    /// [`GameAdminEvent::AnswerFileOffer`] before the offer times out.
+    FileOffered {
+        peer_id: PeerId,
+        transfer: u64,
+        name: String,
+        size: u64,
+    },
+    /// A file from `peer_id` was saved to `path`.
+    FileReceived { peer_id: PeerId, path: PathBuf },
+    /// `peer_id` got the whole of a file we sent.
+    FileSent { peer_id: PeerId, name: String },
+    /// `peer_id` didn't want a file we offered.
+    FileDeclined { peer_id: PeerId, name: String },
+    /// A file transfer to or from `peer_id` broke off.
+    FileFailed {
+        peer_id: PeerId,
+        name: String,
+        reason: String,
+    },
+    /// The room now encrypts with a new key, after a [`GameAdminEvent::RotateRoomKey`]
+    /// by us or the host.
+    RoomKeyRotated,
+    /// The host put `members` on `team`. Empty when the team was disbanded.
+    TeamChanged { team: String, members: Vec<PeerId> },
+    /// `from` invited us to `room_code`. Nothing checks who `from` is, so only act on
+    /// invites from peers you know.
+    InviteReceived { from: PeerId, room_code: String },
+    /// `peer_id` got the invite we sent it directly.
+    InviteDelivered(PeerId),
+    /// An invite we sent never arrived.
+    InviteFailed { peer_id: PeerId, reason: String },
+    /// The invite is in `peer_id`'s mailbox.
+    InvitePosted(PeerId),
+    /// `from` left an invite to `room_code` in our mailbox while we were away. The
+    /// invite is signed by `from`, but whether to trust `from` is up to the game.
+    JoinInvitation {
+        from: PeerId,
+        room_code: String,
+        /// Seconds since the Unix epoch.
+        sent_at: u64,
+    },
+    /// The connection we keep to a peer now goes over `path`, through `relay` if it is
+    /// relayed.
+    ConnectionPathChanged {
+        peer_id: PeerId,
+        path: ConnectionPath,
+        relay: Option<PeerId>,
+    },
+    /// Hole punching turned our relayed connection to `peer_id` into a direct one.
+    HolePunchSucceeded(PeerId),
+    /// Hole punching to `peer_id` failed, so we stay on the relay.
+    HolePunchFailed { peer_id: PeerId, reason: String },
+    /// We reached `peer_id` only through the relay for `relayed_for`, exchanging `bytes`
+    /// of room messages, past [`RelayUsageLimits`](super::RelayUsageLimits). Relays are
+    /// shared and slow, so players should forward a port or enable UPnP. Sent once per
+    /// stay on the relay.
+    RelayUsageWarning {
+        peer_id: PeerId,
+        relayed_for: Duration,
+        bytes: u64,
+    },
+    /// Host only: `peer_id` joined while the game is on and needs its state. Answer
+    /// with [`GameAdminEvent::AnswerSnapshot`].
+    SnapshotRequested(PeerId),
+    /// The host's game state, or `None` if its game hasn't started yet.
+    SnapshotReceived(Option<Snapshot>),
+    /// The host couldn't or wouldn't send its game state.
+    SnapshotFailed { reason: String },
+    /// `from` said `text` in the room's chat. Whether `from` is muted is up to the game.
+    ChatReceived { from: PeerId, text: String },
+    /// `from` sent an [`Emote`]. As with chat, muting and rate limits are up to the game.
+    EmoteReceived { from: PeerId, emote: Emote },
+    /// The host muted, or unmuted, `peer_id` for the whole room. Also sent to the host
+    /// itself.
+    RoomMuted { peer_id: PeerId, muted: bool },
+    /// `peer_id` got our [`GameEvent::Latest`] messages numbered `acked`, and never will
+    /// get those numbered `lost`. Only peers that send latest-wins messages themselves
+    /// report back, as the acks ride along on those.
+    Delivery {
+        peer_id: PeerId,
+        acked: Vec<u32>,
+        lost: Vec<u32>,
+    },
+    /// The host gave the entity with network id `id` to `owner`. Also sent to the host
+    /// itself.
+    AuthorityChanged { id: u64, owner: PeerId },
+    /// Our [`GameAdminEvent::RequestAuthority`] was turned down because `owner` has
+    /// the entity.
+    AuthorityDenied { id: u64, owner: PeerId },
+    /// The [`GameEvent::Command`] with this id took effect. Sent just before the event
+    /// that says how, e.g. [`NetworkAdminEvent::JoinAccepted`] for a join.
+    CommandCompleted(CommandId),
+    /// The [`GameEvent::Command`] with this id didn't take effect.
+    CommandFailed { id: CommandId, reason: String },
+}
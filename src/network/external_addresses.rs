@@ -0,0 +1,102 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+
+use super::{NetworkAdminEvent, NetworkEvent};
+
+/// Distinct peers that must report the same address before we advertise it.
+pub(super) const CONFIRMATIONS: usize = 2;
+
+/// Addresses other peers told us they see us at, via identify.
+#[derive(Default)]
+pub(super) struct ObservedAddresses(HashMap<Multiaddr, HashSet<PeerId>>);
+
+impl ObservedAddresses {
+    /// Records that `peer` sees us at `address` and returns how many distinct peers have
+    /// so far, or `None` for addresses that can't be external, like relay circuits.
+    pub(super) fn report(&mut self, address: Multiaddr, peer: PeerId) -> Option<usize> {
+        if address.iter().any(|p| p == Protocol::P2pCircuit) {
+            return None;
+        }
+        let reporters = self.0.entry(address).or_default();
+        reporters.insert(peer);
+        Some(reporters.len())
+    }
+
+    /// Forgets every observation, returning the addresses that had been confirmed.
+    pub(super) fn clear(&mut self) -> Vec<Multiaddr> {
+        self.0
+            .drain()
+            .filter(|(_, reporters)| reporters.len() >= CONFIRMATIONS)
+            .map(|(address, _)| address)
+            .collect()
+    }
+}
+
+/// What the game knows about one of our candidate external addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalAddress {
+    /// How many distinct peers reported it.
+    pub confirmations: usize,
+    /// Whether the swarm advertises it.
+    pub confirmed: bool,
+}
+
+/// Our candidate external addresses, as seen by other peers.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ExternalAddresses(HashMap<Multiaddr, ExternalAddress>);
+
+impl ExternalAddresses {
+    pub fn get(&self, address: &Multiaddr) -> Option<&ExternalAddress> {
+        self.0.get(address)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Multiaddr, &ExternalAddress)> {
+        self.0.iter()
+    }
+
+    /// Addresses the swarm advertises.
+    pub fn confirmed(&self) -> impl Iterator<Item = &Multiaddr> {
+        self.0
+            .iter()
+            .filter(|(_, external)| external.confirmed)
+            .map(|(address, _)| address)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+pub(super) fn track_external_addresses<ToGame>(
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+    mut addresses: ResMut<ExternalAddresses>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        match event {
+            NetworkEvent::Admin(NetworkAdminEvent::ExternalAddressObserved {
+                address,
+                confirmations,
+                confirmed,
+            }) => {
+                addresses.0.insert(
+                    address.clone(),
+                    ExternalAddress {
+                        confirmations: *confirmations,
+                        confirmed: *confirmed,
+                    },
+                );
+            }
+            // Whatever peers saw us at belonged to the old network.
+            NetworkEvent::Admin(NetworkAdminEvent::NetworkMigrated { .. }) => addresses.0.clear(),
+            _ => {}
+        }
+    }
+}
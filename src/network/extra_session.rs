@@ -0,0 +1,128 @@
+//! A further network stack in the same app, fully independent of the main one: its
+//! own identity, swarm thread and channels. Meant for debug builds that play host and
+//! joiner side by side in one process.
+//!
+//! Every resource and event of an extra session is keyed by a marker type `S`, used as
+//! both payload types: create it with `setup_network::<S, S>`, add an
+//! [`ExtraSessionPlugin<S>`] and insert the manager. A unit struct deriving
+//! `Serialize` and `Deserialize` encodes like `()`, so it talks to the main session
+//! just fine.
+
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+
+use super::{
+    process_network_events, session, tasks, AsyncNetworkTasks, CurrentRoom, GameAdminEvent,
+    GameEvent, LocalPeerInfo, NetworkEvent, NetworkFailurePolicy, NetworkLog, NetworkManager,
+};
+use crate::shutdown::Shutdown;
+
+/// What [`NetworkPlugin`](super::NetworkPlugin) keeps in separate resources for the
+/// main session, kept together for extra session `S`.
+#[derive(Resource, Debug)]
+pub struct SessionState<S> {
+    pub log: NetworkLog,
+    pub local_peer: Option<LocalPeerInfo>,
+    pub room: Option<CurrentRoom>,
+    session: PhantomData<S>,
+}
+
+impl<S> Default for SessionState<S> {
+    fn default() -> Self {
+        Self {
+            log: NetworkLog::default(),
+            local_peer: None,
+            room: None,
+            session: PhantomData,
+        }
+    }
+}
+
+/// [`NetworkPlugin`](super::NetworkPlugin) for extra session `S`: forwards its
+/// [`NetworkManager<S, S>`] into [`NetworkEvent<S>`]s, sets up
+/// [`AsyncNetworkTasks<S>`] and keeps its [`SessionState<S>`]. Its tasks report to
+/// the same [`NetworkTaskFinished`](super::NetworkTaskFinished) events as the main
+/// session's, so their ids may repeat. It quits along with the main session when
+/// [`ShutdownPlugin`](crate::shutdown::ShutdownPlugin) shuts the app down.
+pub struct ExtraSessionPlugin<S>(PhantomData<S>);
+
+impl<S> Default for ExtraSessionPlugin<S> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<S: Send + Sync + 'static> Plugin for ExtraSessionPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SessionState<S>>()
+            .init_resource::<NetworkFailurePolicy>()
+            .add_event::<NetworkEvent<S>>()
+            .add_systems(
+                PreUpdate,
+                (
+                    tasks::insert_network_tasks::<S, S>
+                        .run_if(resource_added::<NetworkManager<S, S>>()),
+                    tasks::poll_network_tasks::<S>
+                        .run_if(resource_exists::<AsyncNetworkTasks<S>>()),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    process_network_events::<S, S>,
+                    track_session_state::<S>,
+                    quit_with_main_session::<S>,
+                )
+                    .chain()
+                    .run_if(resource_exists::<NetworkManager<S, S>>()),
+            );
+    }
+}
+
+fn track_session_state<S: Send + Sync + 'static>(
+    manager: Res<NetworkManager<S, S>>,
+    mut state: ResMut<SessionState<S>>,
+    mut network_events: EventReader<NetworkEvent<S>>,
+) {
+    while let Ok(entry) = manager.log.try_recv() {
+        state.log.push(entry);
+    }
+    let peer_id = manager.local_peer_id();
+    if state
+        .local_peer
+        .map_or(true, |local| local.peer_id != peer_id)
+    {
+        state.local_peer = Some(LocalPeerInfo { peer_id });
+    }
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        if let Some(room) = session::room_change(event, peer_id) {
+            state.room = room;
+        }
+    }
+}
+
+/// Asks session `S` to quit once the main one was, without waiting for it: the main
+/// session's goodbye usually gives it enough time to say its own.
+fn quit_with_main_session<S: Send + Sync + 'static>(
+    shutdown: Option<Res<Shutdown>>,
+    manager: Res<NetworkManager<S, S>>,
+    mut quit: Local<bool>,
+) {
+    let requested = shutdown.map_or(false, |shutdown| {
+        matches!(*shutdown, Shutdown::Requested { .. })
+    });
+    if *quit || !requested || manager.stopped() {
+        return;
+    }
+    *quit = true;
+    if manager
+        .try_send_to_network(GameEvent::Admin(GameAdminEvent::Quit))
+        .is_err()
+    {
+        log::warn!("Network thread of an extra session is already gone");
+    }
+}
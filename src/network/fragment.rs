@@ -0,0 +1,293 @@
+//! Splits room messages too big for one gossipsub publish into fragments, and puts
+//! them back together on the other side.
+//!
+//! Reassembly buffers are bounded: a message may be at most [`MAX_MESSAGE_SIZE`], a
+//! peer may have at most [`MAX_BUFFERED_PER_PEER`] bytes of incomplete messages, and
+//! whatever is still incomplete after [`FRAGMENT_TIMEOUT`] is dropped.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+/// Largest gossipsub message we send or accept. Set on the gossipsub config too.
+pub(super) const MAX_TRANSMIT_SIZE: usize = 64 * 1024;
+/// Bytes of message per fragment. Leaves room for the fragment header, the room
+/// encryption and gossipsub's own framing.
+const FRAGMENT_SIZE: usize = MAX_TRANSMIT_SIZE - 4 * 1024;
+/// Messages are never fragmented beyond this.
+pub(super) const MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+/// Incomplete messages a single peer can make us hold on to, in bytes.
+const MAX_BUFFERED_PER_PEER: usize = 2 * MAX_MESSAGE_SIZE;
+/// How long the rest of a fragmented message may take to arrive.
+const FRAGMENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One piece of a message published in several.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct Fragment {
+    /// Unique per sender, shared by the pieces of one message.
+    pub id: u64,
+    pub index: u32,
+    pub count: u32,
+    pub data: Vec<u8>,
+}
+
+/// Whether `data` has to go out in fragments.
+pub(super) fn needs_fragmenting(data: &[u8]) -> bool {
+    data.len() > FRAGMENT_SIZE
+}
+
+/// Splits `data` into the fragments of message `id`.
+pub(super) fn fragment(id: u64, data: &[u8]) -> Vec<Fragment> {
+    let count = ((data.len() + FRAGMENT_SIZE - 1) / FRAGMENT_SIZE) as u32;
+    data.chunks(FRAGMENT_SIZE)
+        .enumerate()
+        .map(|(index, chunk)| Fragment {
+            id,
+            index: index as u32,
+            count,
+            data: chunk.to_vec(),
+        })
+        .collect()
+}
+
+/// Why a fragment was refused. The peer that sent it is either broken or abusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum FragmentError {
+    BadIndex { index: u32, count: u32 },
+    TooBig { count: u32 },
+    Inconsistent,
+    TooMuchBuffered,
+}
+
+impl fmt::Display for FragmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadIndex { index, count } => {
+                write!(f, "fragment {} of {} is out of range", index, count)
+            }
+            Self::TooBig { count } => {
+                write!(f, "a message of {} fragments would be too big", count)
+            }
+            Self::Inconsistent => write!(f, "fragment doesn't match its message"),
+            Self::TooMuchBuffered => write!(f, "too many incomplete messages"),
+        }
+    }
+}
+
+impl std::error::Error for FragmentError {}
+
+/// A message still missing fragments.
+struct Partial {
+    fragments: Vec<Option<Vec<u8>>>,
+    received: u32,
+    bytes: usize,
+    started: Instant,
+}
+
+/// Fragments received so far, by sender and message id.
+#[derive(Default)]
+pub(super) struct Reassembler {
+    partials: HashMap<(PeerId, u64), Partial>,
+}
+
+impl Reassembler {
+    /// Stores `fragment` and returns the whole message once it's complete. On error,
+    /// everything buffered for `source` is dropped.
+    pub(super) fn insert(
+        &mut self,
+        source: PeerId,
+        fragment: Fragment,
+        now: Instant,
+    ) -> Result<Option<Vec<u8>>, FragmentError> {
+        let result = self.try_insert(source, fragment, now);
+        if result.is_err() {
+            self.partials.retain(|(peer, _), _| *peer != source);
+        }
+        result
+    }
+
+    fn try_insert(
+        &mut self,
+        source: PeerId,
+        fragment: Fragment,
+        now: Instant,
+    ) -> Result<Option<Vec<u8>>, FragmentError> {
+        let Fragment {
+            id,
+            index,
+            count,
+            data,
+        } = fragment;
+        if index >= count {
+            return Err(FragmentError::BadIndex { index, count });
+        }
+        if count as usize > MAX_MESSAGE_SIZE / FRAGMENT_SIZE + 1 {
+            return Err(FragmentError::TooBig { count });
+        }
+        // Only the last fragment may be short.
+        let full = index + 1 < count;
+        if data.len() > FRAGMENT_SIZE || (full && data.len() != FRAGMENT_SIZE) {
+            return Err(FragmentError::Inconsistent);
+        }
+        if self.buffered(&source) + data.len() > MAX_BUFFERED_PER_PEER {
+            return Err(FragmentError::TooMuchBuffered);
+        }
+        let partial = self
+            .partials
+            .entry((source, id))
+            .or_insert_with(|| Partial {
+                fragments: vec![None; count as usize],
+                received: 0,
+                bytes: 0,
+                started: now,
+            });
+        if partial.fragments.len() != count as usize {
+            return Err(FragmentError::Inconsistent);
+        }
+        let slot = &mut partial.fragments[index as usize];
+        if slot.is_some() {
+            // Gossipsub deduplicates, so this is a replay. Nothing to do.
+            return Ok(None);
+        }
+        partial.bytes += data.len();
+        partial.received += 1;
+        *slot = Some(data);
+        if partial.received < count {
+            return Ok(None);
+        }
+        let partial = self
+            .partials
+            .remove(&(source, id))
+            .expect("Partial was just updated");
+        Ok(Some(
+            partial.fragments.into_iter().flatten().flatten().collect(),
+        ))
+    }
+
+    fn buffered(&self, source: &PeerId) -> usize {
+        self.partials
+            .iter()
+            .filter(|((peer, _), _)| peer == source)
+            .map(|(_, partial)| partial.bytes)
+            .sum()
+    }
+
+    /// When the oldest incomplete message times out.
+    pub(super) fn next_expiry(&self) -> Option<Instant> {
+        self.partials
+            .values()
+            .map(|partial| partial.started + FRAGMENT_TIMEOUT)
+            .min()
+    }
+
+    /// Drops messages that took too long, returning who sent them.
+    pub(super) fn expire(&mut self, now: Instant) -> Vec<PeerId> {
+        let mut expired = Vec::new();
+        self.partials.retain(|(peer, _), partial| {
+            let alive = now < partial.started + FRAGMENT_TIMEOUT;
+            if !alive {
+                expired.push(*peer);
+            }
+            alive
+        });
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fragments_reassemble_in_any_order() {
+        let data: Vec<u8> = (0..FRAGMENT_SIZE * 2 + 100).map(|i| i as u8).collect();
+        let mut fragments = fragment(7, &data);
+        assert_eq!(fragments.len(), 3);
+        fragments.reverse();
+        let source = PeerId::random();
+        let now = Instant::now();
+        let mut reassembler = Reassembler::default();
+        let mut results: Vec<_> = fragments
+            .into_iter()
+            .map(|f| reassembler.insert(source, f, now).unwrap())
+            .collect();
+        assert_eq!(results.pop().unwrap(), Some(data));
+        assert!(results.iter().all(Option::is_none));
+        assert_eq!(reassembler.next_expiry(), None);
+    }
+
+    #[test]
+    fn incomplete_messages_time_out() {
+        let data = vec![0; FRAGMENT_SIZE + 1];
+        let source = PeerId::random();
+        let now = Instant::now();
+        let mut reassembler = Reassembler::default();
+        let first = fragment(1, &data).remove(0);
+        assert_eq!(reassembler.insert(source, first, now), Ok(None));
+        assert_eq!(reassembler.expire(now), []);
+        assert_eq!(reassembler.expire(now + FRAGMENT_TIMEOUT), [source]);
+        assert_eq!(reassembler.next_expiry(), None);
+    }
+
+    #[test]
+    fn oversized_and_malformed_fragments_are_refused() {
+        let source = PeerId::random();
+        let now = Instant::now();
+        let mut reassembler = Reassembler::default();
+        let huge = Fragment {
+            id: 0,
+            index: 0,
+            count: u32::MAX,
+            data: Vec::new(),
+        };
+        assert!(matches!(
+            reassembler.insert(source, huge, now),
+            Err(FragmentError::TooBig { .. })
+        ));
+        let out_of_range = Fragment {
+            id: 0,
+            index: 2,
+            count: 2,
+            data: Vec::new(),
+        };
+        assert!(matches!(
+            reassembler.insert(source, out_of_range, now),
+            Err(FragmentError::BadIndex { .. })
+        ));
+        let short = Fragment {
+            id: 0,
+            index: 0,
+            count: 2,
+            data: vec![0; 10],
+        };
+        assert_eq!(
+            reassembler.insert(source, short, now),
+            Err(FragmentError::Inconsistent)
+        );
+    }
+
+    #[test]
+    fn buffered_bytes_are_capped_per_peer() {
+        let source = PeerId::random();
+        let now = Instant::now();
+        let mut reassembler = Reassembler::default();
+        let data = vec![0; MAX_MESSAGE_SIZE];
+        // Send all but the last fragment of enough messages to hit the cap.
+        let result = (0..)
+            .flat_map(|id| {
+                let mut fragments = fragment(id, &data);
+                fragments.pop();
+                fragments
+            })
+            .map(|f| reassembler.insert(source, f, now))
+            .find(Result::is_err);
+        assert_eq!(result, Some(Err(FragmentError::TooMuchBuffered)));
+        // Everything from the abusive peer is gone, so others are unaffected.
+        assert_eq!(reassembler.buffered(&source), 0);
+    }
+}
@@ -0,0 +1,106 @@
+//! Notices when the machine's addresses change, e.g. a laptop moving from Wi-Fi to
+//! Ethernet, so the network thread can move its connections over.
+
+use std::{
+    collections::HashSet,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use futures::{future::Either, prelude::*};
+use if_watch::{smol::IfWatcher, IfEvent};
+
+/// Interfaces flap while a network comes up, so we wait this long after the last
+/// change before acting on it.
+const SETTLE: Duration = Duration::from_secs(2);
+
+/// Addresses gained and lost since the last [`Migration`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct Migration {
+    pub(super) gained: Vec<IpAddr>,
+    pub(super) lost: Vec<IpAddr>,
+}
+
+pub(super) struct InterfaceWatcher {
+    /// `None` if the platform wouldn't let us watch, in which case we never migrate.
+    watcher: Option<IfWatcher>,
+    /// Addresses as of the last migration.
+    settled: HashSet<IpAddr>,
+    current: HashSet<IpAddr>,
+    changed_at: Option<Instant>,
+}
+
+/// Loopback and link-local addresses come and go without affecting our peers.
+fn is_routable(address: &IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => !address.is_loopback() && !address.is_link_local(),
+        IpAddr::V6(address) => !address.is_loopback() && (address.segments()[0] & 0xffc0) != 0xfe80,
+    }
+}
+
+impl InterfaceWatcher {
+    pub(super) fn new() -> Self {
+        let watcher = IfWatcher::new()
+            .map_err(|e| log::warn!("Can't watch network interfaces: {}", e))
+            .ok();
+        let current: HashSet<_> = watcher
+            .iter()
+            .flat_map(|watcher| watcher.iter())
+            .map(|net| net.addr())
+            .filter(is_routable)
+            .collect();
+        Self {
+            watcher,
+            settled: current.clone(),
+            current,
+            changed_at: None,
+        }
+    }
+
+    /// Resolves once our addresses changed and then stayed put for [`SETTLE`]. Safe
+    /// to drop part way, as every change is recorded before waiting again.
+    pub(super) async fn next_migration(&mut self) -> Migration {
+        let Some(watcher) = self.watcher.as_mut() else {
+            return future::pending().await;
+        };
+        loop {
+            let settled = match self.changed_at {
+                Some(at) => Either::Left(async_std::task::sleep(
+                    (at + SETTLE).saturating_duration_since(Instant::now()),
+                )),
+                None => Either::Right(future::pending()),
+            };
+            futures::pin_mut!(settled);
+            match future::select(watcher.next(), settled).await {
+                Either::Left((Some(Ok(event)), _)) => {
+                    let changed = match event {
+                        IfEvent::Up(net) => {
+                            is_routable(&net.addr()) && self.current.insert(net.addr())
+                        }
+                        IfEvent::Down(net) => self.current.remove(&net.addr()),
+                    };
+                    if changed {
+                        self.changed_at = Some(Instant::now());
+                    }
+                }
+                Either::Left((Some(Err(e)), _)) => {
+                    log::warn!("Watching network interfaces failed: {}", e)
+                }
+                Either::Left((None, _)) => return future::pending().await,
+                Either::Right(((), _)) => {
+                    self.changed_at = None;
+                    // Back where we started, e.g. a brief drop of the same Wi-Fi.
+                    if self.current == self.settled {
+                        continue;
+                    }
+                    let migration = Migration {
+                        gained: self.current.difference(&self.settled).copied().collect(),
+                        lost: self.settled.difference(&self.current).copied().collect(),
+                    };
+                    self.settled = self.current.clone();
+                    return migration;
+                }
+            }
+        }
+    }
+}
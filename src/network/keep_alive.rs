@@ -0,0 +1,210 @@
+//! A behaviour whose only job is holding connections open according to a
+//! [`KeepAlivePolicy`], since a connection closes once no handler asks for it.
+//!
+//! The libp2p version we use has no swarm-wide idle timeout, so the handlers here also
+//! stand in for it: under [`KeepAlivePolicy::IdleTimeout`] a connection is held for
+//! the timeout after it opens, and after that only while another protocol uses it.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    convert::Infallible,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use libp2p::{
+    core::{upgrade::DeniedUpgrade, Endpoint},
+    swarm::{
+        handler::ConnectionEvent, ConnectionDenied, ConnectionHandler, ConnectionHandlerEvent,
+        ConnectionId, FromSwarm, KeepAlive, NetworkBehaviour, NotifyHandler, PollParameters,
+        SubstreamProtocol, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId,
+};
+
+use super::config::KeepAlivePolicy;
+
+pub struct Behaviour {
+    game_peers: KeepAlivePolicy,
+    other_peers: KeepAlivePolicy,
+    idle_timeout: Duration,
+    /// Peers known to run this game.
+    game: HashSet<PeerId>,
+    connections: HashMap<PeerId, HashSet<ConnectionId>>,
+    pending: VecDeque<ToSwarm<Infallible, bool>>,
+}
+
+impl Behaviour {
+    pub fn new(
+        game_peers: KeepAlivePolicy,
+        other_peers: KeepAlivePolicy,
+        idle_timeout: Duration,
+    ) -> Self {
+        Self {
+            game_peers,
+            other_peers,
+            idle_timeout,
+            game: HashSet::new(),
+            connections: HashMap::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn keep_alive(&self, peer_id: &PeerId) -> bool {
+        let policy = if self.game.contains(peer_id) {
+            self.game_peers
+        } else {
+            self.other_peers
+        };
+        policy == KeepAlivePolicy::Always
+    }
+
+    /// Switches `peer_id`'s connections to the game peer policy.
+    pub fn mark_game_peer(&mut self, peer_id: PeerId) {
+        if !self.game.insert(peer_id) {
+            return;
+        }
+        let keep_alive = self.keep_alive(&peer_id);
+        for connection_id in self.connections.get(&peer_id).into_iter().flatten() {
+            self.pending.push_back(ToSwarm::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::One(*connection_id),
+                event: keep_alive,
+            });
+        }
+    }
+
+    fn new_handler(&mut self, peer_id: PeerId, connection_id: ConnectionId) -> Handler {
+        self.connections
+            .entry(peer_id)
+            .or_default()
+            .insert(connection_id);
+        Handler {
+            keep_alive: self.keep_alive(&peer_id),
+            idle_timeout: self.idle_timeout,
+            idle_until: Instant::now() + self.idle_timeout,
+        }
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = Handler;
+    type ToSwarm = Infallible;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.new_handler(peer, connection_id))
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        _: &Multiaddr,
+        _: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.new_handler(peer, connection_id))
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        if let FromSwarm::ConnectionClosed(closed) = event {
+            if let Some(connections) = self.connections.get_mut(&closed.peer_id) {
+                connections.remove(&closed.connection_id);
+            }
+            if closed.remaining_established == 0 {
+                self.connections.remove(&closed.peer_id);
+                self.game.remove(&closed.peer_id);
+            }
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        match self.pending.pop_front() {
+            Some(action) => Poll::Ready(action),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Never opens a stream; only answers whether its connection should stay open.
+pub struct Handler {
+    keep_alive: bool,
+    idle_timeout: Duration,
+    /// When this handler stops holding the connection, unless `keep_alive` is set.
+    idle_until: Instant,
+}
+
+impl ConnectionHandler for Handler {
+    type FromBehaviour = bool;
+    type ToBehaviour = Infallible;
+    type Error = Infallible;
+    type InboundProtocol = DeniedUpgrade;
+    type OutboundProtocol = DeniedUpgrade;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = ();
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        SubstreamProtocol::new(DeniedUpgrade, ())
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        if self.keep_alive {
+            KeepAlive::Yes
+        } else if Instant::now() < self.idle_until {
+            KeepAlive::Until(self.idle_until)
+        } else {
+            KeepAlive::No
+        }
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<
+            Self::OutboundProtocol,
+            Self::OutboundOpenInfo,
+            Self::ToBehaviour,
+            Self::Error,
+        >,
+    > {
+        Poll::Pending
+    }
+
+    fn on_behaviour_event(&mut self, keep_alive: bool) {
+        if self.keep_alive && !keep_alive {
+            // Idle from now on, not from when the connection opened.
+            self.idle_until = Instant::now() + self.idle_timeout;
+        }
+        self.keep_alive = keep_alive;
+    }
+
+    fn on_connection_event(
+        &mut self,
+        _: ConnectionEvent<
+            Self::InboundProtocol,
+            Self::OutboundProtocol,
+            Self::InboundOpenInfo,
+            Self::OutboundOpenInfo,
+        >,
+    ) {
+    }
+}
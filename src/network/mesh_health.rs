@@ -0,0 +1,159 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::Ordering,
+};
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use super::{NetworkAdminEvent, NetworkEvent, NetworkManager};
+
+/// Who is subscribed to, and in our mesh for, each gossip topic. Messages only spread
+/// through the mesh, so something published while a topic's mesh is empty reaches
+/// nobody. Games can hold back critical state until [`room_mesh_peers`] is non-zero.
+///
+/// [`room_mesh_peers`]: Self::room_mesh_peers
+#[derive(Resource, Debug, Clone, Default)]
+pub struct MeshHealth {
+    subscribers: HashMap<String, HashSet<PeerId>>,
+    mesh: HashMap<String, HashSet<PeerId>>,
+    /// Counted by the network thread, which knows the room's topic.
+    room_mesh_peers: usize,
+}
+
+impl MeshHealth {
+    /// Peers we know are subscribed to `topic`.
+    pub fn subscribers(&self, topic: &str) -> usize {
+        self.subscribers.get(topic).map_or(0, HashSet::len)
+    }
+
+    /// Peers in our mesh for `topic`, which our messages on it go to.
+    pub fn mesh_peers(&self, topic: &str) -> usize {
+        self.mesh.get(topic).map_or(0, HashSet::len)
+    }
+
+    /// [`mesh_peers`](Self::mesh_peers) of the room we are in, or zero outside one.
+    pub fn room_mesh_peers(&self) -> usize {
+        self.room_mesh_peers
+    }
+
+    /// Every topic with subscribers or mesh peers, with the mesh size.
+    pub fn topics(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.subscribers
+            .keys()
+            .chain(
+                self.mesh
+                    .keys()
+                    .filter(|topic| !self.subscribers.contains_key(*topic)),
+            )
+            .map(|topic| (topic.as_str(), self.mesh_peers(topic)))
+    }
+
+    fn update(set: &mut HashMap<String, HashSet<PeerId>>, topic: &str, peer_id: PeerId, add: bool) {
+        if add {
+            set.entry(topic.to_string()).or_default().insert(peer_id);
+        } else if let Some(peers) = set.get_mut(topic) {
+            peers.remove(&peer_id);
+            if peers.is_empty() {
+                set.remove(topic);
+            }
+        }
+    }
+
+    /// Follows one event from the network thread.
+    fn apply(&mut self, event: &NetworkAdminEvent) {
+        match event {
+            NetworkAdminEvent::PeerSubscribed { peer_id, topic } => {
+                Self::update(&mut self.subscribers, topic, *peer_id, true)
+            }
+            NetworkAdminEvent::PeerUnsubscribed { peer_id, topic } => {
+                Self::update(&mut self.subscribers, topic, *peer_id, false)
+            }
+            NetworkAdminEvent::MeshGrafted { peer_id, topic } => {
+                Self::update(&mut self.mesh, topic, *peer_id, true)
+            }
+            NetworkAdminEvent::MeshPruned { peer_id, topic } => {
+                Self::update(&mut self.mesh, topic, *peer_id, false)
+            }
+            NetworkAdminEvent::Disconnected(peer_id) => self.forget(peer_id),
+            NetworkAdminEvent::NetworkRestarted { .. } => *self = Self::default(),
+            _ => {}
+        }
+    }
+
+    fn forget(&mut self, peer_id: &PeerId) {
+        for set in [&mut self.subscribers, &mut self.mesh] {
+            set.retain(|_, peers| {
+                peers.remove(peer_id);
+                !peers.is_empty()
+            });
+        }
+    }
+}
+
+pub(super) fn track_mesh_health<FromGame, ToGame>(
+    manager: Res<NetworkManager<FromGame, ToGame>>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+    mut health: ResMut<MeshHealth>,
+) where
+    FromGame: Send + Sync + 'static,
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        if let NetworkEvent::Admin(event) = event {
+            health.apply(event);
+        }
+    }
+    let room_mesh_peers = manager.metrics.counters.mesh_peers.load(Ordering::Relaxed);
+    if health.room_mesh_peers != room_mesh_peers {
+        health.room_mesh_peers = room_mesh_peers;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscribed(peer_id: PeerId, topic: &str) -> NetworkAdminEvent {
+        NetworkAdminEvent::PeerSubscribed {
+            peer_id,
+            topic: topic.to_string(),
+        }
+    }
+
+    fn grafted(peer_id: PeerId, topic: &str) -> NetworkAdminEvent {
+        NetworkAdminEvent::MeshGrafted {
+            peer_id,
+            topic: topic.to_string(),
+        }
+    }
+
+    #[test]
+    fn fills_up_and_empties_with_the_mesh() {
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let room = "/bevy-libp2p-demo/room/ABCD";
+        let mut health = MeshHealth::default();
+        for event in [
+            subscribed(a, room),
+            subscribed(b, room),
+            subscribed(a, "/bevy-libp2p-demo/team/red"),
+            grafted(a, room),
+        ] {
+            health.apply(&event);
+        }
+        assert_eq!(health.subscribers(room), 2);
+        assert_eq!(health.mesh_peers(room), 1);
+        assert_eq!(health.topics().count(), 2);
+
+        health.apply(&NetworkAdminEvent::MeshPruned {
+            peer_id: a,
+            topic: room.to_string(),
+        });
+        assert_eq!(health.subscribers(room), 2);
+        assert_eq!(health.mesh_peers(room), 0);
+
+        health.apply(&NetworkAdminEvent::Disconnected(a));
+        assert_eq!(health.subscribers(room), 1);
+        assert_eq!(health.subscribers("/bevy-libp2p-demo/team/red"), 0);
+    }
+}
@@ -0,0 +1,274 @@
+//! Network counters fed into Bevy's [`Diagnostics`], so [`LogDiagnosticsPlugin`] and
+//! any overlay reading them show networking next to the frame time.
+//!
+//! [`LogDiagnosticsPlugin`]: bevy::diagnostic::LogDiagnosticsPlugin
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics, DiagnosticsStore},
+    prelude::*,
+};
+use libp2p::bandwidth::BandwidthSinks;
+
+use super::NetworkManager;
+use crate::crypto::{CryptoStatus, KeyRing};
+
+/// How often rates are measured. Shorter windows make messages/sec jump around.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const HISTORY: usize = 20;
+
+/// Connected peers, game or not.
+pub const PEERS: DiagnosticId = DiagnosticId::from_u128(0x8c1c_58b7_7b4e_4c47_a0fd_3f4f_e2a9_6c01);
+/// Peers in our gossip mesh for the room topic.
+pub const MESH_PEERS: DiagnosticId =
+    DiagnosticId::from_u128(0x8c1c_58b7_7b4e_4c47_a0fd_3f4f_e2a9_6c02);
+/// Peers we only reach through a relay.
+pub const RELAYED_PEERS: DiagnosticId =
+    DiagnosticId::from_u128(0x8c1c_58b7_7b4e_4c47_a0fd_3f4f_e2a9_6c03);
+pub const MESSAGES_IN: DiagnosticId =
+    DiagnosticId::from_u128(0x8c1c_58b7_7b4e_4c47_a0fd_3f4f_e2a9_6c04);
+pub const MESSAGES_OUT: DiagnosticId =
+    DiagnosticId::from_u128(0x8c1c_58b7_7b4e_4c47_a0fd_3f4f_e2a9_6c05);
+/// Bytes received over every transport, including relay and DHT traffic.
+pub const BYTES_IN: DiagnosticId =
+    DiagnosticId::from_u128(0x8c1c_58b7_7b4e_4c47_a0fd_3f4f_e2a9_6c06);
+pub const BYTES_OUT: DiagnosticId =
+    DiagnosticId::from_u128(0x8c1c_58b7_7b4e_4c47_a0fd_3f4f_e2a9_6c07);
+/// Percentage of the network thread's batches that used up a source's budget. Staying
+/// high means the thread can't keep up with the swarm or the game.
+pub const SATURATION: DiagnosticId =
+    DiagnosticId::from_u128(0x8c1c_58b7_7b4e_4c47_a0fd_3f4f_e2a9_6c08);
+/// Game messages dropped for being older than
+/// [`NetworkConfig::message_ttl`](super::NetworkConfig::message_ttl).
+pub const STALE_DROPPED: DiagnosticId =
+    DiagnosticId::from_u128(0x8c1c_58b7_7b4e_4c47_a0fd_3f4f_e2a9_6c09);
+/// Events from the network thread left for later frames by the
+/// [`NetworkEventBudget`](super::NetworkEventBudget).
+pub const PENDING_EVENTS: DiagnosticId =
+    DiagnosticId::from_u128(0x8c1c_58b7_7b4e_4c47_a0fd_3f4f_e2a9_6c0a);
+
+/// Gauges and totals the network thread updates as it goes.
+#[derive(Debug, Default)]
+pub(super) struct SwarmCounters {
+    pub(super) peers: AtomicUsize,
+    pub(super) mesh_peers: AtomicUsize,
+    pub(super) relayed_peers: AtomicUsize,
+    pub(super) messages_in: AtomicU64,
+    pub(super) messages_out: AtomicU64,
+    pub(super) batches: AtomicU64,
+    pub(super) saturated_batches: AtomicU64,
+    pub(super) stale_dropped: AtomicU64,
+}
+
+/// Shared view of the network thread's counters, sampled by
+/// [`NetworkManager::metrics`].
+#[derive(Clone)]
+pub(super) struct MetricsSource {
+    pub(super) counters: Arc<SwarmCounters>,
+    pub(super) bandwidth: BandwidthTotals,
+    pub(super) keys: SharedKeyRing,
+}
+
+/// The byte counters of every transport the network thread had. Restarting the swarm
+/// adds one, and the totals keep counting from where the last one stopped.
+#[derive(Clone, Default)]
+pub(super) struct BandwidthTotals(Arc<Mutex<Vec<Arc<BandwidthSinks>>>>);
+
+impl BandwidthTotals {
+    pub(super) fn push(&self, sinks: Arc<BandwidthSinks>) {
+        self.0.lock().expect("Bandwidth lock poisoned").push(sinks);
+    }
+
+    fn total(&self, bytes: impl Fn(&BandwidthSinks) -> u64) -> u64 {
+        let sinks = self.0.lock().expect("Bandwidth lock poisoned");
+        sinks.iter().map(|sinks| bytes(sinks)).sum()
+    }
+}
+
+/// The room [`KeyRing`] of whichever swarm the network thread runs now.
+#[derive(Clone)]
+pub(super) struct SharedKeyRing(Arc<Mutex<KeyRing>>);
+
+impl SharedKeyRing {
+    pub(super) fn new(keys: KeyRing) -> Self {
+        Self(Arc::new(Mutex::new(keys)))
+    }
+
+    /// Points at the ring of a restarted swarm.
+    pub(super) fn set(&self, keys: KeyRing) {
+        *self.0.lock().expect("Key ring lock poisoned") = keys;
+    }
+
+    fn status(&self) -> CryptoStatus {
+        self.0.lock().expect("Key ring lock poisoned").status()
+    }
+}
+
+impl fmt::Debug for MetricsSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricsSource")
+            .field("counters", &self.counters)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A snapshot of the network thread's counters. Messages and bytes are totals since
+/// startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkMetrics {
+    pub peers: usize,
+    pub mesh_peers: usize,
+    pub relayed_peers: usize,
+    pub messages_in: u64,
+    pub messages_out: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Times the network thread woke up and handled what was waiting.
+    pub batches: u64,
+    /// Batches that stopped with work left because a source used up its budget.
+    pub saturated_batches: u64,
+    /// Game messages dropped for being older than
+    /// [`NetworkConfig::message_ttl`](super::NetworkConfig::message_ttl).
+    pub stale_dropped: u64,
+    /// Events from the network thread waiting for the game to take them. Staying up
+    /// means the [`NetworkEventBudget`](super::NetworkEventBudget) is too small for
+    /// what comes in.
+    pub pending_events: usize,
+}
+
+impl MetricsSource {
+    pub(super) fn crypto_status(&self) -> CryptoStatus {
+        self.keys.status()
+    }
+
+    pub(super) fn sample(&self) -> NetworkMetrics {
+        NetworkMetrics {
+            peers: self.counters.peers.load(Ordering::Relaxed),
+            mesh_peers: self.counters.mesh_peers.load(Ordering::Relaxed),
+            relayed_peers: self.counters.relayed_peers.load(Ordering::Relaxed),
+            messages_in: self.counters.messages_in.load(Ordering::Relaxed),
+            messages_out: self.counters.messages_out.load(Ordering::Relaxed),
+            bytes_in: self.bandwidth.total(BandwidthSinks::total_inbound),
+            bytes_out: self.bandwidth.total(BandwidthSinks::total_outbound),
+            batches: self.counters.batches.load(Ordering::Relaxed),
+            saturated_batches: self.counters.saturated_batches.load(Ordering::Relaxed),
+            stale_dropped: self.counters.stale_dropped.load(Ordering::Relaxed),
+            // Only the manager sees its end of the channel.
+            pending_events: 0,
+        }
+    }
+}
+
+/// Refreshes [`CryptoStatus`], only touching it when something changed.
+pub(super) fn update_crypto_status<FromGame, ToGame>(
+    mut commands: Commands,
+    manager: Res<NetworkManager<FromGame, ToGame>>,
+    status: Option<ResMut<CryptoStatus>>,
+) where
+    FromGame: Send + Sync + 'static,
+    ToGame: Send + Sync + 'static,
+{
+    let now = manager.crypto_status();
+    match status {
+        Some(mut status) => {
+            status.set_if_neq(now);
+        }
+        None => commands.insert_resource(now),
+    }
+}
+
+/// Registers the network diagnostics and samples them every [`SAMPLE_INTERVAL`].
+pub struct NetworkDiagnosticsPlugin;
+
+impl Plugin for NetworkDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, register_diagnostics).add_systems(
+            Update,
+            sample_diagnostics::<(), ()>.run_if(resource_exists::<NetworkManager<(), ()>>()),
+        );
+    }
+}
+
+fn register_diagnostics(mut diagnostics: ResMut<DiagnosticsStore>) {
+    for (id, name, suffix) in [
+        (PEERS, "network_peers", ""),
+        (MESH_PEERS, "network_mesh_peers", ""),
+        (RELAYED_PEERS, "network_relayed_peers", ""),
+        (MESSAGES_IN, "network_messages_in", "/s"),
+        (MESSAGES_OUT, "network_messages_out", "/s"),
+        (BYTES_IN, "network_bytes_in", " B/s"),
+        (BYTES_OUT, "network_bytes_out", " B/s"),
+        (SATURATION, "network_saturation", "%"),
+        (STALE_DROPPED, "network_stale_dropped", "/s"),
+        (PENDING_EVENTS, "network_pending_events", ""),
+    ] {
+        diagnostics.add(Diagnostic::new(id, name, HISTORY).with_suffix(suffix));
+    }
+}
+
+/// Per second rate of a total that grew from `before` to `now` over `elapsed`.
+fn rate(before: u64, now: u64, elapsed: Duration) -> f64 {
+    now.saturating_sub(before) as f64 / elapsed.as_secs_f64()
+}
+
+/// Percentage of the batches between two samples that were saturated.
+fn saturation(before: &NetworkMetrics, now: &NetworkMetrics) -> f64 {
+    let batches = now.batches.saturating_sub(before.batches);
+    if batches == 0 {
+        return 0.0;
+    }
+    let saturated = now
+        .saturated_batches
+        .saturating_sub(before.saturated_batches);
+    saturated as f64 * 100.0 / batches as f64
+}
+
+fn sample_diagnostics<FromGame, ToGame>(
+    time: Res<Time>,
+    manager: Res<NetworkManager<FromGame, ToGame>>,
+    mut diagnostics: Diagnostics,
+    mut last: Local<Option<(Duration, NetworkMetrics)>>,
+) where
+    FromGame: Send + Sync + 'static,
+    ToGame: Send + Sync + 'static,
+{
+    let now = time.elapsed();
+    let Some((at, before)) = *last else {
+        *last = Some((now, manager.metrics()));
+        return;
+    };
+    let elapsed = now - at;
+    if elapsed < SAMPLE_INTERVAL {
+        return;
+    }
+    let metrics = manager.metrics();
+    diagnostics.add_measurement(PEERS, || metrics.peers as f64);
+    diagnostics.add_measurement(MESH_PEERS, || metrics.mesh_peers as f64);
+    diagnostics.add_measurement(RELAYED_PEERS, || metrics.relayed_peers as f64);
+    diagnostics.add_measurement(MESSAGES_IN, || {
+        rate(before.messages_in, metrics.messages_in, elapsed)
+    });
+    diagnostics.add_measurement(MESSAGES_OUT, || {
+        rate(before.messages_out, metrics.messages_out, elapsed)
+    });
+    diagnostics.add_measurement(BYTES_IN, || {
+        rate(before.bytes_in, metrics.bytes_in, elapsed)
+    });
+    diagnostics.add_measurement(BYTES_OUT, || {
+        rate(before.bytes_out, metrics.bytes_out, elapsed)
+    });
+    diagnostics.add_measurement(SATURATION, || saturation(&before, &metrics));
+    diagnostics.add_measurement(STALE_DROPPED, || {
+        rate(before.stale_dropped, metrics.stale_dropped, elapsed)
+    });
+    diagnostics.add_measurement(PENDING_EVENTS, || metrics.pending_events as f64);
+    *last = Some((now, metrics));
+}
@@ -0,0 +1,557 @@
+//! The networking layer: a libp2p swarm running on its own thread, bridged into Bevy
+//! through [`NetworkManager`] and the [`NetworkEvent`] event.
+//!
+//! Add [`NetworkPlugin`] to an app and insert a [`NetworkSetup`] with the
+//! [`NetworkConfig`] to use. The plugin sets the network up on the
+//! [`IoTaskPool`](bevy::tasks::IoTaskPool) and inserts the [`NetworkManager`] once it
+//! is up, or sends [`NetworkAdminEvent::NetworkSetupFailed`]. Games that need the
+//! manager right away can still insert the one [`setup_network`] returns themselves.
+
+use async_std::channel::{unbounded, Receiver, SendError, Sender, TryRecvError, TrySendError};
+use bevy::{app::AppExit, prelude::*};
+use futures::future::{FutureExt, LocalBoxFuture};
+use libp2p::{
+    bandwidth::BandwidthSinks,
+    core::upgrade,
+    dns, identity, noise, relay,
+    swarm::{dummy, Swarm, SwarmBuilder},
+    tcp, websocket, yamux, PeerId, Transport, TransportExt,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    any::Any,
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::crypto::{CryptoStatus, KeyRing};
+use custom::{CustomCall, CustomFactory};
+use metrics::MetricsSource;
+use runtime::{Runtime, Spawner};
+use swarm_task::{GameChannels, SwarmTask};
+use watchdog::Liveness;
+
+mod acks;
+mod behaviour;
+mod config;
+mod connections;
+mod custom;
+mod discovery;
+mod event_log;
+mod events;
+mod external_addresses;
+mod extra_session;
+mod fragment;
+mod interfaces;
+mod keep_alive;
+mod mesh_health;
+mod metrics;
+mod outbound;
+mod protocol;
+mod relay_usage;
+mod runtime;
+mod session;
+mod setup;
+mod suspend;
+mod swarm_task;
+mod tape;
+mod tasks;
+mod watchdog;
+
+pub use behaviour::{
+    Behaviour, BehaviourEvent, DefaultBehaviour, IDENTIFY_PROTOCOL, RELAY_PROTOCOL,
+};
+pub use config::{
+    DnsConfig, DnsLookup, DnsProvider, FileDropConfig, IpVersions, JoinLimits, JoinStageTimeouts,
+    KeepAlivePolicy, ListenStrategy, NetworkConfig, ProtocolNames, RelaySelection,
+    RelayUsageLimits, TapeConfig,
+};
+pub use connections::ConnectionPath;
+pub use custom::{CustomBehaviour, CustomBehaviourEvent, CustomBehaviourPlugin, CustomEvent};
+pub use discovery::DiscoveryMethod;
+pub use event_log::{LogEntry, LogFilter, LogSubscription, NetworkLog, Severity};
+pub use events::{
+    CommandId, Emote, GameAdminEvent, GameEvent, JoinStage, NetworkAdminEvent, NetworkEvent,
+};
+pub use external_addresses::{ExternalAddress, ExternalAddresses};
+pub use extra_session::{ExtraSessionPlugin, SessionState};
+pub use mesh_health::MeshHealth;
+pub use metrics::{NetworkDiagnosticsPlugin, NetworkMetrics};
+pub use outbound::Priority;
+#[doc(hidden)]
+pub use protocol::{decode_game_message, encode_game_message};
+pub use protocol::{
+    join_proof_payload, mailbox_invite_payload, profile_payload, room_info_payload, FileRequest,
+    FileResponse, InviteAck, JoinError, JoinRequest, JoinResponse, LinkConditions, MailboxInvite,
+    PeerProfile, RoomInfo, RoomInvite, RoomPeer, Snapshot, SnapshotRequest, SnapshotResponse,
+    TickSettings, FILE_PROTOCOL, INVITE_PROTOCOL, JOIN_PROTOCOL, SNAPSHOT_PROTOCOL,
+};
+pub use session::{CurrentRoom, LocalPeerInfo};
+pub use setup::{NetworkSetup, NetworkSetupError};
+pub use tasks::{AsyncNetworkTasks, NetworkTaskFinished, NetworkTaskId};
+pub use watchdog::NetworkWatchdog;
+
+/// Handle to the network thread.
+///
+/// `FromGame` is the payload type the game sends to its peers and `ToGame` the one it
+/// receives from them.
+#[derive(Resource, Debug, Clone)]
+pub struct NetworkManager<FromGame, ToGame> {
+    to_network: Sender<GameEvent<FromGame>>,
+    from_network: Receiver<NetworkEvent<ToGame>>,
+    log: Receiver<LogEntry>,
+    custom_events: Receiver<CustomEvent>,
+    to_custom: Sender<CustomCall>,
+    metrics: MetricsSource,
+    liveness: Arc<Liveness>,
+    respawn: Respawn<FromGame, ToGame>,
+    local_peer_id: PeerId,
+    /// The network thread shut down, or died.
+    stopped: bool,
+}
+
+/// Sets up another network thread like the one a [`NetworkManager`] talks to, for the
+/// [`NetworkWatchdog`] to replace it with.
+struct Respawn<FromGame, ToGame>(
+    Arc<
+        dyn Fn() -> LocalBoxFuture<
+                'static,
+                Result<NetworkManager<FromGame, ToGame>, NetworkSetupError>,
+            > + Send
+            + Sync,
+    >,
+);
+
+impl<FromGame, ToGame> Clone for Respawn<FromGame, ToGame> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<FromGame, ToGame> fmt::Debug for Respawn<FromGame, ToGame> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Respawn")
+    }
+}
+
+impl<FromGame, ToGame> NetworkManager<FromGame, ToGame> {
+    /// Queues `event` for the network thread.
+    pub async fn send_to_network(
+        &mut self,
+        event: GameEvent<FromGame>,
+    ) -> Result<(), SendError<GameEvent<FromGame>>> {
+        self.to_network.send(event).await
+    }
+
+    /// Queues `event` without waiting, for callers that must not block the frame. The
+    /// channel is unbounded, so this only fails once the network thread is gone.
+    pub fn try_send_to_network(
+        &self,
+        event: GameEvent<FromGame>,
+    ) -> Result<(), TrySendError<GameEvent<FromGame>>> {
+        self.to_network.try_send(event)
+    }
+
+    /// Runs `call` on the network thread with the behaviour passed to
+    /// [`setup_network_with_behaviour`], which must be a `Custom`. Fails only once the
+    /// network thread is gone.
+    pub fn with_custom_behaviour<Custom: 'static>(
+        &self,
+        call: impl FnOnce(&mut Custom) + Send + 'static,
+    ) -> Result<(), anyhow::Error> {
+        let call: CustomCall =
+            Box::new(
+                move |custom: &mut dyn Any| match custom.downcast_mut::<Custom>() {
+                    Some(custom) => call(custom),
+                    None => log::error!(
+                        "The custom behaviour is not a {}",
+                        std::any::type_name::<Custom>()
+                    ),
+                },
+            );
+        self.to_custom
+            .try_send(call)
+            .map_err(|_| anyhow::anyhow!("The network thread is gone"))
+    }
+
+    /// Events sent with [`send_to_network`](Self::send_to_network) that the network
+    /// thread hasn't picked up yet. A growing backlog means we send faster than it keeps up.
+    pub fn queued(&self) -> usize {
+        self.to_network.len()
+    }
+
+    /// Whether the network thread shut down, or died.
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Who we are to the other peers. Changes when the network restarts with a new
+    /// identity, see [`GameAdminEvent::RestartNetwork`].
+    pub fn local_peer_id(&self) -> PeerId {
+        self.local_peer_id
+    }
+
+    /// The network thread's counters right now. [`NetworkDiagnosticsPlugin`] turns these
+    /// into Bevy diagnostics.
+    pub fn metrics(&self) -> NetworkMetrics {
+        NetworkMetrics {
+            pending_events: self.pending_events(),
+            ..self.metrics.sample()
+        }
+    }
+
+    /// Events from the network thread the game hasn't taken yet.
+    pub fn pending_events(&self) -> usize {
+        self.from_network.len()
+    }
+
+    /// The room key ring right now, also kept in the [`CryptoStatus`] resource.
+    pub fn crypto_status(&self) -> CryptoStatus {
+        self.metrics.crypto_status()
+    }
+
+    /// A new network thread, set up like this one was, with the identity it started
+    /// with.
+    fn respawn(&self) -> LocalBoxFuture<'static, Result<Self, NetworkSetupError>> {
+        (self.respawn.0)()
+    }
+}
+
+/// Builds the transport and swarm and spawns the thread driving them. To have
+/// [`NetworkPlugin`] do so, insert a [`NetworkSetup`] instead.
+pub async fn setup_network<FromGame, ToGame>(
+    config: NetworkConfig,
+) -> Result<NetworkManager<FromGame, ToGame>, NetworkSetupError>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+{
+    setup_network_with_behaviour(config, |_| dummy::Behaviour).await
+}
+
+/// Like [`setup_network`], but runs a behaviour of the game's own in the swarm, built
+/// by `make_custom` for our identity. Reach it with
+/// [`NetworkManager::with_custom_behaviour`], and read its events through a
+/// [`CustomBehaviourPlugin`].
+pub async fn setup_network_with_behaviour<FromGame, ToGame, Custom>(
+    config: NetworkConfig,
+    make_custom: impl Fn(&identity::Keypair) -> Custom + Send + Sync + 'static,
+) -> Result<NetworkManager<FromGame, ToGame>, NetworkSetupError>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    let id_keys = config
+        .identity
+        .clone()
+        .unwrap_or_else(identity::Keypair::generate_ed25519);
+    let make_custom: CustomFactory<Custom> = Arc::new(make_custom);
+    spawn_network(config, id_keys, make_custom).await
+}
+
+/// Builds the swarm for `id_keys` and spawns the thread driving it. Not an `async fn`,
+/// as the [`Respawn`] it hands out calls it again.
+fn spawn_network<FromGame, ToGame, Custom>(
+    config: NetworkConfig,
+    id_keys: identity::Keypair,
+    make_custom: CustomFactory<Custom>,
+) -> LocalBoxFuture<'static, Result<NetworkManager<FromGame, ToGame>, NetworkSetupError>>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    async move {
+        let local_peer_id = PeerId::from(id_keys.public());
+        log::info!("Local peer id: {}", local_peer_id);
+        let (swarm, keys, bandwidth) = build_swarm(&id_keys, &config, &make_custom).await?;
+
+        // Send events over channel.
+        let (to_network, from_game): (Sender<GameEvent<FromGame>>, Receiver<GameEvent<FromGame>>) =
+            unbounded();
+        let (to_game, from_network): (
+            Sender<NetworkEvent<ToGame>>,
+            Receiver<NetworkEvent<ToGame>>,
+        ) = unbounded();
+        let (log_tx, log) = unbounded();
+        let (custom_tx, custom_events) = unbounded();
+        let (to_custom, custom_calls) = unbounded();
+        let channels = GameChannels {
+            to_game,
+            from_game,
+            log: log_tx,
+            custom_events: custom_tx,
+            custom_calls,
+        };
+
+        let respawn = {
+            let (config, id_keys, make_custom) =
+                (config.clone(), id_keys.clone(), Arc::clone(&make_custom));
+            Respawn(Arc::new(move || {
+                spawn_network(config.clone(), id_keys.clone(), Arc::clone(&make_custom))
+            }))
+        };
+
+        // Start thread that loops for events and reads the channels
+        let swarm_task = SwarmTask::new(swarm, &id_keys, keys, &config, channels, make_custom)
+            .map_err(NetworkSetupError::network_thread)?;
+        let metrics = MetricsSource {
+            counters: swarm_task.counters(),
+            bandwidth: swarm_task.bandwidth(),
+            keys: swarm_task.shared_keys(),
+        };
+        metrics.bandwidth.push(bandwidth);
+        let liveness = swarm_task.liveness();
+        Runtime::default().run_on_thread(move || swarm_task.run());
+
+        Ok(NetworkManager {
+            from_network,
+            to_network,
+            log,
+            custom_events,
+            to_custom,
+            metrics,
+            liveness,
+            respawn,
+            local_peer_id,
+            stopped: false,
+        })
+    }
+    .boxed_local()
+}
+
+/// The swarm for identity `id_keys`, with the room [`KeyRing`] its gossip uses and
+/// the byte counters of its transport. Nothing listens or dials yet.
+async fn build_swarm<Custom: CustomBehaviour>(
+    id_keys: &identity::Keypair,
+    config: &NetworkConfig,
+    make_custom: &CustomFactory<Custom>,
+) -> Result<(Swarm<Behaviour<Custom>>, KeyRing, Arc<BandwidthSinks>), NetworkSetupError> {
+    let local_peer_id = PeerId::from(id_keys.public());
+    let (relay_transport, relay) = relay::client::new(local_peer_id);
+    // Every transport that dials host names must resolve them the same way.
+    let (resolver, resolver_opts) = config.resolver();
+    #[cfg(not(feature = "tokio"))]
+    let (tcp_transport, ws_transport) = (
+        dns::DnsConfig::custom(
+            tcp::async_io::Transport::new(tcp::Config::default().nodelay(true)),
+            resolver.clone(),
+            resolver_opts,
+        )
+        .await
+        .map_err(NetworkSetupError::transport)?,
+        websocket::WsConfig::new(
+            dns::DnsConfig::custom(
+                tcp::async_io::Transport::new(tcp::Config::default().nodelay(true)),
+                resolver,
+                resolver_opts,
+            )
+            .await
+            .map_err(NetworkSetupError::transport)?,
+        ),
+    );
+    #[cfg(feature = "tokio")]
+    let (tcp_transport, ws_transport) = (
+        dns::TokioDnsConfig::custom(
+            tcp::tokio::Transport::new(tcp::Config::default().nodelay(true)),
+            resolver.clone(),
+            resolver_opts,
+        )
+        .map_err(NetworkSetupError::transport)?,
+        websocket::WsConfig::new(
+            dns::TokioDnsConfig::custom(
+                tcp::tokio::Transport::new(tcp::Config::default().nodelay(true)),
+                resolver,
+                resolver_opts,
+            )
+            .map_err(NetworkSetupError::transport)?,
+        ),
+    );
+    // TODO: quic transport, behind the same resolver
+
+    let transport = tcp_transport
+        .or_transport(ws_transport)
+        .or_transport(relay_transport)
+        .upgrade(upgrade::Version::V1Lazy)
+        .authenticate(noise::Config::new(id_keys).expect("signing libp2p-noise static keypair"))
+        .multiplex(yamux::Config::default())
+        .timeout(std::time::Duration::from_secs(20))
+        .boxed();
+    let (transport, bandwidth) = transport.with_bandwidth_logging();
+
+    let (behaviour, keys) = Behaviour::new(id_keys, relay, config, make_custom(id_keys))
+        .map_err(NetworkSetupError::behaviour)?;
+
+    let swarm =
+        SwarmBuilder::with_executor(transport, behaviour, local_peer_id, Runtime::default())
+            .build();
+    Ok((swarm, keys, bandwidth))
+}
+
+/// What the app does when the network thread stops without being asked to, e.g.
+/// because it panicked. Either way the game gets [`NetworkAdminEvent::NetworkDied`].
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NetworkFailurePolicy {
+    /// Keep running without a network.
+    #[default]
+    KeepRunning,
+    /// Send [`AppExit`], so a process supervisor can restart the game.
+    ExitApp,
+}
+
+/// How many events from the network thread are turned into [`NetworkEvent`]s each
+/// frame, so a flood of messages can't stall the game. Whatever is over the budget
+/// waits for the next frame; [`NetworkMetrics::pending_events`] shows how much.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkEventBudget {
+    /// Events per frame, or `None` for no limit.
+    pub max_events: Option<usize>,
+    /// Time spent on them per frame, or `None` for no limit. At least one event is
+    /// taken every frame either way.
+    pub max_time: Option<Duration>,
+    /// Warn once events have been left over this long without a break.
+    pub warn_after: Duration,
+}
+
+impl Default for NetworkEventBudget {
+    fn default() -> Self {
+        Self {
+            max_events: Some(1024),
+            max_time: Some(Duration::from_millis(4)),
+            warn_after: Duration::from_secs(2),
+        }
+    }
+}
+
+impl NetworkEventBudget {
+    /// Whether a frame that took `taken` events in `spent` is done.
+    fn exhausted(&self, taken: usize, spent: Duration) -> bool {
+        taken > 0
+            && (self.max_events.map_or(false, |max| taken >= max)
+                || self.max_time.map_or(false, |max| spent >= max))
+    }
+}
+
+/// Forwards everything the network thread reports into Bevy as [`NetworkEvent`]s, and
+/// its log into the [`NetworkLog`]. Also sets up [`AsyncNetworkTasks`] for sending,
+/// and ties the network thread's lifetime to the app's, see [`NetworkFailurePolicy`];
+/// add [`ShutdownPlugin`](crate::shutdown::ShutdownPlugin) as well for it to say
+/// goodbye to its peers when the app exits. Starts the network itself if the app has
+/// a [`NetworkSetup`]; its systems wait for a [`NetworkManager`] either way.
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetworkLog>()
+            .init_resource::<ExternalAddresses>()
+            .init_resource::<MeshHealth>()
+            .init_resource::<NetworkFailurePolicy>()
+            .init_resource::<NetworkWatchdog>()
+            .init_resource::<NetworkEventBudget>()
+            .add_systems(Startup, setup::start_network)
+            .add_systems(
+                PreUpdate,
+                (
+                    setup::poll_network_setup.run_if(resource_exists::<setup::PendingNetwork>()),
+                    tasks::insert_network_tasks::<(), ()>
+                        .run_if(resource_added::<NetworkManager<(), ()>>()),
+                    tasks::poll_network_tasks::<()>
+                        .run_if(resource_exists::<tasks::AsyncNetworkTasks<()>>()),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    process_network_events::<(), ()>,
+                    watchdog::watch_network::<(), ()>,
+                    (
+                        forward_network_log::<(), ()>,
+                        session::update_local_peer_info::<(), ()>,
+                        metrics::update_crypto_status::<(), ()>,
+                        session::track_current_room::<(), ()>,
+                        external_addresses::track_external_addresses::<()>,
+                        mesh_health::track_mesh_health::<(), ()>,
+                    ),
+                )
+                    .chain()
+                    .run_if(resource_exists::<NetworkManager<(), ()>>()),
+            )
+            .add_event::<NetworkEvent<()>>()
+            .add_event::<NetworkTaskFinished>();
+    }
+}
+
+fn process_network_events<ToGame, FromGame>(
+    mut network_manager: ResMut<NetworkManager<FromGame, ToGame>>,
+    mut network_events: EventWriter<NetworkEvent<ToGame>>,
+    policy: Res<NetworkFailurePolicy>,
+    budget: Res<NetworkEventBudget>,
+    mut exit: EventWriter<AppExit>,
+    mut behind: Local<Option<(Instant, bool)>>,
+) where
+    ToGame: Send + Sync + 'static,
+    FromGame: Send + 'static,
+{
+    let started = Instant::now();
+    let mut taken = 0;
+    loop {
+        if budget.exhausted(taken, started.elapsed()) {
+            break;
+        }
+        let event = match network_manager.from_network.try_recv() {
+            Ok(event) => event,
+            Err(TryRecvError::Empty) => break,
+            Err(TryRecvError::Closed) => {
+                if !network_manager.stopped {
+                    network_manager.stopped = true;
+                    log::error!("The network thread stopped unexpectedly");
+                    network_events.send(NetworkEvent::Admin(NetworkAdminEvent::NetworkDied));
+                    if *policy == NetworkFailurePolicy::ExitApp {
+                        exit.send(AppExit);
+                    }
+                }
+                break;
+            }
+        };
+        match &event {
+            NetworkEvent::Admin(NetworkAdminEvent::NetworkRestarted { local_peer_id }) => {
+                network_manager.local_peer_id = *local_peer_id;
+            }
+            NetworkEvent::Admin(NetworkAdminEvent::ShutdownComplete) => {
+                network_manager.stopped = true
+            }
+            _ => {}
+        }
+        taken += 1;
+        network_events.send(event);
+    }
+
+    let pending = network_manager.pending_events();
+    if pending == 0 {
+        *behind = None;
+        return;
+    }
+    let (since, warned) = behind.get_or_insert((started, false));
+    if !*warned && since.elapsed() >= budget.warn_after {
+        *warned = true;
+        log::warn!(
+            "Network events have been over budget for {:.1}s, {} waiting",
+            since.elapsed().as_secs_f32(),
+            pending
+        );
+    }
+}
+
+fn forward_network_log<FromGame, ToGame>(
+    manager: Res<NetworkManager<FromGame, ToGame>>,
+    mut network_log: ResMut<NetworkLog>,
+) where
+    FromGame: Send + Sync + 'static,
+    ToGame: Send + Sync + 'static,
+{
+    while let Ok(entry) = manager.log.try_recv() {
+        network_log.push(entry);
+    }
+}
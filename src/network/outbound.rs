@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// How urgently an outbound game message should go out when the network thread falls
+/// behind. Sent with [`GameEvent::Prioritized`](super::GameEvent::Prioritized).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Priority {
+    /// Inputs and authoritative state.
+    Critical,
+    #[default]
+    Normal,
+    /// Chat, emotes and anything else that can arrive late.
+    Cosmetic,
+}
+
+impl Priority {
+    const ALL: [Priority; 3] = [Priority::Critical, Priority::Normal, Priority::Cosmetic];
+
+    /// Messages of this class sent per round of the scheduler.
+    fn weight(self) -> u32 {
+        match self {
+            Priority::Critical => 6,
+            Priority::Normal => 3,
+            Priority::Cosmetic => 1,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Weighted round robin over one queue per [`Priority`]. Higher classes get most of
+/// the sends, but lower ones still trickle out instead of starving.
+pub(super) struct OutboundQueue<T> {
+    queues: [VecDeque<T>; 3],
+    current: usize,
+    /// Sends left for `current` in this round.
+    credit: u32,
+}
+
+impl<T> Default for OutboundQueue<T> {
+    fn default() -> Self {
+        Self {
+            queues: Default::default(),
+            current: 0,
+            credit: Priority::ALL[0].weight(),
+        }
+    }
+}
+
+impl<T> OutboundQueue<T> {
+    pub(super) fn push(&mut self, priority: Priority, item: T) {
+        if self.is_empty() {
+            // Start a fresh round so a new burst leads with critical traffic.
+            self.current = 0;
+            self.credit = Priority::ALL[0].weight();
+        }
+        self.queues[priority.index()].push_back(item);
+    }
+
+    pub(super) fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        loop {
+            if self.credit > 0 {
+                if let Some(item) = self.queues[self.current].pop_front() {
+                    self.credit -= 1;
+                    return Some(item);
+                }
+            }
+            self.current = (self.current + 1) % Priority::ALL.len();
+            self.credit = Priority::ALL[self.current].weight();
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.queues.iter_mut().for_each(VecDeque::clear);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes_share_sends_by_weight() {
+        let mut queue = OutboundQueue::default();
+        for _ in 0..20 {
+            for priority in Priority::ALL {
+                queue.push(priority, priority);
+            }
+        }
+        let round: Vec<_> = (0..10).map(|_| queue.pop().unwrap()).collect();
+        let count = |p| round.iter().filter(|&&sent| sent == p).count();
+        assert_eq!(count(Priority::Critical), 6);
+        assert_eq!(count(Priority::Normal), 3);
+        assert_eq!(count(Priority::Cosmetic), 1);
+        assert_eq!(round[0], Priority::Critical);
+
+        // Once critical traffic dries up the rest drains in order.
+        let mut queue = OutboundQueue::default();
+        queue.push(Priority::Cosmetic, 1);
+        queue.push(Priority::Normal, 2);
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), None);
+    }
+}
@@ -0,0 +1,481 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use libp2p::{Multiaddr, PeerId};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{acks::AckField, events::Emote, fragment::Fragment};
+use crate::crypto::PeerCiphertext;
+
+/// Everything published on a room topic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum WireMessage<T> {
+    Control(ControlMessage),
+    /// `sent_at` is in milliseconds since the Unix epoch, on the sender's clock, for
+    /// [`NetworkConfig::message_ttl`](super::NetworkConfig::message_ttl).
+    Game {
+        sent_at: u64,
+        event: T,
+    },
+    /// A [`GameEvent::Latest`](super::GameEvent::Latest) payload, with what we got on
+    /// that channel from each peer.
+    Latest {
+        sent_at: u64,
+        seq: u32,
+        acks: Vec<(PeerId, AckField)>,
+        event: T,
+    },
+    /// A game payload encrypted for a single peer. Everyone else drops it.
+    Unicast {
+        to: PeerId,
+        payload: PeerCiphertext,
+    },
+    /// A piece of a message too big to publish at once. Put back together, the
+    /// pieces hold any of the other variants.
+    Fragment(Fragment),
+}
+
+/// Session control messages shared by every game built on this crate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum ControlMessage {
+    /// The host started the game; everyone seeds their RNG with `seed` and starts
+    /// playing `countdown_ms` after receiving this, at `tick` if the host picked one.
+    StartGame {
+        seed: u64,
+        countdown_ms: u64,
+        tick: Option<TickSettings>,
+    },
+    /// The host started a new round with a fresh seed.
+    NewRound { round: u32, seed: u64 },
+    /// The sender is leaving the room or quitting. Sent by anyone, not just the host,
+    /// so peers can drop the player without waiting for the connection to time out.
+    PlayerLeaving,
+    /// The sender is, or no longer is, ready for the game to start.
+    Ready(bool),
+    /// Whether the sender only watches, and sends no game messages.
+    Spectating(bool),
+    /// The host switched to a new room key, encrypted for each peer in the room. Older
+    /// keys still decrypt for `grace_ms`.
+    RoomKey {
+        sealed: Vec<(PeerId, PeerCiphertext)>,
+        grace_ms: u64,
+    },
+    /// The sender encrypts with the room key of this
+    /// [`fingerprint`](crate::crypto::RoomKey::fingerprint). Joiners send it to the host
+    /// once it's in their mesh, and after every key rotation.
+    KeyAck([u8; 8]),
+    /// The sender's game is still running. Unlike libp2p's ping this stops when the
+    /// game freezes, even though its connections stay up.
+    Heartbeat,
+    /// A chat line from the sender.
+    Chat(String),
+    /// An emote from the sender.
+    Emote(Emote),
+    /// The host muted, or unmuted, `peer_id` for the whole room.
+    RoomMute { peer_id: PeerId, muted: bool },
+    /// The sender wants to simulate entity `id`. Only the host answers.
+    RequestAuthority(u64),
+    /// The sender hands entity `id` back to the host.
+    ReleaseAuthority(u64),
+    /// The host gave entity `id` to `owner`.
+    Authority { id: u64, owner: PeerId },
+    /// The host turned down `requester`'s [`ControlMessage::RequestAuthority`] because
+    /// `owner` has entity `id`.
+    AuthorityDenied {
+        id: u64,
+        requester: PeerId,
+        owner: PeerId,
+    },
+    /// The sender's profile. Sent when it changes, and again to peers we hear from for
+    /// the first time.
+    Profile(SignedProfile),
+    /// The host changed who is on `team`, and switched it to a new key, encrypted for
+    /// each member. Peers left out drop the team's topic. No members disbands the team.
+    TeamKey {
+        team: String,
+        generation: u64,
+        members: Vec<PeerId>,
+        sealed: Vec<(PeerId, PeerCiphertext)>,
+    },
+    /// What the sender measured about its links and machine, for the host to pick
+    /// [`TickSettings`] from.
+    Conditions(LinkConditions),
+    /// The host switched the game in progress to new [`TickSettings`].
+    TickSettings(TickSettings),
+}
+
+/// How fast a lockstep game steps, and how far ahead inputs are scheduled so they
+/// reach every peer in time. Picked by the host for the slowest link and machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TickSettings {
+    /// Ticks per second.
+    pub tick_rate: u32,
+    /// Ticks between sampling an input and simulating it.
+    pub input_delay: u32,
+}
+
+/// What a peer measured about its links and machine, see
+/// [`GameAdminEvent::ReportConditions`](super::GameAdminEvent::ReportConditions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkConditions {
+    /// The round trip to the slowest peer it reaches, at the 95th percentile.
+    pub worst_rtt_ms: u32,
+    /// The fastest tick rate its machine keeps up with.
+    pub max_tick_rate: u32,
+}
+
+/// Encodes a game payload exactly as it's published to the room, before encryption.
+/// Only public for the benchmarks.
+#[doc(hidden)]
+pub fn encode_game_message<T: Serialize>(event: &T) -> bincode::Result<Vec<u8>> {
+    bincode::serialize(&WireMessage::Game {
+        sent_at: unix_millis(),
+        event,
+    })
+}
+
+/// Inverse of [`encode_game_message`]. Only public for the benchmarks.
+#[doc(hidden)]
+pub fn decode_game_message<T: DeserializeOwned>(data: &[u8]) -> bincode::Result<Option<T>> {
+    match bincode::deserialize(data)? {
+        WireMessage::Game { event, .. } => Ok(Some(event)),
+        _ => Ok(None),
+    }
+}
+
+/// Milliseconds since the Unix epoch, as game messages are stamped with.
+pub(super) fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64)
+}
+
+/// Whether a game message stamped `sent_at` is older than `ttl` at `now`.
+pub(super) fn is_stale(sent_at: u64, now: u64, ttl: Duration) -> bool {
+    now.saturating_sub(sent_at) > ttl.as_millis() as u64
+}
+
+/// Protocol for sending a friend a room invite directly, after
+/// [`ProtocolNames::prefix`](super::ProtocolNames::prefix).
+pub const INVITE_PROTOCOL: &str = "/invite/1";
+
+/// Asks the receiving player to join `room_code`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomInvite {
+    pub room_code: String,
+}
+
+/// Sent back once a [`RoomInvite`] reached the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InviteAck;
+
+/// Protocol a late joiner asks the host for the state of the game in progress over,
+/// after [`ProtocolNames::prefix`](super::ProtocolNames::prefix).
+pub const SNAPSHOT_PROTOCOL: &str = "/snapshot/1";
+
+/// Asks the host of the room we just joined for a [`Snapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotRequest;
+
+/// The host's answer to a [`SnapshotRequest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SnapshotResponse {
+    Snapshot(Snapshot),
+    /// The game hasn't started, so the joiner will see it start like everyone else.
+    NotStarted,
+    Refused,
+}
+
+/// The whole game state as of `tick`, encoded by the game. Game messages for later
+/// ticks apply on top of it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: u64,
+    pub state: Vec<u8>,
+}
+
+/// Protocol players send each other files over, see
+/// [`GameAdminEvent::SendFile`](super::GameAdminEvent::SendFile), after
+/// [`ProtocolNames::prefix`](super::ProtocolNames::prefix).
+pub const FILE_PROTOCOL: &str = "/file/1";
+
+/// Most of a file sent in one [`FileRequest::Chunk`], well under the request size
+/// limit once encoded.
+pub(super) const FILE_CHUNK_SIZE: usize = 256 * 1024;
+
+/// A file transfer, one request at a time: the offer, then each chunk once the last
+/// one was [`Received`](FileResponse::Received).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileRequest {
+    /// Asks to send a file. Answered once the receiving player accepted or declined.
+    Offer {
+        transfer: u64,
+        name: String,
+        size: u64,
+    },
+    /// The piece of an accepted file starting at `offset`.
+    Chunk {
+        transfer: u64,
+        offset: u64,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileResponse {
+    Accepted,
+    Declined,
+    /// The chunk arrived, send the next one.
+    Received,
+    /// The receiver gave up on the transfer.
+    Failed(String),
+}
+
+/// An invite left in a peer's DHT mailbox for when they next come online. The record
+/// holds it as a [`SealedBox`](crate::crypto::SealedBox) only the recipient can open.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MailboxInvite {
+    pub from: PeerId,
+    pub room_code: String,
+    /// Seconds since the Unix epoch.
+    pub sent_at: u64,
+    /// Signature over [`mailbox_invite_payload`] with `from`'s identity key.
+    pub signature: Vec<u8>,
+}
+
+/// What the sender of a [`MailboxInvite`] signs. Binding the recipient means an
+/// invite opened by one peer can't be re-sealed and posted to another.
+pub fn mailbox_invite_payload(to: &PeerId, room_code: &str, sent_at: u64) -> Vec<u8> {
+    [
+        b"bevy-p2p-demo mailbox invite".as_slice(),
+        &to.to_bytes(),
+        room_code.as_bytes(),
+        &sent_at.to_be_bytes(),
+    ]
+    .concat()
+}
+
+/// What a host publishes about its room next to the provider record, so joiners can
+/// see what they'd be joining before dialing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub mode: String,
+    /// Including the host.
+    pub players: u32,
+    /// `None` if there is no limit.
+    pub max_players: Option<u32>,
+    /// Crate version the host runs.
+    pub version: String,
+    pub started: bool,
+}
+
+impl RoomInfo {
+    /// Why a joiner with game `modes` can't play in the room, if that's the case. An
+    /// empty list means the joiner plays whatever the room does.
+    pub fn unknown_mode(&self, modes: &[String]) -> Option<JoinError> {
+        (!modes.is_empty() && !modes.contains(&self.mode)).then(|| JoinError::UnknownMode {
+            mode: self.mode.clone(),
+        })
+    }
+
+    /// Why we, or anybody else, can't join right now, if that's the case.
+    pub fn closed(&self) -> Option<JoinError> {
+        if self.version != PROTOCOL_VERSION {
+            Some(JoinError::VersionMismatch {
+                host: self.version.clone(),
+            })
+        } else if self.started {
+            Some(JoinError::HostRejected {
+                reason: "The game already started".to_string(),
+            })
+        } else if self.max_players.map_or(false, |max| self.players >= max) {
+            Some(JoinError::RoomFull)
+        } else {
+            None
+        }
+    }
+}
+
+/// The crate version, which peers have to agree on to play together.
+pub(super) const PROTOCOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Why a [`GameAdminEvent::Join`](super::GameAdminEvent::Join) didn't get us into the
+/// room, as reported by [`NetworkAdminEvent::JoinFailed`](super::NetworkAdminEvent::JoinFailed).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JoinError {
+    /// Nobody hosts a room under that code, or no host of it could be found.
+    RoomNotFound,
+    RoomFull,
+    /// The host runs version `host` of the game, and we another.
+    VersionMismatch {
+        host: String,
+    },
+    /// The invite doesn't match the private room's. Typos in invites are caught
+    /// before joining, so it's likely from an older match under the same code.
+    WrongPassword,
+    /// No host let us in within [`JoinLimits::timeout`](super::JoinLimits::timeout),
+    /// or we took too long to answer its challenge.
+    Timeout,
+    /// The host, or its game, turned us down.
+    HostRejected {
+        reason: String,
+    },
+    /// The host couldn't be reached, or the handshake with it broke down.
+    TransportFailure {
+        reason: String,
+    },
+    /// We joined too soon after the last join. Try again in `retry_in`.
+    Throttled {
+        retry_in: Duration,
+    },
+    /// The room plays a game mode we don't have registered.
+    UnknownMode {
+        mode: String,
+    },
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RoomNotFound => write!(f, "room not found"),
+            Self::RoomFull => write!(f, "the room is full"),
+            Self::VersionMismatch { host } => write!(
+                f,
+                "the host runs version {}, we run {}",
+                host, PROTOCOL_VERSION
+            ),
+            Self::WrongPassword => write!(f, "the invite is not valid for this room"),
+            Self::Timeout => write!(f, "timed out"),
+            Self::HostRejected { reason } => write!(f, "rejected: {}", reason),
+            Self::TransportFailure { reason } => write!(f, "could not reach the host: {}", reason),
+            Self::Throttled { retry_in } => {
+                write!(
+                    f,
+                    "too many joins, try again in {}s",
+                    retry_in.as_secs() + 1
+                )
+            }
+            Self::UnknownMode { mode } => write!(f, "the room plays {}, which we don't have", mode),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// A [`RoomInfo`] as stored on the DHT, signed by the host it describes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct SignedRoomInfo {
+    pub host: PeerId,
+    pub info: RoomInfo,
+    /// Seconds since the Unix epoch.
+    pub published_at: u64,
+    /// Signature over [`room_info_payload`] with `host`'s identity key.
+    pub signature: Vec<u8>,
+}
+
+/// What a host signs when publishing its [`RoomInfo`]. Binding the room code means a
+/// record can't be copied over to describe some other room.
+pub fn room_info_payload(room_code: &str, info: &RoomInfo, published_at: u64) -> Vec<u8> {
+    [
+        b"bevy-p2p-demo room info".as_slice(),
+        room_code.as_bytes(),
+        &bincode::serialize(info).expect("Room info always serializes"),
+        &published_at.to_be_bytes(),
+    ]
+    .concat()
+}
+
+/// What a player shows the room about themselves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerProfile {
+    pub name: String,
+    /// An avatar by a name the game knows, `None` for its default one.
+    pub avatar: Option<String>,
+}
+
+/// A [`PeerProfile`] signed by the peer it describes, so nobody else can put a name
+/// on it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct SignedProfile {
+    pub peer_id: PeerId,
+    pub profile: PeerProfile,
+    /// Milliseconds since the Unix epoch. Receivers drop profiles older than the last
+    /// one they took, so an old name can't be replayed.
+    pub signed_at: u64,
+    /// Signature over [`profile_payload`] with `peer_id`'s identity key.
+    pub signature: Vec<u8>,
+}
+
+/// What a peer signs when telling the room its [`PeerProfile`].
+pub fn profile_payload(peer_id: &PeerId, profile: &PeerProfile, signed_at: u64) -> Vec<u8> {
+    [
+        b"bevy-p2p-demo profile".as_slice(),
+        &peer_id.to_bytes(),
+        &bincode::serialize(profile).expect("Profiles always serialize"),
+        &signed_at.to_be_bytes(),
+    ]
+    .concat()
+}
+
+/// Protocol used by joiners to ask the host for admission into a room, after
+/// [`ProtocolNames::prefix`](super::ProtocolNames::prefix).
+pub const JOIN_PROTOCOL: &str = "/join/6";
+
+/// Sent by a joiner directly to a peer discovery says may host its room.
+///
+/// A join takes two round trips: `Hello` earns a [`JoinResponse::Challenge`], and
+/// the signed nonce sent back in `Proof` gets the final answer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JoinRequest {
+    Hello {
+        room_code: String,
+        name: String,
+        /// The joiner's [`RoomInfo::version`]. Hosts turn other versions away.
+        version: String,
+        /// The game modes the joiner has registered. Hosts turn the joiner away if
+        /// the room's [`RoomInfo::mode`] isn't one of them, unless this is empty.
+        modes: Vec<String>,
+    },
+    /// Signature over [`join_proof_payload`] with the joiner's identity key.
+    Proof { signature: Vec<u8> },
+}
+
+/// The host's answer to a [`JoinRequest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JoinResponse {
+    /// Prove you own your peer id by signing this nonce.
+    Challenge { nonce: [u8; 32] },
+    /// Admitted: `room_key` decrypts the room's gossip. It is encrypted with the
+    /// secret the host and joiner share. `peers` are the room's other members, for the
+    /// joiner to dial so it isn't only connected through the host.
+    Accepted {
+        room_key: PeerCiphertext,
+        peers: Vec<RoomPeer>,
+    },
+    /// Not let in. Only ever one of the reasons the host can tell.
+    Rejected(JoinError),
+    /// We don't host that room, so the joiner should try its next candidate.
+    UnknownRoom,
+}
+
+/// Peer exchange: a room member and where it says it listens.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomPeer {
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+}
+
+/// What a joiner signs to answer a challenge. Binding the host and room means a
+/// proof copied from one handshake is useless in any other. For a private match
+/// `room_code` is the invite token, so only joiners holding it can sign.
+pub fn join_proof_payload(nonce: &[u8; 32], host: &PeerId, room_code: &str) -> Vec<u8> {
+    [
+        b"bevy-p2p-demo join proof".as_slice(),
+        &host.to_bytes(),
+        room_code.as_bytes(),
+        nonce,
+    ]
+    .concat()
+}
@@ -0,0 +1,127 @@
+//! Notices peers we only reach through the relay for long, or for a lot of traffic.
+//! Relays are shared and rate limited, so games should nudge players towards a direct
+//! connection, see [`NetworkAdminEvent::RelayUsageWarning`](super::NetworkAdminEvent::RelayUsageWarning).
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use libp2p::PeerId;
+
+use super::{ConnectionPath, RelayUsageLimits};
+
+#[derive(Debug, Clone, Copy)]
+struct Relayed {
+    since: Instant,
+    bytes: u64,
+    warned: bool,
+}
+
+/// A peer that went over a [`RelayUsageLimits`] limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Overuse {
+    pub peer_id: PeerId,
+    pub relayed_for: Duration,
+    pub bytes: u64,
+}
+
+/// How long, and for how many bytes, each relayed peer has been relayed. Bytes are
+/// the room messages between us, so they leave out gossip and protocol overhead.
+#[derive(Debug, Default)]
+pub(super) struct RelayUsage(HashMap<PeerId, Relayed>);
+
+impl RelayUsage {
+    /// Starts counting when `peer_id` goes on the relay, and stops when it leaves it.
+    pub(super) fn path_changed(&mut self, peer_id: PeerId, path: ConnectionPath, now: Instant) {
+        match path {
+            ConnectionPath::Relayed => {
+                self.0.entry(peer_id).or_insert(Relayed {
+                    since: now,
+                    bytes: 0,
+                    warned: false,
+                });
+            }
+            ConnectionPath::Direct => self.forget(&peer_id),
+        }
+    }
+
+    pub(super) fn forget(&mut self, peer_id: &PeerId) {
+        self.0.remove(peer_id);
+    }
+
+    /// Counts `bytes` sent to or received from `peer_id`, if it is relayed.
+    pub(super) fn count(&mut self, peer_id: &PeerId, bytes: usize) {
+        if let Some(relayed) = self.0.get_mut(peer_id) {
+            relayed.bytes += bytes as u64;
+        }
+    }
+
+    /// When the next peer runs out of [`RelayUsageLimits::duration`].
+    pub(super) fn next_deadline(&self, limits: &RelayUsageLimits) -> Option<Instant> {
+        self.0
+            .values()
+            .filter(|relayed| !relayed.warned)
+            .map(|relayed| relayed.since + limits.duration)
+            .min()
+    }
+
+    /// Peers over a limit that weren't reported yet. Each is reported once per stay on
+    /// the relay.
+    pub(super) fn overused(&mut self, limits: &RelayUsageLimits, now: Instant) -> Vec<Overuse> {
+        self.0
+            .iter_mut()
+            .filter(|(_, relayed)| !relayed.warned)
+            .filter(|(_, relayed)| {
+                now.saturating_duration_since(relayed.since) >= limits.duration
+                    || relayed.bytes >= limits.bytes
+            })
+            .map(|(peer_id, relayed)| {
+                relayed.warned = true;
+                Overuse {
+                    peer_id: *peer_id,
+                    relayed_for: now.saturating_duration_since(relayed.since),
+                    bytes: relayed.bytes,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warns_once_per_stay_on_the_relay() {
+        let limits = RelayUsageLimits {
+            duration: Duration::from_secs(60),
+            bytes: 1000,
+        };
+        let start = Instant::now();
+        let (slow, busy) = (PeerId::random(), PeerId::random());
+        let mut usage = RelayUsage::default();
+        usage.path_changed(slow, ConnectionPath::Relayed, start);
+        usage.path_changed(busy, ConnectionPath::Relayed, start);
+        assert_eq!(usage.next_deadline(&limits), Some(start + limits.duration));
+
+        usage.count(&busy, 600);
+        usage.count(&busy, 400);
+        usage.count(&PeerId::random(), 1000);
+        let overused = usage.overused(&limits, start);
+        assert_eq!(overused.len(), 1);
+        assert_eq!(overused[0].peer_id, busy);
+        assert_eq!(overused[0].bytes, 1000);
+
+        let later = start + limits.duration;
+        let overused = usage.overused(&limits, later);
+        assert_eq!(overused.len(), 1);
+        assert_eq!(overused[0].peer_id, slow);
+        assert!(usage.overused(&limits, later).is_empty());
+        assert_eq!(usage.next_deadline(&limits), None);
+
+        usage.path_changed(slow, ConnectionPath::Direct, later);
+        usage.path_changed(slow, ConnectionPath::Relayed, later);
+        assert_eq!(usage.next_deadline(&limits), Some(later + limits.duration));
+    }
+}
@@ -0,0 +1,74 @@
+//! The async runtime under the network thread: async-std by default, or tokio with the
+//! `tokio` feature, for games embedded in a tokio app. Only the thread driving the
+//! swarm, its connection tasks and its TCP transports depend on it; our channels and
+//! timers work under either.
+
+use std::{future::Future, pin::Pin, thread};
+
+use libp2p::swarm::Executor;
+
+/// Runs the network thread and the swarm's connection tasks.
+pub(super) trait Spawner: Executor + Default + Send + 'static {
+    /// Runs the future `make` returns to completion on a thread of its own.
+    fn run_on_thread<F, Fut>(self, make: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()>;
+}
+
+#[cfg(not(feature = "tokio"))]
+pub(super) type Runtime = AsyncStd;
+#[cfg(feature = "tokio")]
+pub(super) type Runtime = Tokio;
+
+#[cfg(not(feature = "tokio"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct AsyncStd;
+
+#[cfg(not(feature = "tokio"))]
+impl Executor for AsyncStd {
+    fn exec(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        async_std::task::spawn(future);
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Spawner for AsyncStd {
+    fn run_on_thread<F, Fut>(self, make: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()>,
+    {
+        thread::spawn(move || async_std::task::block_on(make()));
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct Tokio;
+
+#[cfg(feature = "tokio")]
+impl Executor for Tokio {
+    fn exec(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        tokio::spawn(future);
+    }
+}
+
+/// The network thread gets a runtime of its own, so it never competes with the app's
+/// tasks, nor needs the app to be inside one.
+#[cfg(feature = "tokio")]
+impl Spawner for Tokio {
+    fn run_on_thread<F, Fut>(self, make: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()>,
+    {
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Building the network thread's tokio runtime");
+            runtime.block_on(make());
+        });
+    }
+}
@@ -0,0 +1,83 @@
+//! Who we are and which room we are in, as resources, so HUDs, logs and game logic
+//! can read them without following the network events themselves.
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use super::{NetworkAdminEvent, NetworkEvent, NetworkManager};
+
+/// Us, kept up to date across network restarts. Lets systems tell their own messages
+/// apart, e.g. chat lines shown with [`ChatEcho`](crate::chat::ChatEcho).
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LocalPeerInfo {
+    pub peer_id: PeerId,
+}
+
+/// The room we host or joined. Only exists while we are in one.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct CurrentRoom {
+    /// The code the room is announced under, without any invite secret.
+    pub room_code: String,
+    pub host: PeerId,
+    /// Whether the host is us.
+    pub hosting: bool,
+}
+
+pub(super) fn update_local_peer_info<FromGame, ToGame>(
+    mut commands: Commands,
+    manager: Res<NetworkManager<FromGame, ToGame>>,
+    local: Option<Res<LocalPeerInfo>>,
+) where
+    FromGame: Send + Sync + 'static,
+    ToGame: Send + Sync + 'static,
+{
+    let peer_id = manager.local_peer_id();
+    if local.map_or(true, |local| local.peer_id != peer_id) {
+        commands.insert_resource(LocalPeerInfo { peer_id });
+    }
+}
+
+/// How `event` changes the room we are in: `Some(Some(room))` when we enter one,
+/// `Some(None)` when we leave it.
+pub(super) fn room_change(
+    event: &NetworkAdminEvent,
+    local_peer_id: PeerId,
+) -> Option<Option<CurrentRoom>> {
+    match event {
+        NetworkAdminEvent::HostingStarted { room_code } => Some(Some(CurrentRoom {
+            room_code: room_code.clone(),
+            host: local_peer_id,
+            hosting: true,
+        })),
+        NetworkAdminEvent::JoinAccepted { host, room_code } => Some(Some(CurrentRoom {
+            room_code: room_code.clone(),
+            host: *host,
+            hosting: false,
+        })),
+        NetworkAdminEvent::LeftRoom
+        | NetworkAdminEvent::HostingFailed { .. }
+        | NetworkAdminEvent::ShutdownComplete
+        | NetworkAdminEvent::NetworkDied => Some(None),
+        _ => None,
+    }
+}
+
+pub(super) fn track_current_room<FromGame, ToGame>(
+    mut commands: Commands,
+    manager: Res<NetworkManager<FromGame, ToGame>>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+) where
+    FromGame: Send + Sync + 'static,
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        match room_change(event, manager.local_peer_id()) {
+            Some(Some(room)) => commands.insert_resource(room),
+            Some(None) => commands.remove_resource::<CurrentRoom>(),
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Starts the network from [`NetworkPlugin`](super::NetworkPlugin), so games don't
+//! have to build a [`NetworkManager`] and insert it themselves: insert a
+//! [`NetworkSetup`] before running the app, and the network comes up on the
+//! [`IoTaskPool`] while the game loads, or [`NetworkAdminEvent::NetworkSetupFailed`]
+//! says why not.
+
+use std::fmt;
+
+use async_std::task;
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+};
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    setup_network, NetworkAdminEvent, NetworkConfig, NetworkEvent, NetworkFailurePolicy,
+    NetworkManager,
+};
+
+/// Why the network could not be started.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NetworkSetupError {
+    /// A transport could not be built, e.g. because the DNS setup is unreadable.
+    Transport { reason: String },
+    /// The swarm's behaviour could not be built from the [`NetworkConfig`].
+    Behaviour { reason: String },
+    /// The network thread could not be set up, e.g. because a bootstrap peer or
+    /// discovery method in the [`NetworkConfig`] is malformed.
+    NetworkThread { reason: String },
+}
+
+impl NetworkSetupError {
+    pub(super) fn transport(e: impl fmt::Display) -> Self {
+        Self::Transport {
+            reason: e.to_string(),
+        }
+    }
+
+    pub(super) fn behaviour(e: impl fmt::Display) -> Self {
+        Self::Behaviour {
+            reason: e.to_string(),
+        }
+    }
+
+    pub(super) fn network_thread(e: impl fmt::Display) -> Self {
+        Self::NetworkThread {
+            reason: e.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for NetworkSetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport { reason } => write!(f, "could not build the transport: {}", reason),
+            Self::Behaviour { reason } => write!(f, "could not build the behaviour: {}", reason),
+            Self::NetworkThread { reason } => {
+                write!(f, "could not set up the network thread: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NetworkSetupError {}
+
+/// The config [`NetworkPlugin`](super::NetworkPlugin) starts the network with. Taken
+/// out at startup; ignored if a [`NetworkManager`] was inserted already.
+#[derive(Resource, Debug, Clone)]
+pub struct NetworkSetup(pub NetworkConfig);
+
+/// The network [`start_network`] is bringing up, until [`poll_network_setup`] sees it
+/// done.
+#[derive(Resource)]
+pub(super) struct PendingNetwork(Task<Result<NetworkManager<(), ()>, NetworkSetupError>>);
+
+pub(super) fn start_network(
+    mut commands: Commands,
+    setup: Option<Res<NetworkSetup>>,
+    manager: Option<Res<NetworkManager<(), ()>>>,
+) {
+    let Some(setup) = setup else {
+        return;
+    };
+    commands.remove_resource::<NetworkSetup>();
+    if manager.is_some() {
+        log::warn!("Not starting the network from NetworkSetup: it's running already");
+        return;
+    }
+    let config = setup.0.clone();
+    // The setup future isn't `Send`, so a pool thread drives it to completion instead
+    // of the pool's executor. Either way the frame doesn't wait for it.
+    let setup =
+        IoTaskPool::get().spawn(async move { task::block_on(setup_network::<(), ()>(config)) });
+    commands.insert_resource(PendingNetwork(setup));
+}
+
+pub(super) fn poll_network_setup(
+    mut commands: Commands,
+    mut pending: ResMut<PendingNetwork>,
+    policy: Res<NetworkFailurePolicy>,
+    mut network_events: EventWriter<NetworkEvent<()>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(result) = (&mut pending.0).now_or_never() else {
+        return;
+    };
+    commands.remove_resource::<PendingNetwork>();
+    match result {
+        Ok(manager) => commands.insert_resource(manager),
+        Err(e) => {
+            log::error!("Could not start the network: {}", e);
+            network_events.send(NetworkEvent::Admin(NetworkAdminEvent::NetworkSetupFailed(
+                e,
+            )));
+            if *policy == NetworkFailurePolicy::ExitApp {
+                exit.send(AppExit);
+            }
+        }
+    }
+}
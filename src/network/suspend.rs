@@ -0,0 +1,81 @@
+//! Notices when the machine slept, e.g. a host closing its laptop lid for a minute.
+//! Meanwhile our DHT records and relay reservation may have expired and our
+//! connections died without either side noticing, so the network thread refreshes
+//! them all on waking.
+//!
+//! There is no portable hook for suspend, so we look at the clocks instead. On most
+//! platforms [`Instant`] stops while asleep and the wall clock doesn't; elsewhere
+//! both keep going, and our periodic check simply runs late.
+
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often we compare the clocks.
+const CHECK_EVERY: Duration = Duration::from_secs(5);
+
+/// Gaps shorter than this are a busy thread or a clock adjustment, not sleep.
+const MIN_GAP: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub(super) struct SuspendDetector {
+    checked_at: Instant,
+    checked_wall: SystemTime,
+}
+
+impl SuspendDetector {
+    pub(super) fn new(now: Instant, wall: SystemTime) -> Self {
+        Self {
+            checked_at: now,
+            checked_wall: wall,
+        }
+    }
+
+    pub(super) fn next_check(&self) -> Instant {
+        self.checked_at + CHECK_EVERY
+    }
+
+    /// How long we were asleep since the last check, if we were.
+    pub(super) fn check(&mut self, now: Instant, wall: SystemTime) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.checked_at);
+        let wall_elapsed = wall
+            .duration_since(self.checked_wall)
+            .unwrap_or(Duration::ZERO);
+        self.checked_at = now;
+        self.checked_wall = wall;
+        let slept = wall_elapsed
+            .saturating_sub(elapsed)
+            .max(elapsed.saturating_sub(CHECK_EVERY));
+        (slept >= MIN_GAP).then_some(slept)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notices_either_clock_jumping() {
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        let mut detector = SuspendDetector::new(now, wall);
+
+        let (now, wall) = (
+            now + CHECK_EVERY,
+            wall + CHECK_EVERY + Duration::from_secs(1),
+        );
+        assert_eq!(detector.check(now, wall), None);
+
+        // The monotonic clock stood still while asleep.
+        let (now, wall) = (now + CHECK_EVERY, wall + Duration::from_secs(65));
+        assert_eq!(detector.check(now, wall), Some(Duration::from_secs(60)));
+
+        // Both clocks kept going, so the check came late.
+        let late = Duration::from_secs(30);
+        let (now, wall) = (now + CHECK_EVERY + late, wall + CHECK_EVERY + late);
+        assert_eq!(detector.check(now, wall), Some(late));
+
+        // The wall clock going back isn't sleep.
+        assert_eq!(
+            detector.check(now + CHECK_EVERY, wall - Duration::from_secs(3600)),
+            None
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! Who simulates which entity. The host keeps the table and settles every request,
+//! so two peers grabbing the same object at once can't both end up with it. Anything
+//! not in the table is the host's, and the host can take any entity back whenever it
+//! wants.
+
+use std::collections::HashMap;
+
+use libp2p::PeerId;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+use crate::network::{
+    protocol::{ControlMessage, WireMessage},
+    NetworkAdminEvent, NetworkEvent,
+};
+
+/// Entities someone other than the host has, by network id. On the host this is the
+/// truth; everyone else keeps a copy from the host's announcements.
+#[derive(Debug, Default)]
+pub(super) struct AuthorityTable {
+    owners: HashMap<u64, PeerId>,
+}
+
+impl AuthorityTable {
+    pub(super) fn owner(&self, id: u64, host: PeerId) -> PeerId {
+        self.owners.get(&id).copied().unwrap_or(host)
+    }
+
+    /// Settles `requester` asking for `id`. The host always gets it, anyone else only
+    /// when it's the host's or already theirs. Returns who has `id` afterwards, as
+    /// `Err` when the request lost.
+    pub(super) fn request(
+        &mut self,
+        id: u64,
+        requester: PeerId,
+        host: PeerId,
+    ) -> Result<PeerId, PeerId> {
+        let owner = self.owner(id, host);
+        if requester == host || owner == host || owner == requester {
+            self.set(id, requester, host);
+            Ok(requester)
+        } else {
+            Err(owner)
+        }
+    }
+
+    /// `peer` hands `id` back. False if it wasn't theirs to give.
+    pub(super) fn release(&mut self, id: u64, peer: PeerId) -> bool {
+        if self.owners.get(&id) != Some(&peer) {
+            return false;
+        }
+        self.owners.remove(&id);
+        true
+    }
+
+    pub(super) fn set(&mut self, id: u64, owner: PeerId, host: PeerId) {
+        if owner == host {
+            self.owners.remove(&id);
+        } else {
+            self.owners.insert(id, owner);
+        }
+    }
+
+    /// Gives everything `peer` had back to the host, returning the ids.
+    pub(super) fn forget(&mut self, peer: PeerId) -> Vec<u64> {
+        let ids: Vec<_> = self
+            .owners
+            .iter()
+            .filter(|(_, owner)| **owner == peer)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &ids {
+            self.owners.remove(id);
+        }
+        ids
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = (u64, PeerId)> + '_ {
+        self.owners.iter().map(|(id, owner)| (*id, *owner))
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.owners.clear();
+    }
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    pub(super) async fn request_authority(&mut self, id: u64) {
+        let local = *self.swarm.local_peer_id();
+        if self.hosting {
+            self.settle_authority(id, local).await;
+        } else if self.room_host.is_some() {
+            self.publish(&WireMessage::Control(ControlMessage::RequestAuthority(id)));
+        } else {
+            log::warn!("Can't take entity {}: not in a room", id);
+        }
+    }
+
+    pub(super) async fn release_authority(&mut self, id: u64) {
+        if self.hosting {
+            // Everything we don't hand out is ours already.
+            return;
+        }
+        self.publish(&WireMessage::Control(ControlMessage::ReleaseAuthority(id)));
+    }
+
+    /// Host only: gives `id` to `owner` and tells the room.
+    pub(super) async fn announce_authority(&mut self, id: u64, owner: PeerId) {
+        let local = *self.swarm.local_peer_id();
+        self.authority.set(id, owner, local);
+        self.publish(&WireMessage::Control(ControlMessage::Authority {
+            id,
+            owner,
+        }));
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::AuthorityChanged {
+            id,
+            owner,
+        }))
+        .await;
+    }
+
+    /// Host only: answers `requester` asking for `id`.
+    pub(super) async fn settle_authority(&mut self, id: u64, requester: PeerId) {
+        let local = *self.swarm.local_peer_id();
+        if requester != local && !self.admitted.contains(&requester) {
+            log::warn!(
+                "Ignoring a claim on entity {} from outsider {}",
+                id,
+                requester
+            );
+            return;
+        }
+        match self.authority.request(id, requester, local) {
+            Ok(owner) => self.announce_authority(id, owner).await,
+            Err(owner) => self.publish(&WireMessage::Control(ControlMessage::AuthorityDenied {
+                id,
+                requester,
+                owner,
+            })),
+        }
+    }
+
+    /// Host only: `peer` handed `id` back.
+    pub(super) async fn returned_authority(&mut self, id: u64, peer: PeerId) {
+        if self.authority.release(id, peer) {
+            let local = *self.swarm.local_peer_id();
+            self.announce_authority(id, local).await;
+        }
+    }
+
+    /// Host only: `peer` left, so whatever it simulated goes back to us.
+    pub(super) async fn reclaim_authority(&mut self, peer: PeerId) {
+        let local = *self.swarm.local_peer_id();
+        for id in self.authority.forget(peer) {
+            self.announce_authority(id, local).await;
+        }
+    }
+
+    /// Host only: repeats who has what, for a late joiner that missed it.
+    pub(super) fn republish_authority(&mut self) {
+        let owners: Vec<_> = self.authority.iter().collect();
+        for (id, owner) in owners {
+            self.publish(&WireMessage::Control(ControlMessage::Authority {
+                id,
+                owner,
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_host_wins_and_others_wait_their_turn() {
+        let host = PeerId::random();
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let mut table = AuthorityTable::default();
+
+        assert_eq!(table.request(7, alice, host), Ok(alice));
+        assert_eq!(table.request(7, bob, host), Err(alice));
+        assert_eq!(table.request(7, host, host), Ok(host));
+        assert_eq!(table.owner(7, host), host);
+
+        assert_eq!(table.request(7, bob, host), Ok(bob));
+        assert!(!table.release(7, alice));
+        assert_eq!(table.forget(bob), [7]);
+        assert_eq!(table.owner(7, host), host);
+    }
+}
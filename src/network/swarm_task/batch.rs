@@ -0,0 +1,113 @@
+//! Handles what is already waiting after [`SwarmTask::run`] wakes up, instead of one
+//! event per wake-up. Sources take turns so a busy swarm can't starve the game's
+//! messages, and each gets a [`Budget`] so the batch ends and timers, flushes and the
+//! checks after it still run under load.
+
+use std::sync::atomic::Ordering;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+
+/// Most swarm events handled in one batch.
+const SWARM_BUDGET: usize = 64;
+/// Most messages from the game handled in one batch.
+const GAME_BUDGET: usize = 64;
+/// Most calls on the custom behaviour handled in one batch.
+const CUSTOM_BUDGET: usize = 16;
+
+/// Where a batch takes its next item from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Source {
+    Swarm,
+    Game,
+    Custom,
+}
+
+const SOURCES: [Source; 3] = [Source::Swarm, Source::Game, Source::Custom];
+
+/// What one batch may still take from each source.
+#[derive(Debug)]
+pub(super) struct Budget {
+    left: [usize; 3],
+    turn: usize,
+    saturated: bool,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self {
+            left: [SWARM_BUDGET, GAME_BUDGET, CUSTOM_BUDGET],
+            turn: 0,
+            saturated: false,
+        }
+    }
+}
+
+impl Budget {
+    /// The sources with budget left, starting after the one that went first last time.
+    pub(super) fn turns(&mut self) -> Vec<Source> {
+        let start = self.turn;
+        self.turn = (self.turn + 1) % SOURCES.len();
+        (0..SOURCES.len())
+            .map(|i| (start + i) % SOURCES.len())
+            .filter(|&i| self.left[i] > 0)
+            .map(|i| SOURCES[i])
+            .collect()
+    }
+
+    /// Takes one item from `source`.
+    pub(super) fn spend(&mut self, source: Source) {
+        let i = SOURCES
+            .iter()
+            .position(|&s| s == source)
+            .expect("Every source has a budget");
+        self.left[i] = self.left[i].saturating_sub(1);
+        if self.left[i] == 0 {
+            self.saturated = true;
+        }
+    }
+
+    /// Whether a source used up its budget, i.e. we may not be keeping up with it.
+    pub(super) fn saturated(&self) -> bool {
+        self.saturated
+    }
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    /// Counts a finished batch for the saturation diagnostic.
+    pub(super) fn count_batch(&self, budget: &Budget) {
+        self.counters.batches.fetch_add(1, Ordering::Relaxed);
+        if budget.saturated() {
+            self.counters
+                .saturated_batches
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources_take_turns_until_their_budget_runs_out() {
+        let mut budget = Budget::default();
+        assert_eq!(budget.turns(), SOURCES);
+        assert_eq!(
+            budget.turns(),
+            [Source::Game, Source::Custom, Source::Swarm]
+        );
+        for _ in 0..CUSTOM_BUDGET {
+            assert!(!budget.saturated());
+            budget.spend(Source::Custom);
+        }
+        assert!(budget.saturated());
+        assert_eq!(budget.turns(), [Source::Swarm, Source::Game]);
+    }
+}
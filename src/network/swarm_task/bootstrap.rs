@@ -0,0 +1,70 @@
+use libp2p::kad::{BootstrapError, BootstrapOk};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{dialer::DialRequest, CustomBehaviour, SwarmTask};
+use crate::network::{event_log::Severity, NetworkAdminEvent, NetworkEvent};
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    /// Dials every bootstrap peer, so unreachable ones show up in the log, and starts
+    /// filling the routing table.
+    pub(super) async fn bootstrap(&mut self) {
+        if self.bootstrap_peers.is_empty() {
+            self.log(
+                Severity::Info,
+                "kad",
+                None,
+                "No bootstrap peers configured, skipping DHT bootstrap",
+            );
+            return;
+        }
+        for peer_id in self.bootstrap_peers.clone() {
+            self.queue_dial(DialRequest::peer(peer_id));
+        }
+        if let Err(e) = self.swarm.behaviour_mut().kad.bootstrap() {
+            self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::BootstrapFailed {
+                reason: e.to_string(),
+            }))
+            .await;
+        }
+    }
+
+    pub(super) async fn bootstrap_finished(&mut self, result: Result<BootstrapOk, BootstrapError>) {
+        let peers: usize = self
+            .swarm
+            .behaviour_mut()
+            .kad
+            .kbuckets()
+            .map(|bucket| bucket.num_entries())
+            .sum();
+        let event = if peers > 0 {
+            self.log(
+                Severity::Info,
+                "kad",
+                None,
+                format!("Bootstrapped with {} peers in the routing table", peers),
+            );
+            // Anything left for us while we were away is reachable now.
+            self.check_mailbox();
+            self.discover_relays();
+            NetworkAdminEvent::Bootstrapped { peers }
+        } else {
+            let reason = match result {
+                Ok(_) => "No bootstrap peer answered".to_string(),
+                Err(e) => e.to_string(),
+            };
+            self.log(
+                Severity::Error,
+                "kad",
+                None,
+                format!("Bootstrap failed: {}", reason),
+            );
+            NetworkAdminEvent::BootstrapFailed { reason }
+        };
+        self.send_to_game(NetworkEvent::Admin(event)).await;
+    }
+}
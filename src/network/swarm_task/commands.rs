@@ -0,0 +1,253 @@
+//! Answers [`GameEvent::Command`](crate::network::GameEvent::Command)s once they took
+//! effect or failed.
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+use crate::network::{CommandId, GameAdminEvent, NetworkAdminEvent, NetworkEvent};
+
+/// Commands that finish later, with one of the events [`outcome`] looks for. The
+/// rest are done as soon as they are handled.
+fn awaits_reply(command: &GameAdminEvent) -> bool {
+    matches!(
+        command,
+        GameAdminEvent::Host { .. }
+            | GameAdminEvent::Join { .. }
+            | GameAdminEvent::FindPeer(_)
+            | GameAdminEvent::Invite { .. }
+            | GameAdminEvent::PostInvite { .. }
+            | GameAdminEvent::GetProviders(_)
+            | GameAdminEvent::RequestSnapshot
+            | GameAdminEvent::Quit
+            | GameAdminEvent::RestartNetwork { .. }
+            | GameAdminEvent::StartGame { .. }
+            | GameAdminEvent::SaveTape(_)
+            | GameAdminEvent::PlayTape(_)
+            | GameAdminEvent::SendFile { .. }
+    )
+}
+
+/// Whether `event` settles `command`, and if so how.
+fn outcome(command: &GameAdminEvent, event: &NetworkAdminEvent) -> Option<Result<(), String>> {
+    use GameAdminEvent as Command;
+    use NetworkAdminEvent as Event;
+    match (command, event) {
+        (Command::Host { .. }, Event::HostingStarted { .. }) => Some(Ok(())),
+        (Command::Host { .. }, Event::HostingFailed { reason }) => Some(Err(reason.clone())),
+        (Command::Host { .. }, Event::RoomCodeTaken { room_code }) => {
+            Some(Err(format!("Room code {} is already hosted", room_code)))
+        }
+        (Command::Join { .. }, Event::JoinAccepted { .. }) => Some(Ok(())),
+        (Command::Join { .. }, Event::JoinFailed(error)) => Some(Err(error.to_string())),
+        (Command::FindPeer(peer_id), Event::PeerFound(found)) if peer_id == found => Some(Ok(())),
+        (Command::FindPeer(peer_id), Event::PeerNotFound(missing)) if peer_id == missing => {
+            Some(Err("Nobody on the DHT knows the peer".to_string()))
+        }
+        (Command::Invite { peer_id, .. }, Event::InviteDelivered(to)) if peer_id == to => {
+            Some(Ok(()))
+        }
+        (Command::PostInvite { peer_id, .. }, Event::InvitePosted(to)) if peer_id == to => {
+            Some(Ok(()))
+        }
+        (
+            Command::Invite { peer_id, .. } | Command::PostInvite { peer_id, .. },
+            Event::InviteFailed {
+                peer_id: to,
+                reason,
+            },
+        ) if peer_id == to => Some(Err(reason.clone())),
+        (Command::GetProviders(key), Event::ProvidersFound { key: found, .. }) if key == found => {
+            Some(Ok(()))
+        }
+        (Command::RequestSnapshot, Event::SnapshotReceived(_)) => Some(Ok(())),
+        (Command::RequestSnapshot, Event::SnapshotFailed { reason }) => Some(Err(reason.clone())),
+        (Command::Quit, Event::ShutdownComplete) => Some(Ok(())),
+        (Command::StartGame { .. }, Event::GameStarted { .. }) => Some(Ok(())),
+        (Command::RestartNetwork { .. }, Event::NetworkRestarted { .. }) => Some(Ok(())),
+        (Command::RestartNetwork { .. }, Event::NetworkRestartFailed { reason }) => {
+            Some(Err(reason.clone()))
+        }
+        (Command::SaveTape(path), Event::TapeSaved { path: saved, .. }) if path == saved => {
+            Some(Ok(()))
+        }
+        (Command::PlayTape(path), Event::TapePlayed { path: played, .. }) if path == played => {
+            Some(Ok(()))
+        }
+        (
+            Command::SaveTape(path) | Command::PlayTape(path),
+            Event::TapeFailed {
+                path: failed,
+                reason,
+            },
+        ) if path == failed => Some(Err(reason.clone())),
+        (Command::SendFile { peer_id, .. }, Event::FileSent { peer_id: to, .. })
+            if peer_id == to =>
+        {
+            Some(Ok(()))
+        }
+        (Command::SendFile { peer_id, .. }, Event::FileDeclined { peer_id: to, .. })
+            if peer_id == to =>
+        {
+            Some(Err("Declined".to_string()))
+        }
+        (
+            Command::SendFile { peer_id, .. },
+            Event::FileFailed {
+                peer_id: to,
+                reason,
+                ..
+            },
+        ) if peer_id == to => Some(Err(reason.clone())),
+        _ => None,
+    }
+}
+
+pub(super) fn answer(id: CommandId, result: Result<(), String>) -> NetworkAdminEvent {
+    match result {
+        Ok(()) => NetworkAdminEvent::CommandCompleted(id),
+        Err(reason) => NetworkAdminEvent::CommandFailed { id, reason },
+    }
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    pub(super) async fn handle_command(&mut self, id: CommandId, command: GameAdminEvent) {
+        if let Some(reason) = self.refusal(&command) {
+            self.send_to_game(NetworkEvent::Admin(answer(id, Err(reason))))
+                .await;
+            return;
+        }
+        let abandoned = match command {
+            GameAdminEvent::Leave => Some("Left the room"),
+            GameAdminEvent::Join { .. } => Some("Replaced by a newer join"),
+            _ => None,
+        };
+        if let Some(reason) = abandoned {
+            self.abandon_commands(reason).await;
+        }
+        if awaits_reply(&command) {
+            self.pending_commands.push((id, command.clone()));
+            self.handle_admin_event(command).await;
+        } else {
+            self.handle_admin_event(command).await;
+            self.send_to_game(NetworkEvent::Admin(answer(id, Ok(()))))
+                .await;
+        }
+    }
+
+    /// Why `command` can't be carried out right now, for commands the network thread
+    /// would otherwise just log and ignore.
+    fn refusal(&self, command: &GameAdminEvent) -> Option<String> {
+        match command {
+            GameAdminEvent::Host { .. } if self.hosting || self.claiming.is_some() => {
+                Some("Already hosting".to_string())
+            }
+            GameAdminEvent::RotateRoomKey | GameAdminEvent::RotateKeysNow if !self.hosting => {
+                Some("Only the host can rotate the room key".to_string())
+            }
+            GameAdminEvent::RoomMute { .. } if !self.hosting => {
+                Some("Only the host can mute players for the room".to_string())
+            }
+            GameAdminEvent::TransferAuthority { .. } if !self.hosting => {
+                Some("Only the host can hand out entities".to_string())
+            }
+            GameAdminEvent::SetTeam { .. } | GameAdminEvent::MoveToTeam { .. } if !self.hosting => {
+                Some("Only the host can set up teams".to_string())
+            }
+            GameAdminEvent::ChangeTickSettings(_) if !self.hosting => {
+                Some("Only the host can change the tick settings".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Fails the host or join in progress, which `reason` put an end to.
+    async fn abandon_commands(&mut self, reason: &str) {
+        let (abandoned, pending): (Vec<_>, Vec<_>) =
+            self.pending_commands.drain(..).partition(|(_, command)| {
+                matches!(
+                    command,
+                    GameAdminEvent::Host { .. } | GameAdminEvent::Join { .. }
+                )
+            });
+        self.pending_commands = pending;
+        for (id, _) in abandoned {
+            self.send_to_game(NetworkEvent::Admin(answer(id, Err(reason.to_string()))))
+                .await;
+        }
+    }
+
+    /// Takes the pending commands `event` settles and returns their answers.
+    pub(super) fn settle_commands(&mut self, event: &NetworkAdminEvent) -> Vec<NetworkAdminEvent> {
+        let mut answers = Vec::new();
+        self.pending_commands
+            .retain(|(id, command)| match outcome(command, event) {
+                Some(result) => {
+                    answers.push(answer(*id, result));
+                    false
+                }
+                None => true,
+            });
+        answers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::PeerId;
+
+    use super::*;
+    use crate::network::JoinError;
+
+    #[test]
+    fn find_peer_is_settled_by_its_own_peer_only() {
+        let peer_id = PeerId::random();
+        let command = GameAdminEvent::FindPeer(peer_id);
+        assert_eq!(
+            outcome(&command, &NetworkAdminEvent::PeerFound(PeerId::random())),
+            None
+        );
+        assert_eq!(
+            outcome(&command, &NetworkAdminEvent::PeerFound(peer_id)),
+            Some(Ok(()))
+        );
+        assert!(matches!(
+            outcome(&command, &NetworkAdminEvent::PeerNotFound(peer_id)),
+            Some(Err(_))
+        ));
+    }
+
+    #[test]
+    fn host_fails_when_its_code_is_taken() {
+        let command = GameAdminEvent::Host {
+            room_code: "ABC-1234".to_string(),
+        };
+        assert!(matches!(
+            outcome(
+                &command,
+                &NetworkAdminEvent::RoomCodeTaken {
+                    room_code: "ABC-1234".to_string()
+                }
+            ),
+            Some(Err(_))
+        ));
+        assert_eq!(
+            outcome(
+                &command,
+                &NetworkAdminEvent::JoinFailed(JoinError::RoomNotFound)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn only_slow_commands_wait_for_a_reply() {
+        assert!(awaits_reply(&GameAdminEvent::Quit));
+        assert!(!awaits_reply(&GameAdminEvent::Leave));
+        assert!(!awaits_reply(&GameAdminEvent::SetReady(true)));
+    }
+}
@@ -0,0 +1,219 @@
+//! Commands meant for poking at the network by hand, e.g. from the debug console.
+
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use libp2p::{
+    kad::{GetProvidersError, GetProvidersOk, QueryId, RecordKey},
+    Multiaddr, PeerId,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use zeroize::Zeroizing;
+
+use super::{dialer::DialRequest, CustomBehaviour, SwarmTask};
+use crate::{
+    crypto::{PeerCiphertext, RoomKey, COMPROMISED_KEY_GRACE, RETIRED_KEY_GRACE},
+    network::{
+        event_log::Severity,
+        protocol::{ControlMessage, WireMessage},
+        NetworkAdminEvent, NetworkEvent,
+    },
+};
+
+/// A [`GameAdminEvent::GetProviders`](crate::network::GameAdminEvent::GetProviders)
+/// lookup and what it found so far.
+pub(super) struct ProviderLookup {
+    key: String,
+    providers: HashSet<PeerId>,
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    pub(super) fn dial(&mut self, address: Multiaddr) {
+        self.queue_dial(DialRequest::address(address));
+    }
+
+    pub(super) fn find_providers(&mut self, key: String) {
+        let query = self
+            .swarm
+            .behaviour_mut()
+            .kad
+            .get_providers(RecordKey::new(&key));
+        self.finding_providers.insert(
+            query,
+            ProviderLookup {
+                key,
+                providers: HashSet::new(),
+            },
+        );
+    }
+
+    pub(super) async fn handle_providers(
+        &mut self,
+        query: QueryId,
+        result: Result<GetProvidersOk, GetProvidersError>,
+        last: bool,
+    ) {
+        let Some(lookup) = self.finding_providers.get_mut(&query) else {
+            return;
+        };
+        match result {
+            Ok(GetProvidersOk::FoundProviders { providers, .. }) => {
+                lookup.providers.extend(providers)
+            }
+            Ok(GetProvidersOk::FinishedWithNoAdditionalRecord { .. }) => {}
+            // A timeout still answers with whatever was found until then.
+            Err(e) => log::debug!("Provider lookup for {} failed: {}", lookup.key, e),
+        }
+        if !last {
+            return;
+        }
+        let Some(ProviderLookup { key, providers }) = self.finding_providers.remove(&query) else {
+            return;
+        };
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::ProvidersFound {
+            key,
+            providers: providers.into_iter().collect(),
+        }))
+        .await;
+    }
+
+    /// Host only: shares a fresh room key with everyone in the room, then switches to
+    /// it. The key goes out under the old one, so peers can still read it.
+    pub(super) async fn rotate_room_key(&mut self) {
+        self.share_room_key(None, RETIRED_KEY_GRACE).await;
+    }
+
+    /// Host only: replaces the room and team keys because `suspect`, or someone we
+    /// don't know, may hold them. The old keys only decrypt for a few more seconds.
+    pub(super) async fn rotate_keys_now(&mut self, suspect: Option<PeerId>) {
+        self.share_room_key(suspect, COMPROMISED_KEY_GRACE).await;
+        self.rekey_teams(suspect).await;
+        let reason = suspect.map_or("on request".to_string(), |peer_id| {
+            format!("after disconnecting {}", peer_id)
+        });
+        self.log(
+            Severity::Warn,
+            "gossip",
+            suspect,
+            format!(
+                "Replaced the room keys {}, the old ones stop working in {:?}",
+                reason, COMPROMISED_KEY_GRACE
+            ),
+        );
+    }
+
+    /// Shares a fresh room key with everyone in the room but `except`, then switches
+    /// to it. The old keys still decrypt for `grace`.
+    async fn share_room_key(&mut self, except: Option<PeerId>, grace: Duration) {
+        let room_key = RoomKey::random();
+        let bytes = room_key.to_bytes();
+        let mut sealed = Vec::new();
+        let peers = self.room_peers();
+        for peer_id in peers.into_iter().filter(|peer_id| Some(*peer_id) != except) {
+            match self.peer_secrets.encrypt(&peer_id, bytes.as_slice()) {
+                Ok(ciphertext) => sealed.push((peer_id, ciphertext)),
+                Err(e) => self.log(
+                    Severity::Warn,
+                    "gossip",
+                    Some(peer_id),
+                    format!("Could not share the new room key: {}", e),
+                ),
+            }
+        }
+        self.publish(&WireMessage::Control(ControlMessage::RoomKey {
+            sealed,
+            grace_ms: grace.as_millis() as u64,
+        }));
+        self.keys.add_key_with_grace(room_key, grace);
+        self.key_acks.clear();
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::RoomKeyRotated))
+            .await;
+    }
+
+    /// Switches to the room key the host rotated to, if it included one for us.
+    pub(super) async fn handle_room_key(
+        &mut self,
+        source: PeerId,
+        sealed: Vec<(PeerId, PeerCiphertext)>,
+        grace: Duration,
+    ) {
+        if self.room_host != Some(source) {
+            log::warn!("Ignoring a room key from {}: not our host", source);
+            return;
+        }
+        let local_peer_id = *self.swarm.local_peer_id();
+        let Some((_, ciphertext)) = sealed
+            .into_iter()
+            .find(|(peer_id, _)| *peer_id == local_peer_id)
+        else {
+            self.log(
+                Severity::Warn,
+                "gossip",
+                Some(source),
+                "The host rotated the room key without sharing it with us",
+            );
+            return;
+        };
+        let room_key = self
+            .peer_secrets
+            .decrypt(&source, &ciphertext)
+            .ok()
+            .map(Zeroizing::new)
+            .and_then(|bytes| RoomKey::from_bytes(&bytes));
+        match room_key {
+            Some(room_key) => {
+                // Never longer than usual, whatever the host says.
+                self.keys
+                    .add_key_with_grace(room_key, grace.min(RETIRED_KEY_GRACE));
+                self.ack_room_key();
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::RoomKeyRotated))
+                    .await;
+            }
+            None => log::warn!("Host sent a malformed room key"),
+        }
+    }
+
+    /// Hands a game message from `source` to the game, after the simulated latency if
+    /// there is one.
+    pub(super) async fn receive_game(&mut self, source: PeerId, event: ToGame) {
+        let event = NetworkEvent::Game { source, event };
+        if self.simulated_latency.is_zero() {
+            self.send_to_game(event).await;
+        } else {
+            self.delayed
+                .push_back((Instant::now() + self.simulated_latency, event));
+        }
+    }
+
+    /// When the next held back message is due.
+    pub(super) fn next_delayed(&self) -> Option<Instant> {
+        self.delayed.front().map(|(due, _)| *due)
+    }
+
+    /// Hands over the held back messages that are due.
+    pub(super) async fn deliver_delayed(&mut self) {
+        let now = Instant::now();
+        while self.next_delayed().map_or(false, |due| due <= now) {
+            if let Some((_, event)) = self.delayed.pop_front() {
+                self.send_to_game(event).await;
+            }
+        }
+    }
+
+    pub(super) async fn set_simulated_latency(&mut self, latency: Duration) {
+        self.simulated_latency = latency;
+        if latency.is_zero() {
+            // Nothing is held back once it's off.
+            while let Some((_, event)) = self.delayed.pop_front() {
+                self.send_to_game(event).await;
+            }
+        }
+    }
+}
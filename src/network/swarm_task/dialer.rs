@@ -0,0 +1,373 @@
+//! Our outgoing dials. Joins, invites, bootstrap and migration all ask for dials, often
+//! to the same peer at once, so they queue them here: a dial to a peer or address
+//! already being dialed is dropped, at most [`MAX_CONCURRENT_DIALS`] run at a time, and
+//! failed ones are retried with exponential backoff. Addresses that failed are skipped
+//! until their own backoff is over. A dial that still fails after
+//! [`MAX_DIAL_ATTEMPTS`] is reported as [`NetworkAdminEvent::DialFailed`].
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use libp2p::{
+    swarm::{
+        dial_opts::{DialOpts, PeerCondition},
+        ConnectionId, DialError,
+    },
+    Multiaddr, PeerId,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+use crate::network::{event_log::Severity, NetworkAdminEvent, NetworkEvent};
+
+/// Dials in flight at once. The rest wait in the queue.
+const MAX_CONCURRENT_DIALS: usize = 8;
+/// Tries per dial before we give up on it.
+const MAX_DIAL_ATTEMPTS: u32 = 4;
+/// Wait after the first failure; doubles with every further one.
+const DIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_DIAL_BACKOFF: Duration = Duration::from_secs(60);
+
+fn backoff(failures: u32) -> Duration {
+    DIAL_BACKOFF
+        .saturating_mul(1 << failures.saturating_sub(1).min(16))
+        .min(MAX_DIAL_BACKOFF)
+}
+
+/// A dial to a peer, optionally at known addresses, or to a bare address.
+#[derive(Debug, Clone)]
+pub(super) struct DialRequest {
+    peer_id: Option<PeerId>,
+    addresses: Vec<Multiaddr>,
+    condition: PeerCondition,
+}
+
+impl DialRequest {
+    /// Dials `peer_id` at whatever addresses the behaviours know, unless connected.
+    pub(super) fn peer(peer_id: PeerId) -> Self {
+        Self {
+            peer_id: Some(peer_id),
+            addresses: Vec::new(),
+            condition: PeerCondition::Disconnected,
+        }
+    }
+
+    pub(super) fn address(address: Multiaddr) -> Self {
+        Self {
+            peer_id: None,
+            addresses: vec![address],
+            condition: PeerCondition::Always,
+        }
+    }
+
+    /// Tries `addresses` on top of what the behaviours know.
+    pub(super) fn with_addresses(mut self, addresses: Vec<Multiaddr>) -> Self {
+        self.addresses = addresses;
+        self
+    }
+
+    /// Opens another connection even if we have one, e.g. over a new network.
+    pub(super) fn even_if_connected(mut self) -> Self {
+        self.condition = PeerCondition::Always;
+        self
+    }
+
+    /// Two requests are the same dial if they go to the same peer, or to the same
+    /// address when the peer is unknown.
+    fn same_dial(&self, other: &DialRequest) -> bool {
+        match (self.peer_id, other.peer_id) {
+            (Some(a), Some(b)) => a == b,
+            (None, None) => self.addresses == other.addresses,
+            _ => false,
+        }
+    }
+
+    fn to_opts(&self) -> DialOpts {
+        match self.peer_id {
+            Some(peer_id) => DialOpts::peer_id(peer_id)
+                .addresses(self.addresses.clone())
+                .extend_addresses_through_behaviour()
+                .condition(self.condition)
+                .build(),
+            None => DialOpts::unknown_peer_id()
+                .address(self.addresses[0].clone())
+                .build(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self.peer_id {
+            Some(peer_id) => peer_id.to_string(),
+            None => self.addresses[0].to_string(),
+        }
+    }
+}
+
+/// A queued or in-flight [`DialRequest`], with how often it failed so far.
+#[derive(Debug)]
+struct Dial {
+    request: DialRequest,
+    attempts: u32,
+    not_before: Instant,
+}
+
+/// A dial we gave up on.
+#[derive(Debug)]
+struct GaveUp {
+    request: DialRequest,
+    reason: String,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Dialer {
+    queued: VecDeque<Dial>,
+    in_flight: HashMap<ConnectionId, Dial>,
+    /// Per address: failures in a row, and when it may be tried again.
+    backoff: HashMap<Multiaddr, (u32, Instant)>,
+    gave_up: Vec<GaveUp>,
+}
+
+impl Dialer {
+    /// Queues `request`, or returns `false` if the same dial is already queued or in
+    /// flight.
+    fn queue(&mut self, request: DialRequest, now: Instant) -> bool {
+        let duplicate = self
+            .queued
+            .iter()
+            .chain(self.in_flight.values())
+            .any(|dial| dial.request.same_dial(&request));
+        if !duplicate {
+            self.queued.push_back(Dial {
+                request,
+                attempts: 0,
+                not_before: now,
+            });
+        }
+        !duplicate
+    }
+
+    /// When `dial` may start, with its addresses' backoff taken into account.
+    fn ready_at(&self, dial: &Dial) -> Instant {
+        let address_ready = |address: &Multiaddr| self.backoff.get(address).map(|(_, at)| *at);
+        // A peer can still be tried at its other addresses, a bare address can't.
+        let addresses = match dial.request.peer_id {
+            Some(_) => None,
+            None => dial
+                .request
+                .addresses
+                .iter()
+                .filter_map(address_ready)
+                .max(),
+        };
+        addresses.map_or(dial.not_before, |at| at.max(dial.not_before))
+    }
+
+    /// Takes the next dial that may start now, if there is room for one more. Its
+    /// addresses that are backing off are left out.
+    fn next_ready(&mut self, now: Instant) -> Option<Dial> {
+        if self.in_flight.len() >= MAX_CONCURRENT_DIALS {
+            return None;
+        }
+        let index = self
+            .queued
+            .iter()
+            .position(|dial| self.ready_at(dial) <= now)?;
+        let mut dial = self.queued.remove(index)?;
+        if dial.request.peer_id.is_some() {
+            let backoff = &self.backoff;
+            dial.request
+                .addresses
+                .retain(|address| backoff.get(address).map_or(true, |(_, at)| *at <= now));
+        }
+        Some(dial)
+    }
+
+    /// When the next queued dial may start, if there is room for it.
+    fn next_retry(&self) -> Option<Instant> {
+        if self.in_flight.len() >= MAX_CONCURRENT_DIALS {
+            return None;
+        }
+        self.queued.iter().map(|dial| self.ready_at(dial)).min()
+    }
+
+    fn started(&mut self, id: ConnectionId, dial: Dial) {
+        self.in_flight.insert(id, dial);
+    }
+
+    /// A connection to `address` came up, whoever dialed it.
+    fn connected(&mut self, id: ConnectionId, address: &Multiaddr) {
+        self.in_flight.remove(&id);
+        self.backoff.remove(address);
+    }
+
+    /// Dial `id` failed at `addresses`, which back off whoever dialed them. Our own
+    /// dials are retried later if `retry` and they have tries left, and given up on
+    /// otherwise.
+    fn failed(
+        &mut self,
+        id: ConnectionId,
+        addresses: &[Multiaddr],
+        retry: bool,
+        reason: String,
+        now: Instant,
+    ) {
+        // Forget addresses whose backoff ran out long ago so the map can't grow
+        // without bound.
+        self.backoff
+            .retain(|_, (_, at)| now.saturating_duration_since(*at) < MAX_DIAL_BACKOFF);
+        for address in addresses {
+            let (failures, at) = self.backoff.entry(address.clone()).or_insert((0, now));
+            *failures += 1;
+            *at = now + backoff(*failures);
+        }
+        let Some(mut dial) = self.in_flight.remove(&id) else {
+            return;
+        };
+        dial.attempts += 1;
+        if retry && dial.attempts < MAX_DIAL_ATTEMPTS {
+            dial.not_before = now + backoff(dial.attempts);
+            self.queued.push_back(dial);
+        } else {
+            self.gave_up.push(GaveUp {
+                request: dial.request,
+                reason,
+            });
+        }
+    }
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    /// Dials as soon as the [`Dialer`] lets us, unless that dial is already on its way.
+    pub(super) fn queue_dial(&mut self, request: DialRequest) {
+        let target = request.describe();
+        if !self.dialer.queue(request, Instant::now()) {
+            log::debug!("Already dialing {}", target);
+        }
+        self.start_dials();
+    }
+
+    /// Starts queued dials that are due, as far as the concurrency cap allows.
+    pub(super) fn start_dials(&mut self) {
+        let now = Instant::now();
+        while let Some(dial) = self.dialer.next_ready(now) {
+            let opts = dial.request.to_opts();
+            let id = opts.connection_id();
+            match self.swarm.dial(opts) {
+                Ok(()) => self.dialer.started(id, dial),
+                // Already connected, or dialing through a behaviour; either is fine.
+                Err(DialError::DialPeerConditionFalse(_)) => {}
+                Err(e) => self.dialer.gave_up.push(GaveUp {
+                    request: dial.request,
+                    reason: e.to_string(),
+                }),
+            }
+        }
+    }
+
+    /// The soonest a queued dial may start.
+    pub(super) fn next_dial(&self) -> Option<Instant> {
+        self.dialer.next_retry()
+    }
+
+    pub(super) fn dial_connected(&mut self, id: ConnectionId, address: &Multiaddr) {
+        self.dialer.connected(id, address);
+        self.start_dials();
+    }
+
+    pub(super) fn dial_failed(&mut self, id: ConnectionId, error: &DialError) {
+        let (addresses, retry) = match error {
+            DialError::Transport(failures) => (
+                failures
+                    .iter()
+                    .map(|(address, _)| address.clone())
+                    .collect(),
+                true,
+            ),
+            // Only unreachable addresses may work on another try.
+            _ => (Vec::new(), false),
+        };
+        self.dialer
+            .failed(id, &addresses, retry, error.to_string(), Instant::now());
+        self.start_dials();
+    }
+
+    /// Tells the game about the dials we gave up on.
+    pub(super) async fn report_failed_dials(&mut self) {
+        for GaveUp { request, reason } in std::mem::take(&mut self.dialer.gave_up) {
+            self.log(
+                Severity::Warn,
+                "swarm",
+                request.peer_id,
+                format!("Gave up dialing {}: {}", request.describe(), reason),
+            );
+            self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::DialFailed {
+                peer_id: request.peer_id,
+                addresses: request.addresses,
+                reason,
+            }))
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(port: u16) -> Multiaddr {
+        format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap()
+    }
+
+    #[test]
+    fn duplicate_dials_are_dropped_and_concurrency_capped() {
+        let now = Instant::now();
+        let mut dialer = Dialer::default();
+        let peer = PeerId::random();
+        assert!(dialer.queue(DialRequest::peer(peer), now));
+        assert!(!dialer.queue(
+            DialRequest::peer(peer).with_addresses(vec![address(1)]),
+            now
+        ));
+        assert!(dialer.queue(DialRequest::address(address(1)), now));
+        assert!(!dialer.queue(DialRequest::address(address(1)), now));
+
+        for port in 0..MAX_CONCURRENT_DIALS {
+            let dial = dialer.next_ready(now).unwrap();
+            dialer.started(ConnectionId::new_unchecked(port), dial);
+            dialer.queue(DialRequest::peer(PeerId::random()), now);
+        }
+        assert!(dialer.next_ready(now).is_none());
+        assert_eq!(dialer.next_retry(), None);
+        // Still in flight, so still a duplicate.
+        assert!(!dialer.queue(DialRequest::peer(peer), now));
+    }
+
+    #[test]
+    fn failed_dials_back_off_then_give_up() {
+        let mut now = Instant::now();
+        let mut dialer = Dialer::default();
+        dialer.queue(DialRequest::address(address(1)), now);
+        for attempt in 0..MAX_DIAL_ATTEMPTS {
+            let id = ConnectionId::new_unchecked(attempt as usize);
+            let dial = dialer.next_ready(now).unwrap();
+            dialer.started(id, dial);
+            dialer.failed(id, &[address(1)], true, "refused".to_string(), now);
+            if attempt + 1 < MAX_DIAL_ATTEMPTS {
+                assert!(dialer.next_ready(now).is_none());
+                let retry = dialer.next_retry().unwrap();
+                assert_eq!(retry - now, backoff(attempt + 1));
+                now = retry;
+            }
+        }
+        assert_eq!(dialer.gave_up.len(), 1);
+        assert!(dialer.queued.is_empty());
+        assert_eq!(backoff(10), MAX_DIAL_BACKOFF);
+    }
+}
@@ -0,0 +1,497 @@
+//! Sending files to players in our room, e.g. screenshots or tapes, over
+//! [`FILE_PROTOCOL`](crate::network::FILE_PROTOCOL). Nothing is written until the
+//! receiving player accepts, and nothing over
+//! [`FileDropConfig::max_size`](crate::network::FileDropConfig::max_size) is sent or
+//! taken.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use libp2p::{
+    request_response::{self, Message, RequestId, ResponseChannel},
+    PeerId,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+use crate::network::{
+    event_log::Severity,
+    protocol::{FileRequest, FileResponse, FILE_CHUNK_SIZE},
+    NetworkAdminEvent, NetworkEvent,
+};
+
+/// A file we offered, sent a chunk at a time once accepted.
+#[derive(Debug)]
+struct Outgoing {
+    peer_id: PeerId,
+    name: String,
+    data: Vec<u8>,
+    sent: usize,
+    started: bool,
+}
+
+impl Outgoing {
+    /// The next chunk and its offset, or `None` once all of it went out. Empty files
+    /// still get one chunk, so the receiver writes them.
+    fn next_chunk(&mut self) -> Option<(u64, Vec<u8>)> {
+        if self.started && self.sent == self.data.len() {
+            return None;
+        }
+        self.started = true;
+        let offset = self.sent;
+        self.sent = (offset + FILE_CHUNK_SIZE).min(self.data.len());
+        Some((offset as u64, self.data[offset..self.sent].to_vec()))
+    }
+}
+
+/// A file our player accepted, filling up.
+#[derive(Debug)]
+struct Incoming {
+    name: String,
+    size: u64,
+    data: Vec<u8>,
+}
+
+impl Incoming {
+    /// Adds the chunk at `offset`, saying whether the file is complete.
+    fn push(&mut self, offset: u64, chunk: &[u8]) -> Result<bool, String> {
+        if offset != self.data.len() as u64 {
+            return Err(format!(
+                "Chunk at {} while expecting {}",
+                offset,
+                self.data.len()
+            ));
+        }
+        if offset + chunk.len() as u64 > self.size {
+            return Err(format!("More than the {} bytes offered", self.size));
+        }
+        self.data.extend_from_slice(chunk);
+        Ok(self.data.len() as u64 == self.size)
+    }
+}
+
+/// An offer waiting for our player.
+struct Offer {
+    name: String,
+    size: u64,
+    channel: ResponseChannel<FileResponse>,
+}
+
+/// Every file transfer in flight, both ways.
+#[derive(Default)]
+pub(super) struct FileTransfers {
+    next_transfer: u64,
+    outgoing: HashMap<u64, Outgoing>,
+    /// Which outgoing transfer each request we sent belongs to.
+    requests: HashMap<RequestId, u64>,
+    offers: HashMap<(PeerId, u64), Offer>,
+    incoming: HashMap<(PeerId, u64), Incoming>,
+}
+
+impl FileTransfers {
+    /// Drops what `peer` was sending us. What we send it fails by itself.
+    pub(super) fn forget(&mut self, peer: &PeerId) {
+        self.offers.retain(|(from, _), _| from != peer);
+        self.incoming.retain(|(from, _), _| from != peer);
+    }
+}
+
+/// Where to save `name` in `directory`: only its last component, so a sender can't
+/// write anywhere else, and numbered rather than overwriting an existing file.
+fn received_path(directory: &Path, name: &str, exists: impl Fn(&Path) -> bool) -> PathBuf {
+    let name = Path::new(name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("file");
+    let path = directory.join(name);
+    if !exists(&path) {
+        return path;
+    }
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (name, String::new()),
+    };
+    (1..)
+        .map(|n| directory.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|path| !exists(path))
+        .expect("Some number is free")
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    pub(super) async fn send_file(&mut self, peer_id: PeerId, path: PathBuf) {
+        let name = path
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        let max_size = self.config.file_drop.max_size;
+        let data = match fs::read(&path) {
+            Ok(data) if data.len() as u64 > max_size => Err(format!(
+                "{} bytes is over the {} byte limit",
+                data.len(),
+                max_size
+            )),
+            Ok(data) => Ok(data),
+            Err(e) => Err(format!("Could not read {}: {}", path.display(), e)),
+        };
+        let data = match data {
+            Ok(data) => data,
+            Err(reason) => return self.file_failed(peer_id, name, reason).await,
+        };
+        let transfer = self.files.next_transfer;
+        self.files.next_transfer += 1;
+        let request = self.swarm.behaviour_mut().file.send_request(
+            &peer_id,
+            FileRequest::Offer {
+                transfer,
+                name: name.clone(),
+                size: data.len() as u64,
+            },
+        );
+        self.files.requests.insert(request, transfer);
+        self.files.outgoing.insert(
+            transfer,
+            Outgoing {
+                peer_id,
+                name,
+                data,
+                sent: 0,
+                started: false,
+            },
+        );
+    }
+
+    pub(super) fn answer_file_offer(&mut self, peer_id: PeerId, transfer: u64, accept: bool) {
+        let Some(offer) = self.files.offers.remove(&(peer_id, transfer)) else {
+            log::warn!("No file offer {} from {}", transfer, peer_id);
+            return;
+        };
+        let response = if accept {
+            FileResponse::Accepted
+        } else {
+            FileResponse::Declined
+        };
+        if self
+            .swarm
+            .behaviour_mut()
+            .file
+            .send_response(offer.channel, response)
+            .is_err()
+        {
+            log::warn!(
+                "{} gave up on sending {} before we answered",
+                peer_id,
+                offer.name
+            );
+            return;
+        }
+        if accept {
+            self.files.incoming.insert(
+                (peer_id, transfer),
+                Incoming {
+                    name: offer.name,
+                    size: offer.size,
+                    data: Vec::new(),
+                },
+            );
+        }
+    }
+
+    pub(super) async fn handle_file_event(
+        &mut self,
+        event: request_response::Event<FileRequest, FileResponse>,
+    ) {
+        match event {
+            request_response::Event::Message {
+                peer,
+                message:
+                    Message::Request {
+                        request, channel, ..
+                    },
+            } => {
+                let response = match request {
+                    FileRequest::Offer {
+                        transfer,
+                        name,
+                        size,
+                    } => self.file_offered(peer, transfer, name, size, channel).await,
+                    FileRequest::Chunk {
+                        transfer,
+                        offset,
+                        data,
+                    } => Some((
+                        channel,
+                        self.file_chunk(peer, transfer, offset, &data).await,
+                    )),
+                };
+                if let Some((channel, response)) = response {
+                    let _ = self
+                        .swarm
+                        .behaviour_mut()
+                        .file
+                        .send_response(channel, response);
+                }
+            }
+            request_response::Event::Message {
+                peer,
+                message:
+                    Message::Response {
+                        request_id,
+                        response,
+                    },
+            } => {
+                let Some(transfer) = self.files.requests.remove(&request_id) else {
+                    return;
+                };
+                match response {
+                    FileResponse::Accepted | FileResponse::Received => {
+                        self.send_next_chunk(transfer).await
+                    }
+                    FileResponse::Declined => {
+                        if let Some(outgoing) = self.files.outgoing.remove(&transfer) {
+                            self.send_to_game(NetworkEvent::Admin(
+                                NetworkAdminEvent::FileDeclined {
+                                    peer_id: peer,
+                                    name: outgoing.name,
+                                },
+                            ))
+                            .await;
+                        }
+                    }
+                    FileResponse::Failed(reason) => {
+                        if let Some(outgoing) = self.files.outgoing.remove(&transfer) {
+                            self.file_failed(peer, outgoing.name, reason).await;
+                        }
+                    }
+                }
+            }
+            request_response::Event::OutboundFailure {
+                peer,
+                request_id,
+                error,
+            } => {
+                let outgoing = self
+                    .files
+                    .requests
+                    .remove(&request_id)
+                    .and_then(|transfer| self.files.outgoing.remove(&transfer));
+                if let Some(outgoing) = outgoing {
+                    self.file_failed(peer, outgoing.name, error.to_string())
+                        .await;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Passes an offer on to the game, or answers it right away if it can't be taken.
+    async fn file_offered(
+        &mut self,
+        peer_id: PeerId,
+        transfer: u64,
+        name: String,
+        size: u64,
+        channel: ResponseChannel<FileResponse>,
+    ) -> Option<(ResponseChannel<FileResponse>, FileResponse)> {
+        let problem = if !self.room_peers().contains(&peer_id) {
+            Some("not in our room".to_string())
+        } else if size > self.config.file_drop.max_size {
+            Some(format!("{} bytes is over our limit", size))
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            self.log(
+                Severity::Info,
+                "file",
+                Some(peer_id),
+                format!("Declined {}: {}", name, problem),
+            );
+            return Some((channel, FileResponse::Declined));
+        }
+        self.files.offers.insert(
+            (peer_id, transfer),
+            Offer {
+                name: name.clone(),
+                size,
+                channel,
+            },
+        );
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::FileOffered {
+            peer_id,
+            transfer,
+            name,
+            size,
+        }))
+        .await;
+        None
+    }
+
+    /// Takes in a chunk of an accepted file, saving the file once it's complete.
+    async fn file_chunk(
+        &mut self,
+        peer_id: PeerId,
+        transfer: u64,
+        offset: u64,
+        data: &[u8],
+    ) -> FileResponse {
+        let key = (peer_id, transfer);
+        let Some(incoming) = self.files.incoming.get_mut(&key) else {
+            return FileResponse::Failed("Not an accepted transfer".to_string());
+        };
+        let saved = match incoming.push(offset, data) {
+            Ok(false) => return FileResponse::Received,
+            Ok(true) => {
+                let directory = &self.config.file_drop.directory;
+                let path = received_path(directory, &incoming.name, Path::exists);
+                fs::create_dir_all(directory)
+                    .and_then(|()| fs::write(&path, &incoming.data))
+                    .map(|()| path)
+                    .map_err(|e| format!("Could not save it: {}", e))
+            }
+            Err(reason) => Err(reason),
+        };
+        let Some(incoming) = self.files.incoming.remove(&key) else {
+            return FileResponse::Failed("Not an accepted transfer".to_string());
+        };
+        match saved {
+            Ok(path) => {
+                self.log(
+                    Severity::Info,
+                    "file",
+                    Some(peer_id),
+                    format!("Saved {}", path.display()),
+                );
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::FileReceived {
+                    peer_id,
+                    path,
+                }))
+                .await;
+                FileResponse::Received
+            }
+            Err(reason) => {
+                self.file_failed(peer_id, incoming.name, reason.clone())
+                    .await;
+                FileResponse::Failed(reason)
+            }
+        }
+    }
+
+    async fn send_next_chunk(&mut self, transfer: u64) {
+        let Some(outgoing) = self.files.outgoing.get_mut(&transfer) else {
+            return;
+        };
+        let peer_id = outgoing.peer_id;
+        match outgoing.next_chunk() {
+            Some((offset, data)) => {
+                let request = self.swarm.behaviour_mut().file.send_request(
+                    &peer_id,
+                    FileRequest::Chunk {
+                        transfer,
+                        offset,
+                        data,
+                    },
+                );
+                self.files.requests.insert(request, transfer);
+            }
+            None => {
+                let Some(outgoing) = self.files.outgoing.remove(&transfer) else {
+                    return;
+                };
+                self.log(
+                    Severity::Info,
+                    "file",
+                    Some(peer_id),
+                    format!("Sent {}", outgoing.name),
+                );
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::FileSent {
+                    peer_id,
+                    name: outgoing.name,
+                }))
+                .await;
+            }
+        }
+    }
+
+    async fn file_failed(&mut self, peer_id: PeerId, name: String, reason: String) {
+        self.log(
+            Severity::Warn,
+            "file",
+            Some(peer_id),
+            format!("{}: {}", name, reason),
+        );
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::FileFailed {
+            peer_id,
+            name,
+            reason,
+        }))
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_go_out_in_chunks_and_come_back_whole() {
+        let data: Vec<u8> = (0..FILE_CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let mut outgoing = Outgoing {
+            peer_id: PeerId::random(),
+            name: "shot.png".to_string(),
+            data: data.clone(),
+            sent: 0,
+            started: false,
+        };
+        let mut incoming = Incoming {
+            name: "shot.png".to_string(),
+            size: data.len() as u64,
+            data: Vec::new(),
+        };
+        let mut chunks = 0;
+        let mut done = false;
+        while let Some((offset, chunk)) = outgoing.next_chunk() {
+            chunks += 1;
+            done = incoming.push(offset, &chunk).unwrap();
+        }
+        assert_eq!(chunks, 3);
+        assert!(done);
+        assert_eq!(incoming.data, data);
+        assert!(incoming.push(0, b"again").is_err());
+
+        let mut empty = Outgoing {
+            data: Vec::new(),
+            sent: 0,
+            started: false,
+            ..outgoing
+        };
+        assert_eq!(empty.next_chunk(), Some((0, Vec::new())));
+        assert_eq!(empty.next_chunk(), None);
+    }
+
+    #[test]
+    fn received_files_stay_in_their_directory() {
+        let directory = Path::new("received");
+        let taken = [directory.join("shot.png"), directory.join("shot (1).png")];
+        let exists = |path: &Path| taken.iter().any(|taken| taken == path);
+        assert_eq!(
+            received_path(directory, "../../.bashrc", exists),
+            directory.join(".bashrc")
+        );
+        assert_eq!(
+            received_path(directory, "shot.png", exists),
+            directory.join("shot (2).png")
+        );
+        assert_eq!(
+            received_path(directory, "..", exists),
+            directory.join("file")
+        );
+    }
+}
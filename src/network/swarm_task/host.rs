@@ -0,0 +1,277 @@
+use std::{
+    iter,
+    time::{Duration, Instant},
+};
+
+use libp2p::{core::transport::ListenerId, kad::Mode, multiaddr::Protocol, Multiaddr, PeerId};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{resolve_room, room_topic, CustomBehaviour, SwarmTask};
+use crate::{
+    crypto::RoomKey,
+    network::{event_log::Severity, NetworkAdminEvent, NetworkEvent},
+    room_code::parse_invite_token,
+};
+
+/// How long a host looks for another host of its room code before giving up and
+/// taking it. A DHT lookup for a code nobody uses can take far longer to finish.
+const CLAIM_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An address we couldn't listen on, and why.
+pub(super) type ListenFailure = (Multiaddr, String);
+
+/// Room codes are short, so two hosts can pick the same one. Before announcing, a
+/// host checks that nobody else provides its code.
+pub(super) struct Claim {
+    room_code: String,
+    /// Discovery methods still looking.
+    searching: usize,
+    started: Instant,
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    pub(super) async fn host(&mut self, room_code: String) {
+        if self.hosting || self.claiming.is_some() {
+            log::warn!("Already hosting {:?}", self.room_code);
+            return;
+        }
+        let searching = self
+            .discoveries
+            .iter()
+            .filter(|discovery| discovery.finds_hosts_only())
+            .count();
+        // Nobody else will ever hold the same invite token.
+        if searching == 0 || parse_invite_token(&room_code).is_ok() {
+            self.finish_claim(room_code).await;
+            return;
+        }
+        self.claiming = Some(Claim {
+            room_code: room_code.clone(),
+            searching,
+            started: Instant::now(),
+        });
+        let mut updates = Vec::new();
+        for discovery in &mut self.discoveries {
+            if discovery.finds_hosts_only() {
+                updates.extend(discovery.find(&mut self.swarm, &room_code));
+            }
+        }
+        self.handle_discovery_updates(updates).await;
+    }
+
+    /// When we stop waiting for discovery to report rival hosts.
+    pub(super) fn claim_deadline(&self) -> Option<Instant> {
+        self.claiming
+            .as_ref()
+            .map(|claim| claim.started + CLAIM_TIMEOUT)
+    }
+
+    /// Gives up on the room code if `peer_id` already hosts it.
+    pub(super) async fn claim_rival(&mut self, peer_id: PeerId) {
+        if peer_id == *self.swarm.local_peer_id() {
+            return;
+        }
+        let Some(claim) = self.claiming.take() else {
+            return;
+        };
+        self.log(
+            Severity::Warn,
+            "host",
+            Some(peer_id),
+            format!("Room code {} is already hosted", claim.room_code),
+        );
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::RoomCodeTaken {
+            room_code: claim.room_code,
+        }))
+        .await;
+    }
+
+    /// A discovery method finished looking. Once all have, the code is ours.
+    pub(super) async fn claim_searched(&mut self) {
+        let Some(claim) = self.claiming.as_mut() else {
+            return;
+        };
+        claim.searching = claim.searching.saturating_sub(1);
+        if claim.searching == 0 {
+            let claim = self.claiming.take().expect("Checked above");
+            self.finish_claim(claim.room_code).await;
+        }
+    }
+
+    /// Takes the code after [`CLAIM_TIMEOUT`] without a rival turning up.
+    pub(super) async fn claim_timed_out(&mut self) {
+        if self.claim_deadline().map_or(true, |at| at > Instant::now()) {
+            return;
+        }
+        let claim = self.claiming.take().expect("Has a deadline");
+        log::info!("No other host of {} found in time", claim.room_code);
+        self.finish_claim(claim.room_code).await;
+    }
+
+    async fn finish_claim(&mut self, room_code: String) {
+        match self.start_hosting(room_code.clone()) {
+            Ok(failures) => {
+                self.report_listen_failures(failures).await;
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::HostingStarted {
+                    room_code,
+                }))
+                .await
+            }
+            Err(e) => self.hosting_failed(e.to_string()).await,
+        }
+    }
+
+    /// Returns the addresses we couldn't listen on, see
+    /// [`listen_for_room`](Self::listen_for_room).
+    fn start_hosting(&mut self, room_code: String) -> anyhow::Result<Vec<ListenFailure>> {
+        let (room_code, invite) = resolve_room(room_code);
+        // Mark ourselves as hosting first so a failure part way through gets undone.
+        self.hosting = true;
+        self.room_code = Some(room_code.clone());
+        self.invite = invite;
+        self.keys.replace(self.master_secret.room_key(&room_code));
+        self.set_kad_mode(Mode::Server);
+        let failures = self.listen_for_room()?;
+        self.announce_room(&room_code)?;
+
+        let topic = room_topic(&self.config.protocol, &room_code);
+        self.swarm
+            .behaviour_mut()
+            .gossip
+            .subscribe(&topic)
+            .map_err(|e| anyhow::anyhow!("Could not subscribe to the room: {}", e))?;
+        self.room_topic = Some(topic);
+        self.publish_room_info();
+        Ok(failures)
+    }
+
+    /// Makes us findable through every discovery, except browsable ones for a private
+    /// match.
+    pub(super) fn announce_room(&mut self, room_code: &str) -> anyhow::Result<()> {
+        for discovery in &mut self.discoveries {
+            if self.invite.is_some() && discovery.browsable() {
+                continue;
+            }
+            discovery
+                .announce(&mut self.swarm, room_code)
+                .map_err(|e| anyhow::anyhow!("{} announce failed: {}", discovery.name(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Opens the listeners joiners reach us on, directly or through the relay, and
+    /// returns the addresses that failed. Only failing on every address is an error.
+    fn listen_for_room(&mut self) -> anyhow::Result<Vec<ListenFailure>> {
+        let relay = self.pick_relay();
+        let circuit = relay.clone().with(Protocol::P2pCircuit);
+        let mut failures = Vec::new();
+        for address in iter::once(circuit).chain(self.listen.addresses()) {
+            match self.swarm.listen_on(address.clone()) {
+                Ok(listener) => self.host_listeners.push((listener, address)),
+                Err(e) => {
+                    log::warn!("Could not listen on {}: {}", address, e);
+                    failures.push((address, e.to_string()));
+                }
+            }
+        }
+        if self.host_listeners.is_empty() {
+            anyhow::bail!(
+                "Could not listen on any address, first error: {}",
+                failures
+                    .first()
+                    .map_or("no addresses configured", |(_, reason)| reason)
+            );
+        }
+        self.swarm
+            .dial(relay)
+            .map_err(|e| anyhow::anyhow!("Could not reach the relay: {}", e))?;
+        Ok(failures)
+    }
+
+    /// Closes our listeners and opens them again, so the relay circuit in particular
+    /// goes over our new network.
+    pub(super) fn relisten(&mut self) -> anyhow::Result<Vec<ListenFailure>> {
+        for (listener, _) in self.host_listeners.drain(..) {
+            self.swarm.remove_listener(listener);
+        }
+        self.listen_for_room()
+    }
+
+    pub(super) async fn report_listen_failures(&mut self, failures: Vec<ListenFailure>) {
+        for (address, reason) in failures {
+            self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::ListenFailed {
+                address,
+                reason,
+            }))
+            .await;
+        }
+    }
+
+    /// One of our host listeners broke. Hosting only fails once none are left.
+    pub(super) async fn host_listener_closed(&mut self, listener: ListenerId, reason: String) {
+        let Some(index) = self
+            .host_listeners
+            .iter()
+            .position(|(id, _)| *id == listener)
+        else {
+            return;
+        };
+        let (_, address) = self.host_listeners.remove(index);
+        self.report_listen_failures(vec![(address, reason.clone())])
+            .await;
+        if self.host_listeners.is_empty() {
+            self.hosting_failed(format!("Stopped listening: {}", reason))
+                .await;
+        }
+    }
+
+    /// Undoes whatever part of hosting got set up, so the game can retry from scratch.
+    pub(super) fn stop_hosting(&mut self) {
+        self.claiming = None;
+        for (listener, _) in self.host_listeners.drain(..) {
+            self.swarm.remove_listener(listener);
+        }
+        self.invite = None;
+        if let Some(room_code) = self.room_code.take() {
+            for discovery in &mut self.discoveries {
+                discovery.withdraw(&mut self.swarm, &room_code);
+            }
+        }
+        if let Some(topic) = self.room_topic.take() {
+            if let Err(e) = self.swarm.behaviour_mut().gossip.unsubscribe(&topic) {
+                log::debug!("Unsubscribing from room topic failed: {}", e);
+            }
+        }
+        self.clear_teams();
+        self.keys.replace(RoomKey::random());
+        self.challenges.clear();
+        self.pending_joins.clear();
+        self.admitted.clear();
+        self.held_slots.clear();
+        self.key_acks.clear();
+        self.pending_start = None;
+        self.authority.clear();
+        self.acks.clear();
+        self.told_spectating.clear();
+        self.told_profile.clear();
+        self.snapshot_requests.clear();
+        self.hosting = false;
+        self.game_started = false;
+        self.published_info = None;
+        self.set_kad_mode(Mode::Client);
+    }
+
+    pub(super) async fn hosting_failed(&mut self, reason: String) {
+        log::error!("Hosting failed: {}", reason);
+        self.stop_hosting();
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::HostingFailed {
+            reason,
+        }))
+        .await;
+    }
+}
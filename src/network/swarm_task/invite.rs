@@ -0,0 +1,103 @@
+use libp2p::{
+    kad::{GetClosestPeersError, GetClosestPeersOk, QueryId},
+    request_response::{self, Message},
+    PeerId,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{dialer::DialRequest, CustomBehaviour, SwarmTask};
+use crate::network::{
+    protocol::{InviteAck, RoomInvite},
+    NetworkAdminEvent, NetworkEvent,
+};
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    pub(super) fn find_peer(&mut self, peer_id: PeerId) {
+        let query = self.swarm.behaviour_mut().kad.get_closest_peers(peer_id);
+        self.finding_peers.insert(query, peer_id);
+    }
+
+    pub(super) async fn handle_closest_peers(
+        &mut self,
+        query: QueryId,
+        result: Result<GetClosestPeersOk, GetClosestPeersError>,
+        last: bool,
+    ) {
+        let Some(&peer_id) = self.finding_peers.get(&query) else {
+            return;
+        };
+        let peers = match result {
+            Ok(GetClosestPeersOk { peers, .. }) => peers,
+            Err(GetClosestPeersError::Timeout { peers, .. }) => peers,
+        };
+        let found = peers.contains(&peer_id) || self.swarm.is_connected(&peer_id);
+        if !found && !last {
+            return;
+        }
+        self.finding_peers.remove(&query);
+        let event = if found {
+            // Identify reports it as Connected once this goes through.
+            self.queue_dial(DialRequest::peer(peer_id));
+            NetworkAdminEvent::PeerFound(peer_id)
+        } else {
+            NetworkAdminEvent::PeerNotFound(peer_id)
+        };
+        self.send_to_game(NetworkEvent::Admin(event)).await;
+    }
+
+    pub(super) fn send_invite(&mut self, peer_id: PeerId, room_code: String) {
+        log::info!("Inviting {} to room {}", peer_id, room_code);
+        self.swarm
+            .behaviour_mut()
+            .invite
+            .send_request(&peer_id, RoomInvite { room_code });
+    }
+
+    pub(super) async fn handle_invite_event(
+        &mut self,
+        event: request_response::Event<RoomInvite, InviteAck>,
+    ) {
+        match event {
+            request_response::Event::Message {
+                peer,
+                message:
+                    Message::Request {
+                        request, channel, ..
+                    },
+            } => {
+                let _ = self
+                    .swarm
+                    .behaviour_mut()
+                    .invite
+                    .send_response(channel, InviteAck);
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::InviteReceived {
+                    from: peer,
+                    room_code: request.room_code,
+                }))
+                .await;
+            }
+            request_response::Event::Message {
+                peer,
+                message: Message::Response { .. },
+            } => {
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::InviteDelivered(
+                    peer,
+                )))
+                .await;
+            }
+            request_response::Event::OutboundFailure { peer, error, .. } => {
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::InviteFailed {
+                    peer_id: peer,
+                    reason: error.to_string(),
+                }))
+                .await;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,655 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use libp2p::{
+    kad::{self, Mode},
+    multiaddr::Protocol,
+    request_response::{self, Message, ResponseChannel},
+    Multiaddr, PeerId,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use zeroize::Zeroizing;
+
+use super::{
+    dialer::DialRequest, proof_secret, resolve_room, room_info::room_info_key, room_topic,
+    CustomBehaviour, SwarmTask,
+};
+use crate::{
+    crypto::{self, RoomKey},
+    network::{
+        discovery::DiscoveryUpdate,
+        protocol::{
+            join_proof_payload, JoinError, JoinRequest, JoinResponse, RoomInfo, RoomPeer,
+            PROTOCOL_VERSION,
+        },
+        ConnectionPath, JoinStage, JoinStageTimeouts, NetworkAdminEvent, NetworkEvent,
+    },
+    room_code::InviteToken,
+};
+
+/// How long a joiner has to answer the host's challenge.
+const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a joiner waits for the room's [`RoomInfo`] before dialing candidates
+/// without it.
+const ROOM_INFO_WAIT: Duration = Duration::from_secs(3);
+/// Peer exchange limits: members shared with a joiner, and addresses per member.
+const MAX_SHARED_PEERS: usize = 32;
+const MAX_SHARED_ADDRESSES: usize = 8;
+
+/// A join waiting on discovery or the host's answer.
+pub(super) struct PendingJoin {
+    pub(super) room_code: String,
+    /// Set when joining a private match.
+    invite: Option<InviteToken>,
+    name: String,
+    /// The candidate we sent our request to and are waiting on.
+    host: Option<PeerId>,
+    /// Peers discovery suggested that we haven't tried yet.
+    candidates: Vec<Candidate>,
+    tried: HashSet<PeerId>,
+    /// Discovery methods still looking.
+    searching: usize,
+    /// The DHT lookup of the room's [`RoomInfo`], while it runs.
+    pub(super) info_lookup: Option<kad::QueryId>,
+    /// What each candidate host published about the room.
+    pub(super) infos: HashMap<PeerId, RoomInfo>,
+    started: Instant,
+    stage: JoinStage,
+    stage_started: Instant,
+    /// Why the candidates we skipped wouldn't let us in, the most telling first.
+    skipped: Option<JoinError>,
+}
+
+impl PendingJoin {
+    /// Whether to hold off dialing until the room info turns up.
+    fn waiting_for_info(&self) -> bool {
+        self.info_lookup.is_some() && self.started.elapsed() < ROOM_INFO_WAIT
+    }
+
+    /// When we stop waiting for the room info.
+    pub(super) fn info_deadline(&self) -> Option<Instant> {
+        self.info_lookup.map(|_| self.started + ROOM_INFO_WAIT)
+    }
+
+    /// When the join fails with [`JoinError::Timeout`].
+    pub(super) fn deadline(&self, timeout: Duration) -> Instant {
+        self.started + timeout
+    }
+
+    /// When the stage we are in takes too long.
+    pub(super) fn stage_deadline(&self, timeouts: &JoinStageTimeouts) -> Option<Instant> {
+        timeouts
+            .of(self.stage)
+            .map(|timeout| self.stage_started + timeout)
+    }
+
+    /// Keeps why a candidate couldn't let us in, unless we already know something
+    /// more useful than that it doesn't host the room.
+    fn skipped(&mut self, error: JoinError) {
+        if self.skipped.is_none() || error != JoinError::RoomNotFound {
+            self.skipped = Some(error);
+        }
+    }
+}
+
+/// A peer that may host the room we are joining.
+struct Candidate {
+    peer_id: PeerId,
+    /// Whatever addresses discovery had for it.
+    addresses: Vec<Multiaddr>,
+    /// When discovery last reported it. A host that is still around keeps showing up,
+    /// while a stale record only comes back from the DHT.
+    seen: Instant,
+}
+
+/// Host only: a nonce handed to a joiner, valid for a single proof.
+pub(super) struct Challenge {
+    nonce: [u8; 32],
+    name: String,
+    issued: Instant,
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    pub(super) async fn join(&mut self, room_code: String, name: String) {
+        let (room_code, invite) = resolve_room(room_code);
+        log::info!("Looking for room {}", room_code);
+        self.set_kad_mode(Mode::Client);
+        // A private room is never announced anywhere browsable, so don't look there.
+        let private = invite.is_some();
+        let searching = self
+            .discoveries
+            .iter()
+            .filter(|discovery| !(private && discovery.browsable()))
+            .count();
+        self.joining = Some(PendingJoin {
+            room_code: room_code.clone(),
+            invite,
+            name,
+            host: None,
+            candidates: Vec::new(),
+            tried: HashSet::new(),
+            searching,
+            // Only hosts on the DHT publish room info.
+            info_lookup: (!self.bootstrap_peers.is_empty()).then(|| {
+                self.swarm
+                    .behaviour_mut()
+                    .kad
+                    .get_record(room_info_key(&self.config.protocol, &room_code))
+            }),
+            infos: HashMap::new(),
+            started: Instant::now(),
+            stage: JoinStage::ResolvingRoom,
+            stage_started: Instant::now(),
+            skipped: None,
+        });
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::JoinProgress(
+            JoinStage::ResolvingRoom,
+        )))
+        .await;
+        let mut updates = Vec::new();
+        for discovery in &mut self.discoveries {
+            if private && discovery.browsable() {
+                continue;
+            }
+            updates.extend(discovery.find(&mut self.swarm, &room_code));
+        }
+        self.handle_discovery_updates(updates).await;
+    }
+
+    /// Queues candidates for the room we are joining, checks for rivals while claiming
+    /// a room code to host, and reports announce failures while hosting.
+    pub(super) async fn handle_discovery_updates(&mut self, updates: Vec<DiscoveryUpdate>) {
+        let local_peer_id = *self.swarm.local_peer_id();
+        for update in updates {
+            match update {
+                DiscoveryUpdate::Candidate { peer_id, .. } if self.claiming.is_some() => {
+                    self.claim_rival(peer_id).await;
+                }
+                DiscoveryUpdate::Finished if self.claiming.is_some() => {
+                    self.claim_searched().await;
+                }
+                DiscoveryUpdate::Candidate { peer_id, addresses } => {
+                    let Some(pending) = self.joining.as_mut() else {
+                        continue;
+                    };
+                    if peer_id == local_peer_id || pending.tried.contains(&peer_id) {
+                        continue;
+                    }
+                    match pending.candidates.iter_mut().find(|c| c.peer_id == peer_id) {
+                        Some(candidate) => {
+                            candidate.seen = Instant::now();
+                            candidate.addresses.extend(addresses);
+                        }
+                        None => pending.candidates.push(Candidate {
+                            peer_id,
+                            addresses,
+                            seen: Instant::now(),
+                        }),
+                    }
+                }
+                DiscoveryUpdate::Finished => {
+                    if let Some(pending) = self.joining.as_mut() {
+                        pending.searching = pending.searching.saturating_sub(1);
+                    }
+                }
+                DiscoveryUpdate::AnnounceFailed(reason) if self.hosting => {
+                    self.hosting_failed(reason).await;
+                }
+                DiscoveryUpdate::AnnounceFailed(_) => {}
+            }
+        }
+        self.try_next_candidate().await;
+    }
+
+    /// Sends our hello to the next candidate host, unless one is already answering.
+    /// Fails the join once every candidate said no and discovery has nothing left.
+    ///
+    /// If two hosts ended up with the same room code, the one we have the best ping
+    /// to goes first, then the one discovery reported most recently. Hosts whose
+    /// [`RoomInfo`] says they are full, started, or play a mode we don't have are
+    /// dropped without dialing them.
+    pub(super) async fn try_next_candidate(&mut self) {
+        let Some(pending) = self.joining.as_mut() else {
+            return;
+        };
+        if pending.host.is_some() || pending.waiting_for_info() {
+            return;
+        }
+        let mut closed = Vec::new();
+        let modes = &self.modes;
+        pending.candidates.retain(|candidate| {
+            match pending
+                .infos
+                .get(&candidate.peer_id)
+                .and_then(|info| info.unknown_mode(modes).or_else(|| info.closed()))
+            {
+                Some(error) => {
+                    log::info!("Skipping {}: {}", candidate.peer_id, error);
+                    closed.push(error);
+                    false
+                }
+                None => true,
+            }
+        });
+        for error in closed {
+            pending.skipped(error);
+        }
+        let connections = &self.connections;
+        let best = pending
+            .candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, candidate)| {
+                (
+                    connections.rtt(&candidate.peer_id).unwrap_or(Duration::MAX),
+                    Reverse(candidate.seen),
+                )
+            })
+            .map(|(index, _)| index);
+        let Some(Candidate {
+            peer_id: host,
+            mut addresses,
+            ..
+        }) = best.map(|index| pending.candidates.swap_remove(index))
+        else {
+            if pending.searching == 0 {
+                let error = pending.skipped.take().unwrap_or(JoinError::RoomNotFound);
+                self.fail_join(error).await;
+            } else {
+                self.enter_join_stage(JoinStage::ResolvingRoom).await;
+            }
+            return;
+        };
+        log::info!("Trying {} as host of room {}", host, pending.room_code);
+        pending.tried.insert(host);
+        pending.host = Some(host);
+        let request = JoinRequest::Hello {
+            room_code: pending.room_code.clone(),
+            name: pending.name.clone(),
+            version: PROTOCOL_VERSION.to_string(),
+            modes: self.modes.clone(),
+        };
+        // Hosts listen on our relay unless they found a faster one, which discovery
+        // hands us with their other addresses, so give the dialer ours on top.
+        addresses.push(
+            self.relay
+                .clone()
+                .with(Protocol::P2pCircuit)
+                .with(Protocol::P2p(host)),
+        );
+        self.queue_dial(DialRequest::peer(host).with_addresses(addresses));
+        self.swarm.behaviour_mut().join.send_request(&host, request);
+        self.enter_join_stage(JoinStage::DialingHost).await;
+    }
+
+    /// Moves our join on to `stage`, and tells the game.
+    async fn enter_join_stage(&mut self, stage: JoinStage) {
+        let Some(pending) = self
+            .joining
+            .as_mut()
+            .filter(|pending| pending.stage != stage)
+        else {
+            return;
+        };
+        log::debug!("Join of {}: {}", pending.room_code, stage.text());
+        pending.stage = stage;
+        pending.stage_started = Instant::now();
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::JoinProgress(stage)))
+            .await;
+    }
+
+    /// A connection to `peer` came up. If it's the candidate host and only reachable
+    /// through a relay so far, we are hole punching.
+    pub(super) async fn join_connected(&mut self, peer: PeerId, path: ConnectionPath) {
+        let dialing = self.joining.as_ref().map_or(false, |pending| {
+            pending.host == Some(peer) && pending.stage == JoinStage::DialingHost
+        });
+        if dialing && path == ConnectionPath::Relayed {
+            self.enter_join_stage(JoinStage::HolePunching).await;
+        }
+    }
+
+    /// Gives up on the stage our join is stuck in: the candidate host is skipped, or
+    /// the join fails if we never found one.
+    pub(super) async fn join_stage_timed_out(&mut self) {
+        let Some(pending) = self.joining.as_ref() else {
+            return;
+        };
+        log::info!(
+            "Join of {} stuck at {}",
+            pending.room_code,
+            pending.stage.text()
+        );
+        match pending.host {
+            Some(host) => self.skip_candidate(host, JoinError::Timeout).await,
+            None => self.fail_join(JoinError::Timeout).await,
+        }
+    }
+
+    /// The current candidate can't let us in, so move on to the next.
+    async fn skip_candidate(&mut self, host: PeerId, error: JoinError) {
+        log::info!("{} is not our host: {}", host, error);
+        if let Some(pending) = self.joining.as_mut() {
+            pending.host = None;
+            pending.skipped(error);
+        }
+        self.try_next_candidate().await;
+    }
+
+    pub(super) async fn handle_join_event(
+        &mut self,
+        event: request_response::Event<JoinRequest, JoinResponse>,
+    ) {
+        match event {
+            request_response::Event::Message {
+                peer,
+                message:
+                    Message::Request {
+                        request, channel, ..
+                    },
+            } => match request {
+                JoinRequest::Hello {
+                    room_code,
+                    name,
+                    version,
+                    modes,
+                } => self.challenge_joiner(peer, room_code, name, version, &modes, channel),
+                JoinRequest::Proof { signature } => {
+                    self.handle_join_proof(peer, signature, channel).await
+                }
+            },
+            request_response::Event::Message {
+                peer,
+                message: Message::Response { response, .. },
+            } => self.handle_join_response(peer, response).await,
+            request_response::Event::OutboundFailure { peer, error, .. } => {
+                if self.joining.as_ref().and_then(|p| p.host) == Some(peer) {
+                    let reason = error.to_string();
+                    self.skip_candidate(peer, JoinError::TransportFailure { reason })
+                        .await;
+                }
+            }
+            request_response::Event::InboundFailure { peer, error, .. } => {
+                log::debug!("Join request from {} failed: {}", peer, error);
+                self.challenges.remove(&peer);
+                self.pending_joins.remove(&peer);
+            }
+            request_response::Event::ResponseSent { .. } => {}
+        }
+    }
+
+    fn challenge_joiner(
+        &mut self,
+        peer: PeerId,
+        room_code: String,
+        name: String,
+        version: String,
+        modes: &[String],
+        channel: ResponseChannel<JoinResponse>,
+    ) {
+        if !self.hosting || self.room_code.as_ref() != Some(&room_code) {
+            let _ = self
+                .swarm
+                .behaviour_mut()
+                .join
+                .send_response(channel, JoinResponse::UnknownRoom);
+            return;
+        }
+        if !self.allow_handshake(peer) {
+            log::warn!("{} started too many join handshakes", peer);
+            self.reject_join(channel, rejected("Too many join attempts"));
+            return;
+        }
+        if version != PROTOCOL_VERSION {
+            let host = PROTOCOL_VERSION.to_string();
+            self.reject_join(channel, JoinError::VersionMismatch { host });
+            return;
+        }
+        let info = self.room_info();
+        if let Some(error) = info.unknown_mode(modes) {
+            self.reject_join(channel, error);
+            return;
+        }
+        let returning = self.held_slots.is_held(&peer) || self.admitted.contains(&peer);
+        if let Some(error) = info.closed().filter(|_| !returning) {
+            self.reject_join(channel, error);
+            return;
+        }
+        // Drop challenges nobody answered so they can't pile up.
+        self.challenges
+            .retain(|_, challenge| challenge.issued.elapsed() < CHALLENGE_TIMEOUT);
+        let nonce = rand::random();
+        self.challenges.insert(
+            peer,
+            Challenge {
+                nonce,
+                name,
+                issued: Instant::now(),
+            },
+        );
+        let _ = self
+            .swarm
+            .behaviour_mut()
+            .join
+            .send_response(channel, JoinResponse::Challenge { nonce });
+    }
+
+    async fn handle_join_proof(
+        &mut self,
+        peer: PeerId,
+        signature: Vec<u8>,
+        channel: ResponseChannel<JoinResponse>,
+    ) {
+        // Each nonce is good for one proof, so a replayed proof finds nothing here.
+        let Some(challenge) = self.challenges.remove(&peer) else {
+            self.reject_join(channel, rejected("No outstanding challenge"));
+            return;
+        };
+        if challenge.issued.elapsed() >= CHALLENGE_TIMEOUT {
+            self.reject_join(channel, JoinError::Timeout);
+            return;
+        }
+        let verified = self.room_code.as_deref().map_or(false, |room_code| {
+            let payload = join_proof_payload(
+                &challenge.nonce,
+                self.swarm.local_peer_id(),
+                &proof_secret(room_code, self.invite.as_ref()),
+            );
+            crypto::identity_public_key(&peer).map_or(false, |key| key.verify(&payload, &signature))
+        });
+        if !verified {
+            log::warn!("{} failed the join challenge", peer);
+            // The invite token is the only secret in the proof, so a bad one is
+            // likely the wrong invite.
+            self.reject_join(channel, JoinError::WrongPassword);
+            return;
+        }
+        if self.reclaims_slot(peer).await {
+            log::info!("{} ({}) is back, letting it in", challenge.name, peer);
+            self.pending_joins.insert(peer, channel);
+            self.answer_join(peer, true);
+            self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::SlotReclaimed {
+                peer_id: peer,
+                name: challenge.name,
+            }))
+            .await;
+            return;
+        }
+        log::info!("{} ({}) asks to join", challenge.name, peer);
+        self.pending_joins.insert(peer, channel);
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::JoinRequested {
+            peer_id: peer,
+            name: challenge.name,
+        }))
+        .await;
+    }
+
+    fn reject_join(&mut self, channel: ResponseChannel<JoinResponse>, error: JoinError) {
+        let _ = self
+            .swarm
+            .behaviour_mut()
+            .join
+            .send_response(channel, JoinResponse::Rejected(error));
+    }
+
+    async fn handle_join_response(&mut self, host: PeerId, response: JoinResponse) {
+        let Some((room_code, secret)) = self
+            .joining
+            .as_ref()
+            .filter(|pending| pending.host == Some(host))
+            .map(|pending| {
+                (
+                    pending.room_code.clone(),
+                    proof_secret(&pending.room_code, pending.invite.as_ref()),
+                )
+            })
+        else {
+            return;
+        };
+        match response {
+            JoinResponse::Challenge { nonce } => {
+                self.enter_join_stage(JoinStage::ExchangingKeys).await;
+                let payload = join_proof_payload(&nonce, &host, &secret);
+                match self.id_keys.sign(&payload) {
+                    Ok(signature) => {
+                        self.swarm
+                            .behaviour_mut()
+                            .join
+                            .send_request(&host, JoinRequest::Proof { signature });
+                    }
+                    Err(e) => {
+                        let reason = format!("Could not sign the host's challenge: {}", e);
+                        self.fail_join(JoinError::TransportFailure { reason }).await
+                    }
+                }
+            }
+            JoinResponse::Accepted { room_key, peers } => {
+                let room_key = match self
+                    .peer_secrets
+                    .decrypt(&host, &room_key)
+                    .ok()
+                    .map(Zeroizing::new)
+                    .and_then(|bytes| RoomKey::from_bytes(&bytes))
+                {
+                    Some(room_key) => room_key,
+                    None => {
+                        let reason = "Host sent a malformed room key".to_string();
+                        self.fail_join(JoinError::TransportFailure { reason }).await;
+                        return;
+                    }
+                };
+                log::info!("Joined room {}", room_code);
+                self.joining = None;
+                self.keys.replace(room_key);
+                let topic = room_topic(&self.config.protocol, &room_code);
+                if let Err(e) = self.swarm.behaviour_mut().gossip.subscribe(&topic) {
+                    log::error!("Subscribing to room topic failed: {}", e);
+                }
+                self.room_topic = Some(topic);
+                self.room_code = Some(room_code.clone());
+                self.room_host = Some(host);
+                self.dial_room_peers(host, peers);
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::JoinProgress(
+                    JoinStage::SyncingState,
+                )))
+                .await;
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::JoinAccepted {
+                    host,
+                    room_code,
+                }))
+                .await;
+            }
+            JoinResponse::UnknownRoom => self.skip_candidate(host, JoinError::RoomNotFound).await,
+            // Another provider of the code may still let us in.
+            JoinResponse::Rejected(error) => self.skip_candidate(host, error).await,
+        }
+    }
+
+    pub(super) fn answer_join(&mut self, peer_id: PeerId, accept: bool) {
+        let Some(channel) = self.pending_joins.remove(&peer_id) else {
+            log::warn!("No pending join request from {}", peer_id);
+            return;
+        };
+        let response = if accept {
+            match self
+                .peer_secrets
+                .encrypt(&peer_id, self.keys.current_key().to_bytes().as_slice())
+            {
+                Ok(room_key) => {
+                    let peers = self.room_peers_for(peer_id);
+                    self.admitted.insert(peer_id);
+                    JoinResponse::Accepted { room_key, peers }
+                }
+                Err(e) => {
+                    log::error!("Could not encrypt room key for {}: {}", peer_id, e);
+                    JoinResponse::Rejected(rejected("The host could not share the room key"))
+                }
+            }
+        } else {
+            JoinResponse::Rejected(rejected("The host declined"))
+        };
+        if self
+            .swarm
+            .behaviour_mut()
+            .join
+            .send_response(channel, response)
+            .is_err()
+        {
+            log::warn!("{} left before we answered its join request", peer_id);
+        }
+    }
+
+    /// Host only: the members a new joiner `joiner` should connect to, besides us.
+    fn room_peers_for(&self, joiner: PeerId) -> Vec<RoomPeer> {
+        self.admitted
+            .iter()
+            .filter(|peer_id| **peer_id != joiner && self.swarm.is_connected(peer_id))
+            .map(|peer_id| RoomPeer {
+                peer_id: *peer_id,
+                addresses: self
+                    .listen_addrs
+                    .get(peer_id)
+                    .into_iter()
+                    .flatten()
+                    .take(MAX_SHARED_ADDRESSES)
+                    .cloned()
+                    .collect(),
+            })
+            .take(MAX_SHARED_PEERS)
+            .collect()
+    }
+
+    /// Connects to the members `host` told us about, so the room's mesh doesn't hang
+    /// off the host alone and survives it leaving.
+    fn dial_room_peers(&mut self, host: PeerId, peers: Vec<RoomPeer>) {
+        let local_peer_id = *self.swarm.local_peer_id();
+        for RoomPeer { peer_id, addresses } in peers.into_iter().take(MAX_SHARED_PEERS) {
+            if peer_id == local_peer_id || peer_id == host {
+                continue;
+            }
+            let addresses = addresses.into_iter().take(MAX_SHARED_ADDRESSES).collect();
+            self.queue_dial(DialRequest::peer(peer_id).with_addresses(addresses));
+        }
+    }
+
+    pub(super) async fn fail_join(&mut self, error: JoinError) {
+        log::warn!("Join failed: {}", error);
+        self.joining = None;
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::JoinFailed(error)))
+            .await;
+    }
+}
+
+fn rejected(reason: &str) -> JoinError {
+    JoinError::HostRejected {
+        reason: reason.to_string(),
+    }
+}
@@ -0,0 +1,185 @@
+//! Invites left on the DHT for peers that aren't online.
+//!
+//! Each peer has one mailbox record, keyed by its peer id. The record holds a single
+//! invite, so a newer invite from anyone replaces the last one left there.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use libp2p::{
+    kad::{self, GetRecordError, GetRecordOk, PutRecordError, PutRecordOk, QueryId, RecordKey},
+    PeerId,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+use crate::{
+    crypto::{identity_public_key, open, seal, x25519_secret, SealedBox},
+    network::{
+        event_log::Severity,
+        protocol::{mailbox_invite_payload, MailboxInvite},
+        NetworkAdminEvent, NetworkEvent, ProtocolNames,
+    },
+};
+
+/// How long an invite waits in a mailbox before the DHT drops it, and before we
+/// ignore it even if some node still has it.
+const MAILBOX_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// DHT key of `peer_id`'s mailbox.
+fn mailbox_key(names: &ProtocolNames, peer_id: &PeerId) -> RecordKey {
+    RecordKey::new(&names.key("mailbox", peer_id).as_bytes())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    pub(super) async fn post_invite(&mut self, peer_id: PeerId, room_code: String) {
+        let sent_at = unix_now();
+        let record = self
+            .id_keys
+            .sign(&mailbox_invite_payload(&peer_id, &room_code, sent_at))
+            .map_err(anyhow::Error::from)
+            .and_then(|signature| {
+                let invite = MailboxInvite {
+                    from: *self.swarm.local_peer_id(),
+                    room_code,
+                    sent_at,
+                    signature,
+                };
+                Ok(bincode::serialize(&seal(
+                    &peer_id,
+                    &bincode::serialize(&invite)?,
+                )?)?)
+            })
+            .map(|value| kad::Record {
+                key: mailbox_key(&self.config.protocol, &peer_id),
+                value,
+                publisher: None,
+                expires: Some(Instant::now() + MAILBOX_TTL),
+            });
+        let query = record.and_then(|record| {
+            self.swarm
+                .behaviour_mut()
+                .kad
+                .put_record(record, kad::Quorum::One)
+                .map_err(|e| anyhow::anyhow!("Could not store the invite: {:?}", e))
+        });
+        match query {
+            Ok(query) => {
+                self.posting_invites.insert(query, peer_id);
+            }
+            Err(e) => {
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::InviteFailed {
+                    peer_id,
+                    reason: e.to_string(),
+                }))
+                .await;
+            }
+        }
+    }
+
+    pub(super) async fn handle_put_invite(
+        &mut self,
+        query: QueryId,
+        result: Result<PutRecordOk, PutRecordError>,
+    ) {
+        let Some(peer_id) = self.posting_invites.remove(&query) else {
+            return;
+        };
+        let event = match result {
+            Ok(_) => NetworkAdminEvent::InvitePosted(peer_id),
+            Err(e) => NetworkAdminEvent::InviteFailed {
+                peer_id,
+                reason: format!("Could not post the invite: {}", e),
+            },
+        };
+        self.send_to_game(NetworkEvent::Admin(event)).await;
+    }
+
+    pub(super) fn check_mailbox(&mut self) {
+        let key = mailbox_key(&self.config.protocol, self.swarm.local_peer_id());
+        self.checking_mailbox = Some(self.swarm.behaviour_mut().kad.get_record(key));
+    }
+
+    pub(super) async fn handle_mailbox_record(
+        &mut self,
+        query: QueryId,
+        result: Result<GetRecordOk, GetRecordError>,
+        last: bool,
+    ) {
+        if self.checking_mailbox != Some(query) {
+            return;
+        }
+        if last {
+            self.checking_mailbox = None;
+        }
+        let record = match result {
+            Ok(GetRecordOk::FoundRecord(found)) => found.record,
+            Ok(GetRecordOk::FinishedWithNoAdditionalRecord { .. }) => return,
+            Err(GetRecordError::NotFound { .. }) => {
+                self.log(Severity::Debug, "kad", None, "Mailbox is empty");
+                return;
+            }
+            Err(e) => {
+                self.log(
+                    Severity::Warn,
+                    "kad",
+                    None,
+                    format!("Checking the mailbox failed: {}", e),
+                );
+                return;
+            }
+        };
+        match self.open_invite(&record.value) {
+            Ok(Some(invite)) => {
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::JoinInvitation {
+                    from: invite.from,
+                    room_code: invite.room_code,
+                    sent_at: invite.sent_at,
+                }))
+                .await;
+            }
+            Ok(None) => {}
+            Err(e) => self.log(
+                Severity::Warn,
+                "kad",
+                record.publisher,
+                format!("Dropping mailbox invite: {}", e),
+            ),
+        }
+    }
+
+    /// Opens and checks a mailbox record. `None` if it's stale or we already passed
+    /// it on, as every node holding the record returns its own copy.
+    fn open_invite(&mut self, value: &[u8]) -> anyhow::Result<Option<MailboxInvite>> {
+        let sealed: SealedBox = bincode::deserialize(value)?;
+        if !self.mailbox_seen.insert(sealed.ephemeral) {
+            return Ok(None);
+        }
+        let invite: MailboxInvite =
+            bincode::deserialize(&open(&x25519_secret(&self.id_keys)?, &sealed)?)?;
+        let payload = mailbox_invite_payload(
+            self.swarm.local_peer_id(),
+            &invite.room_code,
+            invite.sent_at,
+        );
+        anyhow::ensure!(
+            identity_public_key(&invite.from)?.verify(&payload, &invite.signature),
+            "bad signature from {}",
+            invite.from
+        );
+        if unix_now().saturating_sub(invite.sent_at) > MAILBOX_TTL.as_secs() {
+            return Ok(None);
+        }
+        Ok(Some(invite))
+    }
+}
@@ -0,0 +1,69 @@
+use libp2p::PeerId;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{dialer::DialRequest, CustomBehaviour, SwarmTask};
+use crate::network::{event_log::Severity, interfaces::Migration, NetworkAdminEvent, NetworkEvent};
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    /// Moves us onto the network we just switched to. Connections on the old one tend
+    /// to die without either side noticing, so rather than wait for them to time out
+    /// we reopen listeners and dial everyone in the room again.
+    pub(super) async fn migrate(&mut self, migration: Migration) {
+        self.log(
+            Severity::Info,
+            "swarm",
+            None,
+            format!(
+                "Network changed, gained {:?} and lost {:?}",
+                migration.gained, migration.lost
+            ),
+        );
+        for address in self.observed_addresses.clear() {
+            self.swarm.remove_external_address(&address);
+        }
+        self.connections.mark_stale();
+        if self.hosting {
+            match self.relisten() {
+                Ok(failures) => self.report_listen_failures(failures).await,
+                Err(e) => {
+                    self.hosting_failed(format!("Could not listen after a network change: {}", e))
+                        .await
+                }
+            }
+        }
+        let redialed = self.room_peers();
+        for &peer_id in &redialed {
+            self.queue_dial(DialRequest::peer(peer_id).even_if_connected());
+        }
+        // Refill the routing table from the new network too.
+        if !self.bootstrap_peers.is_empty() {
+            let _ = self.swarm.behaviour_mut().kad.bootstrap();
+        }
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::NetworkMigrated {
+            gained: migration.gained,
+            lost: migration.lost,
+            redialed,
+        }))
+        .await;
+    }
+
+    /// Everyone subscribed to our room's topic.
+    pub(super) fn room_peers(&self) -> Vec<PeerId> {
+        let Some(topic) = &self.room_topic else {
+            return Vec::new();
+        };
+        let topic = topic.hash();
+        self.swarm
+            .behaviour()
+            .gossip
+            .all_peers()
+            .filter(|(_, topics)| topics.contains(&&topic))
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+}
@@ -0,0 +1,1861 @@
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_std::channel::{Receiver, Sender};
+use futures::{future::Either, prelude::*};
+use libp2p::{
+    core::transport::ListenerId, dcutr, gossipsub, identify, identity, kad, ping,
+    request_response::ResponseChannel, swarm::SwarmEvent, Multiaddr, PeerId, StreamProtocol, Swarm,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    acks::Acks,
+    connections::{ConnectionPath, Connections},
+    custom::{CustomBehaviour, CustomCall, CustomEvent, CustomFactory},
+    discovery::{self, RoomDiscovery},
+    event_log::{LogEntry, Severity},
+    external_addresses::{ObservedAddresses, CONFIRMATIONS},
+    fragment::{self, Reassembler, MAX_MESSAGE_SIZE},
+    interfaces::{InterfaceWatcher, Migration},
+    metrics::{BandwidthTotals, SharedKeyRing, SwarmCounters},
+    outbound::{OutboundQueue, Priority},
+    protocol::{
+        self, unix_millis, ControlMessage, JoinError, JoinResponse, RoomInfo, SignedProfile,
+        SnapshotResponse, TickSettings, WireMessage,
+    },
+    relay_usage::RelayUsage,
+    suspend::SuspendDetector,
+    tape::{TapeDirection, TapePlayer, TapeRecorder},
+    watchdog::Liveness,
+    Behaviour, BehaviourEvent, CommandId, GameAdminEvent, GameEvent, ListenStrategy,
+    NetworkAdminEvent, NetworkConfig, NetworkEvent, ProtocolNames, IDENTIFY_PROTOCOL,
+    RELAY_PROTOCOL,
+};
+use crate::{
+    crypto::{KeyRing, MasterSecret, PeerSecrets, RoomKey},
+    room_code::{parse_invite_token, InviteToken},
+};
+
+mod authority;
+mod batch;
+mod bootstrap;
+mod commands;
+mod debug;
+mod dialer;
+mod file_drop;
+mod host;
+mod invite;
+mod join;
+mod mailbox;
+mod migration;
+mod profile;
+mod reclaim;
+mod relays;
+mod restart;
+mod resume;
+mod room_info;
+mod snapshot;
+mod start_barrier;
+mod tape;
+mod teams;
+mod throttle;
+
+/// Most game messages published before the swarm gets polled again.
+const OUTBOUND_BATCH: usize = 32;
+/// Backlog size at which we start warning that the game sends faster than we can.
+const OUTBOUND_BACKLOG_WARNING: usize = 256;
+
+/// A game message waiting in the [`OutboundQueue`] for its turn.
+enum Outbound<FromGame> {
+    Broadcast(FromGame),
+    Latest { seq: u32, event: FromGame },
+    Unicast { to: PeerId, event: FromGame },
+    Team { team: String, event: FromGame },
+}
+
+/// What woke up [`SwarmTask::run`].
+enum Next<E, FromGame> {
+    Swarm(E),
+    Game(GameEvent<FromGame>),
+    Custom(CustomCall),
+    Flush,
+    Migrate(Migration),
+    /// A deadline from [`SwarmTask::next_deadline`] passed.
+    Deadline,
+}
+
+/// Why [`SwarmTask::run_swarm`] stops.
+enum Stop {
+    Quit,
+    Restart(restart::Restart),
+}
+
+/// Splits what the game passed as a room code into the id the room is announced
+/// under and, for a private match, the invite joiners must prove they hold.
+fn resolve_room(room_code: String) -> (String, Option<InviteToken>) {
+    match parse_invite_token(&room_code) {
+        Ok(invite) => (invite.room_id(), Some(invite)),
+        Err(_) => (room_code, None),
+    }
+}
+
+/// What joiners sign into their proof: the invite for a private match, otherwise the
+/// room code.
+fn proof_secret(room_code: &str, invite: Option<&InviteToken>) -> String {
+    invite.map_or_else(|| room_code.to_string(), InviteToken::to_string)
+}
+
+/// What the gossip topic every room publishes on is called, between the
+/// [namespace](super::ProtocolNames::namespace) and the room code.
+const ROOM_TOPIC_KIND: &str = "room";
+
+/// Gossip topic all messages in `room_code` are published on.
+fn room_topic(names: &ProtocolNames, room_code: &str) -> gossipsub::IdentTopic {
+    gossipsub::IdentTopic::new(names.key(ROOM_TOPIC_KIND, room_code))
+}
+
+/// How long we keep driving the swarm after Quit so our goodbye gets out.
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(500);
+
+/// How many gossip messages a throttled peer may get through to the game per second.
+const THROTTLED_MESSAGES_PER_SECOND: u32 = 5;
+
+/// Counts messages from a throttled peer over one second windows.
+struct RateWindow {
+    started: Instant,
+    messages: u32,
+}
+
+impl RateWindow {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            messages: 0,
+        }
+    }
+
+    /// Records a message and returns whether it fits in the current window.
+    fn allow(&mut self) -> bool {
+        if self.started.elapsed() >= Duration::from_secs(1) {
+            self.started = Instant::now();
+            self.messages = 0;
+        }
+        self.messages += 1;
+        self.messages <= THROTTLED_MESSAGES_PER_SECOND
+    }
+}
+
+/// Ends of the channels between the game and the network thread. They outlive the
+/// swarm, which can be rebuilt.
+pub(super) struct GameChannels<FromGame, ToGame> {
+    pub(super) to_game: Sender<NetworkEvent<ToGame>>,
+    pub(super) from_game: Receiver<GameEvent<FromGame>>,
+    pub(super) log: Sender<LogEntry>,
+    pub(super) custom_events: Sender<CustomEvent>,
+    pub(super) custom_calls: Receiver<CustomCall>,
+}
+
+/// State owned by the network thread.
+pub(super) struct SwarmTask<FromGame, ToGame, Custom: CustomBehaviour> {
+    swarm: Swarm<Behaviour<Custom>>,
+    id_keys: identity::Keypair,
+    keys: KeyRing,
+    /// Host only: every room we host gets its key from this.
+    master_secret: MasterSecret,
+    /// Keys shared with each peer for unicast and key distribution.
+    peer_secrets: PeerSecrets,
+    to_game: Sender<NetworkEvent<ToGame>>,
+    from_game: Receiver<GameEvent<FromGame>>,
+    /// Feeds the game's [`NetworkLog`](super::NetworkLog).
+    log: Sender<LogEntry>,
+    log_seq: u64,
+    /// The custom behaviour's events, for the game.
+    custom_events: Sender<CustomEvent>,
+    custom_calls: Receiver<CustomCall>,
+    /// Builds the custom behaviour again on a restart.
+    make_custom: CustomFactory<Custom>,
+    /// Sampled by the game into its diagnostics.
+    counters: Arc<SwarmCounters>,
+    bandwidth: BandwidthTotals,
+    /// Lets the game read [`keys`](Self::keys) across restarts.
+    shared_keys: SharedKeyRing,
+    /// Tells the game's watchdog we are still making progress.
+    liveness: Arc<Liveness>,
+    /// What the swarm was built from, to build it again on a restart.
+    config: NetworkConfig,
+    /// Commands to redo once bootstrapped, e.g. rejoining our room after a restart.
+    redo: Vec<GameAdminEvent>,
+    /// The `Host` or `Join` that got us into the room we are in or heading for.
+    entered_with: Option<GameAdminEvent>,
+    /// Game messages not yet handed to gossipsub.
+    outbound: OutboundQueue<Outbound<FromGame>>,
+    room_topic: Option<gossipsub::IdentTopic>,
+    /// Id of the next message we publish in fragments.
+    next_fragment_id: u64,
+    /// Fragmented messages we are still receiving.
+    fragments: Reassembler,
+    throttled: HashMap<PeerId, RateWindow>,
+    /// How long and how much we talk to relayed peers, for relay usage warnings.
+    relay_usage: RelayUsage,
+    /// Relays found on the DHT, under
+    /// [`RelaySelection::Discover`](crate::network::RelaySelection::Discover).
+    relays: relays::RelayCandidates,
+    connections: Connections,
+    reported_paths: HashMap<PeerId, (ConnectionPath, Option<PeerId>)>,
+    /// Our mesh peers per topic, as last reported to the game.
+    reported_mesh: HashMap<gossipsub::TopicHash, HashSet<PeerId>>,
+    /// Where each connected game peer says it listens, for peer exchange.
+    listen_addrs: HashMap<PeerId, Vec<Multiaddr>>,
+    observed_addresses: ObservedAddresses,
+    /// Tells us when we move to another network.
+    interfaces: InterfaceWatcher,
+    /// Tells us when the machine slept.
+    suspend: SuspendDetector,
+    /// DHT lookups started by [`GameAdminEvent::FindPeer`].
+    finding_peers: HashMap<kad::QueryId, PeerId>,
+    /// DHT lookups started by [`GameAdminEvent::GetProviders`].
+    finding_providers: HashMap<kad::QueryId, debug::ProviderLookup>,
+    /// Invites being stored in a peer's mailbox by [`GameAdminEvent::PostInvite`].
+    posting_invites: HashMap<kad::QueryId, PeerId>,
+    checking_mailbox: Option<kad::QueryId>,
+    /// Mailbox invites already passed to the game, by their sealed box's ephemeral key.
+    mailbox_seen: HashSet<[u8; 32]>,
+    /// DHT entry points we probe at startup.
+    bootstrap_peers: HashSet<PeerId>,
+    dialer: dialer::Dialer,
+    /// Announces our room and finds the ones we join, one per
+    /// [`NetworkConfig::discovery`] entry.
+    discoveries: Vec<Box<dyn RoomDiscovery<Custom>>>,
+    hosting: bool,
+    /// The mode kad was last set to, see [`set_kad_mode`](Self::set_kad_mode).
+    kad_mode: kad::Mode,
+    /// Host only: listeners opened for the room and their addresses, closed again if
+    /// hosting fails.
+    host_listeners: Vec<(ListenerId, Multiaddr)>,
+    /// Where [`host_listeners`](Self::host_listeners) are opened.
+    listen: ListenStrategy,
+    /// [`NetworkConfig::relay`].
+    relay: Multiaddr,
+    room_code: Option<String>,
+    /// Joiner only: the host of the room we are in.
+    room_host: Option<PeerId>,
+    /// Set by [`GameAdminEvent::SimulateLatency`].
+    simulated_latency: Duration,
+    /// Game messages held back by the simulated latency, with when they are due.
+    delayed: VecDeque<(Instant, NetworkEvent<ToGame>)>,
+    /// Set by [`GameAdminEvent::RecordTape`] until the tape is saved.
+    tape: Option<TapeRecorder>,
+    /// A [`GameAdminEvent::PlayTape`] in progress, with the tape's path.
+    tape_player: Option<(PathBuf, TapePlayer)>,
+    /// Host only: what the game wants published about the room.
+    room_description: room_info::RoomDescription,
+    /// Set by [`GameAdminEvent::SetModes`]: the game modes we can play.
+    modes: Vec<String>,
+    /// The teams in our room.
+    teams: teams::Teams,
+    /// Host only: the room info last stored on the DHT.
+    published_info: Option<RoomInfo>,
+    /// Host only: the game in our room has started.
+    game_started: bool,
+    /// Host only: set while hosting a private match.
+    invite: Option<InviteToken>,
+    /// Host only: nonces sent to joiners that have not proven their identity yet.
+    challenges: HashMap<PeerId, join::Challenge>,
+    /// Host only: join requests waiting for the game to accept or reject them.
+    pending_joins: HashMap<PeerId, ResponseChannel<JoinResponse>>,
+    /// Host only: peers we let into the room.
+    admitted: HashSet<PeerId>,
+    /// Host only: slots kept for admitted peers that dropped, see [`reclaim`].
+    held_slots: reclaim::HeldSlots,
+    /// Host only: admitted peers that acknowledged the current room key.
+    key_acks: HashSet<PeerId>,
+    /// Host only: a start waiting for the room to be ready, see [`start_barrier`].
+    pending_start: Option<(u64, Duration, Option<TickSettings>)>,
+    /// Acks for the latest-wins channel, for the room we are in.
+    acks: Acks,
+    /// Who simulates which entity. See [`authority`].
+    authority: authority::AuthorityTable,
+    /// We only watch: game messages from the game are dropped instead of sent.
+    spectating: bool,
+    /// Peers in our room we told that we are spectating.
+    told_spectating: HashSet<PeerId>,
+    /// Our profile as the game last set it, signed.
+    profile: Option<SignedProfile>,
+    /// Peers in our room we told our current profile.
+    told_profile: HashSet<PeerId>,
+    /// When the newest profile we took from each peer was signed.
+    profile_versions: HashMap<PeerId, u64>,
+    /// Files we are sending or getting, see [`file_drop`].
+    files: file_drop::FileTransfers,
+    /// Host only: late joiners waiting for the game to hand over its state.
+    snapshot_requests: HashMap<PeerId, ResponseChannel<SnapshotResponse>>,
+    /// Host only: the room code we are checking nobody else hosts.
+    claiming: Option<host::Claim>,
+    /// Joiner only: the join we are trying to complete.
+    joining: Option<join::PendingJoin>,
+    /// [`GameEvent::Command`]s waiting for the event that settles them.
+    pending_commands: Vec<(CommandId, GameAdminEvent)>,
+    join_cooldown: throttle::JoinCooldown,
+    /// See [`JoinLimits::max_dht_queries`](super::JoinLimits::max_dht_queries).
+    max_dht_queries: usize,
+    queued_lookups: throttle::QueuedLookups,
+    /// Host only: join handshakes each peer started lately.
+    handshakes: throttle::HandshakeLimiter,
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    pub(super) fn new(
+        swarm: Swarm<Behaviour<Custom>>,
+        id_keys: &identity::Keypair,
+        keys: KeyRing,
+        config: &NetworkConfig,
+        channels: GameChannels<FromGame, ToGame>,
+        make_custom: CustomFactory<Custom>,
+    ) -> Result<Self, anyhow::Error> {
+        let GameChannels {
+            to_game,
+            from_game,
+            log,
+            custom_events,
+            custom_calls,
+        } = channels;
+        let shared_keys = SharedKeyRing::new(keys.clone());
+        Ok(Self {
+            swarm,
+            id_keys: id_keys.clone(),
+            keys,
+            master_secret: MasterSecret::generate(),
+            peer_secrets: PeerSecrets::new(id_keys)?,
+            to_game,
+            from_game,
+            log,
+            log_seq: 0,
+            custom_events,
+            custom_calls,
+            make_custom,
+            counters: Arc::default(),
+            bandwidth: BandwidthTotals::default(),
+            shared_keys,
+            liveness: Arc::default(),
+            config: config.clone(),
+            redo: Vec::new(),
+            entered_with: None,
+            outbound: OutboundQueue::default(),
+            room_topic: None,
+            next_fragment_id: 0,
+            fragments: Reassembler::default(),
+            throttled: HashMap::new(),
+            relay_usage: RelayUsage::default(),
+            relays: relays::RelayCandidates::default(),
+            connections: Connections::default(),
+            reported_paths: HashMap::new(),
+            reported_mesh: HashMap::new(),
+            listen_addrs: HashMap::new(),
+            observed_addresses: ObservedAddresses::default(),
+            interfaces: InterfaceWatcher::new(),
+            suspend: SuspendDetector::new(Instant::now(), SystemTime::now()),
+            finding_peers: HashMap::new(),
+            finding_providers: HashMap::new(),
+            posting_invites: HashMap::new(),
+            checking_mailbox: None,
+            mailbox_seen: HashSet::new(),
+            bootstrap_peers: config
+                .bootstrap_peers()?
+                .into_iter()
+                .map(|(peer_id, _)| peer_id)
+                .collect(),
+            dialer: dialer::Dialer::default(),
+            discoveries: discovery::from_config(config)?,
+            hosting: false,
+            // Set by `Behaviour::new`.
+            kad_mode: kad::Mode::Client,
+            host_listeners: Vec::new(),
+            listen: config.listen.clone(),
+            relay: config.relay.clone(),
+            room_code: None,
+            room_host: None,
+            simulated_latency: Duration::ZERO,
+            delayed: VecDeque::new(),
+            tape: None,
+            tape_player: None,
+            room_description: room_info::RoomDescription::default(),
+            modes: Vec::new(),
+            teams: teams::Teams::default(),
+            published_info: None,
+            game_started: false,
+            invite: None,
+            challenges: HashMap::new(),
+            pending_joins: HashMap::new(),
+            admitted: HashSet::new(),
+            held_slots: reclaim::HeldSlots::default(),
+            key_acks: HashSet::new(),
+            pending_start: None,
+            acks: Acks::default(),
+            authority: authority::AuthorityTable::default(),
+            spectating: false,
+            told_spectating: HashSet::new(),
+            profile: None,
+            told_profile: HashSet::new(),
+            profile_versions: HashMap::new(),
+            files: file_drop::FileTransfers::default(),
+            snapshot_requests: HashMap::new(),
+            claiming: None,
+            joining: None,
+            pending_commands: Vec::new(),
+            join_cooldown: throttle::JoinCooldown::new(config.join_limits.cooldown),
+            max_dht_queries: config.join_limits.max_dht_queries,
+            queued_lookups: throttle::QueuedLookups::default(),
+            handshakes: throttle::HandshakeLimiter::new(&config.join_limits),
+        })
+    }
+
+    /// The soonest moment something has to happen even if the swarm and the game
+    /// stay quiet.
+    fn next_deadline(&self) -> Option<Instant> {
+        let info = self
+            .joining
+            .as_ref()
+            .and_then(join::PendingJoin::info_deadline);
+        let join_timeout = self
+            .joining
+            .as_ref()
+            .map(|pending| pending.deadline(self.config.join_limits.timeout));
+        let join_stage = self
+            .joining
+            .as_ref()
+            .and_then(|pending| pending.stage_deadline(&self.config.join_limits.stages));
+        self.claim_deadline()
+            .into_iter()
+            .chain(Some(self.suspend.next_check()))
+            .chain(info)
+            .chain(join_timeout)
+            .chain(join_stage)
+            .chain(self.fragments.next_expiry())
+            .chain(self.next_delayed())
+            .chain(self.next_tape_entry())
+            .chain(self.next_dial())
+            .chain(self.keys.next_expiry())
+            .chain(self.relay_usage.next_deadline(&self.config.relay_usage))
+            .chain(
+                self.held_slots
+                    .next_expiry(self.config.join_limits.reclaim_window),
+            )
+            .min()
+    }
+
+    /// Acts on whichever deadlines from [`next_deadline`](Self::next_deadline) passed.
+    async fn handle_deadlines(&mut self) {
+        self.check_suspend().await;
+        self.claim_timed_out().await;
+        self.deliver_delayed().await;
+        self.play_due_tape().await;
+        self.release_expired_slots().await;
+        self.start_dials();
+        let pruned = self.keys.prune(Instant::now());
+        if pruned > 0 {
+            log::debug!("Dropped {} retired room keys", pruned);
+        }
+        for peer in self.fragments.expire(Instant::now()) {
+            self.log(
+                Severity::Debug,
+                "gossipsub",
+                Some(peer),
+                "Dropped a fragmented message that never completed",
+            );
+        }
+        let join_timeout = self.config.join_limits.timeout;
+        if self.joining.as_ref().map_or(false, |pending| {
+            pending.deadline(join_timeout) <= Instant::now()
+        }) {
+            self.fail_join(JoinError::Timeout).await;
+        }
+        let join_stages = &self.config.join_limits.stages;
+        if self.joining.as_ref().map_or(false, |pending| {
+            pending
+                .stage_deadline(join_stages)
+                .map_or(false, |at| at <= Instant::now())
+        }) {
+            self.join_stage_timed_out().await;
+        }
+        if let Some(pending) = self.joining.as_mut() {
+            if pending
+                .info_deadline()
+                .map_or(false, |at| at <= Instant::now())
+            {
+                log::debug!("No room info in time, trying hosts without it");
+                pending.info_lookup = None;
+                self.try_next_candidate().await;
+            }
+        }
+    }
+
+    pub(super) fn counters(&self) -> Arc<SwarmCounters> {
+        Arc::clone(&self.counters)
+    }
+
+    pub(super) fn bandwidth(&self) -> BandwidthTotals {
+        self.bandwidth.clone()
+    }
+
+    pub(super) fn shared_keys(&self) -> SharedKeyRing {
+        self.shared_keys.clone()
+    }
+
+    pub(super) fn liveness(&self) -> Arc<Liveness> {
+        Arc::clone(&self.liveness)
+    }
+
+    /// Remembers how we got into our room, for a restart here or on a new thread.
+    fn set_entered_with(&mut self, event: Option<GameAdminEvent>) {
+        self.liveness.set_entered_with(event.clone());
+        self.entered_with = event;
+    }
+
+    /// Drives the swarm until asked to quit, rebuilding it whenever the game asks for a
+    /// restart.
+    pub(super) async fn run(mut self) {
+        while let Some(restart) = self.run_swarm().await {
+            self = self.restart(restart).await;
+        }
+    }
+
+    /// Loops over swarm events and messages from the game until asked to quit or
+    /// restart.
+    async fn run_swarm(&mut self) -> Option<restart::Restart> {
+        self.liveness.beat();
+        self.bootstrap().await;
+        for command in std::mem::take(&mut self.redo) {
+            self.handle_admin_event(command).await;
+        }
+        'run: loop {
+            // Swarm events and new game messages come first, so the queue is sorted
+            // with everything the game has sent so far before we publish.
+            let flush = if self.outbound.is_empty() {
+                Either::Left(future::pending())
+            } else {
+                Either::Right(future::ready(()))
+            };
+            let deadline = match self.next_deadline() {
+                Some(at) => Either::Left(async_std::task::sleep(
+                    at.saturating_duration_since(Instant::now()),
+                )),
+                None => Either::Right(future::pending()),
+            };
+            futures::pin_mut!(deadline);
+            // The migration future borrows the watcher, so drop it before handling.
+            let next = {
+                let migration = self.interfaces.next_migration();
+                futures::pin_mut!(migration);
+                match future::select(
+                    future::select(
+                        self.swarm.select_next_some(),
+                        future::select(
+                            self.from_game.select_next_some(),
+                            self.custom_calls.select_next_some(),
+                        ),
+                    ),
+                    future::select(flush, future::select(migration, deadline)),
+                )
+                .await
+                {
+                    Either::Left((Either::Left((event, _)), _)) => Next::Swarm(event),
+                    Either::Left((Either::Right((Either::Left((msg, _)), _)), _)) => {
+                        Next::Game(msg)
+                    }
+                    Either::Left((Either::Right((Either::Right((call, _)), _)), _)) => {
+                        Next::Custom(call)
+                    }
+                    Either::Right((Either::Left(((), _)), _)) => Next::Flush,
+                    Either::Right((Either::Right((Either::Left((migration, _)), _)), _)) => {
+                        Next::Migrate(migration)
+                    }
+                    Either::Right((Either::Right((Either::Right(((), _)), _)), _)) => {
+                        Next::Deadline
+                    }
+                }
+            };
+            // Then whatever else is already waiting, taking turns between the sources.
+            let mut budget = batch::Budget::default();
+            let mut next = Some(next);
+            while let Some(item) = next.take() {
+                match self.handle_next(item).await {
+                    Some(Stop::Quit) => break 'run,
+                    Some(Stop::Restart(restart)) => return Some(restart),
+                    None => {}
+                }
+                for source in budget.turns() {
+                    next = match source {
+                        batch::Source::Swarm => self
+                            .swarm
+                            .select_next_some()
+                            .now_or_never()
+                            .map(Next::Swarm),
+                        batch::Source::Game => self.from_game.try_recv().ok().map(Next::Game),
+                        batch::Source::Custom => {
+                            self.custom_calls.try_recv().ok().map(Next::Custom)
+                        }
+                    };
+                    if next.is_some() {
+                        budget.spend(source);
+                        break;
+                    }
+                }
+            }
+            self.count_batch(&budget);
+            self.liveness.beat();
+            if self.liveness.is_abandoned() {
+                // We were stuck long enough for the watchdog to start a new thread,
+                // which speaks for us now. Saying goodbye would only confuse our peers.
+                log::warn!("Replaced by a new network thread, stopping this one");
+                return None;
+            }
+            self.run_queued_lookups().await;
+            self.report_failed_dials().await;
+            self.report_mesh_changes().await;
+            self.check_start_barrier().await;
+        }
+        self.shutdown().await;
+        None
+    }
+
+    /// Handles one thing that woke up [`run`](Self::run), saying if it ends the loop.
+    async fn handle_next<E: std::fmt::Debug>(
+        &mut self,
+        next: Next<SwarmEvent<BehaviourEvent<Custom>, E>, FromGame>,
+    ) -> Option<Stop> {
+        match next {
+            Next::Swarm(event) => self.handle_swarm_event(event).await,
+            Next::Game(GameEvent::Admin(GameAdminEvent::Quit)) => return Some(Stop::Quit),
+            Next::Game(GameEvent::Admin(GameAdminEvent::RestartNetwork {
+                new_identity,
+                relay,
+            })) => {
+                return Some(Stop::Restart(restart::Restart {
+                    new_identity,
+                    relay,
+                }))
+            }
+            Next::Game(GameEvent::Command {
+                id,
+                command:
+                    GameAdminEvent::RestartNetwork {
+                        new_identity,
+                        relay,
+                    },
+            }) => {
+                // Answered by the restarted task.
+                let command = GameAdminEvent::RestartNetwork {
+                    new_identity,
+                    relay: relay.clone(),
+                };
+                self.pending_commands.push((id, command));
+                return Some(Stop::Restart(restart::Restart {
+                    new_identity,
+                    relay,
+                }));
+            }
+            Next::Game(GameEvent::Command {
+                id,
+                command: GameAdminEvent::Quit,
+            }) => {
+                // Answered once shutdown is complete.
+                self.pending_commands.push((id, GameAdminEvent::Quit));
+                return Some(Stop::Quit);
+            }
+            Next::Game(msg) => self.handle_game_event(msg).await,
+            Next::Custom(call) => call(self.swarm.behaviour_mut().custom() as &mut dyn Any),
+            Next::Flush => self.flush_outbound(),
+            Next::Migrate(migration) => self.migrate(migration).await,
+            Next::Deadline => self.handle_deadlines().await,
+        }
+        self.warn_relay_usage().await;
+        None
+    }
+
+    /// Tells the game about peers that went over a relay usage limit.
+    async fn warn_relay_usage(&mut self) {
+        let overused = self
+            .relay_usage
+            .overused(&self.config.relay_usage, Instant::now());
+        for overuse in overused {
+            self.log(
+                Severity::Info,
+                "relay",
+                Some(overuse.peer_id),
+                format!(
+                    "Relayed for {} s and {} bytes",
+                    overuse.relayed_for.as_secs(),
+                    overuse.bytes
+                ),
+            );
+            self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::RelayUsageWarning {
+                peer_id: overuse.peer_id,
+                relayed_for: overuse.relayed_for,
+                bytes: overuse.bytes,
+            }))
+            .await;
+        }
+    }
+
+    /// Joiners only query the DHT, so they stay in client mode and neither store
+    /// records nor show up in other peers' routing tables. Hosts must serve their
+    /// provider record, so they switch to server mode while hosting.
+    fn set_kad_mode(&mut self, mode: kad::Mode) {
+        if self.kad_mode == mode {
+            return;
+        }
+        self.swarm.behaviour_mut().kad.set_mode(Some(mode));
+        self.kad_mode = mode;
+        self.log(
+            Severity::Debug,
+            "kad",
+            None,
+            format!("Switched to {} mode", mode),
+        );
+    }
+
+    /// Says goodbye to the room and forgets about it. Does nothing outside a room.
+    fn leave_room(&mut self) {
+        self.joining = None;
+        self.claiming = None;
+        self.outbound.clear();
+        self.told_spectating.clear();
+        self.told_profile.clear();
+        if self.room_topic.is_none() {
+            return;
+        }
+        self.publish(&WireMessage::Control(ControlMessage::PlayerLeaving));
+        if self.hosting {
+            self.stop_hosting();
+        } else if let Some(topic) = self.room_topic.take() {
+            if let Err(e) = self.swarm.behaviour_mut().gossip.unsubscribe(&topic) {
+                log::debug!("Unsubscribing from room topic failed: {}", e);
+            }
+            self.clear_teams();
+            self.room_code = None;
+            self.room_host = None;
+            self.authority.clear();
+            self.acks.clear();
+            self.keys.replace(RoomKey::random());
+        }
+    }
+
+    /// Tells the room we are spectating once we hear from `source`, as peers that
+    /// joined after we said so, or before our room subscription settled, missed it.
+    fn tell_spectating(&mut self, source: PeerId) {
+        if self.spectating && self.told_spectating.insert(source) {
+            self.publish(&WireMessage::Control(ControlMessage::Spectating(true)));
+        }
+    }
+
+    /// Sends what the game still queued, tells the room we are leaving and gives the
+    /// swarm a moment to deliver all that.
+    async fn say_goodbye(&mut self) {
+        while let Some(message) = self.outbound.pop() {
+            self.send_outbound(message);
+        }
+        self.leave_room();
+        let drain = async {
+            loop {
+                let event = self.swarm.select_next_some().await;
+                self.handle_swarm_event(event).await;
+            }
+        };
+        let _ = async_std::future::timeout(SHUTDOWN_GRACE, drain).await;
+    }
+
+    /// Tells the room we are leaving, gives the swarm a moment to deliver that, and
+    /// confirms to the game that it can close.
+    async fn shutdown(&mut self) {
+        self.say_goodbye().await;
+        self.log(Severity::Info, "swarm", None, "Network shut down");
+        // The game may already be gone if it didn't wait for us.
+        let _ = self
+            .to_game
+            .send(NetworkEvent::Admin(NetworkAdminEvent::ShutdownComplete))
+            .await;
+    }
+
+    /// Hands `event` to the game, after answering the commands it settles.
+    async fn send_to_game(&mut self, event: NetworkEvent<ToGame>) {
+        if let NetworkEvent::Admin(admin) = &event {
+            for answer in self.settle_commands(admin) {
+                self.deliver(NetworkEvent::Admin(answer)).await;
+            }
+        }
+        self.deliver(event).await;
+    }
+
+    async fn deliver(&mut self, event: NetworkEvent<ToGame>) {
+        self.to_game
+            .send(event)
+            .await
+            .expect("Game side of the channel should outlive the network thread");
+    }
+
+    /// Records an entry in the game's log and mirrors it to the `log` crate.
+    fn log(
+        &mut self,
+        severity: Severity,
+        target: &'static str,
+        peer: Option<PeerId>,
+        message: impl Into<String>,
+    ) {
+        let message = message.into();
+        log::log!(severity.level(), "[{}] {}", target, message);
+        let entry = LogEntry {
+            seq: self.log_seq,
+            at: Instant::now(),
+            severity,
+            target,
+            peer,
+            message,
+        };
+        self.log_seq += 1;
+        // The log is best effort; it only fails once the game is gone.
+        let _ = self.log.try_send(entry);
+    }
+
+    async fn handle_swarm_event<E: std::fmt::Debug>(
+        &mut self,
+        event: SwarmEvent<BehaviourEvent<Custom>, E>,
+    ) {
+        match event {
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                connection_id,
+                endpoint,
+                ..
+            } => {
+                self.log(
+                    Severity::Debug,
+                    "swarm",
+                    Some(peer_id),
+                    format!("Connected over {:?}", endpoint),
+                );
+                self.dial_connected(connection_id, endpoint.get_remote_address());
+                self.connections
+                    .established(peer_id, connection_id, &endpoint);
+                self.select_connection_path(peer_id).await;
+                self.join_connected(peer_id, ConnectionPath::of(&endpoint))
+                    .await;
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                connection_id,
+                num_established,
+                ..
+            } => {
+                self.log(
+                    Severity::Debug,
+                    "swarm",
+                    Some(peer_id),
+                    format!("Connection {} closed", connection_id),
+                );
+                self.connections.closed(&peer_id, connection_id);
+                if num_established == 0 {
+                    self.acks.forget(&peer_id);
+                    if self.hosting {
+                        self.hold_slot(peer_id).await;
+                    }
+                    self.reported_paths.remove(&peer_id);
+                    self.relay_usage.forget(&peer_id);
+                    self.listen_addrs.remove(&peer_id);
+                    self.peer_secrets.forget(&peer_id);
+                    self.files.forget(&peer_id);
+                    self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::Disconnected(
+                        peer_id,
+                    )))
+                    .await;
+                } else {
+                    self.select_connection_path(peer_id).await;
+                }
+            }
+            SwarmEvent::NewListenAddr { address, .. } => {
+                self.log(
+                    Severity::Info,
+                    "swarm",
+                    None,
+                    format!("New listen addr: {}", address),
+                );
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::NewNetworkAddress(
+                    address,
+                )))
+                .await;
+            }
+            SwarmEvent::ListenerClosed {
+                listener_id,
+                reason: Err(e),
+                ..
+            } => self.host_listener_closed(listener_id, e.to_string()).await,
+            SwarmEvent::Behaviour(e) => self.handle_behaviour_event(e).await,
+            SwarmEvent::OutgoingConnectionError {
+                connection_id,
+                peer_id: Some(peer_id),
+                error,
+            } if self.bootstrap_peers.contains(&peer_id) => {
+                self.dial_failed(connection_id, &error);
+                self.log(
+                    Severity::Warn,
+                    "kad",
+                    Some(peer_id),
+                    format!("Bootstrap peer unreachable: {}", error),
+                );
+            }
+            SwarmEvent::OutgoingConnectionError {
+                connection_id,
+                peer_id,
+                error,
+            } => {
+                self.dial_failed(connection_id, &error);
+                self.log(
+                    Severity::Warn,
+                    "swarm",
+                    peer_id,
+                    format!("Outgoing connection failed: {}", error),
+                );
+            }
+            SwarmEvent::IncomingConnectionError { error, .. } => {
+                self.log(
+                    Severity::Warn,
+                    "swarm",
+                    None,
+                    format!("Incoming connection failed: {}", error),
+                );
+            }
+            event => self.log(Severity::Trace, "swarm", None, format!("{:?}", event)),
+        }
+        self.update_gauges();
+    }
+
+    /// Refreshes the peer counts in [`SwarmCounters`].
+    fn update_gauges(&self) {
+        let mesh_peers = self.room_topic.as_ref().map_or(0, |topic| {
+            self.swarm
+                .behaviour()
+                .gossip
+                .mesh_peers(&topic.hash())
+                .count()
+        });
+        self.counters
+            .peers
+            .store(self.swarm.connected_peers().count(), Ordering::Relaxed);
+        self.counters
+            .mesh_peers
+            .store(mesh_peers, Ordering::Relaxed);
+        self.counters
+            .relayed_peers
+            .store(self.connections.relayed(), Ordering::Relaxed);
+    }
+
+    /// Tells the game who gossipsub grafted into, or pruned from, our mesh since the
+    /// last call. Gossipsub doesn't report either, so we compare against the mesh we
+    /// last saw.
+    async fn report_mesh_changes(&mut self) {
+        let gossip = &self.swarm.behaviour().gossip;
+        let mesh: HashMap<_, _> = gossip
+            .topics()
+            .map(|topic| {
+                let peers: HashSet<_> = gossip.mesh_peers(topic).copied().collect();
+                (topic.clone(), peers)
+            })
+            .filter(|(_, peers)| !peers.is_empty())
+            .collect();
+        if mesh == self.reported_mesh {
+            return;
+        }
+        let reported = std::mem::replace(&mut self.reported_mesh, mesh);
+        let mut changes = Vec::new();
+        for (topic, peers) in &self.reported_mesh {
+            let before = reported.get(topic);
+            for peer_id in peers
+                .iter()
+                .filter(|peer| !before.map_or(false, |b| b.contains(peer)))
+            {
+                changes.push(NetworkAdminEvent::MeshGrafted {
+                    peer_id: *peer_id,
+                    topic: topic.to_string(),
+                });
+            }
+        }
+        for (topic, peers) in &reported {
+            let now = self.reported_mesh.get(topic);
+            for peer_id in peers
+                .iter()
+                .filter(|peer| !now.map_or(false, |n| n.contains(peer)))
+            {
+                changes.push(NetworkAdminEvent::MeshPruned {
+                    peer_id: *peer_id,
+                    topic: topic.to_string(),
+                });
+            }
+        }
+        // The host can hear us now, so tell it which room key we use.
+        let room_hash = self.room_topic.as_ref().map(gossipsub::IdentTopic::hash);
+        let host_grafted = changes.iter().any(|change| {
+            matches!(change, NetworkAdminEvent::MeshGrafted { peer_id, topic }
+                if Some(*peer_id) == self.room_host
+                    && room_hash.as_ref().map_or(false, |hash| hash.as_str() == topic))
+        });
+        if host_grafted {
+            self.ack_room_key();
+        }
+        for change in changes {
+            self.send_to_game(NetworkEvent::Admin(change)).await;
+        }
+    }
+
+    async fn handle_behaviour_event(&mut self, event: BehaviourEvent<Custom>) {
+        let target = match &event {
+            BehaviourEvent::Relay(_) => "relay",
+            BehaviourEvent::Dcutr(_) => "dcutr",
+            BehaviourEvent::Kad(_) => "kad",
+            BehaviourEvent::Gossip(_) => "gossip",
+            BehaviourEvent::Ping(_) => "ping",
+            BehaviourEvent::Identify(_) => "identify",
+            BehaviourEvent::Join(_) => "join",
+            BehaviourEvent::Invite(_) => "invite",
+            BehaviourEvent::Snapshot(_) => "snapshot",
+            BehaviourEvent::File(_) => "file",
+            BehaviourEvent::KeepAlive(never) => match *never {},
+            BehaviourEvent::Mdns(_) => "mdns",
+            BehaviourEvent::Rendezvous(_) => "rendezvous",
+            BehaviourEvent::Custom(_) => "custom",
+        };
+        self.log(Severity::Trace, target, None, format!("{:?}", event));
+        let updates: Vec<_> = self
+            .discoveries
+            .iter_mut()
+            .flat_map(|discovery| discovery.on_event(&event))
+            .collect();
+        self.handle_discovery_updates(updates).await;
+        match event {
+            BehaviourEvent::Custom(event) => {
+                // Only fails once the game is gone.
+                let _ = self.custom_events.send(event).await;
+            }
+            BehaviourEvent::Identify(identify::Event::Received { peer_id, info }) => {
+                self.observe_address(peer_id, info.observed_addr.clone())
+                    .await;
+                // Only peers of our own game advertise our protocol version.
+                if info.protocol_version == self.config.protocol.protocol(IDENTIFY_PROTOCOL) {
+                    self.swarm
+                        .behaviour_mut()
+                        .keep_alive
+                        .mark_game_peer(peer_id);
+                    self.listen_addrs.insert(peer_id, info.listen_addrs.clone());
+                    self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::Connected(peer_id)))
+                        .await;
+                    if let Some((path, relay)) = self.reported_paths.get(&peer_id).copied() {
+                        self.send_to_game(NetworkEvent::Admin(
+                            NetworkAdminEvent::ConnectionPathChanged {
+                                peer_id,
+                                path,
+                                relay,
+                            },
+                        ))
+                        .await;
+                    }
+                }
+
+                if info
+                    .protocols
+                    .contains(&StreamProtocol::new(RELAY_PROTOCOL))
+                {
+                    self.relay_offered(peer_id, &info.listen_addrs);
+                }
+            }
+            BehaviourEvent::Kad(kad::KademliaEvent::OutboundQueryProgressed {
+                result: kad::QueryResult::Bootstrap(result),
+                step,
+                ..
+            }) if step.last => self.bootstrap_finished(result).await,
+            BehaviourEvent::Join(event) => self.handle_join_event(event).await,
+            BehaviourEvent::Invite(event) => self.handle_invite_event(event).await,
+            BehaviourEvent::Snapshot(event) => self.handle_snapshot_event(event).await,
+            BehaviourEvent::File(event) => self.handle_file_event(event).await,
+            BehaviourEvent::Kad(kad::KademliaEvent::OutboundQueryProgressed {
+                id,
+                result: kad::QueryResult::GetClosestPeers(result),
+                step,
+                ..
+            }) => self.handle_closest_peers(id, result, step.last).await,
+            BehaviourEvent::Kad(kad::KademliaEvent::OutboundQueryProgressed {
+                id,
+                result: kad::QueryResult::GetProviders(result),
+                step,
+                ..
+            }) if self.finding_providers.contains_key(&id) => {
+                self.handle_providers(id, result, step.last).await
+            }
+            BehaviourEvent::Kad(kad::KademliaEvent::OutboundQueryProgressed {
+                id,
+                result: kad::QueryResult::PutRecord(result),
+                ..
+            }) if self.posting_invites.contains_key(&id) => {
+                self.handle_put_invite(id, result).await
+            }
+            BehaviourEvent::Kad(kad::KademliaEvent::OutboundQueryProgressed {
+                result: kad::QueryResult::PutRecord(result),
+                ..
+            }) => self.handle_room_info_put(result),
+            BehaviourEvent::Kad(kad::KademliaEvent::OutboundQueryProgressed {
+                id,
+                result: kad::QueryResult::GetRecord(result),
+                step,
+                ..
+            }) if self.checking_mailbox == Some(id) => {
+                self.handle_mailbox_record(id, result, step.last).await
+            }
+            BehaviourEvent::Kad(kad::KademliaEvent::OutboundQueryProgressed {
+                id,
+                result: kad::QueryResult::GetRecord(result),
+                step,
+                ..
+            }) => self.handle_room_info_record(id, result, step.last).await,
+            BehaviourEvent::Gossip(gossipsub::Event::Message {
+                propagation_source,
+                message,
+                ..
+            }) => {
+                let source = message.source.unwrap_or(propagation_source);
+                // The game already knows what it sent, so never hand it back.
+                if source == *self.swarm.local_peer_id() {
+                    log::debug!("Ignoring our own message echoed back");
+                } else if let Some(team) = self.team_of_topic(&message.topic) {
+                    self.handle_team_message(team, source, &message.data).await;
+                } else {
+                    self.handle_game_message(source, &message.data, self.config.message_ttl)
+                        .await;
+                }
+            }
+            BehaviourEvent::Gossip(
+                event @ (gossipsub::Event::Subscribed { .. }
+                | gossipsub::Event::Unsubscribed { .. }),
+            ) => {
+                // Our player count changed.
+                self.publish_room_info();
+                let event = match event {
+                    gossipsub::Event::Subscribed { peer_id, topic } => {
+                        NetworkAdminEvent::PeerSubscribed {
+                            peer_id,
+                            topic: topic.to_string(),
+                        }
+                    }
+                    gossipsub::Event::Unsubscribed { peer_id, topic } => {
+                        NetworkAdminEvent::PeerUnsubscribed {
+                            peer_id,
+                            topic: topic.to_string(),
+                        }
+                    }
+                    _ => unreachable!("Matched above"),
+                };
+                self.send_to_game(NetworkEvent::Admin(event)).await;
+            }
+            BehaviourEvent::Ping(ping::Event {
+                peer,
+                connection,
+                result: Ok(rtt),
+            }) => {
+                self.connections.record_rtt(&peer, connection, rtt);
+                self.relay_rtt(&peer, rtt);
+                self.select_connection_path(peer).await;
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::Latency {
+                    peer_id: peer,
+                    rtt,
+                }))
+                .await;
+            }
+            BehaviourEvent::Ping(ping::Event {
+                peer,
+                result: Err(e),
+                ..
+            }) => {
+                self.log(
+                    Severity::Debug,
+                    "ping",
+                    Some(peer),
+                    format!("Ping failed: {}", e),
+                );
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::PingFailed(peer)))
+                    .await;
+            }
+            BehaviourEvent::Dcutr(dcutr::Event::DirectConnectionUpgradeSucceeded {
+                remote_peer_id,
+            }) => {
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::HolePunchSucceeded(
+                    remote_peer_id,
+                )))
+                .await;
+            }
+            BehaviourEvent::Dcutr(dcutr::Event::DirectConnectionUpgradeFailed {
+                remote_peer_id,
+                error,
+            }) => {
+                self.log(
+                    Severity::Info,
+                    "dcutr",
+                    Some(remote_peer_id),
+                    format!("Hole punch failed: {}", error),
+                );
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::HolePunchFailed {
+                    peer_id: remote_peer_id,
+                    reason: error.to_string(),
+                }))
+                .await;
+            }
+            _ => {}
+        }
+    }
+
+    /// Counts `peer_id`'s view of our address, and advertises it once enough distinct
+    /// peers agree.
+    async fn observe_address(&mut self, peer_id: PeerId, address: Multiaddr) {
+        let Some(confirmations) = self.observed_addresses.report(address.clone(), peer_id) else {
+            return;
+        };
+        let confirmed = confirmations >= CONFIRMATIONS;
+        if confirmations == CONFIRMATIONS {
+            self.log(
+                Severity::Info,
+                "identify",
+                None,
+                format!("Confirmed external address {}", address),
+            );
+            self.swarm.add_external_address(address.clone());
+        }
+        self.send_to_game(NetworkEvent::Admin(
+            NetworkAdminEvent::ExternalAddressObserved {
+                address,
+                confirmations,
+                confirmed,
+            },
+        ))
+        .await;
+    }
+
+    /// Closes connections to `peer_id` that are beaten by a better path and tells the
+    /// game when the path we use changes.
+    async fn select_connection_path(&mut self, peer_id: PeerId) {
+        for connection_id in self.connections.redundant(&peer_id) {
+            self.log(
+                Severity::Debug,
+                "swarm",
+                Some(peer_id),
+                format!("Closing redundant connection {}", connection_id),
+            );
+            self.swarm.close_connection(connection_id);
+        }
+        let Some(path) = self.connections.preferred_path(&peer_id) else {
+            return;
+        };
+        self.relay_usage.path_changed(peer_id, path, Instant::now());
+        let relay = self.connections.relay(&peer_id);
+        if self.reported_paths.insert(peer_id, (path, relay)) != Some((path, relay)) {
+            self.send_to_game(NetworkEvent::Admin(
+                NetworkAdminEvent::ConnectionPathChanged {
+                    peer_id,
+                    path,
+                    relay,
+                },
+            ))
+            .await;
+        }
+    }
+
+    /// Handles a message published on our room's topic. Game messages older than `ttl`
+    /// are dropped.
+    async fn handle_game_message(&mut self, source: PeerId, data: &[u8], ttl: Option<Duration>) {
+        self.tape_message(TapeDirection::In(source), data);
+        if !self.count_incoming(source, data) {
+            return;
+        }
+        self.tell_spectating(source);
+        self.tell_profile(source);
+        let Some(message) = self.reassemble(source, data) else {
+            return;
+        };
+        match message {
+            Ok(WireMessage::Fragment(_)) => unreachable!("Reassembled above"),
+            Ok(WireMessage::Game { sent_at, event }) => {
+                if !self.is_stale(source, sent_at, ttl) {
+                    self.receive_game(source, event).await;
+                }
+            }
+            Ok(WireMessage::Latest {
+                sent_at,
+                seq,
+                acks,
+                event,
+            }) => {
+                let local = *self.swarm.local_peer_id();
+                if let Some((_, field)) = acks.into_iter().find(|(peer, _)| *peer == local) {
+                    let (acked, lost) = self.acks.acknowledge(source, field);
+                    if !acked.is_empty() || !lost.is_empty() {
+                        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::Delivery {
+                            peer_id: source,
+                            acked,
+                            lost,
+                        }))
+                        .await;
+                    }
+                }
+                if self.acks.receive(source, seq) && !self.is_stale(source, sent_at, ttl) {
+                    self.receive_game(source, event).await;
+                }
+            }
+            Ok(WireMessage::Control(message)) => self.handle_control_message(source, message).await,
+            Ok(WireMessage::Unicast { to, payload }) => {
+                if to != *self.swarm.local_peer_id() {
+                    return;
+                }
+                let decoded = self
+                    .peer_secrets
+                    .decrypt(&source, &payload)
+                    .and_then(|data| bincode::deserialize::<ToGame>(&data).map_err(Into::into));
+                match decoded {
+                    Ok(event) => self.receive_game(source, event).await,
+                    Err(e) => {
+                        log::warn!("Failed to open unicast from {}: {}", source, e);
+                        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::DecodeFailed(
+                            source,
+                        )))
+                        .await;
+                    }
+                }
+            }
+            Err(e) => {
+                log::warn!("Failed to decode message from {}: {}", source, e);
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::DecodeFailed(source)))
+                    .await;
+            }
+        }
+    }
+
+    /// Counts a message from `source`, returning whether it gets past its throttle.
+    fn count_incoming(&mut self, source: PeerId, data: &[u8]) -> bool {
+        self.counters.messages_in.fetch_add(1, Ordering::Relaxed);
+        self.relay_usage.count(&source, data.len());
+        if let Some(window) = self.throttled.get_mut(&source) {
+            if !window.allow() {
+                log::debug!("Dropping message from throttled peer {}", source);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Decodes a message from `source`, or `None` if it's a fragment of one that isn't
+    /// complete yet.
+    fn reassemble(
+        &mut self,
+        source: PeerId,
+        data: &[u8],
+    ) -> Option<anyhow::Result<WireMessage<ToGame>>> {
+        match bincode::deserialize::<WireMessage<ToGame>>(data) {
+            Ok(WireMessage::Fragment(fragment)) => {
+                match self.fragments.insert(source, fragment, Instant::now()) {
+                    // Fragments never nest, so the whole message can't be one either.
+                    Ok(Some(whole)) => Some(
+                        bincode::deserialize::<WireMessage<ToGame>>(&whole)
+                            .map_err(anyhow::Error::from)
+                            .and_then(|message| match message {
+                                WireMessage::Fragment(_) => Err(anyhow::anyhow!("nested fragment")),
+                                message => Ok(message),
+                            }),
+                    ),
+                    Ok(None) => None,
+                    Err(e) => Some(Err(e.into())),
+                }
+            }
+            message => Some(message.map_err(Into::into)),
+        }
+    }
+
+    /// Whether a game message from `source` stamped `sent_at` outlived `ttl`, counting
+    /// it if so. A peer that froze would otherwise replay its backlog of old state.
+    fn is_stale(&self, source: PeerId, sent_at: u64, ttl: Option<Duration>) -> bool {
+        let Some(ttl) = ttl else {
+            return false;
+        };
+        if !protocol::is_stale(sent_at, unix_millis(), ttl) {
+            return false;
+        }
+        log::debug!("Dropping a stale message from {}", source);
+        self.counters.stale_dropped.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    async fn handle_control_message(&mut self, source: PeerId, message: ControlMessage) {
+        match message {
+            ControlMessage::PlayerLeaving => {
+                self.acks.forget(&source);
+                self.told_profile.remove(&source);
+                if self.hosting {
+                    self.admitted.remove(&source);
+                    self.key_acks.remove(&source);
+                    self.reclaim_authority(source).await;
+                    self.move_to_team(source, None).await;
+                }
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::PlayerLeft(source)))
+                    .await;
+                return;
+            }
+            ControlMessage::Ready(ready) => {
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::PeerReady {
+                    peer_id: source,
+                    ready,
+                }))
+                .await;
+                return;
+            }
+            ControlMessage::Spectating(spectating) => {
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::PeerSpectating {
+                    peer_id: source,
+                    spectating,
+                }))
+                .await;
+                return;
+            }
+            ControlMessage::RoomKey { sealed, grace_ms } => {
+                self.handle_room_key(source, sealed, Duration::from_millis(grace_ms))
+                    .await;
+                return;
+            }
+            ControlMessage::KeyAck(fingerprint) => {
+                self.handle_key_ack(source, fingerprint);
+                if self.hosting {
+                    self.return_entities(source).await;
+                }
+                return;
+            }
+            ControlMessage::Heartbeat => {
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::Heartbeat(source)))
+                    .await;
+                return;
+            }
+            ControlMessage::Chat(text) => {
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::ChatReceived {
+                    from: source,
+                    text,
+                }))
+                .await;
+                return;
+            }
+            ControlMessage::Emote(emote) => {
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::EmoteReceived {
+                    from: source,
+                    emote,
+                }))
+                .await;
+                return;
+            }
+            ControlMessage::RequestAuthority(id) => {
+                if self.hosting {
+                    self.settle_authority(id, source).await;
+                }
+                return;
+            }
+            ControlMessage::ReleaseAuthority(id) => {
+                if self.hosting {
+                    self.returned_authority(id, source).await;
+                }
+                return;
+            }
+            ControlMessage::Profile(signed) => {
+                self.handle_profile(source, signed).await;
+                return;
+            }
+            ControlMessage::TeamKey {
+                team,
+                generation,
+                members,
+                sealed,
+            } => {
+                self.handle_team_key(source, team, generation, members, sealed)
+                    .await;
+                return;
+            }
+            ControlMessage::Conditions(conditions) => {
+                self.handle_conditions(source, conditions).await;
+                return;
+            }
+            _ => {}
+        }
+        if self.hosting {
+            log::warn!("Ignoring {:?} from {}: we are the host", message, source);
+            return;
+        }
+        let event = match message {
+            ControlMessage::StartGame {
+                seed,
+                countdown_ms,
+                tick,
+            } => {
+                if self.room_host != Some(source) {
+                    log::warn!("Ignoring a game start from {}: not our host", source);
+                    return;
+                }
+                NetworkAdminEvent::GameStarted {
+                    seed,
+                    countdown: Duration::from_millis(countdown_ms),
+                    tick,
+                }
+            }
+            ControlMessage::TickSettings(tick) => {
+                if self.room_host != Some(source) {
+                    log::warn!("Ignoring tick settings from {}: not our host", source);
+                    return;
+                }
+                NetworkAdminEvent::TickSettingsChanged(tick)
+            }
+            ControlMessage::NewRound { round, seed } => {
+                if self.room_host != Some(source) {
+                    log::warn!("Ignoring a new round from {}: not our host", source);
+                    return;
+                }
+                NetworkAdminEvent::RoundStarted { round, seed }
+            }
+            ControlMessage::Authority { id, owner } => {
+                let Some(host) = self.room_host.filter(|host| *host == source) else {
+                    log::warn!("Ignoring an authority change from {}: not our host", source);
+                    return;
+                };
+                self.authority.set(id, owner, host);
+                NetworkAdminEvent::AuthorityChanged { id, owner }
+            }
+            ControlMessage::RoomMute { peer_id, muted } => {
+                // Gossip is signed, so only the host itself can have sent this.
+                if self.room_host != Some(source) {
+                    log::warn!("Ignoring a room mute from {}: not our host", source);
+                    return;
+                }
+                NetworkAdminEvent::RoomMuted { peer_id, muted }
+            }
+            ControlMessage::AuthorityDenied {
+                id,
+                requester,
+                owner,
+            } => {
+                if self.room_host != Some(source) {
+                    log::warn!("Ignoring an authority denial from {}: not our host", source);
+                    return;
+                }
+                if requester != *self.swarm.local_peer_id() {
+                    return;
+                }
+                NetworkAdminEvent::AuthorityDenied { id, owner }
+            }
+            ControlMessage::PlayerLeaving
+            | ControlMessage::Ready(_)
+            | ControlMessage::Spectating(_)
+            | ControlMessage::RoomKey { .. }
+            | ControlMessage::KeyAck(_)
+            | ControlMessage::Heartbeat
+            | ControlMessage::Chat(_)
+            | ControlMessage::Emote(_)
+            | ControlMessage::RequestAuthority(_)
+            | ControlMessage::ReleaseAuthority(_)
+            | ControlMessage::Profile(_)
+            | ControlMessage::TeamKey { .. }
+            | ControlMessage::Conditions(_) => unreachable!("Handled above"),
+        };
+        self.send_to_game(NetworkEvent::Admin(event)).await;
+    }
+
+    async fn handle_game_event(&mut self, event: GameEvent<FromGame>) {
+        if self.spectating && !matches!(event, GameEvent::Admin(_) | GameEvent::Command { .. }) {
+            log::debug!("Spectating, dropping a game message");
+            return;
+        }
+        match event {
+            GameEvent::Admin(command) => self.handle_admin_event(command).await,
+            GameEvent::Command { id, command } => self.handle_command(id, command).await,
+            GameEvent::Game(event) => self
+                .outbound
+                .push(Priority::Normal, Outbound::Broadcast(event)),
+            GameEvent::Prioritized { priority, event } => {
+                self.outbound.push(priority, Outbound::Broadcast(event))
+            }
+            GameEvent::Latest { seq, event } => self
+                .outbound
+                .push(Priority::Normal, Outbound::Latest { seq, event }),
+            GameEvent::Unicast { to, event } => self
+                .outbound
+                .push(Priority::Normal, Outbound::Unicast { to, event }),
+            GameEvent::Team { team, event } => self
+                .outbound
+                .push(Priority::Normal, Outbound::Team { team, event }),
+        }
+    }
+
+    async fn handle_admin_event(&mut self, event: GameAdminEvent) {
+        if let Some(event) = self.throttle(event).await {
+            self.run_admin_event(event).await;
+        }
+    }
+
+    async fn run_admin_event(&mut self, event: GameAdminEvent) {
+        match event {
+            // Both take over the run loop, see `run_swarm`.
+            GameAdminEvent::Quit | GameAdminEvent::RestartNetwork { .. } => {}
+            GameAdminEvent::Leave => {
+                self.set_entered_with(None);
+                self.drop_queued_joins();
+                self.leave_room();
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::LeftRoom))
+                    .await;
+            }
+            GameAdminEvent::Host { room_code } => {
+                self.host(room_code.clone()).await;
+                self.set_entered_with(Some(GameAdminEvent::Host { room_code }));
+            }
+            GameAdminEvent::Join { room_code, name } => {
+                self.join(room_code.clone(), name.clone()).await;
+                self.set_entered_with(Some(GameAdminEvent::Join { room_code, name }));
+            }
+            GameAdminEvent::AnswerJoin { peer_id, accept } => self.answer_join(peer_id, accept),
+            GameAdminEvent::Throttle { peer_id, throttled } => {
+                if throttled {
+                    self.throttled
+                        .entry(peer_id)
+                        .or_insert_with(RateWindow::new);
+                } else {
+                    self.throttled.remove(&peer_id);
+                }
+            }
+            GameAdminEvent::Disconnect(peer_id) => {
+                log::info!("Disconnecting from {}", peer_id);
+                self.swarm.behaviour_mut().gossip.blacklist_peer(&peer_id);
+                if self.swarm.disconnect_peer_id(peer_id).is_err() {
+                    log::debug!("{} was not connected", peer_id);
+                }
+                // It may have passed our keys on, or still be listening with them.
+                if self.hosting && self.admitted.remove(&peer_id) {
+                    self.key_acks.remove(&peer_id);
+                    self.publish_room_info();
+                    self.rotate_keys_now(Some(peer_id)).await;
+                }
+            }
+            GameAdminEvent::FindPeer(peer_id) => self.find_peer(peer_id),
+            GameAdminEvent::Invite { peer_id, room_code } => self.send_invite(peer_id, room_code),
+            GameAdminEvent::PostInvite { peer_id, room_code } => {
+                self.post_invite(peer_id, room_code).await
+            }
+            GameAdminEvent::CheckMailbox => self.check_mailbox(),
+            GameAdminEvent::Dial(address) => self.dial(address),
+            GameAdminEvent::GetProviders(key) => self.find_providers(key),
+            GameAdminEvent::RotateRoomKey => self.rotate_room_key().await,
+            GameAdminEvent::RotateKeysNow => self.rotate_keys_now(None).await,
+            GameAdminEvent::SimulateLatency(latency) => self.set_simulated_latency(latency).await,
+            GameAdminEvent::RecordTape => self.record_tape(),
+            GameAdminEvent::SaveTape(path) => self.save_tape(path).await,
+            GameAdminEvent::PlayTape(path) => self.play_tape(path).await,
+            GameAdminEvent::SendFile { peer_id, path } => self.send_file(peer_id, path).await,
+            GameAdminEvent::AnswerFileOffer {
+                peer_id,
+                transfer,
+                accept,
+            } => self.answer_file_offer(peer_id, transfer, accept),
+            GameAdminEvent::RequestSnapshot => self.request_snapshot().await,
+            GameAdminEvent::AnswerSnapshot { peer_id, snapshot } => {
+                self.answer_snapshot(peer_id, snapshot)
+            }
+            GameAdminEvent::Heartbeat => {
+                if self.room_topic.is_some() {
+                    self.publish(&WireMessage::Control(ControlMessage::Heartbeat));
+                }
+            }
+            GameAdminEvent::Chat(text) => {
+                self.publish(&WireMessage::Control(ControlMessage::Chat(text)))
+            }
+            GameAdminEvent::Emote(emote) => {
+                self.publish(&WireMessage::Control(ControlMessage::Emote(emote)))
+            }
+            GameAdminEvent::RoomMute { peer_id, muted } => {
+                if !self.hosting {
+                    log::warn!("Only the host can mute {} for the room", peer_id);
+                    return;
+                }
+                self.publish(&WireMessage::Control(ControlMessage::RoomMute {
+                    peer_id,
+                    muted,
+                }));
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::RoomMuted {
+                    peer_id,
+                    muted,
+                }))
+                .await;
+            }
+            GameAdminEvent::RequestAuthority(id) => self.request_authority(id).await,
+            GameAdminEvent::ReleaseAuthority(id) => self.release_authority(id).await,
+            GameAdminEvent::TransferAuthority { id, owner } => {
+                if self.hosting {
+                    self.announce_authority(id, owner).await
+                } else {
+                    log::warn!("Only the host can hand out entity {}", id);
+                }
+            }
+            GameAdminEvent::DescribeRoom { mode, max_players } => {
+                self.room_description = room_info::RoomDescription { mode, max_players };
+                self.publish_room_info();
+            }
+            GameAdminEvent::SetModes(modes) => self.modes = modes,
+            GameAdminEvent::SetTeam { team, members } => {
+                if self.hosting {
+                    self.set_team(team, members).await
+                } else {
+                    log::warn!("Only the host can set up teams");
+                }
+            }
+            GameAdminEvent::MoveToTeam { peer_id, team } => {
+                if self.hosting {
+                    self.move_to_team(peer_id, team).await
+                } else {
+                    log::warn!("Only the host can move players between teams");
+                }
+            }
+            GameAdminEvent::StartGame {
+                seed,
+                countdown,
+                tick,
+            } => self.start_game(seed, countdown, tick).await,
+            GameAdminEvent::ReportConditions(conditions) => self.report_conditions(conditions),
+            GameAdminEvent::ChangeTickSettings(tick) => {
+                if self.hosting {
+                    self.change_tick_settings(tick).await
+                } else {
+                    log::warn!("Only the host can change the tick settings");
+                }
+            }
+            GameAdminEvent::SetSpectating(spectating) => {
+                self.spectating = spectating;
+                self.told_spectating.clear();
+                self.publish(&WireMessage::Control(ControlMessage::Spectating(
+                    spectating,
+                )));
+            }
+            GameAdminEvent::SetProfile(profile) => self.set_profile(profile),
+            GameAdminEvent::SetReady(ready) => {
+                self.publish(&WireMessage::Control(ControlMessage::Ready(ready)))
+            }
+            GameAdminEvent::NewRound { round, seed } => {
+                self.publish(&WireMessage::Control(ControlMessage::NewRound {
+                    round,
+                    seed,
+                }));
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::RoundStarted {
+                    round,
+                    seed,
+                }))
+                .await;
+            }
+        }
+    }
+
+    /// Publishes the next batch of queued game messages, highest priority first.
+    fn flush_outbound(&mut self) {
+        let backlog = self.outbound.len();
+        if backlog >= OUTBOUND_BACKLOG_WARNING {
+            log::warn!("{} game messages waiting to be sent", backlog);
+        }
+        for _ in 0..OUTBOUND_BATCH {
+            let Some(message) = self.outbound.pop() else {
+                break;
+            };
+            self.send_outbound(message);
+        }
+    }
+
+    fn send_outbound(&mut self, message: Outbound<FromGame>) {
+        match message {
+            Outbound::Broadcast(event) => self.publish(&WireMessage::Game {
+                sent_at: unix_millis(),
+                event: &event,
+            }),
+            Outbound::Latest { seq, event } => {
+                self.acks.sent(seq);
+                self.publish(&WireMessage::Latest {
+                    sent_at: unix_millis(),
+                    seq,
+                    acks: self.acks.fields(),
+                    event: &event,
+                });
+            }
+            Outbound::Unicast { to, event } => {
+                let payload = bincode::serialize(&event)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|data| self.peer_secrets.encrypt(&to, &data));
+                match payload {
+                    Ok(payload) => self.publish(&WireMessage::Unicast { to, payload }),
+                    Err(e) => log::error!("Failed to encrypt message for {}: {}", to, e),
+                }
+            }
+            Outbound::Team { team, event } => self.publish_team(&team, &event),
+        }
+    }
+
+    fn publish(&mut self, message: &WireMessage<&FromGame>) {
+        let Some(topic) = self.room_topic.clone() else {
+            log::warn!("Dropping message: not in a room");
+            return;
+        };
+        self.publish_to(topic, message);
+    }
+
+    /// Publishes `message` on `topic`, in fragments if it's too big for one message.
+    fn publish_to(&mut self, topic: gossipsub::IdentTopic, message: &WireMessage<&FromGame>) {
+        let data = match bincode::serialize(message) {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("Failed to encode message: {}", e);
+                return;
+            }
+        };
+        if data.len() > MAX_MESSAGE_SIZE {
+            log::error!(
+                "Dropping message: {} bytes is over the {} byte limit",
+                data.len(),
+                MAX_MESSAGE_SIZE
+            );
+            return;
+        }
+        if !fragment::needs_fragmenting(&data) {
+            self.publish_data(topic, data);
+            return;
+        }
+        let id = self.next_fragment_id;
+        self.next_fragment_id += 1;
+        for fragment in fragment::fragment(id, &data) {
+            let message = WireMessage::<&FromGame>::Fragment(fragment);
+            match bincode::serialize(&message) {
+                Ok(data) => self.publish_data(topic.clone(), data),
+                Err(e) => log::error!("Failed to encode fragment: {}", e),
+            }
+        }
+    }
+
+    fn publish_data(&mut self, topic: gossipsub::IdentTopic, data: Vec<u8>) {
+        for peer_id in self.swarm.behaviour().gossip.mesh_peers(&topic.hash()) {
+            self.relay_usage.count(peer_id, data.len());
+        }
+        self.tape_message(TapeDirection::Out, &data);
+        match self.swarm.behaviour_mut().gossip.publish(topic, data) {
+            Ok(_) => {
+                self.counters.messages_out.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => log::warn!("Failed to publish message: {}", e),
+        }
+    }
+}
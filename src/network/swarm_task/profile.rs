@@ -0,0 +1,102 @@
+//! Signed player profiles. Gossip is signed too, but only proves who relayed a message
+//! into the room; a signed profile also ties the name to its peer wherever it's copied.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use libp2p::PeerId;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+use crate::{
+    crypto::identity_public_key,
+    network::{
+        event_log::Severity,
+        protocol::{profile_payload, ControlMessage, PeerProfile, SignedProfile, WireMessage},
+        NetworkAdminEvent, NetworkEvent,
+    },
+};
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    /// Signs `profile` and tells the room about it.
+    pub(super) fn set_profile(&mut self, profile: PeerProfile) {
+        let peer_id = *self.swarm.local_peer_id();
+        let signed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as u64);
+        match self
+            .id_keys
+            .sign(&profile_payload(&peer_id, &profile, signed_at))
+        {
+            Ok(signature) => {
+                self.profile = Some(SignedProfile {
+                    peer_id,
+                    profile,
+                    signed_at,
+                    signature,
+                });
+                self.told_profile.clear();
+                self.publish_profile();
+            }
+            Err(e) => self.log(
+                Severity::Error,
+                "profile",
+                None,
+                format!("Signing our profile failed: {}", e),
+            ),
+        }
+    }
+
+    fn publish_profile(&mut self) {
+        if self.room_topic.is_none() {
+            return;
+        }
+        if let Some(profile) = self.profile.clone() {
+            self.publish(&WireMessage::Control(ControlMessage::Profile(profile)));
+        }
+    }
+
+    /// Tells the room our profile once we hear from `source`, like
+    /// [`tell_spectating`](Self::tell_spectating).
+    pub(super) fn tell_profile(&mut self, source: PeerId) {
+        if self.profile.is_some() && self.told_profile.insert(source) {
+            self.publish_profile();
+        }
+    }
+
+    pub(super) async fn handle_profile(&mut self, source: PeerId, signed: SignedProfile) {
+        let payload = profile_payload(&signed.peer_id, &signed.profile, signed.signed_at);
+        let problem = if signed.peer_id != source {
+            Some(format!(
+                "Profile of {} relayed by {}",
+                signed.peer_id, source
+            ))
+        } else if !identity_public_key(&source)
+            .map_or(false, |key| key.verify(&payload, &signed.signature))
+        {
+            Some("Bad signature on profile".to_string())
+        } else if self
+            .profile_versions
+            .get(&source)
+            .map_or(false, |&last| signed.signed_at < last)
+        {
+            Some("Profile older than the one we have".to_string())
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            self.log(Severity::Warn, "profile", Some(source), problem);
+            return;
+        }
+        self.profile_versions.insert(source, signed.signed_at);
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::ProfileReceived {
+            peer_id: source,
+            profile: signed.profile,
+        }))
+        .await;
+    }
+}
@@ -0,0 +1,183 @@
+//! Host only: places kept for peers that dropped without saying goodbye, most likely
+//! because their game crashed. A peer that comes back with the same identity within
+//! [`JoinLimits::reclaim_window`](crate::network::JoinLimits::reclaim_window) proves
+//! it's the same player through the join challenge, so it's let in again as itself
+//! instead of showing up as a second player.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use libp2p::PeerId;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+use crate::network::{event_log::Severity, NetworkAdminEvent, NetworkEvent};
+
+/// Slots held for dropped peers, and the entities they get back once they return.
+#[derive(Debug, Default)]
+pub(super) struct HeldSlots {
+    held: HashMap<PeerId, (Instant, Vec<u64>)>,
+    /// Peers let back in, waiting to hear the room before their entities are handed
+    /// back.
+    returning: HashMap<PeerId, Vec<u64>>,
+}
+
+impl HeldSlots {
+    pub(super) fn hold(&mut self, peer: PeerId, entities: Vec<u64>, now: Instant) {
+        self.returning.remove(&peer);
+        self.held.insert(peer, (now, entities));
+    }
+
+    pub(super) fn is_held(&self, peer: &PeerId) -> bool {
+        self.held.contains_key(peer)
+    }
+
+    /// Slots taken by peers that haven't come back yet.
+    pub(super) fn len(&self) -> usize {
+        self.held.len()
+    }
+
+    /// `peer` is back. False if it had no slot held.
+    pub(super) fn reclaim(&mut self, peer: PeerId) -> bool {
+        let Some((_, entities)) = self.held.remove(&peer) else {
+            return false;
+        };
+        self.returning.insert(peer, entities);
+        true
+    }
+
+    /// The entities to hand back to `peer`, once.
+    pub(super) fn returned(&mut self, peer: &PeerId) -> Vec<u64> {
+        self.returning.remove(peer).unwrap_or_default()
+    }
+
+    pub(super) fn next_expiry(&self, window: Duration) -> Option<Instant> {
+        self.held.values().map(|(since, _)| *since + window).min()
+    }
+
+    /// Frees the slots held for longer than `window`, returning whose they were.
+    pub(super) fn expire(&mut self, window: Duration, now: Instant) -> Vec<PeerId> {
+        let expired: Vec<_> = self
+            .held
+            .iter()
+            .filter(|(_, (since, _))| now.saturating_duration_since(*since) >= window)
+            .map(|(peer, _)| *peer)
+            .collect();
+        for peer in &expired {
+            self.held.remove(peer);
+        }
+        expired
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.held.clear();
+        self.returning.clear();
+    }
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    /// Host only: `peer` dropped. Its entities go back to us for now, and if it was
+    /// in the room its slot is held for it.
+    pub(super) async fn hold_slot(&mut self, peer: PeerId) {
+        let local = *self.swarm.local_peer_id();
+        let entities = self.authority.forget(peer);
+        for id in &entities {
+            self.announce_authority(*id, local).await;
+        }
+        if !self.admitted.remove(&peer) {
+            return;
+        }
+        self.key_acks.remove(&peer);
+        self.held_slots.hold(peer, entities, Instant::now());
+        self.log(
+            Severity::Info,
+            "join",
+            Some(peer),
+            format!(
+                "Holding its slot for {:?} in case it comes back",
+                self.config.join_limits.reclaim_window
+            ),
+        );
+        self.publish_room_info();
+    }
+
+    /// Host only: whether `peer`, which just proved its identity, may take back a
+    /// slot. Covers peers back before we noticed they dropped, too.
+    pub(super) async fn reclaims_slot(&mut self, peer: PeerId) -> bool {
+        if self.admitted.contains(&peer) {
+            self.hold_slot(peer).await;
+        }
+        self.held_slots.reclaim(peer)
+    }
+
+    /// Host only: `peer` reclaimed its slot and hears the room now, so it gets back
+    /// what it simulated before dropping.
+    pub(super) async fn return_entities(&mut self, peer: PeerId) {
+        let local = *self.swarm.local_peer_id();
+        for id in self.held_slots.returned(&peer) {
+            if self.authority.owner(id, local) != local {
+                continue;
+            }
+            self.authority.set(id, peer, local);
+            self.announce_authority(id, peer).await;
+        }
+    }
+
+    /// Host only: frees the slots of peers that didn't come back in time.
+    pub(super) async fn release_expired_slots(&mut self) {
+        let window = self.config.join_limits.reclaim_window;
+        let expired = self.held_slots.expire(window, Instant::now());
+        if expired.is_empty() {
+            return;
+        }
+        for peer_id in expired {
+            self.log(
+                Severity::Info,
+                "join",
+                Some(peer_id),
+                "Did not come back, freeing its slot",
+            );
+            self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::SlotReleased(
+                peer_id,
+            )))
+            .await;
+        }
+        self.publish_room_info();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_are_reclaimed_once_and_expire() {
+        let window = Duration::from_secs(60);
+        let start = Instant::now();
+        let (back, gone) = (PeerId::random(), PeerId::random());
+        let mut slots = HeldSlots::default();
+        slots.hold(back, vec![1, 2], start);
+        slots.hold(gone, Vec::new(), start + Duration::from_secs(10));
+        assert_eq!(slots.len(), 2);
+        assert_eq!(slots.next_expiry(window), Some(start + window));
+
+        assert!(slots.reclaim(back));
+        assert!(!slots.reclaim(back));
+        assert!(!slots.is_held(&back));
+        assert_eq!(slots.returned(&back), vec![1, 2]);
+        assert!(slots.returned(&back).is_empty());
+
+        assert!(slots.expire(window, start + window).is_empty());
+        let later = start + window + Duration::from_secs(10);
+        assert_eq!(slots.expire(window, later), vec![gone]);
+        assert_eq!(slots.len(), 0);
+        assert!(!slots.reclaim(gone));
+    }
+}
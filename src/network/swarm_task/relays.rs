@@ -0,0 +1,176 @@
+//! Finds relays on the DHT for hosts to listen on, instead of always using
+//! [`NetworkConfig::relay`](crate::network::NetworkConfig::relay). Peers that identify
+//! with the relay hop protocol become candidates, ranked by the ping round trip to
+//! them, and the fastest is where the next host listener reserves a circuit.
+
+use std::{collections::HashMap, net::IpAddr, time::Duration};
+
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+use crate::network::{event_log::Severity, RelaySelection};
+
+/// A relay found on the DHT.
+struct Candidate {
+    address: Multiaddr,
+    /// The best ping round trip so far, once there was one.
+    rtt: Option<Duration>,
+}
+
+#[derive(Default)]
+pub(super) struct RelayCandidates(HashMap<PeerId, Candidate>);
+
+impl RelayCandidates {
+    /// Remembers `peer_id` as a relay, dialed at the first of `listen_addrs` joiners
+    /// elsewhere can reach. Returns whether it's new.
+    fn offer(&mut self, peer_id: PeerId, listen_addrs: &[Multiaddr]) -> bool {
+        if self.0.contains_key(&peer_id) {
+            return false;
+        }
+        let Some(address) = listen_addrs.iter().find(|address| is_public(address)) else {
+            return false;
+        };
+        let address = match address.iter().last() {
+            Some(Protocol::P2p(_)) => address.clone(),
+            _ => address.clone().with(Protocol::P2p(peer_id)),
+        };
+        self.0.insert(peer_id, Candidate { address, rtt: None });
+        true
+    }
+
+    fn record_rtt(&mut self, peer_id: &PeerId, rtt: Duration) {
+        if let Some(candidate) = self.0.get_mut(peer_id) {
+            candidate.rtt = Some(candidate.rtt.map_or(rtt, |best| best.min(rtt)));
+        }
+    }
+
+    /// The address of the relay with the lowest round trip, once any answered a ping.
+    fn best(&self) -> Option<&Multiaddr> {
+        self.0
+            .values()
+            .filter_map(|candidate| Some((candidate.rtt?, &candidate.address)))
+            .min_by_key(|(rtt, _)| *rtt)
+            .map(|(_, address)| address)
+    }
+}
+
+/// Whether `address` is a direct one peers on other networks could dial.
+fn is_public(address: &Multiaddr) -> bool {
+    let mut ip = None;
+    for protocol in address.iter() {
+        match protocol {
+            Protocol::P2pCircuit => return false,
+            Protocol::Ip4(address) => ip = Some(IpAddr::V4(address)),
+            Protocol::Ip6(address) => ip = Some(IpAddr::V6(address)),
+            Protocol::Dns(_) | Protocol::Dns4(_) | Protocol::Dns6(_) | Protocol::Dnsaddr(_) => {
+                return true
+            }
+            _ => {}
+        }
+    }
+    match ip {
+        Some(IpAddr::V4(address)) => {
+            !(address.is_private()
+                || address.is_loopback()
+                || address.is_link_local()
+                || address.is_unspecified())
+        }
+        Some(IpAddr::V6(address)) => {
+            !(address.is_loopback()
+                || address.is_unspecified()
+                || (address.segments()[0] & 0xfe00) == 0xfc00
+                || (address.segments()[0] & 0xffc0) == 0xfe80)
+        }
+        None => false,
+    }
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    /// Walks the DHT towards a random key, so we connect to, and identify, peers that
+    /// may be relays.
+    pub(super) fn discover_relays(&mut self) {
+        if self.config.relay_selection != RelaySelection::Discover || self.config.lan_only {
+            return;
+        }
+        self.swarm
+            .behaviour_mut()
+            .kad
+            .get_closest_peers(PeerId::random());
+    }
+
+    /// `peer_id` identified with the relay hop protocol.
+    pub(super) fn relay_offered(&mut self, peer_id: PeerId, listen_addrs: &[Multiaddr]) {
+        if self.relays.offer(peer_id, listen_addrs) {
+            self.log(
+                Severity::Debug,
+                "identify",
+                Some(peer_id),
+                "Supports relay, added as a candidate",
+            );
+        }
+    }
+
+    pub(super) fn relay_rtt(&mut self, peer_id: &PeerId, rtt: Duration) {
+        self.relays.record_rtt(peer_id, rtt);
+    }
+
+    /// The relay the next host listener reserves a circuit on.
+    pub(super) fn pick_relay(&mut self) -> Multiaddr {
+        let discovered = match self.config.relay_selection {
+            RelaySelection::Manual => None,
+            RelaySelection::Discover => self.relays.best().cloned(),
+        };
+        match discovered {
+            Some(relay) => {
+                self.log(
+                    Severity::Info,
+                    "relay",
+                    None,
+                    format!("Using the fastest relay found: {}", relay),
+                );
+                relay
+            }
+            None => self.relay.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_fastest_public_relay() {
+        let mut relays = RelayCandidates::default();
+        let (near, far, hidden) = (PeerId::random(), PeerId::random(), PeerId::random());
+        assert!(!relays.offer(hidden, &["/ip4/192.168.1.2/tcp/4001".parse().unwrap()]));
+        assert!(relays.offer(
+            far,
+            &[
+                "/ip4/127.0.0.1/tcp/4001".parse().unwrap(),
+                "/ip4/1.2.3.4/tcp/4001".parse().unwrap(),
+            ]
+        ));
+        assert!(relays.offer(near, &["/dns4/relay.example/tcp/4001".parse().unwrap()]));
+        assert_eq!(relays.best(), None);
+
+        relays.record_rtt(&far, Duration::from_millis(120));
+        let far_address: Multiaddr = format!("/ip4/1.2.3.4/tcp/4001/p2p/{}", far)
+            .parse()
+            .unwrap();
+        assert_eq!(relays.best(), Some(&far_address));
+
+        relays.record_rtt(&near, Duration::from_millis(30));
+        relays.record_rtt(&near, Duration::from_millis(300));
+        assert_eq!(
+            relays.best().and_then(|address| address.iter().last()),
+            Some(Protocol::P2p(near))
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Replaces the swarm on [`GameAdminEvent::RestartNetwork`] while the game keeps
+//! running. Everything the game set up that lives in the task carries over, and what
+//! lives in the swarm, i.e. the room, is redone on the new one. The game's custom
+//! behaviour is built afresh, so whatever state it held is lost.
+
+use std::sync::Arc;
+
+use libp2p::{identity, Multiaddr};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, GameChannels, SwarmTask};
+use crate::network::{
+    build_swarm, event_log::Severity, GameAdminEvent, NetworkAdminEvent, NetworkEvent,
+    RelaySelection,
+};
+
+/// A [`GameAdminEvent::RestartNetwork`] taken out of the run loop.
+pub(super) struct Restart {
+    pub(super) new_identity: bool,
+    pub(super) relay: Option<Multiaddr>,
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    /// Builds the new task, or keeps this one going if that fails.
+    pub(super) async fn restart(mut self, restart: Restart) -> Self {
+        let id_keys = if restart.new_identity {
+            identity::Keypair::generate_ed25519()
+        } else {
+            self.id_keys.clone()
+        };
+        let mut config = self.config.clone();
+        if let Some(relay) = restart.relay {
+            config.relay = relay;
+            config.relay_selection = RelaySelection::Manual;
+        }
+        let channels = GameChannels {
+            to_game: self.to_game.clone(),
+            from_game: self.from_game.clone(),
+            log: self.log.clone(),
+            custom_events: self.custom_events.clone(),
+            custom_calls: self.custom_calls.clone(),
+        };
+        let make_custom = Arc::clone(&self.make_custom);
+        let built = match build_swarm(&id_keys, &config, &make_custom).await {
+            Ok((swarm, keys, bandwidth)) => {
+                SwarmTask::new(swarm, &id_keys, keys, &config, channels, make_custom)
+                    .map(|task| (task, bandwidth))
+            }
+            Err(e) => Err(e.into()),
+        };
+        let (mut fresh, bandwidth) = match built {
+            Ok(built) => built,
+            Err(e) => {
+                let reason = format!("Could not restart the network: {}", e);
+                self.log(Severity::Error, "swarm", None, reason.clone());
+                self.send_to_game(NetworkEvent::Admin(
+                    NetworkAdminEvent::NetworkRestartFailed { reason },
+                ))
+                .await;
+                return self;
+            }
+        };
+
+        // Decided before saying goodbye, which forgets the room.
+        let in_room =
+            self.room_topic.is_some() || self.joining.is_some() || self.claiming.is_some();
+        // Hosting again publishes the room as described before.
+        fresh.redo = self
+            .entered_with
+            .take()
+            .filter(|_| in_room)
+            .into_iter()
+            .collect();
+        self.say_goodbye().await;
+
+        // Whatever only the old swarm could have answered won't be answered now.
+        let (restarts, abandoned): (Vec<_>, Vec<_>) = self
+            .pending_commands
+            .drain(..)
+            .partition(|(_, command)| matches!(command, GameAdminEvent::RestartNetwork { .. }));
+        for (id, _) in abandoned {
+            self.send_to_game(NetworkEvent::Admin(super::commands::answer(
+                id,
+                Err("The network restarted".to_string()),
+            )))
+            .await;
+        }
+        fresh.pending_commands = restarts;
+
+        fresh.counters = self.counters;
+        fresh.bandwidth = self.bandwidth;
+        fresh.bandwidth.push(bandwidth);
+        self.shared_keys.set(fresh.keys.clone());
+        fresh.shared_keys = self.shared_keys;
+        fresh.liveness = self.liveness;
+        fresh.liveness.set_entered_with(fresh.redo.first().cloned());
+        fresh.log_seq = self.log_seq;
+        fresh.master_secret = self.master_secret;
+        fresh.throttled = self.throttled;
+        fresh.spectating = self.spectating;
+        if let Some(signed) = self.profile {
+            // Signed again, as a new identity can't use the old signature.
+            fresh.set_profile(signed.profile);
+        }
+        fresh.profile_versions = self.profile_versions;
+        fresh.simulated_latency = self.simulated_latency;
+        fresh.delayed = self.delayed;
+        fresh.tape = self.tape;
+        fresh.tape_player = self.tape_player;
+        fresh.mailbox_seen = self.mailbox_seen;
+        fresh.room_description = self.room_description;
+        fresh.modes = self.modes;
+
+        let local_peer_id = *fresh.swarm.local_peer_id();
+        fresh.log(
+            Severity::Info,
+            "swarm",
+            None,
+            format!("Network restarted as {}", local_peer_id),
+        );
+        fresh
+            .send_to_game(NetworkEvent::Admin(NetworkAdminEvent::NetworkRestarted {
+                local_peer_id,
+            }))
+            .await;
+        fresh
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{dialer::DialRequest, CustomBehaviour, SwarmTask};
+use crate::network::{event_log::Severity, NetworkAdminEvent, NetworkEvent};
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    /// Runs [`resume`](Self::resume) if the machine slept since the last check.
+    pub(super) async fn check_suspend(&mut self) {
+        let now = Instant::now();
+        if now < self.suspend.next_check() {
+            return;
+        }
+        if let Some(slept) = self.suspend.check(now, SystemTime::now()) {
+            self.resume(slept).await;
+        }
+    }
+
+    /// Picks up where we left off after the machine slept for `slept`. Like a
+    /// [`migrate`](Self::migrate), except our records may have expired too: the room's
+    /// announcements and relay reservation are renewed and the DHT bootstrapped again.
+    async fn resume(&mut self, slept: Duration) {
+        self.log(
+            Severity::Warn,
+            "swarm",
+            None,
+            format!("Woke up after sleeping for about {:?}", slept),
+        );
+        self.connections.mark_stale();
+        if self.hosting {
+            match self.relisten() {
+                Ok(failures) => self.report_listen_failures(failures).await,
+                Err(e) => {
+                    self.hosting_failed(format!("Could not listen after waking up: {}", e))
+                        .await;
+                }
+            }
+        }
+        if let Some(room_code) = self.room_code.clone().filter(|_| self.hosting) {
+            if let Err(e) = self.announce_room(&room_code) {
+                self.log(Severity::Warn, "swarm", None, e.to_string());
+            }
+            self.published_info = None;
+            self.publish_room_info();
+        }
+        let mut redialed = self.room_peers();
+        redialed.extend(self.room_host);
+        redialed.extend(self.admitted.iter().copied());
+        redialed.sort();
+        redialed.dedup();
+        for &peer_id in &redialed {
+            self.queue_dial(DialRequest::peer(peer_id).even_if_connected());
+        }
+        self.bootstrap().await;
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::Resumed {
+            slept,
+            redialed,
+        }))
+        .await;
+    }
+}
@@ -0,0 +1,179 @@
+//! The [`RoomInfo`] record hosts keep on the DHT next to their provider record.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use libp2p::kad::{
+    self, GetRecordError, GetRecordOk, PutRecordError, PutRecordOk, QueryId, RecordKey,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+use crate::{
+    crypto::identity_public_key,
+    network::{
+        event_log::Severity,
+        protocol::{room_info_payload, RoomInfo, SignedRoomInfo, PROTOCOL_VERSION},
+        NetworkAdminEvent, NetworkEvent, ProtocolNames,
+    },
+};
+
+/// Records expire unless republished, so a crashed host's room stops showing up.
+const ROOM_INFO_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// DHT key of the [`RoomInfo`] for `room_code`.
+pub(super) fn room_info_key(names: &ProtocolNames, room_code: &str) -> RecordKey {
+    RecordKey::new(&names.key("room-info", room_code).as_bytes())
+}
+
+/// What the game told us to publish about the room we host.
+#[derive(Debug, Clone, Default)]
+pub(super) struct RoomDescription {
+    pub(super) mode: String,
+    pub(super) max_players: Option<u32>,
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    /// The room we host as it looks right now.
+    pub(super) fn room_info(&self) -> RoomInfo {
+        RoomInfo {
+            mode: self.room_description.mode.clone(),
+            players: (self.room_peers().len() + self.held_slots.len()) as u32 + 1,
+            max_players: self.room_description.max_players,
+            version: PROTOCOL_VERSION.to_string(),
+            started: self.game_started,
+        }
+    }
+
+    /// Signs and stores the current [`RoomInfo`], unless it's what we last published.
+    /// Does nothing unless we host a room and the DHT is in use.
+    pub(super) fn publish_room_info(&mut self) {
+        if !self.hosting || self.bootstrap_peers.is_empty() {
+            return;
+        }
+        let Some(room_code) = self.room_code.clone() else {
+            return;
+        };
+        let info = self.room_info();
+        if self.published_info.as_ref() == Some(&info) {
+            return;
+        }
+        let published_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        let signed = self
+            .id_keys
+            .sign(&room_info_payload(&room_code, &info, published_at))
+            .map_err(anyhow::Error::from)
+            .and_then(|signature| {
+                Ok(bincode::serialize(&SignedRoomInfo {
+                    host: *self.swarm.local_peer_id(),
+                    info: info.clone(),
+                    published_at,
+                    signature,
+                })?)
+            });
+        let value = match signed {
+            Ok(value) => value,
+            Err(e) => {
+                self.log(
+                    Severity::Error,
+                    "kad",
+                    None,
+                    format!("Could not sign the room info: {}", e),
+                );
+                return;
+            }
+        };
+        let record = kad::Record {
+            key: room_info_key(&self.config.protocol, &room_code),
+            value,
+            publisher: None,
+            expires: Some(Instant::now() + ROOM_INFO_TTL),
+        };
+        match self
+            .swarm
+            .behaviour_mut()
+            .kad
+            .put_record(record, kad::Quorum::One)
+        {
+            Ok(_) => self.published_info = Some(info),
+            Err(e) => self.log(
+                Severity::Warn,
+                "kad",
+                None,
+                format!("Could not store the room info: {:?}", e),
+            ),
+        }
+    }
+
+    pub(super) fn handle_room_info_put(&mut self, result: Result<PutRecordOk, PutRecordError>) {
+        if let Err(e) = result {
+            // Joiners just won't see the info; the room is still findable.
+            self.log(
+                Severity::Warn,
+                "kad",
+                None,
+                format!("Publishing the room info failed: {}", e),
+            );
+        }
+    }
+
+    /// Joiner only: records what a candidate host says about the room.
+    pub(super) async fn handle_room_info_record(
+        &mut self,
+        query: QueryId,
+        result: Result<GetRecordOk, GetRecordError>,
+        last: bool,
+    ) {
+        let Some(pending) = self.joining.as_mut() else {
+            return;
+        };
+        // Late answers are still welcome, but mustn't end a newer lookup.
+        if last && pending.info_lookup == Some(query) {
+            pending.info_lookup = None;
+        }
+        let room_code = pending.room_code.clone();
+        let record = match result {
+            Ok(GetRecordOk::FoundRecord(found)) => found.record,
+            Ok(GetRecordOk::FinishedWithNoAdditionalRecord { .. }) => {
+                self.try_next_candidate().await;
+                return;
+            }
+            Err(e) => {
+                log::debug!("No room info for {}: {}", room_code, e);
+                self.try_next_candidate().await;
+                return;
+            }
+        };
+        let verified = bincode::deserialize::<SignedRoomInfo>(&record.value)
+            .map_err(anyhow::Error::from)
+            .and_then(|signed| {
+                let payload = room_info_payload(&room_code, &signed.info, signed.published_at);
+                anyhow::ensure!(
+                    identity_public_key(&signed.host)?.verify(&payload, &signed.signature),
+                    "bad signature from {}",
+                    signed.host
+                );
+                Ok(signed)
+            });
+        match verified {
+            Ok(SignedRoomInfo { host, info, .. }) => {
+                if let Some(pending) = self.joining.as_mut() {
+                    pending.infos.insert(host, info.clone());
+                }
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::RoomInfoFound {
+                    host,
+                    info,
+                }))
+                .await;
+            }
+            Err(e) => log::debug!("Ignoring room info for {}: {}", room_code, e),
+        }
+        self.try_next_candidate().await;
+    }
+}
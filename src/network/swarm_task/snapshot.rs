@@ -0,0 +1,131 @@
+//! Catching late joiners up: the joiner asks the host for the game state over
+//! [`SNAPSHOT_PROTOCOL`](crate::network::SNAPSHOT_PROTOCOL) right after getting in,
+//! and the host's game answers with a [`Snapshot`].
+
+use libp2p::{
+    request_response::{self, Message},
+    PeerId,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+use crate::network::{
+    event_log::Severity,
+    protocol::{Snapshot, SnapshotRequest, SnapshotResponse},
+    NetworkAdminEvent, NetworkEvent,
+};
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    pub(super) async fn request_snapshot(&mut self) {
+        let Some(host) = self.room_host else {
+            self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::SnapshotFailed {
+                reason: "Not in a room".to_string(),
+            }))
+            .await;
+            return;
+        };
+        self.swarm
+            .behaviour_mut()
+            .snapshot
+            .send_request(&host, SnapshotRequest);
+    }
+
+    pub(super) fn answer_snapshot(&mut self, peer_id: PeerId, snapshot: Option<Snapshot>) {
+        let Some(channel) = self.snapshot_requests.remove(&peer_id) else {
+            log::warn!("No pending snapshot request from {}", peer_id);
+            return;
+        };
+        if snapshot.is_some() {
+            // Ownership isn't part of the game's state, so the joiner hears it again.
+            self.republish_authority();
+        }
+        let response = snapshot.map_or(SnapshotResponse::Refused, SnapshotResponse::Snapshot);
+        if self
+            .swarm
+            .behaviour_mut()
+            .snapshot
+            .send_response(channel, response)
+            .is_err()
+        {
+            log::warn!("{} left before we sent it the game state", peer_id);
+        }
+    }
+
+    pub(super) async fn handle_snapshot_event(
+        &mut self,
+        event: request_response::Event<SnapshotRequest, SnapshotResponse>,
+    ) {
+        match event {
+            request_response::Event::Message {
+                peer,
+                message: Message::Request { channel, .. },
+            } => {
+                // Only the peers we let in get to see the game.
+                let response = if !self.hosting || !self.admitted.contains(&peer) {
+                    SnapshotResponse::Refused
+                } else if !self.game_started {
+                    SnapshotResponse::NotStarted
+                } else {
+                    self.snapshot_requests.insert(peer, channel);
+                    self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::SnapshotRequested(
+                        peer,
+                    )))
+                    .await;
+                    return;
+                };
+                let _ = self
+                    .swarm
+                    .behaviour_mut()
+                    .snapshot
+                    .send_response(channel, response);
+            }
+            request_response::Event::Message {
+                peer,
+                message: Message::Response { response, .. },
+            } => {
+                if self.room_host != Some(peer) {
+                    log::warn!("Ignoring a snapshot from {}: not our host", peer);
+                    return;
+                }
+                let event = match response {
+                    SnapshotResponse::Snapshot(snapshot) => {
+                        self.log(
+                            Severity::Info,
+                            "snapshot",
+                            Some(peer),
+                            format!(
+                                "Caught up to tick {} ({} bytes)",
+                                snapshot.tick,
+                                snapshot.state.len()
+                            ),
+                        );
+                        NetworkAdminEvent::SnapshotReceived(Some(snapshot))
+                    }
+                    SnapshotResponse::NotStarted => NetworkAdminEvent::SnapshotReceived(None),
+                    SnapshotResponse::Refused => NetworkAdminEvent::SnapshotFailed {
+                        reason: "The host refused".to_string(),
+                    },
+                };
+                self.send_to_game(NetworkEvent::Admin(event)).await;
+            }
+            request_response::Event::OutboundFailure { peer, error, .. } => {
+                self.log(
+                    Severity::Warn,
+                    "snapshot",
+                    Some(peer),
+                    format!("Could not get the game state: {}", error),
+                );
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::SnapshotFailed {
+                    reason: error.to_string(),
+                }))
+                .await;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Host only: holds [`GameAdminEvent::StartGame`](crate::network::GameAdminEvent::StartGame)
+//! back until every admitted peer is in our gossip mesh for the room and confirmed it
+//! holds the current room key. Starting earlier loses the first seconds of messages
+//! to peers the mesh doesn't reach yet, or that can't decrypt them.
+//!
+//! While peers wait, they report their [`LinkConditions`] so the host's game can pick
+//! [`TickSettings`] for the start, and change them later if a link gets worse.
+
+use std::{collections::HashSet, time::Duration};
+
+use libp2p::PeerId;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+use crate::network::{
+    event_log::Severity,
+    protocol::{ControlMessage, WireMessage},
+    LinkConditions, NetworkAdminEvent, NetworkEvent, TickSettings,
+};
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    /// Admitted peers that are still connected but not yet in our room mesh, or
+    /// haven't acknowledged the current room key.
+    fn unready_peers(&self) -> Vec<PeerId> {
+        let Some(topic) = &self.room_topic else {
+            return Vec::new();
+        };
+        let meshed: HashSet<_> = self
+            .swarm
+            .behaviour()
+            .gossip
+            .mesh_peers(&topic.hash())
+            .copied()
+            .collect();
+        self.admitted
+            .iter()
+            .filter(|peer_id| self.swarm.is_connected(peer_id))
+            .filter(|peer_id| !meshed.contains(peer_id) || !self.key_acks.contains(peer_id))
+            .copied()
+            .collect()
+    }
+
+    /// Starts the game now if the room is ready, and otherwise as soon as it is.
+    pub(super) async fn start_game(
+        &mut self,
+        seed: u64,
+        countdown: Duration,
+        tick: Option<TickSettings>,
+    ) {
+        let waiting = self.unready_peers();
+        if waiting.is_empty() {
+            self.publish_start(seed, countdown, tick).await;
+            return;
+        }
+        self.log(
+            Severity::Info,
+            "gossip",
+            None,
+            format!("Holding the start back for {} peers", waiting.len()),
+        );
+        self.pending_start = Some((seed, countdown, tick));
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::StartDelayed {
+            waiting,
+        }))
+        .await;
+    }
+
+    /// Sends the start held back by [`start_game`](Self::start_game) once the room is
+    /// ready for it.
+    pub(super) async fn check_start_barrier(&mut self) {
+        let Some((seed, countdown, tick)) = self.pending_start else {
+            return;
+        };
+        if self.unready_peers().is_empty() {
+            self.pending_start = None;
+            self.publish_start(seed, countdown, tick).await;
+        }
+    }
+
+    async fn publish_start(&mut self, seed: u64, countdown: Duration, tick: Option<TickSettings>) {
+        self.game_started = true;
+        self.publish_room_info();
+        self.publish(&WireMessage::Control(ControlMessage::StartGame {
+            seed,
+            countdown_ms: countdown.as_millis() as u64,
+            tick,
+        }));
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::GameStarted {
+            seed,
+            countdown,
+            tick,
+        }))
+        .await;
+    }
+
+    /// Joiner only: tells the host what we measured.
+    pub(super) fn report_conditions(&mut self, conditions: LinkConditions) {
+        if self.hosting || self.room_topic.is_none() {
+            return;
+        }
+        self.publish(&WireMessage::Control(ControlMessage::Conditions(
+            conditions,
+        )));
+    }
+
+    /// Host only: passes what an admitted peer measured on to the game.
+    pub(super) async fn handle_conditions(&mut self, source: PeerId, conditions: LinkConditions) {
+        if !self.hosting || !self.admitted.contains(&source) {
+            return;
+        }
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::ConditionsReported {
+            peer_id: source,
+            conditions,
+        }))
+        .await;
+    }
+
+    /// Host only: switches the game in progress to `tick`.
+    pub(super) async fn change_tick_settings(&mut self, tick: TickSettings) {
+        self.log(
+            Severity::Info,
+            "gossip",
+            None,
+            format!(
+                "Switching to {} ticks per second with {} ticks of input delay",
+                tick.tick_rate, tick.input_delay
+            ),
+        );
+        self.publish(&WireMessage::Control(ControlMessage::TickSettings(tick)));
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::TickSettingsChanged(
+            tick,
+        )))
+        .await;
+    }
+
+    /// Joiner only: tells the host which room key we encrypt with.
+    pub(super) fn ack_room_key(&mut self) {
+        if self.hosting || self.room_topic.is_none() {
+            return;
+        }
+        let fingerprint = self.keys.current_key().fingerprint();
+        self.publish(&WireMessage::Control(ControlMessage::KeyAck(fingerprint)));
+    }
+
+    /// Host only: `source` acknowledged a room key, which counts if it's the current one.
+    pub(super) fn handle_key_ack(&mut self, source: PeerId, fingerprint: [u8; 8]) {
+        if !self.hosting || !self.admitted.contains(&source) {
+            return;
+        }
+        if fingerprint == self.keys.current_key().fingerprint() {
+            self.key_acks.insert(source);
+        } else {
+            log::debug!("{} acknowledged an old room key", source);
+        }
+    }
+}
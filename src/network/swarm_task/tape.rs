@@ -0,0 +1,129 @@
+//! Recording room messages to a [`Tape`] and playing one back.
+
+use std::{path::PathBuf, time::Instant};
+
+use libp2p::PeerId;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+use crate::{
+    at_rest,
+    network::{
+        event_log::Severity,
+        tape::{Tape, TapeDirection, TapePlayer, TapeRecorder},
+        NetworkAdminEvent, NetworkEvent,
+    },
+};
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    pub(super) fn record_tape(&mut self) {
+        self.tape = Some(TapeRecorder::new(
+            self.config.tape.max_entries,
+            Instant::now(),
+        ));
+        self.log(Severity::Info, "tape", None, "Recording");
+    }
+
+    /// Puts `data` on the tape, if we are recording.
+    pub(super) fn tape_message(&mut self, direction: TapeDirection, data: &[u8]) {
+        if let Some(tape) = self.tape.as_mut() {
+            tape.record(direction, data, Instant::now());
+        }
+    }
+
+    pub(super) async fn save_tape(&mut self, path: PathBuf) {
+        let Some(tape) = self.tape.take() else {
+            self.tape_failed(path, "Not recording".to_string()).await;
+            return;
+        };
+        let tape = tape.finish();
+        let entries = tape.entries.len();
+        let saved = tape
+            .encode()
+            .and_then(|contents| at_rest::write(&path, &contents, &self.config.tape.encryption));
+        match saved {
+            Ok(()) => {
+                self.log(
+                    Severity::Info,
+                    "tape",
+                    None,
+                    format!("Saved {} messages to {}", entries, path.display()),
+                );
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::TapeSaved {
+                    path,
+                    entries,
+                }))
+                .await;
+            }
+            Err(e) => self.tape_failed(path, e.to_string()).await,
+        }
+    }
+
+    pub(super) async fn play_tape(&mut self, path: PathBuf) {
+        let loaded = at_rest::read_to_string(&path, &self.config.tape.encryption)
+            .and_then(|contents| Tape::decode(&contents));
+        match loaded {
+            Ok(tape) => {
+                self.log(
+                    Severity::Info,
+                    "tape",
+                    None,
+                    format!("Playing {}", path.display()),
+                );
+                self.tape_player = Some((path, TapePlayer::new(tape, Instant::now())));
+                self.play_due_tape().await;
+            }
+            Err(e) => self.tape_failed(path, e.to_string()).await,
+        }
+    }
+
+    pub(super) fn next_tape_entry(&self) -> Option<Instant> {
+        self.tape_player
+            .as_ref()
+            .and_then(|(_, player)| player.next_due())
+    }
+
+    /// Feeds in the tape's messages that are due, as if they just came in. They are
+    /// old by now, so the message TTL doesn't apply.
+    pub(super) async fn play_due_tape(&mut self) {
+        let Some((_, player)) = self.tape_player.as_mut() else {
+            return;
+        };
+        let due: Vec<(PeerId, Vec<u8>)> = player.due(Instant::now());
+        for (source, data) in due {
+            self.handle_game_message(source, &data, None).await;
+        }
+        if self
+            .tape_player
+            .as_ref()
+            .map_or(false, |(_, player)| player.is_done())
+        {
+            if let Some((path, player)) = self.tape_player.take() {
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::TapePlayed {
+                    path,
+                    entries: player.played,
+                }))
+                .await;
+            }
+        }
+    }
+
+    async fn tape_failed(&mut self, path: PathBuf, reason: String) {
+        self.log(
+            Severity::Warn,
+            "tape",
+            None,
+            format!("{}: {}", path.display(), reason),
+        );
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::TapeFailed {
+            path,
+            reason,
+        }))
+        .await;
+    }
+}
@@ -0,0 +1,303 @@
+//! Teams: topics inside a room that only their members can read. The host derives a
+//! key per team from its [`MasterSecret`](crate::crypto::MasterSecret) and hands it to
+//! the members, and switches to a new one whenever a member leaves or joins.
+
+use std::collections::HashMap;
+
+use libp2p::{gossipsub, PeerId};
+use serde::{de::DeserializeOwned, Serialize};
+use zeroize::Zeroizing;
+
+use super::{unix_millis, CustomBehaviour, SwarmTask};
+use crate::{
+    crypto::{PeerCiphertext, RoomKey},
+    network::{
+        event_log::Severity,
+        protocol::{ControlMessage, WireMessage},
+        NetworkAdminEvent, NetworkEvent, ProtocolNames,
+    },
+};
+
+/// The room code and the team's name follow in team topics.
+fn team_topic(names: &ProtocolNames, room_code: &str, team: &str) -> gossipsub::IdentTopic {
+    gossipsub::IdentTopic::new(names.key("team", format!("{}/{}", room_code, team)))
+}
+
+/// A team in our room, as the host last described it.
+pub(super) struct Team {
+    members: Vec<PeerId>,
+    /// Goes up with every new key. Disbanded teams are kept around with no members, so
+    /// a team set up again under the same name doesn't reuse an old key.
+    generation: u64,
+    topic: gossipsub::IdentTopic,
+}
+
+#[derive(Default)]
+pub(super) struct Teams(HashMap<String, Team>);
+
+impl Teams {
+    /// The team whose topic `hash` is.
+    fn by_topic(&self, hash: &gossipsub::TopicHash) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(_, team)| team.topic.hash() == *hash)
+            .map(|(name, _)| name.as_str())
+    }
+
+    fn members(&self, team: &str) -> &[PeerId] {
+        self.0.get(team).map_or(&[], |team| &team.members)
+    }
+}
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    /// Host only: puts `members` on `team` under a new key.
+    pub(super) async fn set_team(&mut self, team: String, members: Vec<PeerId>) {
+        let Some(room_code) = self.room_code.clone() else {
+            log::warn!("Not setting up team {}: not in a room", team);
+            return;
+        };
+        let generation = self
+            .teams
+            .0
+            .get(&team)
+            .map_or(0, |team| team.generation + 1);
+        let key = self.master_secret.team_key(&room_code, &team, generation);
+        let bytes = key.to_bytes();
+        let local_peer_id = *self.swarm.local_peer_id();
+        let mut sealed = Vec::new();
+        for &peer_id in members.iter().filter(|peer_id| **peer_id != local_peer_id) {
+            match self.peer_secrets.encrypt(&peer_id, bytes.as_slice()) {
+                Ok(ciphertext) => sealed.push((peer_id, ciphertext)),
+                Err(e) => self.log(
+                    Severity::Warn,
+                    "gossip",
+                    Some(peer_id),
+                    format!("Could not share the key of team {}: {}", team, e),
+                ),
+            }
+        }
+        self.publish(&WireMessage::Control(ControlMessage::TeamKey {
+            team: team.clone(),
+            generation,
+            members: members.clone(),
+            sealed,
+        }));
+        let key = members.contains(&local_peer_id).then_some(key);
+        self.apply_team(team, generation, members, key).await;
+    }
+
+    /// Host only: takes `peer_id` off its teams and puts it on `team`, if any.
+    pub(super) async fn move_to_team(&mut self, peer_id: PeerId, team: Option<String>) {
+        let left: Vec<(String, Vec<PeerId>)> = self
+            .teams
+            .0
+            .iter()
+            .filter(|(name, _)| Some(*name) != team.as_ref())
+            .filter(|(_, team)| team.members.contains(&peer_id))
+            .map(|(name, team)| {
+                let members = team.members.iter().copied().filter(|m| *m != peer_id);
+                (name.clone(), members.collect())
+            })
+            .collect();
+        for (name, members) in left {
+            self.set_team(name, members).await;
+        }
+        if let Some(team) = team {
+            let mut members = self.teams.members(&team).to_vec();
+            if !members.contains(&peer_id) {
+                members.push(peer_id);
+                self.set_team(team, members).await;
+            }
+        }
+    }
+
+    /// Switches to the key of a team the host changed, or drops the team if we are not
+    /// on it anymore.
+    pub(super) async fn handle_team_key(
+        &mut self,
+        source: PeerId,
+        team: String,
+        generation: u64,
+        members: Vec<PeerId>,
+        sealed: Vec<(PeerId, PeerCiphertext)>,
+    ) {
+        if self.room_host != Some(source) {
+            log::warn!("Ignoring a team key from {}: not our host", source);
+            return;
+        }
+        if self
+            .teams
+            .0
+            .get(&team)
+            .map_or(false, |known| generation <= known.generation)
+        {
+            log::debug!("Ignoring an old key for team {}", team);
+            return;
+        }
+        let local_peer_id = *self.swarm.local_peer_id();
+        let key = sealed
+            .into_iter()
+            .find(|(peer_id, _)| *peer_id == local_peer_id)
+            .and_then(|(_, ciphertext)| {
+                let key = self
+                    .peer_secrets
+                    .decrypt(&source, &ciphertext)
+                    .ok()
+                    .map(Zeroizing::new)
+                    .and_then(|bytes| RoomKey::from_bytes(&bytes));
+                if key.is_none() {
+                    log::warn!("Host sent a malformed key for team {}", team);
+                }
+                key
+            });
+        if key.is_none() && members.contains(&local_peer_id) {
+            self.log(
+                Severity::Warn,
+                "gossip",
+                Some(source),
+                format!("The host put us on team {} without its key", team),
+            );
+        }
+        self.apply_team(team, generation, members, key).await;
+    }
+
+    /// Host only: puts every team under a new key, taking `suspect` off them.
+    pub(super) async fn rekey_teams(&mut self, suspect: Option<PeerId>) {
+        let teams: Vec<(String, Vec<PeerId>)> = self
+            .teams
+            .0
+            .iter()
+            .filter(|(_, team)| !team.members.is_empty())
+            .map(|(name, team)| {
+                let members = team.members.iter().copied();
+                let members = members.filter(|member| Some(*member) != suspect);
+                (name.clone(), members.collect())
+            })
+            .collect();
+        for (name, members) in teams {
+            self.set_team(name, members).await;
+        }
+    }
+
+    /// Reads and writes `team`'s topic with `key`, or leaves it without one.
+    async fn apply_team(
+        &mut self,
+        team: String,
+        generation: u64,
+        members: Vec<PeerId>,
+        key: Option<RoomKey>,
+    ) {
+        let Some(room_code) = self.room_code.as_deref() else {
+            return;
+        };
+        let topic = team_topic(&self.config.protocol, room_code, &team);
+        let gossip = &mut self.swarm.behaviour_mut().gossip;
+        match key {
+            Some(key) => {
+                self.keys.add_topic_key(topic.hash(), key);
+                if let Err(e) = gossip.subscribe(&topic) {
+                    log::warn!("Could not subscribe to team {}: {}", team, e);
+                }
+            }
+            None => {
+                if let Err(e) = gossip.unsubscribe(&topic) {
+                    log::debug!("Unsubscribing from team {} failed: {}", team, e);
+                }
+                self.keys.remove_topic_key(&topic.hash());
+            }
+        }
+        self.teams.0.insert(
+            team.clone(),
+            Team {
+                members: members.clone(),
+                generation,
+                topic,
+            },
+        );
+        self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::TeamChanged {
+            team,
+            members,
+        }))
+        .await;
+    }
+
+    /// Leaves every team topic, as the room they are in is gone.
+    pub(super) fn clear_teams(&mut self) {
+        for (name, team) in self.teams.0.drain() {
+            let topic_hash = team.topic.hash();
+            self.keys.remove_topic_key(&topic_hash);
+            if let Err(e) = self.swarm.behaviour_mut().gossip.unsubscribe(&team.topic) {
+                log::debug!("Unsubscribing from team {} failed: {}", name, e);
+            }
+        }
+    }
+
+    /// The team whose topic `hash` is, if it's one of ours.
+    pub(super) fn team_of_topic(&self, hash: &gossipsub::TopicHash) -> Option<String> {
+        self.teams.by_topic(hash).map(str::to_string)
+    }
+
+    /// Publishes `event` on `team`'s topic, if we are on the team.
+    pub(super) fn publish_team(&mut self, team: &str, event: &FromGame) {
+        let local_peer_id = *self.swarm.local_peer_id();
+        let Some(topic) = self
+            .teams
+            .0
+            .get(team)
+            .filter(|team| team.members.contains(&local_peer_id))
+            .map(|team| team.topic.clone())
+        else {
+            log::warn!("Dropping message to team {}: we are not on it", team);
+            return;
+        };
+        self.publish_to(
+            topic,
+            &WireMessage::Game {
+                sent_at: unix_millis(),
+                event,
+            },
+        );
+    }
+
+    /// Handles a message published on one of our teams' topics. Only game messages
+    /// are sent there.
+    pub(super) async fn handle_team_message(&mut self, team: String, source: PeerId, data: &[u8]) {
+        if !self.count_incoming(source, data) {
+            return;
+        }
+        match self.reassemble(source, data) {
+            None => {}
+            Some(Ok(WireMessage::Game { sent_at, event }))
+                if !self.is_stale(source, sent_at, self.config.message_ttl) =>
+            {
+                self.send_to_game(NetworkEvent::Team {
+                    team,
+                    source,
+                    event,
+                })
+                .await;
+            }
+            Some(Ok(WireMessage::Game { .. })) => {}
+            Some(Ok(_)) => log::warn!(
+                "Ignoring a non-game message from {} on team {}",
+                source,
+                team
+            ),
+            Some(Err(e)) => {
+                log::warn!(
+                    "Failed to decode message from {} on team {}: {}",
+                    source,
+                    team,
+                    e
+                );
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::DecodeFailed(source)))
+                    .await;
+            }
+        }
+    }
+}
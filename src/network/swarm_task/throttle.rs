@@ -0,0 +1,175 @@
+//! Client side limits on joins and DHT lookups, and the host's limit on join
+//! handshakes. See [`JoinLimits`].
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use libp2p::PeerId;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CustomBehaviour, SwarmTask};
+use crate::network::{
+    config::JoinLimits, GameAdminEvent, JoinError, NetworkAdminEvent, NetworkEvent,
+};
+
+/// Commands that start DHT queries on the game's behalf, and so wait for a free
+/// query slot.
+fn queries_dht(command: &GameAdminEvent) -> bool {
+    matches!(
+        command,
+        GameAdminEvent::Join { .. }
+            | GameAdminEvent::FindPeer(_)
+            | GameAdminEvent::PostInvite { .. }
+            | GameAdminEvent::GetProviders(_)
+    )
+}
+
+/// Enforces [`JoinLimits::cooldown`] between our joins.
+pub(super) struct JoinCooldown {
+    cooldown: Duration,
+    last: Option<Instant>,
+}
+
+impl JoinCooldown {
+    pub(super) fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            last: None,
+        }
+    }
+
+    /// Starts a join at `now`, or says how long until one may start.
+    fn start(&mut self, now: Instant) -> Result<(), Duration> {
+        if let Some(last) = self.last {
+            let ready_at = last + self.cooldown;
+            if now < ready_at {
+                return Err(ready_at - now);
+            }
+        }
+        self.last = Some(now);
+        Ok(())
+    }
+}
+
+/// Host only: counts join handshakes per peer over fixed windows.
+pub(super) struct HandshakeLimiter {
+    per_peer: u32,
+    window: Duration,
+    attempts: HashMap<PeerId, (Instant, u32)>,
+}
+
+impl HandshakeLimiter {
+    pub(super) fn new(limits: &JoinLimits) -> Self {
+        Self {
+            per_peer: limits.handshakes_per_peer,
+            window: limits.handshake_window,
+            attempts: HashMap::new(),
+        }
+    }
+
+    /// Records a handshake from `peer` and returns whether it is within the limit.
+    fn allow(&mut self, peer: PeerId, now: Instant) -> bool {
+        let window = self.window;
+        // Forget peers whose window is over so the map can't grow without bound.
+        self.attempts
+            .retain(|_, (started, _)| now.duration_since(*started) < window);
+        let (_, attempts) = self.attempts.entry(peer).or_insert((now, 0));
+        *attempts += 1;
+        *attempts <= self.per_peer
+    }
+}
+
+/// Lookups waiting for a free DHT query slot, oldest first.
+#[derive(Default)]
+pub(super) struct QueuedLookups(VecDeque<GameAdminEvent>);
+
+impl<FromGame, ToGame, Custom> SwarmTask<FromGame, ToGame, Custom>
+where
+    FromGame: Serialize + Send + 'static,
+    ToGame: DeserializeOwned + Send + 'static,
+    Custom: CustomBehaviour,
+{
+    /// Holds `command` back if it's a join too soon after the last one, or a lookup
+    /// while the DHT is as busy as we let it get. Returns the command if it can run now.
+    pub(super) async fn throttle(&mut self, command: GameAdminEvent) -> Option<GameAdminEvent> {
+        if let GameAdminEvent::Join { .. } = command {
+            if let Err(retry_in) = self.join_cooldown.start(Instant::now()) {
+                log::info!("Join throttled, retry in {:?}", retry_in);
+                self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::JoinFailed(
+                    JoinError::Throttled { retry_in },
+                )))
+                .await;
+                return None;
+            }
+        }
+        if queries_dht(&command) && !self.dht_query_free() {
+            self.queued_lookups.0.push_back(command);
+            let queued = self.queued_lookups.0.len();
+            log::debug!("DHT busy, {} lookups queued", queued);
+            self.send_to_game(NetworkEvent::Admin(NetworkAdminEvent::DhtBusy { queued }))
+                .await;
+            return None;
+        }
+        Some(command)
+    }
+
+    fn dht_query_free(&self) -> bool {
+        self.swarm.behaviour().kad.iter_queries().count() < self.max_dht_queries
+    }
+
+    /// Runs queued lookups for as long as query slots are free.
+    pub(super) async fn run_queued_lookups(&mut self) {
+        while !self.queued_lookups.0.is_empty() && self.dht_query_free() {
+            if let Some(command) = self.queued_lookups.0.pop_front() {
+                self.run_admin_event(command).await;
+            }
+        }
+    }
+
+    /// Leaving drops the queued join, which was the room being left.
+    pub(super) fn drop_queued_joins(&mut self) {
+        self.queued_lookups
+            .0
+            .retain(|command| !matches!(command, GameAdminEvent::Join { .. }));
+    }
+
+    /// Host only: whether `peer` may start another join handshake.
+    pub(super) fn allow_handshake(&mut self, peer: PeerId) -> bool {
+        self.handshakes.allow(peer, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_wait_for_the_cooldown() {
+        let now = Instant::now();
+        let mut cooldown = JoinCooldown::new(Duration::from_secs(2));
+        assert_eq!(cooldown.start(now), Ok(()));
+        assert_eq!(
+            cooldown.start(now + Duration::from_millis(500)),
+            Err(Duration::from_millis(1500))
+        );
+        assert_eq!(cooldown.start(now + Duration::from_secs(2)), Ok(()));
+    }
+
+    #[test]
+    fn handshakes_are_limited_per_peer_and_window() {
+        let now = Instant::now();
+        let mut limiter = HandshakeLimiter::new(&JoinLimits {
+            handshakes_per_peer: 2,
+            handshake_window: Duration::from_secs(10),
+            ..JoinLimits::default()
+        });
+        let peer = PeerId::random();
+        assert!(limiter.allow(peer, now));
+        assert!(limiter.allow(peer, now));
+        assert!(!limiter.allow(peer, now + Duration::from_secs(1)));
+        assert!(limiter.allow(PeerId::random(), now + Duration::from_secs(1)));
+        assert!(limiter.allow(peer, now + Duration::from_secs(10)));
+    }
+}
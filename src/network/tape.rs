@@ -0,0 +1,191 @@
+//! Network tapes: every room message we sent or got, with when, for reproducing
+//! protocol bugs offline. See [`GameAdminEvent::RecordTape`](super::GameAdminEvent::RecordTape).
+//!
+//! Messages are taped as encoded envelopes, after the room key came off, so a tape
+//! plays back anywhere. Saved tapes go through [`at_rest`](crate::at_rest), sealed per
+//! [`TapeConfig::encryption`](super::TapeConfig::encryption).
+
+use std::{
+    collections::VecDeque,
+    io,
+    time::{Duration, Instant},
+};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+use crate::at_rest::{from_hex, to_hex};
+
+/// Bumped whenever [`TapeEntry`] changes, as old tapes won't decode anymore.
+const TAPE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum TapeDirection {
+    In(PeerId),
+    Out,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct TapeEntry {
+    /// Since the recording started.
+    pub at: Duration,
+    pub direction: TapeDirection,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct Tape {
+    version: u32,
+    pub entries: Vec<TapeEntry>,
+}
+
+impl Tape {
+    /// The tape as a file's contents.
+    pub(super) fn encode(&self) -> io::Result<String> {
+        bincode::serialize(self)
+            .map(|bytes| to_hex(&bytes))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub(super) fn decode(contents: &str) -> io::Result<Self> {
+        let tape: Self = bincode::deserialize(&from_hex(contents.trim())?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if tape.version != TAPE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Tape version {} is not {}", tape.version, TAPE_VERSION),
+            ));
+        }
+        Ok(tape)
+    }
+}
+
+/// The tape being recorded. Past `max_entries` the oldest entries make room, so the
+/// moments before a bug are always on it.
+#[derive(Debug)]
+pub(super) struct TapeRecorder {
+    started: Instant,
+    entries: VecDeque<TapeEntry>,
+    max_entries: usize,
+}
+
+impl TapeRecorder {
+    pub(super) fn new(max_entries: usize, now: Instant) -> Self {
+        Self {
+            started: now,
+            entries: VecDeque::new(),
+            max_entries,
+        }
+    }
+
+    pub(super) fn record(&mut self, direction: TapeDirection, data: &[u8], now: Instant) {
+        if self.max_entries == 0 {
+            return;
+        }
+        if self.entries.len() == self.max_entries {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(TapeEntry {
+            at: now.saturating_duration_since(self.started),
+            direction,
+            data: data.to_vec(),
+        });
+    }
+
+    pub(super) fn finish(self) -> Tape {
+        Tape {
+            version: TAPE_VERSION,
+            entries: self.entries.into(),
+        }
+    }
+}
+
+/// Plays the messages we got on a tape back at the pace they came in. What we sent
+/// ourselves is skipped.
+#[derive(Debug)]
+pub(super) struct TapePlayer {
+    started: Instant,
+    /// The first entry's time, so playback starts right away.
+    offset: Duration,
+    entries: VecDeque<(Duration, PeerId, Vec<u8>)>,
+    pub played: usize,
+}
+
+impl TapePlayer {
+    pub(super) fn new(tape: Tape, now: Instant) -> Self {
+        let entries: VecDeque<_> = tape
+            .entries
+            .into_iter()
+            .filter_map(|entry| match entry.direction {
+                TapeDirection::In(source) => Some((entry.at, source, entry.data)),
+                TapeDirection::Out => None,
+            })
+            .collect();
+        Self {
+            started: now,
+            offset: entries.front().map_or(Duration::ZERO, |(at, ..)| *at),
+            entries,
+            played: 0,
+        }
+    }
+
+    pub(super) fn next_due(&self) -> Option<Instant> {
+        self.entries
+            .front()
+            .map(|(at, ..)| self.started + at.saturating_sub(self.offset))
+    }
+
+    /// The messages due by `now`, in the order they came in.
+    pub(super) fn due(&mut self, now: Instant) -> Vec<(PeerId, Vec<u8>)> {
+        let mut due = Vec::new();
+        while self.next_due().map_or(false, |at| at <= now) {
+            if let Some((_, source, data)) = self.entries.pop_front() {
+                due.push((source, data));
+            }
+        }
+        self.played += due.len();
+        due
+    }
+
+    pub(super) fn is_done(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_and_plays_back_what_came_in() {
+        let start = Instant::now();
+        let peer = PeerId::random();
+        let mut recorder = TapeRecorder::new(3, start);
+        recorder.record(TapeDirection::In(peer), b"dropped", start);
+        recorder.record(
+            TapeDirection::In(peer),
+            b"first",
+            start + Duration::from_secs(1),
+        );
+        recorder.record(TapeDirection::Out, b"ours", start + Duration::from_secs(2));
+        recorder.record(
+            TapeDirection::In(peer),
+            b"second",
+            start + Duration::from_secs(3),
+        );
+        let tape = Tape::decode(&recorder.finish().encode().unwrap()).unwrap();
+        assert_eq!(tape.entries.len(), 3);
+
+        let now = Instant::now();
+        let mut player = TapePlayer::new(tape, now);
+        assert_eq!(player.next_due(), Some(now));
+        assert_eq!(player.due(now), vec![(peer, b"first".to_vec())]);
+        assert!(player.due(now + Duration::from_secs(1)).is_empty());
+        assert_eq!(
+            player.due(now + Duration::from_secs(2)),
+            vec![(peer, b"second".to_vec())]
+        );
+        assert!(player.is_done());
+        assert_eq!(player.played, 2);
+    }
+}
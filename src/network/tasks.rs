@@ -0,0 +1,131 @@
+//! Talks to the network thread from systems without blocking the frame.
+//!
+//! Systems queue work on [`AsyncNetworkTasks`], which runs it on Bevy's [`IoTaskPool`]
+//! and reports each result as a [`NetworkTaskFinished`] event.
+
+use async_std::channel::Sender;
+use bevy::{
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+};
+use futures::{channel::oneshot, prelude::*};
+
+use super::{CommandId, GameAdminEvent, GameEvent, NetworkManager};
+
+/// Identifies one piece of work queued on [`AsyncNetworkTasks`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NetworkTaskId(u64);
+
+/// Sent once a task queued on [`AsyncNetworkTasks`] is done.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct NetworkTaskFinished {
+    pub id: NetworkTaskId,
+    pub result: Result<(), String>,
+}
+
+/// Queues sends and lookups for the network thread. Inserted by [`NetworkPlugin`]
+/// once a [`NetworkManager`] exists.
+///
+/// [`NetworkPlugin`]: super::NetworkPlugin
+#[derive(Resource)]
+pub struct AsyncNetworkTasks<FromGame> {
+    to_network: Sender<GameEvent<FromGame>>,
+    next_id: u64,
+    next_command: u64,
+    /// Done when the last queued send is, so sends reach the network in queue order.
+    last_send: Option<oneshot::Receiver<()>>,
+    running: Vec<(NetworkTaskId, Task<Result<(), String>>)>,
+}
+
+impl<FromGame> AsyncNetworkTasks<FromGame>
+where
+    FromGame: Send + 'static,
+{
+    pub fn new<ToGame>(manager: &NetworkManager<FromGame, ToGame>) -> Self {
+        Self {
+            to_network: manager.to_network.clone(),
+            next_id: 0,
+            next_command: 0,
+            last_send: None,
+            running: Vec::new(),
+        }
+    }
+
+    /// Queues `event` for the network thread. Lookups such as
+    /// [`FindPeer`](super::GameAdminEvent::FindPeer) are answered with a
+    /// [`NetworkEvent`](super::NetworkEvent) later, as before.
+    pub fn send(&mut self, event: GameEvent<FromGame>) -> NetworkTaskId {
+        let to_network = self.to_network.clone();
+        let previous = self.last_send.take();
+        let (done, last_send) = oneshot::channel();
+        self.last_send = Some(last_send);
+        self.spawn(async move {
+            if let Some(previous) = previous {
+                // Cancelled only if the previous task was dropped, which changes nothing.
+                let _ = previous.await;
+            }
+            let result = to_network
+                .send(event)
+                .await
+                .map_err(|_| "The network thread is gone".to_string());
+            let _ = done.send(());
+            result
+        })
+    }
+
+    /// Queues `command` like [`send`](Self::send), but asks for an answer: a
+    /// [`CommandCompleted`](super::NetworkAdminEvent::CommandCompleted) or
+    /// [`CommandFailed`](super::NetworkAdminEvent::CommandFailed) with the returned id
+    /// follows once it took effect or couldn't.
+    pub fn command(&mut self, command: GameAdminEvent) -> CommandId {
+        let id = CommandId(self.next_command);
+        self.next_command += 1;
+        self.send(GameEvent::Command { id, command });
+        id
+    }
+
+    /// Runs `work` on the [`IoTaskPool`], for anything that needn't be ordered with
+    /// [`send`](Self::send).
+    pub fn spawn(
+        &mut self,
+        work: impl Future<Output = Result<(), String>> + Send + 'static,
+    ) -> NetworkTaskId {
+        let id = NetworkTaskId(self.next_id);
+        self.next_id += 1;
+        self.running.push((id, IoTaskPool::get().spawn(work)));
+        id
+    }
+
+    /// Tasks that haven't finished yet.
+    pub fn running(&self) -> usize {
+        self.running.len()
+    }
+}
+
+pub(super) fn insert_network_tasks<FromGame, ToGame>(
+    mut commands: Commands,
+    manager: Res<NetworkManager<FromGame, ToGame>>,
+) where
+    FromGame: Send + Sync + 'static,
+    ToGame: Send + Sync + 'static,
+{
+    commands.insert_resource(AsyncNetworkTasks::new(&manager));
+}
+
+pub(super) fn poll_network_tasks<FromGame>(
+    mut tasks: ResMut<AsyncNetworkTasks<FromGame>>,
+    mut finished: EventWriter<NetworkTaskFinished>,
+) where
+    FromGame: Send + Sync + 'static,
+{
+    tasks.running.retain_mut(|(id, task)| {
+        let Some(result) = task.now_or_never() else {
+            return true;
+        };
+        if let Err(e) = &result {
+            log::error!("Network task failed: {}", e);
+        }
+        finished.send(NetworkTaskFinished { id: *id, result });
+        false
+    });
+}
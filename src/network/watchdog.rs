@@ -0,0 +1,166 @@
+//! Notices a network thread that stopped coming around its loop, e.g. because
+//! something in it blocks forever, and replaces it.
+//!
+//! The thread stamps a shared [`Liveness`] every time it wakes up, which is at least
+//! every few seconds even when nothing happens. Once the stamp is older than
+//! [`NetworkWatchdog::stall_after`] the game gets a
+//! [`NetworkAdminEvent::NetworkStalled`], and, if [`NetworkWatchdog::recover`] is set,
+//! a new network thread that gets back into the room we were in.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_std::task;
+use bevy::prelude::*;
+
+use super::{
+    CurrentRoom, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent, NetworkManager,
+};
+
+/// What the network thread shares with the watchdog: when its loop last came around,
+/// and how it entered the room it's in, to enter it again from a new thread.
+#[derive(Debug)]
+pub(super) struct Liveness {
+    started: Instant,
+    /// Milliseconds after `started`. Monotonic, so a suspended machine doesn't look
+    /// like a stalled thread.
+    beat: AtomicU64,
+    entered_with: Mutex<Option<GameAdminEvent>>,
+    /// Set once a new thread took over. A stalled thread that wakes up again must quit
+    /// without a word, as the new one speaks for us now.
+    abandoned: AtomicBool,
+}
+
+impl Default for Liveness {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            beat: AtomicU64::new(0),
+            entered_with: Mutex::new(None),
+            abandoned: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Liveness {
+    pub(super) fn beat(&self) {
+        let beat = self.started.elapsed().as_millis() as u64;
+        self.beat.store(beat, Ordering::Relaxed);
+    }
+
+    /// How long ago the loop last came around.
+    pub(super) fn since_beat(&self) -> Duration {
+        let beat = Duration::from_millis(self.beat.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(beat)
+    }
+
+    /// Remembers the [`Host`](GameAdminEvent::Host) or [`Join`](GameAdminEvent::Join)
+    /// that got us into our room, or that we are in none.
+    pub(super) fn set_entered_with(&self, event: Option<GameAdminEvent>) {
+        if let Ok(mut entered_with) = self.entered_with.lock() {
+            *entered_with = event;
+        }
+    }
+
+    fn entered_with(&self) -> Option<GameAdminEvent> {
+        self.entered_with
+            .lock()
+            .ok()
+            .and_then(|event| event.clone())
+    }
+
+    pub(super) fn is_abandoned(&self) -> bool {
+        self.abandoned.load(Ordering::Relaxed)
+    }
+}
+
+/// When the network thread counts as stalled, and what happens then.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkWatchdog {
+    /// How long the network thread may go without coming around its loop. An idle
+    /// thread still does every 5 seconds.
+    pub stall_after: Duration,
+    /// Start a new network thread with the same identity once the old one stalled,
+    /// and host or join our room again. Peers we connected to since a
+    /// [`RestartNetwork`](GameAdminEvent::RestartNetwork) with a new identity see the
+    /// identity we started with.
+    pub recover: bool,
+}
+
+impl Default for NetworkWatchdog {
+    fn default() -> Self {
+        Self {
+            stall_after: Duration::from_secs(15),
+            recover: true,
+        }
+    }
+}
+
+pub(super) fn watch_network<FromGame, ToGame>(
+    mut commands: Commands,
+    watchdog: Res<NetworkWatchdog>,
+    manager: Res<NetworkManager<FromGame, ToGame>>,
+    room: Option<Res<CurrentRoom>>,
+    mut network_events: EventWriter<NetworkEvent<ToGame>>,
+    mut reported: Local<bool>,
+) where
+    FromGame: Send + Sync + 'static,
+    ToGame: Send + Sync + 'static,
+{
+    // A thread that ended is reported as `NetworkDied` instead.
+    if manager.stopped {
+        return;
+    }
+    let stalled_for = manager.liveness.since_beat();
+    if stalled_for < watchdog.stall_after {
+        if *reported {
+            log::info!("The network thread is making progress again");
+            *reported = false;
+        }
+        return;
+    }
+    if *reported {
+        return;
+    }
+    *reported = true;
+    log::error!(
+        "The network thread made no progress for {:.1}s",
+        stalled_for.as_secs_f32()
+    );
+    network_events.send(NetworkEvent::Admin(NetworkAdminEvent::NetworkStalled {
+        stalled_for,
+    }));
+    if !watchdog.recover {
+        return;
+    }
+
+    let fresh = match task::block_on(manager.respawn()) {
+        Ok(fresh) => fresh,
+        Err(e) => {
+            log::error!("Could not replace the stalled network thread: {}", e);
+            network_events.send(NetworkEvent::Admin(
+                NetworkAdminEvent::NetworkRestartFailed {
+                    reason: e.to_string(),
+                },
+            ));
+            return;
+        }
+    };
+    manager.liveness.abandoned.store(true, Ordering::Relaxed);
+    if let (Some(room), Some(enter)) = (room, manager.liveness.entered_with()) {
+        log::info!("Getting back into room {}", room.room_code);
+        // Only fails if the new thread is gone already, which it reports itself.
+        let _ = fresh.try_send_to_network(GameEvent::Admin(enter));
+    }
+    network_events.send(NetworkEvent::Admin(NetworkAdminEvent::NetworkRestarted {
+        local_peer_id: fresh.local_peer_id(),
+    }));
+    // Removed first, so `AsyncNetworkTasks` is set up again for the new thread.
+    commands.remove_resource::<NetworkManager<FromGame, ToGame>>();
+    commands.insert_resource(fresh);
+}
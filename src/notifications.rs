@@ -0,0 +1,312 @@
+//! Short on-screen toasts about connectivity, so players aren't left guessing when a
+//! peer comes or goes.
+//!
+//! Key network events are turned into toasts automatically. Anything else can send a
+//! [`Notification`].
+
+use std::collections::{HashSet, VecDeque};
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::friends::FriendInvite;
+use crate::loading::FontAssets;
+use crate::network::{NetworkAdminEvent, NetworkEvent};
+
+/// At most this many toasts are on screen; the rest wait their turn.
+const MAX_VISIBLE: usize = 3;
+const TOAST_SECS: f32 = 4.0;
+/// Toasts fade out over the end of their life.
+const FADE_SECS: f32 = 1.0;
+
+pub struct NotificationPlugin;
+
+impl Plugin for NotificationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Notification>()
+            .init_resource::<PendingToasts>()
+            .add_systems(
+                Update,
+                (
+                    notify_network_events,
+                    notify_friend_invites,
+                    queue_notifications,
+                    show_toasts.run_if(resource_exists::<FontAssets>()),
+                    fade_toasts,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl NotificationLevel {
+    fn color(self) -> Color {
+        match self {
+            NotificationLevel::Info => Color::rgb(0.9, 0.9, 0.9),
+            NotificationLevel::Warning => Color::rgb(0.95, 0.8, 0.3),
+            NotificationLevel::Error => Color::rgb(0.95, 0.4, 0.4),
+        }
+    }
+}
+
+/// Send this to show a toast.
+#[derive(Event, Debug, Clone)]
+pub struct Notification {
+    pub level: NotificationLevel,
+    pub message: String,
+}
+
+impl Notification {
+    pub fn info(message: impl Into<String>) -> Self {
+        Self {
+            level: NotificationLevel::Info,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            level: NotificationLevel::Warning,
+            message: message.into(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            level: NotificationLevel::Error,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct PendingToasts(VecDeque<Notification>);
+
+/// Column in the top right corner the toasts stack in.
+#[derive(Component)]
+struct ToastContainer;
+
+#[derive(Component)]
+struct Toast {
+    timer: Timer,
+    color: Color,
+}
+
+fn short_id(peer_id: &PeerId) -> String {
+    let peer = peer_id.to_base58();
+    peer[peer.len().saturating_sub(6)..].to_string()
+}
+
+fn notify_network_events(
+    mut network_events: EventReader<NetworkEvent<()>>,
+    mut notifications: EventWriter<Notification>,
+    mut players: Local<HashSet<PeerId>>,
+) {
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        let notification = match event {
+            NetworkAdminEvent::Connected(peer_id) => {
+                if !players.insert(*peer_id) {
+                    continue;
+                }
+                Notification::info(format!("Player {} joined", short_id(peer_id)))
+            }
+            NetworkAdminEvent::PlayerLeft(peer_id) => {
+                players.remove(peer_id);
+                Notification::info(format!("Player {} left", short_id(peer_id)))
+            }
+            // Peers that said goodbye were already reported above.
+            NetworkAdminEvent::Disconnected(peer_id) if players.remove(peer_id) => {
+                Notification::warning(format!("Lost connection to {}", short_id(peer_id)))
+            }
+            NetworkAdminEvent::JoinAccepted { .. } => Notification::info("Joined the room"),
+            NetworkAdminEvent::SlotReclaimed { name, .. } => {
+                Notification::info(format!("{} is back", name))
+            }
+            NetworkAdminEvent::SlotReleased(peer_id) => Notification::info(format!(
+                "{} did not come back, their slot is free",
+                short_id(peer_id)
+            )),
+            NetworkAdminEvent::JoinFailed(error) => {
+                Notification::error(format!("Join failed: {}", error))
+            }
+            NetworkAdminEvent::HostingFailed { reason } => {
+                Notification::error(format!("Hosting failed: {}", reason))
+            }
+            NetworkAdminEvent::InviteFailed { peer_id, reason } => Notification::warning(format!(
+                "Invite to {} failed: {}",
+                short_id(peer_id),
+                reason
+            )),
+            NetworkAdminEvent::FileOffered { peer_id, name, .. } => {
+                Notification::info(format!("{} wants to send you {}", short_id(peer_id), name))
+            }
+            NetworkAdminEvent::FileReceived { path, .. } => {
+                Notification::info(format!("Saved {}", path.display()))
+            }
+            NetworkAdminEvent::FileDeclined { peer_id, name } => {
+                Notification::info(format!("{} declined {}", short_id(peer_id), name))
+            }
+            NetworkAdminEvent::FileFailed { name, reason, .. } => {
+                Notification::warning(format!("Transfer of {} failed: {}", name, reason))
+            }
+            NetworkAdminEvent::InvitePosted(peer_id) => Notification::info(format!(
+                "Left an invite for {} to find later",
+                short_id(peer_id)
+            )),
+            NetworkAdminEvent::NetworkMigrated { redialed, .. } if !redialed.is_empty() => {
+                Notification::warning(format!(
+                    "Network changed, reconnecting to {} players",
+                    redialed.len()
+                ))
+            }
+            NetworkAdminEvent::Resumed { redialed, .. } if !redialed.is_empty() => {
+                Notification::warning(format!(
+                    "Woke up, reconnecting to {} players",
+                    redialed.len()
+                ))
+            }
+            NetworkAdminEvent::BootstrapFailed { reason } => {
+                Notification::warning(format!("Couldn't reach the DHT: {}", reason))
+            }
+            NetworkAdminEvent::StartDelayed { waiting } => Notification::info(format!(
+                "Starting once {} more players are connected",
+                waiting.len()
+            )),
+            NetworkAdminEvent::RelayUsageWarning { peer_id, .. } => Notification::warning(format!(
+                "{} is only reachable through a slow relay. Forwarding a port or \
+                         enabling UPnP on your router lets players connect directly",
+                short_id(peer_id)
+            )),
+            NetworkAdminEvent::NetworkDied => {
+                Notification::error("The network stopped, restart the game to play online")
+            }
+            NetworkAdminEvent::TickSettingsChanged(tick) => Notification::info(format!(
+                "The host switched to {} ticks per second",
+                tick.tick_rate
+            )),
+            NetworkAdminEvent::NetworkSetupFailed(e) => {
+                Notification::error(format!("Could not go online: {}", e))
+            }
+            NetworkAdminEvent::NetworkStalled { .. } => {
+                Notification::warning("The network stopped responding")
+            }
+            _ => continue,
+        };
+        notifications.send(notification);
+    }
+}
+
+fn notify_friend_invites(
+    mut invites: EventReader<FriendInvite>,
+    mut notifications: EventWriter<Notification>,
+) {
+    for invite in invites.iter() {
+        notifications.send(Notification::info(format!(
+            "{} invited you to {}",
+            invite.name, invite.room_code
+        )));
+    }
+}
+
+fn queue_notifications(
+    mut notifications: EventReader<Notification>,
+    mut pending: ResMut<PendingToasts>,
+) {
+    pending.0.extend(notifications.iter().cloned());
+}
+
+fn show_toasts(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    mut pending: ResMut<PendingToasts>,
+    containers: Query<Entity, With<ToastContainer>>,
+    toasts: Query<(), With<Toast>>,
+) {
+    if pending.0.is_empty() {
+        return;
+    }
+    let container = match containers.get_single() {
+        Ok(container) => container,
+        Err(_) => commands
+            .spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        top: Val::Px(10.0),
+                        right: Val::Px(10.0),
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::FlexEnd,
+                        row_gap: Val::Px(6.0),
+                        ..Default::default()
+                    },
+                    z_index: ZIndex::Global(100),
+                    ..Default::default()
+                },
+                ToastContainer,
+            ))
+            .id(),
+    };
+    let free = MAX_VISIBLE.saturating_sub(toasts.iter().count());
+    for notification in pending.0.drain(..free.min(pending.0.len())) {
+        let color = notification.level.color();
+        let toast = commands
+            .spawn((
+                NodeBundle {
+                    style: Style {
+                        padding: UiRect::axes(Val::Px(12.0), Val::Px(6.0)),
+                        ..Default::default()
+                    },
+                    background_color: Color::rgba(0.1, 0.1, 0.1, 0.85).into(),
+                    ..Default::default()
+                },
+                Toast {
+                    timer: Timer::from_seconds(TOAST_SECS, TimerMode::Once),
+                    color,
+                },
+            ))
+            .with_children(|toast| {
+                toast.spawn(TextBundle::from_section(
+                    notification.message,
+                    TextStyle {
+                        font: font_assets.fira_sans.clone(),
+                        font_size: 20.0,
+                        color,
+                    },
+                ));
+            })
+            .id();
+        commands.entity(container).add_child(toast);
+    }
+}
+
+fn fade_toasts(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut toasts: Query<(Entity, &mut Toast, &mut BackgroundColor, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    for (entity, mut toast, mut background, children) in &mut toasts {
+        toast.timer.tick(time.delta());
+        if toast.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let alpha = (toast.timer.remaining_secs() / FADE_SECS).min(1.0);
+        background.0.set_a(0.85 * alpha);
+        for &child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(child) {
+                text.sections[0].style.color = toast.color.with_a(alpha);
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Where the game keeps its files on each platform.
+//!
+//! Native builds use the platform's usual directories, e.g. `~/.config/bevy_libp2p` on
+//! Linux or `%APPDATA%\favilo\bevy_libp2p` on Windows. The browser has no filesystem,
+//! so on wasm the paths are only names and [`read_to_string`] and [`write`] keep the
+//! files in `localStorage` instead.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+
+pub(crate) const APPLICATION: &str = "bevy_libp2p";
+
+/// The directories everything else stores its files under. Insert your own before
+/// adding the plugins to move them, e.g. with [`Paths::under`] for a portable install.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    /// Settings and anything else the player chose, like the friends list.
+    pub config: PathBuf,
+    /// What the game produces for itself: identity, keys.
+    pub data: PathBuf,
+    pub replays: PathBuf,
+    /// Files other players sent us.
+    pub received: PathBuf,
+    pub logs: PathBuf,
+}
+
+impl Paths {
+    /// Everything in subdirectories of `root`.
+    pub fn under(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self {
+            config: root.join("config"),
+            data: root.join("data"),
+            replays: root.join("replays"),
+            received: root.join("received"),
+            logs: root.join("logs"),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for Paths {
+    fn default() -> Self {
+        let Some(dirs) = directories::ProjectDirs::from("io.github", "favilo", APPLICATION) else {
+            log::warn!("No home directory, keeping files in the working directory");
+            return Self::under(".");
+        };
+        Self {
+            config: dirs.config_dir().to_path_buf(),
+            data: dirs.data_dir().to_path_buf(),
+            replays: dirs.data_dir().join("replays"),
+            received: dirs.data_dir().join("received"),
+            logs: dirs.data_local_dir().join("logs"),
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Default for Paths {
+    fn default() -> Self {
+        Self::under(APPLICATION)
+    }
+}
+
+/// Reads a whole file, or the `localStorage` entry standing in for it on wasm.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    std::fs::read_to_string(path)
+}
+
+/// Writes a whole file, creating the directories it's in.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, contents)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> io::Result<web_sys::Storage> {
+    web_sys::window()
+        .and_then(|window| window.local_storage().ok().flatten())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "No localStorage"))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    local_storage()?
+        .get_item(&path.to_string_lossy())
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))?
+        .ok_or_else(|| io::ErrorKind::NotFound.into())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write(path: &Path, contents: &str) -> io::Result<()> {
+    local_storage()?
+        .set_item(&path.to_string_lossy(), contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_creates_directories() {
+        let root = std::env::temp_dir().join(format!("{}-{}", APPLICATION, std::process::id()));
+        let paths = Paths::under(&root);
+        let file = paths.config.join("nested").join("friends.txt");
+        write(&file, "hello").unwrap();
+        assert_eq!(read_to_string(&file).unwrap(), "hello");
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+//! Liveness of the peers' games, as opposed to their connections: every game sends a
+//! heartbeat a second from a Bevy system, so a peer whose game froze stops sending
+//! them while libp2p's ping still answers. Such peers are marked
+//! [`PeerStats::unresponsive`].
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use super::{PeerIdComp, PeerStats};
+use crate::network::{GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent, NetworkManager};
+
+/// Seconds between our heartbeats.
+const HEARTBEAT_INTERVAL: f64 = 1.0;
+/// Seconds without a heartbeat before a peer counts as unresponsive.
+const UNRESPONSIVE_AFTER: f64 = 3.0;
+
+pub(super) struct HeartbeatPlugin;
+
+impl Plugin for HeartbeatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                send_heartbeat::<(), ()>.run_if(resource_exists::<NetworkManager<(), ()>>()),
+                track_heartbeats::<()>,
+            ),
+        );
+    }
+}
+
+fn unresponsive(last_heard: Option<f64>, now: f64) -> bool {
+    last_heard.map_or(false, |heard| now - heard > UNRESPONSIVE_AFTER)
+}
+
+fn send_heartbeat<FromGame, ToGame>(
+    time: Res<Time>,
+    manager: Res<NetworkManager<FromGame, ToGame>>,
+    mut last: Local<f64>,
+) where
+    FromGame: Send + Sync + 'static,
+    ToGame: Send + Sync + 'static,
+{
+    let now = time.elapsed_seconds_f64();
+    if now - *last < HEARTBEAT_INTERVAL {
+        return;
+    }
+    *last = now;
+    // Fails only once the network thread is gone, which is reported elsewhere.
+    let _ = manager.try_send_to_network(GameEvent::Admin(GameAdminEvent::Heartbeat));
+}
+
+fn track_heartbeats<ToGame>(
+    time: Res<Time>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+    mut last_heard: Local<HashMap<PeerId, f64>>,
+    mut peers: Query<(&PeerIdComp, &mut PeerStats)>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    let now = time.elapsed_seconds_f64();
+    for event in network_events.iter() {
+        match event {
+            NetworkEvent::Admin(NetworkAdminEvent::Heartbeat(peer_id)) => {
+                last_heard.insert(*peer_id, now);
+            }
+            NetworkEvent::Admin(
+                NetworkAdminEvent::Disconnected(peer_id) | NetworkAdminEvent::PlayerLeft(peer_id),
+            ) => {
+                last_heard.remove(peer_id);
+            }
+            _ => {}
+        }
+    }
+    for (peer_id, mut stats) in &mut peers {
+        let unresponsive = unresponsive(last_heard.get(&peer_id.0).copied(), now);
+        if stats.unresponsive != unresponsive {
+            if unresponsive {
+                log::warn!("{} stopped sending heartbeats", peer_id.0);
+            } else {
+                log::info!("{} is responsive again", peer_id.0);
+            }
+            stats.unresponsive = unresponsive;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_peers_that_went_quiet_are_unresponsive() {
+        assert!(!unresponsive(None, 100.0));
+        assert!(!unresponsive(Some(98.0), 100.0));
+        assert!(unresponsive(Some(96.0), 100.0));
+    }
+}
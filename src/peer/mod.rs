@@ -0,0 +1,341 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::network::{ConnectionPath, NetworkAdminEvent, NetworkEvent};
+
+mod heartbeat;
+mod reputation;
+mod rtt_histogram;
+mod send_rate;
+mod stats_history;
+mod timeline;
+
+pub use reputation::{
+    Misbehaviour, PeerMisbehaved, PeerReputations, Reputation, ReputationConfig, ReputationEvent,
+};
+pub use rtt_histogram::RttHistogram;
+pub use send_rate::{SendRate, SendRateConfig};
+pub use stats_history::{StatsHistory, StatsSample};
+pub use timeline::{PeerTimelines, TimelineEntry, TimelineEvent};
+
+/// Spawns an entity per peer running this game and keeps its components in sync with
+/// the events from the network layer.
+pub struct PeerPlugin;
+
+/// Identifies a peer entity. Every peer entity has one, along with a [`Name`], a
+/// [`SendRate`], [`PeerStats`] and [`PeerConnected`].
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PeerIdComp(pub PeerId);
+
+/// The name the peer goes by: the one in its signed profile, or until it sends one,
+/// the name it asked to join our room as, which only hosts learn.
+#[derive(Component, Reflect, Debug, Clone, PartialEq, Eq)]
+pub struct PeerNameComp(pub String);
+
+/// The avatar in the peer's signed profile, if it picked one.
+#[derive(Component, Reflect, Debug, Clone, PartialEq, Eq)]
+pub struct PeerAvatar(pub String);
+
+/// Latest ping round trip. Added once the first ping completes.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct Latency(pub Duration);
+
+/// How our latest-wins messages, [`GameEvent::Latest`](crate::network::GameEvent::Latest),
+/// fare with this peer, from what it reports back, and how long pings to it took.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PeerStats {
+    pub acked: u64,
+    pub lost: u64,
+    /// The newest message the peer is known to have, e.g. the baseline to encode the
+    /// next delta against.
+    pub last_acked: Option<u32>,
+    /// The peer stopped sending heartbeats, so its game is likely frozen even if its
+    /// connection is fine. Only peers that sent one before can become unresponsive.
+    pub unresponsive: bool,
+    /// Every [`Latency`] so far, for its percentiles.
+    pub rtt: RttHistogram,
+}
+
+impl PeerStats {
+    /// Share of the messages settled so far that were lost.
+    pub fn loss(&self) -> f32 {
+        let settled = self.acked + self.lost;
+        if settled == 0 {
+            0.0
+        } else {
+            self.lost as f32 / settled as f32
+        }
+    }
+}
+
+/// The relay our connection to the peer goes through. Only on relayed peers.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RelayedVia(pub PeerId);
+
+/// How our last attempt to hole punch through to the peer went.
+#[derive(Component, Reflect, Debug, Clone, PartialEq, Eq)]
+pub enum HolePunch {
+    Succeeded,
+    Failed { reason: String },
+}
+
+/// Marks the entity a remote peer plays as, e.g. its character, as opposed to the
+/// peer entity itself.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RemotePlayer(pub PeerId);
+
+/// Marks a peer that only watches the game. Spectators send no game messages.
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+pub struct Spectator;
+
+/// On every peer entity, from when we connect to the peer until it's despawned, so
+/// game systems can use change detection instead of reading network events: query
+/// `Added<PeerConnected>` for peers that just came in, and read
+/// `RemovedComponents<PeerConnected>` for those that just left, with
+/// [`PeerEntities::departed`] to tell which peer each was.
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+pub struct PeerConnected;
+
+/// The team the peer is on, as far as we've heard from the host. See
+/// [`Teams`](crate::teams::Teams).
+#[derive(Component, Reflect, Debug, Clone, PartialEq, Eq)]
+pub struct PeerTeam(pub String);
+
+/// Marks the host of the room we joined.
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+pub struct IsHost;
+
+/// Finds the entity of a peer by its id.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct PeerEntities {
+    entities: HashMap<PeerId, Entity>,
+    /// Entities despawned this frame and the last, as long as Bevy reports their
+    /// removed components.
+    departed: [HashMap<Entity, PeerId>; 2],
+}
+
+impl PeerEntities {
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.entities.contains_key(peer_id)
+    }
+
+    pub fn get(&self, peer_id: &PeerId) -> Option<Entity> {
+        self.entities.get(peer_id).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&PeerId, &Entity)> {
+        self.entities.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// The peer a despawned peer entity was, for entities from
+    /// `RemovedComponents<PeerConnected>`. Remembered for as long as Bevy reports the
+    /// removal.
+    pub fn departed(&self, entity: Entity) -> Option<PeerId> {
+        self.departed
+            .iter()
+            .find_map(|departed| departed.get(&entity))
+            .copied()
+    }
+
+    fn insert(&mut self, peer_id: PeerId, entity: Entity) {
+        self.entities.insert(peer_id, entity);
+    }
+
+    fn remove(&mut self, peer_id: &PeerId) -> Option<Entity> {
+        let entity = self.entities.remove(peer_id)?;
+        self.departed[0].insert(entity, *peer_id);
+        Some(entity)
+    }
+
+    /// Forgets the entities despawned two frames ago.
+    fn age_departed(&mut self) {
+        let [current, previous] = &mut self.departed;
+        std::mem::swap(current, previous);
+        current.clear();
+    }
+}
+
+impl Plugin for PeerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            heartbeat::HeartbeatPlugin,
+            reputation::ReputationPlugin,
+            send_rate::SendRatePlugin,
+            stats_history::StatsHistoryPlugin,
+            timeline::TimelinePlugin,
+        ))
+        .register_type::<PeerNameComp>()
+        .register_type::<PeerAvatar>()
+        .register_type::<Latency>()
+        .register_type::<IsHost>()
+        .register_type::<PeerConnected>()
+        .register_type::<PeerTeam>()
+        .register_type::<Spectator>()
+        .register_type::<PeerStats>()
+        .register_type::<HolePunch>()
+        .register_type::<ConnectionPath>()
+        .add_systems(First, age_departed)
+        .add_systems(Update, track_peers::<()>)
+        .init_resource::<PeerEntities>();
+    }
+}
+
+fn age_departed(mut peers: ResMut<PeerEntities>) {
+    peers.age_departed();
+}
+
+fn track_peers<ToGame>(
+    mut commands: Commands,
+    mut event: EventReader<NetworkEvent<ToGame>>,
+    mut peers: ResMut<PeerEntities>,
+    mut stats: Query<&mut PeerStats>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in event.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        match event {
+            NetworkAdminEvent::Connected(peer_id) => {
+                if peers.contains(peer_id) {
+                    continue;
+                }
+                log::info!("Peer added: {}", peer_id);
+                let peer = peer_id.to_base58();
+                let entity = commands
+                    .spawn((
+                        PeerIdComp(*peer_id),
+                        Name::new(format!("Peer {}", &peer[peer.len().saturating_sub(6)..])),
+                        SendRate::default(),
+                        PeerStats::default(),
+                        PeerConnected,
+                    ))
+                    .id();
+                peers.insert(*peer_id, entity);
+            }
+            NetworkAdminEvent::Disconnected(peer_id) | NetworkAdminEvent::PlayerLeft(peer_id) => {
+                if let Some(entity) = peers.remove(peer_id) {
+                    log::info!("Peer removed: {}", peer_id);
+                    commands.entity(entity).despawn();
+                }
+            }
+            NetworkAdminEvent::ConnectionPathChanged {
+                peer_id,
+                path,
+                relay,
+            } => {
+                if let Some(entity) = peers.get(peer_id) {
+                    log::info!("Peer {} now connected via {:?}", peer_id, path);
+                    let mut entity = commands.entity(entity);
+                    entity.insert(*path);
+                    match relay {
+                        Some(relay) => entity.insert(RelayedVia(*relay)),
+                        None => entity.remove::<RelayedVia>(),
+                    };
+                }
+            }
+            NetworkAdminEvent::HolePunchSucceeded(peer_id) => {
+                if let Some(entity) = peers.get(peer_id) {
+                    commands.entity(entity).insert(HolePunch::Succeeded);
+                }
+            }
+            NetworkAdminEvent::HolePunchFailed { peer_id, reason } => {
+                if let Some(entity) = peers.get(peer_id) {
+                    commands.entity(entity).insert(HolePunch::Failed {
+                        reason: reason.clone(),
+                    });
+                }
+            }
+            NetworkAdminEvent::Latency { peer_id, rtt } => {
+                let Some(entity) = peers.get(peer_id) else {
+                    continue;
+                };
+                commands.entity(entity).insert(Latency(*rtt));
+                if let Ok(mut stats) = stats.get_mut(entity) {
+                    stats.rtt.record(*rtt);
+                }
+            }
+            NetworkAdminEvent::Delivery {
+                peer_id,
+                acked,
+                lost,
+            } => {
+                let Some(mut stats) = peers
+                    .get(peer_id)
+                    .and_then(|entity| stats.get_mut(entity).ok())
+                else {
+                    continue;
+                };
+                stats.acked += acked.len() as u64;
+                stats.lost += lost.len() as u64;
+                stats.last_acked = acked.iter().copied().chain(stats.last_acked).max();
+            }
+            NetworkAdminEvent::JoinRequested { peer_id, name } => {
+                if let Some(entity) = peers.get(peer_id) {
+                    commands.entity(entity).insert(PeerNameComp(name.clone()));
+                }
+            }
+            NetworkAdminEvent::PeerSpectating {
+                peer_id,
+                spectating,
+            } => {
+                if let Some(entity) = peers.get(peer_id) {
+                    if *spectating {
+                        commands.entity(entity).insert(Spectator);
+                    } else {
+                        commands.entity(entity).remove::<Spectator>();
+                    }
+                }
+            }
+            NetworkAdminEvent::ProfileReceived { peer_id, profile } => {
+                if let Some(entity) = peers.get(peer_id) {
+                    let mut entity = commands.entity(entity);
+                    entity.insert(PeerNameComp(profile.name.clone()));
+                    match &profile.avatar {
+                        Some(avatar) => entity.insert(PeerAvatar(avatar.clone())),
+                        None => entity.remove::<PeerAvatar>(),
+                    };
+                }
+            }
+            NetworkAdminEvent::JoinAccepted { host, .. } => {
+                if let Some(entity) = peers.get(host) {
+                    commands.entity(entity).insert(IsHost);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn departed_peers_last_two_frames() {
+        let peer_id = PeerId::random();
+        let entity = Entity::from_raw(7);
+        let mut peers = PeerEntities::default();
+        peers.insert(peer_id, entity);
+        assert_eq!(peers.departed(entity), None);
+
+        assert_eq!(peers.remove(&peer_id), Some(entity));
+        assert!(!peers.contains(&peer_id));
+        assert_eq!(peers.departed(entity), Some(peer_id));
+        peers.age_departed();
+        assert_eq!(peers.departed(entity), Some(peer_id));
+        peers.age_departed();
+        assert_eq!(peers.departed(entity), None);
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::network::{
+    AsyncNetworkTasks, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent,
+};
+
+/// Score every peer starts with, and the most it can recover to.
+pub const MAX_SCORE: f32 = 100.0;
+
+/// Tracks misbehaving peers and throttles or disconnects them when their score drops.
+pub struct ReputationPlugin;
+
+impl Plugin for ReputationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReputationConfig>()
+            .init_resource::<PeerReputations>()
+            .add_event::<PeerMisbehaved>()
+            .add_event::<ReputationEvent>()
+            .add_systems(
+                Update,
+                (
+                    track_network_events::<()>,
+                    track_reported_misbehaviour,
+                    apply_reputation_thresholds,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Things a peer can do wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Misbehaviour {
+    /// The message decoded but the game rejected it.
+    InvalidMessage,
+    /// The message could not be decoded at all.
+    DecodeFailure,
+    /// The peer sent more than [`ReputationConfig::max_messages_per_second`].
+    ExcessiveRate,
+    /// A ping to the peer failed or timed out.
+    Timeout,
+    /// The host's [`InputValidator`](crate::validation::InputValidator) turned down one
+    /// of the peer's inputs, e.g. for moving faster than anyone can.
+    IllegalInput,
+}
+
+/// Game systems send this to report a peer, e.g. for a message that failed validation.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerMisbehaved {
+    pub peer_id: PeerId,
+    pub kind: Misbehaviour,
+}
+
+/// Sent whenever a peer crosses one of the [`ReputationConfig`] thresholds.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReputationEvent {
+    Throttled(PeerId),
+    Unthrottled(PeerId),
+    Disconnected(PeerId),
+}
+
+/// Penalties and thresholds used to score peers.
+#[derive(Resource, Debug, Clone)]
+pub struct ReputationConfig {
+    pub invalid_message_penalty: f32,
+    pub decode_failure_penalty: f32,
+    pub excessive_rate_penalty: f32,
+    pub timeout_penalty: f32,
+    pub illegal_input_penalty: f32,
+    /// Messages a peer may send per second before it counts as [`Misbehaviour::ExcessiveRate`].
+    pub max_messages_per_second: u32,
+    /// How much score a peer regains every second.
+    pub recovery_per_second: f32,
+    /// Peers below this score only get a trickle of messages through to the game.
+    pub throttle_below: f32,
+    /// Peers below this score are disconnected.
+    pub disconnect_below: f32,
+}
+
+impl Default for ReputationConfig {
+    fn default() -> Self {
+        Self {
+            invalid_message_penalty: 10.0,
+            decode_failure_penalty: 5.0,
+            excessive_rate_penalty: 15.0,
+            timeout_penalty: 5.0,
+            illegal_input_penalty: 20.0,
+            max_messages_per_second: 60,
+            recovery_per_second: 1.0,
+            throttle_below: 50.0,
+            disconnect_below: 0.0,
+        }
+    }
+}
+
+impl ReputationConfig {
+    fn penalty(&self, kind: Misbehaviour) -> f32 {
+        match kind {
+            Misbehaviour::InvalidMessage => self.invalid_message_penalty,
+            Misbehaviour::DecodeFailure => self.decode_failure_penalty,
+            Misbehaviour::ExcessiveRate => self.excessive_rate_penalty,
+            Misbehaviour::Timeout => self.timeout_penalty,
+            Misbehaviour::IllegalInput => self.illegal_input_penalty,
+        }
+    }
+}
+
+/// The reputation of a single peer.
+#[derive(Debug, Clone)]
+pub struct Reputation {
+    pub score: f32,
+    pub invalid_messages: u32,
+    pub decode_failures: u32,
+    pub rate_violations: u32,
+    pub timeouts: u32,
+    pub illegal_inputs: u32,
+    pub throttled: bool,
+    pub disconnected: bool,
+    window_start: f32,
+    window_messages: u32,
+}
+
+impl Default for Reputation {
+    fn default() -> Self {
+        Self {
+            score: MAX_SCORE,
+            invalid_messages: 0,
+            decode_failures: 0,
+            rate_violations: 0,
+            timeouts: 0,
+            illegal_inputs: 0,
+            throttled: false,
+            disconnected: false,
+            window_start: 0.0,
+            window_messages: 0,
+        }
+    }
+}
+
+impl Reputation {
+    fn penalize(&mut self, kind: Misbehaviour, config: &ReputationConfig) {
+        match kind {
+            Misbehaviour::InvalidMessage => self.invalid_messages += 1,
+            Misbehaviour::DecodeFailure => self.decode_failures += 1,
+            Misbehaviour::ExcessiveRate => self.rate_violations += 1,
+            Misbehaviour::Timeout => self.timeouts += 1,
+            Misbehaviour::IllegalInput => self.illegal_inputs += 1,
+        }
+        self.score -= config.penalty(kind);
+    }
+
+    /// Counts a message received at `now` (seconds since startup). Returns `true` the
+    /// first time the peer goes over the rate limit in the current one second window.
+    fn count_message(&mut self, now: f32, config: &ReputationConfig) -> bool {
+        if now - self.window_start >= 1.0 {
+            self.window_start = now;
+            self.window_messages = 0;
+        }
+        self.window_messages += 1;
+        self.window_messages == config.max_messages_per_second + 1
+    }
+}
+
+/// Reputation of every peer we have heard from.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct PeerReputations(HashMap<PeerId, Reputation>);
+
+impl PeerReputations {
+    pub fn get(&self, peer_id: &PeerId) -> Option<&Reputation> {
+        self.0.get(peer_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&PeerId, &Reputation)> {
+        self.0.iter()
+    }
+}
+
+fn track_network_events<ToGame>(
+    time: Res<Time>,
+    config: Res<ReputationConfig>,
+    mut reputations: ResMut<PeerReputations>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        let (peer_id, kind) = match event {
+            NetworkEvent::Game { source, .. } => {
+                let reputation = reputations.0.entry(*source).or_default();
+                if !reputation.count_message(time.elapsed_seconds(), &config) {
+                    continue;
+                }
+                (*source, Misbehaviour::ExcessiveRate)
+            }
+            NetworkEvent::Admin(NetworkAdminEvent::DecodeFailed(peer_id)) => {
+                (*peer_id, Misbehaviour::DecodeFailure)
+            }
+            NetworkEvent::Admin(NetworkAdminEvent::PingFailed(peer_id)) => {
+                (*peer_id, Misbehaviour::Timeout)
+            }
+            _ => continue,
+        };
+        reputations
+            .0
+            .entry(peer_id)
+            .or_default()
+            .penalize(kind, &config);
+    }
+}
+
+fn track_reported_misbehaviour(
+    config: Res<ReputationConfig>,
+    mut reputations: ResMut<PeerReputations>,
+    mut reports: EventReader<PeerMisbehaved>,
+) {
+    for report in reports.iter() {
+        reputations
+            .0
+            .entry(report.peer_id)
+            .or_default()
+            .penalize(report.kind, &config);
+    }
+}
+
+fn apply_reputation_thresholds(
+    time: Res<Time>,
+    config: Res<ReputationConfig>,
+    mut reputations: ResMut<PeerReputations>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    mut events: EventWriter<ReputationEvent>,
+) {
+    let recovered = config.recovery_per_second * time.delta_seconds();
+    for (peer_id, reputation) in reputations.0.iter_mut() {
+        if reputation.disconnected {
+            continue;
+        }
+        reputation.score = (reputation.score + recovered).min(MAX_SCORE);
+
+        let command = if reputation.score < config.disconnect_below {
+            log::warn!("Disconnecting {} for bad reputation", peer_id);
+            reputation.disconnected = true;
+            events.send(ReputationEvent::Disconnected(*peer_id));
+            GameAdminEvent::Disconnect(*peer_id)
+        } else if reputation.score < config.throttle_below && !reputation.throttled {
+            log::info!("Throttling {} for bad reputation", peer_id);
+            reputation.throttled = true;
+            events.send(ReputationEvent::Throttled(*peer_id));
+            GameAdminEvent::Throttle {
+                peer_id: *peer_id,
+                throttled: true,
+            }
+        } else if reputation.score >= config.throttle_below && reputation.throttled {
+            reputation.throttled = false;
+            events.send(ReputationEvent::Unthrottled(*peer_id));
+            GameAdminEvent::Throttle {
+                peer_id: *peer_id,
+                throttled: false,
+            }
+        } else {
+            continue;
+        };
+        tasks.send(GameEvent::Admin(command));
+    }
+}
@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+/// Round trips under this many milliseconds get a bucket each.
+const LINEAR_MS: u64 = 16;
+/// Buckets each power of two above [`LINEAR_MS`] is split into, so a bucket is never
+/// wider than an eighth of the values in it.
+const SUB_BUCKETS: u64 = 8;
+/// Round trips of `2^MAX_EXPONENT` ms, about 16 s, and longer share the last bucket.
+const MAX_EXPONENT: u32 = 14;
+const BUCKETS: usize = LINEAR_MS as usize
+    + (MAX_EXPONENT - LINEAR_MS.trailing_zeros()) as usize * SUB_BUCKETS as usize;
+
+/// Every ping round trip to a peer, in buckets as wide as an eighth of their value
+/// like an HDR histogram, so a p99 shows spikes an average smooths over.
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RttHistogram {
+    counts: [u32; BUCKETS],
+    samples: u64,
+}
+
+impl Default for RttHistogram {
+    fn default() -> Self {
+        Self {
+            counts: [0; BUCKETS],
+            samples: 0,
+        }
+    }
+}
+
+impl RttHistogram {
+    pub fn record(&mut self, rtt: Duration) {
+        let bucket = bucket(rtt.as_millis().min(u64::MAX as u128) as u64);
+        self.counts[bucket] = self.counts[bucket].saturating_add(1);
+        self.samples += 1;
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// The round trip `percentile` percent of the samples were at most, rounded up to
+    /// the end of its bucket. `None` before the first sample.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.samples == 0 {
+            return None;
+        }
+        let rank =
+            ((percentile.clamp(0.0, 100.0) / 100.0 * self.samples as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += u64::from(*count);
+            if seen >= rank {
+                return Some(Duration::from_millis(highest_in(bucket)));
+            }
+        }
+        Some(Duration::from_millis(highest_in(BUCKETS - 1)))
+    }
+
+    pub fn p50(&self) -> Option<Duration> {
+        self.percentile(50.0)
+    }
+
+    pub fn p95(&self) -> Option<Duration> {
+        self.percentile(95.0)
+    }
+
+    pub fn p99(&self) -> Option<Duration> {
+        self.percentile(99.0)
+    }
+}
+
+fn bucket(ms: u64) -> usize {
+    if ms < LINEAR_MS {
+        return ms as usize;
+    }
+    let exponent = 63 - ms.leading_zeros();
+    if exponent >= MAX_EXPONENT {
+        return BUCKETS - 1;
+    }
+    let sub_bits = SUB_BUCKETS.trailing_zeros();
+    let sub = (ms >> (exponent - sub_bits)) & (SUB_BUCKETS - 1);
+    let first = LINEAR_MS.trailing_zeros();
+    (LINEAR_MS + u64::from(exponent - first) * SUB_BUCKETS + sub) as usize
+}
+
+/// The largest round trip in milliseconds that lands in `bucket`.
+fn highest_in(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < LINEAR_MS {
+        return bucket;
+    }
+    let sub_bits = SUB_BUCKETS.trailing_zeros();
+    let exponent = (bucket - LINEAR_MS) / SUB_BUCKETS + u64::from(LINEAR_MS.trailing_zeros());
+    let sub = (bucket - LINEAR_MS) % SUB_BUCKETS;
+    let width = 1 << (exponent - u64::from(sub_bits));
+    (SUB_BUCKETS + sub) * width + width - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_stay_within_an_eighth() {
+        for ms in [0, 15, 16, 17, 40, 100, 250, 1000, 5000, 16_383] {
+            let highest = highest_in(bucket(ms));
+            assert!(highest >= ms, "{} ms in a bucket up to {}", ms, highest);
+            assert!(
+                highest - ms <= ms / 8,
+                "{} ms in a bucket up to {}",
+                ms,
+                highest
+            );
+        }
+        assert_eq!(bucket(60_000), BUCKETS - 1);
+    }
+
+    #[test]
+    fn percentiles_show_spikes() {
+        let mut histogram = RttHistogram::default();
+        assert_eq!(histogram.p50(), None);
+        for _ in 0..98 {
+            histogram.record(Duration::from_millis(40));
+        }
+        histogram.record(Duration::from_millis(400));
+        histogram.record(Duration::from_millis(900));
+
+        assert_eq!(histogram.samples(), 100);
+        assert_eq!(histogram.p50(), Some(Duration::from_millis(43)));
+        assert_eq!(histogram.p95(), Some(Duration::from_millis(43)));
+        let p99 = histogram.p99().unwrap();
+        assert!(p99 >= Duration::from_millis(400) && p99 < Duration::from_millis(450));
+        assert!(histogram.percentile(100.0).unwrap() >= Duration::from_millis(900));
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use super::Latency;
+use crate::network::NetworkManager;
+
+/// Adapts every peer's [`SendRate`] to its latency and to how backed up the network
+/// thread is.
+pub struct SendRatePlugin;
+
+impl Plugin for SendRatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SendRateConfig>()
+            .register_type::<SendRate>()
+            .add_systems(
+                Update,
+                adapt_send_rates::<(), ()>.run_if(resource_exists::<NetworkManager<(), ()>>()),
+            );
+    }
+}
+
+/// How often a peer can take an update from us.
+#[derive(Component, Reflect, Debug, Clone, Default)]
+pub struct SendRate {
+    /// Updates per second this peer can take right now, adapted by [`SendRateConfig`].
+    pub rate: f32,
+    /// When [`take_due`](Self::take_due) last said yes, in seconds since startup.
+    last_sent: Option<f64>,
+}
+
+impl SendRate {
+    /// Whether the peer is due another update at `now` (e.g.
+    /// `Time::elapsed_seconds_f64`). If so it's marked as sent to, so call this once
+    /// per update you send.
+    pub fn take_due(&mut self, now: f64) -> bool {
+        let due = self.rate > 0.0
+            && self
+                .last_sent
+                .map_or(true, |last| now - last >= 1.0 / self.rate as f64);
+        if due {
+            self.last_sent = Some(now);
+        }
+        due
+    }
+}
+
+/// Bounds for the per-peer send rate.
+#[derive(Resource, Debug, Clone)]
+pub struct SendRateConfig {
+    /// Updates per second for peers with a good link.
+    pub max_rate: f32,
+    /// Updates per second for the worst links. We never go below this.
+    pub min_rate: f32,
+    /// Round trips up to this get [`max_rate`](Self::max_rate).
+    pub good_rtt: Duration,
+    /// Round trips from this up get [`min_rate`](Self::min_rate).
+    pub bad_rtt: Duration,
+    /// Messages waiting for the network thread before every peer is slowed down.
+    pub max_queue_depth: usize,
+    /// How quickly the rate follows its target, as a fraction per second. Keeps a single
+    /// slow ping from halving the rate.
+    pub adjust_per_second: f32,
+}
+
+impl Default for SendRateConfig {
+    fn default() -> Self {
+        Self {
+            max_rate: 30.0,
+            min_rate: 5.0,
+            good_rtt: Duration::from_millis(100),
+            bad_rtt: Duration::from_millis(500),
+            max_queue_depth: 32,
+            adjust_per_second: 2.0,
+        }
+    }
+}
+
+impl SendRateConfig {
+    /// The rate a peer should settle at. A peer we haven't pinged yet gets the full rate.
+    pub fn target_rate(&self, rtt: Option<Duration>, queue_depth: usize) -> f32 {
+        let mut rate = match rtt {
+            None => self.max_rate,
+            Some(rtt) => {
+                let range = self.bad_rtt.saturating_sub(self.good_rtt).as_secs_f32();
+                let excess = rtt.saturating_sub(self.good_rtt).as_secs_f32();
+                let slowdown = if range > 0.0 {
+                    (excess / range).min(1.0)
+                } else if excess > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                };
+                self.max_rate - slowdown * (self.max_rate - self.min_rate)
+            }
+        };
+        // The queue is shared, so a backlog slows everyone down in proportion.
+        if queue_depth > self.max_queue_depth {
+            rate *= self.max_queue_depth as f32 / queue_depth as f32;
+        }
+        rate.max(self.min_rate)
+    }
+}
+
+fn adapt_send_rates<FromGame, ToGame>(
+    time: Res<Time>,
+    config: Res<SendRateConfig>,
+    manager: Res<NetworkManager<FromGame, ToGame>>,
+    mut peers: Query<(&mut SendRate, Option<&Latency>)>,
+) where
+    FromGame: Send + 'static,
+    ToGame: Send + 'static,
+{
+    let queue_depth = manager.queued();
+    let step = (config.adjust_per_second * time.delta_seconds()).min(1.0);
+    for (mut send_rate, latency) in &mut peers {
+        let target = config.target_rate(latency.map(|latency| latency.0), queue_depth);
+        send_rate.rate = if send_rate.rate > 0.0 {
+            send_rate.rate + (target - send_rate.rate) * step
+        } else {
+            target
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_drops_with_latency_and_backlog() {
+        let config = SendRateConfig::default();
+        assert_eq!(config.target_rate(None, 0), 30.0);
+        assert_eq!(config.target_rate(Some(Duration::from_millis(50)), 0), 30.0);
+        assert_eq!(
+            config.target_rate(Some(Duration::from_millis(300)), 0),
+            17.5
+        );
+        assert_eq!(config.target_rate(Some(Duration::from_secs(2)), 0), 5.0);
+        assert_eq!(config.target_rate(None, 64), 15.0);
+        assert_eq!(config.target_rate(Some(Duration::from_secs(2)), 64), 5.0);
+    }
+
+    #[test]
+    fn take_due_follows_the_rate() {
+        let mut send_rate = SendRate::default();
+        assert!(!send_rate.take_due(0.0));
+        send_rate.rate = 10.0;
+        assert!(send_rate.take_due(0.0));
+        assert!(!send_rate.take_due(0.05));
+        assert!(send_rate.take_due(0.1));
+    }
+}
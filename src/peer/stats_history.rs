@@ -0,0 +1,176 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+use serde::Serialize;
+
+use super::{Latency, PeerIdComp, PeerStats};
+use crate::network::{ConnectionPath, NetworkManager};
+
+/// How often every peer's link is sampled.
+const SAMPLE_EVERY: Duration = Duration::from_secs(1);
+
+/// Samples kept across all peers, an hour's worth for ten peers. The oldest go first.
+const MAX_SAMPLES: usize = 36_000;
+
+/// Samples every peer's [`Latency`], [`PeerStats`] and [`ConnectionPath`] once a
+/// second, so a netcode tuning session can be exported and looked at offline.
+pub struct StatsHistoryPlugin;
+
+impl Plugin for StatsHistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StatsHistory>()
+            .add_systems(Update, sample_stats::<(), ()>);
+    }
+}
+
+/// One peer's link at one point in time.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatsSample {
+    /// Milliseconds since the Unix epoch, to line up with the other peers' logs.
+    pub unix_ms: u64,
+    pub peer_id: PeerId,
+    /// The latest ping round trip, once there was one.
+    pub rtt_ms: Option<f64>,
+    /// Latest-wins messages the peer acknowledged or lost so far.
+    pub acked: u64,
+    pub lost: u64,
+    pub path: Option<ConnectionPath>,
+    /// Bytes exchanged with all peers so far. The network layer doesn't count bytes
+    /// per peer.
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+/// Every [`StatsSample`] taken this session, oldest first.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct StatsHistory(VecDeque<StatsSample>);
+
+impl StatsHistory {
+    pub fn iter(&self) -> impl Iterator<Item = &StatsSample> {
+        self.0.iter()
+    }
+
+    pub fn record(&mut self, sample: StatsSample) {
+        if self.0.len() == MAX_SAMPLES {
+            self.0.pop_front();
+        }
+        self.0.push_back(sample);
+    }
+
+    /// All samples as a JSON array.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&self.0)
+    }
+
+    /// All samples as CSV with a header row. Missing values are left empty.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("unix_ms,peer_id,rtt_ms,acked,lost,path,bytes_in,bytes_out\n");
+        for sample in &self.0 {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{}",
+                sample.unix_ms,
+                sample.peer_id,
+                sample
+                    .rtt_ms
+                    .map_or(String::new(), |rtt| format!("{:.3}", rtt)),
+                sample.acked,
+                sample.lost,
+                sample
+                    .path
+                    .map_or(String::new(), |path| format!("{:?}", path)),
+                sample.bytes_in,
+                sample.bytes_out,
+            );
+        }
+        csv
+    }
+}
+
+fn sample_stats<FromGame, ToGame>(
+    time: Res<Time>,
+    manager: Option<Res<NetworkManager<FromGame, ToGame>>>,
+    mut history: ResMut<StatsHistory>,
+    mut last_sample: Local<Option<Duration>>,
+    peers: Query<(
+        &PeerIdComp,
+        &PeerStats,
+        Option<&Latency>,
+        Option<&ConnectionPath>,
+    )>,
+) where
+    FromGame: Send + Sync + 'static,
+    ToGame: Send + Sync + 'static,
+{
+    let now = time.elapsed();
+    if last_sample.map_or(false, |last| now - last < SAMPLE_EVERY) {
+        return;
+    }
+    *last_sample = Some(now);
+    let metrics = manager.map(|manager| manager.metrics()).unwrap_or_default();
+    let unix_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64);
+    for (peer_id, stats, latency, path) in &peers {
+        history.record(StatsSample {
+            unix_ms,
+            peer_id: peer_id.0,
+            rtt_ms: latency.map(|latency| latency.0.as_secs_f64() * 1000.0),
+            acked: stats.acked,
+            lost: stats.lost,
+            path: path.copied(),
+            bytes_in: metrics.bytes_in,
+            bytes_out: metrics.bytes_out,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_leave_missing_values_empty() {
+        let peer_id = PeerId::random();
+        let mut history = StatsHistory::default();
+        history.record(StatsSample {
+            unix_ms: 1,
+            peer_id,
+            rtt_ms: None,
+            acked: 9,
+            lost: 1,
+            path: None,
+            bytes_in: 100,
+            bytes_out: 200,
+        });
+        history.record(StatsSample {
+            unix_ms: 1001,
+            peer_id,
+            rtt_ms: Some(42.5),
+            acked: 10,
+            lost: 1,
+            path: Some(ConnectionPath::Relayed),
+            bytes_in: 150,
+            bytes_out: 300,
+        });
+
+        let csv = history.to_csv();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1], format!("1,{},,9,1,,100,200", peer_id));
+        assert_eq!(
+            rows[2],
+            format!("1001,{},42.500,10,1,Relayed,150,300", peer_id)
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&history.to_json().unwrap()).unwrap();
+        assert_eq!(json[0]["rtt_ms"], serde_json::Value::Null);
+        assert_eq!(json[1]["rtt_ms"], 42.5);
+        assert_eq!(json[1]["peer_id"], peer_id.to_string());
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+use serde::Serialize;
+
+use super::ReputationEvent;
+use crate::lobby::AnswerJoinRequest;
+use crate::network::{ConnectionPath, NetworkAdminEvent, NetworkEvent};
+
+/// Entries kept per peer. The oldest go first, but a peer rarely gets near this.
+const MAX_ENTRIES: usize = 256;
+
+/// Records what happened to every peer and when, so "a player randomly dropped" can
+/// be traced after the fact. Kept for peers that are gone too.
+pub struct TimelinePlugin;
+
+impl Plugin for TimelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PeerTimelines>()
+            .add_systems(Update, record_timelines::<()>);
+    }
+}
+
+/// A step in a peer's life, as seen from here.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TimelineEvent {
+    /// We have a connection to the peer now, or it moved to a different path.
+    Connected {
+        path: ConnectionPath,
+    },
+    /// The peer turned out to run this game.
+    Identified,
+    JoinRequested {
+        name: String,
+    },
+    /// The peer has the room key: we let it in, or it let us into its room.
+    Keyed,
+    Ready {
+        ready: bool,
+    },
+    /// A ping went unanswered.
+    TimedOut,
+    DecodeFailed,
+    Throttled,
+    Unthrottled,
+    /// Its reputation got it disconnected.
+    Kicked,
+    /// It said it's leaving the room.
+    Left,
+    Disconnected,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TimelineEntry {
+    /// Milliseconds since the Unix epoch, to line up with the other peers' logs.
+    pub unix_ms: u64,
+    #[serde(flatten)]
+    pub event: TimelineEvent,
+}
+
+/// Every peer's [`TimelineEntry`]s, oldest first.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct PeerTimelines(HashMap<PeerId, VecDeque<TimelineEntry>>);
+
+impl PeerTimelines {
+    pub fn get(&self, peer_id: &PeerId) -> Option<&VecDeque<TimelineEntry>> {
+        self.0.get(peer_id)
+    }
+
+    pub fn peers(&self) -> impl Iterator<Item = &PeerId> {
+        self.0.keys()
+    }
+
+    pub fn record(&mut self, peer_id: PeerId, event: TimelineEvent) {
+        let unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as u64);
+        let entries = self.0.entry(peer_id).or_default();
+        if entries.len() == MAX_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back(TimelineEntry { unix_ms, event });
+    }
+
+    /// All timelines as a JSON object keyed by peer id, for attaching to bug reports.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        let by_peer: BTreeMap<String, &VecDeque<TimelineEntry>> = self
+            .0
+            .iter()
+            .map(|(peer_id, entries)| (peer_id.to_string(), entries))
+            .collect();
+        serde_json::to_string_pretty(&by_peer)
+    }
+}
+
+fn record_timelines<ToGame>(
+    mut timelines: ResMut<PeerTimelines>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+    mut answers: EventReader<AnswerJoinRequest>,
+    mut reputation_events: EventReader<ReputationEvent>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        let (peer_id, event) = match event {
+            NetworkAdminEvent::ConnectionPathChanged { peer_id, path, .. } => {
+                (*peer_id, TimelineEvent::Connected { path: *path })
+            }
+            NetworkAdminEvent::Connected(peer_id) => (*peer_id, TimelineEvent::Identified),
+            NetworkAdminEvent::JoinRequested { peer_id, name } => (
+                *peer_id,
+                TimelineEvent::JoinRequested { name: name.clone() },
+            ),
+            NetworkAdminEvent::JoinAccepted { host, .. } => (*host, TimelineEvent::Keyed),
+            NetworkAdminEvent::PeerReady { peer_id, ready } => {
+                (*peer_id, TimelineEvent::Ready { ready: *ready })
+            }
+            NetworkAdminEvent::PingFailed(peer_id) => (*peer_id, TimelineEvent::TimedOut),
+            NetworkAdminEvent::DecodeFailed(peer_id) => (*peer_id, TimelineEvent::DecodeFailed),
+            NetworkAdminEvent::PlayerLeft(peer_id) => (*peer_id, TimelineEvent::Left),
+            NetworkAdminEvent::Disconnected(peer_id) => (*peer_id, TimelineEvent::Disconnected),
+            _ => continue,
+        };
+        timelines.record(peer_id, event);
+    }
+    for answer in answers.iter().filter(|answer| answer.accept) {
+        timelines.record(answer.peer_id, TimelineEvent::Keyed);
+    }
+    for event in reputation_events.iter() {
+        let (peer_id, event) = match event {
+            ReputationEvent::Throttled(peer_id) => (*peer_id, TimelineEvent::Throttled),
+            ReputationEvent::Unthrottled(peer_id) => (*peer_id, TimelineEvent::Unthrottled),
+            ReputationEvent::Disconnected(peer_id) => (*peer_id, TimelineEvent::Kicked),
+        };
+        timelines.record(peer_id, event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timelines_export_to_json_by_peer() {
+        let peer_id = PeerId::random();
+        let mut timelines = PeerTimelines::default();
+        timelines.record(peer_id, TimelineEvent::Ready { ready: true });
+        let json: serde_json::Value = serde_json::from_str(&timelines.to_json().unwrap()).unwrap();
+        let entry = &json[peer_id.to_string()][0];
+        assert_eq!(entry["event"], "ready");
+        assert_eq!(entry["ready"], true);
+        assert!(entry["unix_ms"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn old_entries_make_room() {
+        let peer_id = PeerId::random();
+        let mut timelines = PeerTimelines::default();
+        timelines.record(peer_id, TimelineEvent::Identified);
+        for _ in 0..MAX_ENTRIES {
+            timelines.record(peer_id, TimelineEvent::TimedOut);
+        }
+        let entries = timelines.get(&peer_id).unwrap();
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].event, TimelineEvent::TimedOut);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::actions::Actions;
 use crate::loading::TextureAssets;
+use crate::lobby::PlayerSettings;
 use crate::GameState;
 use bevy::prelude::*;
 
@@ -17,7 +18,15 @@ impl Plugin for PlayerPlugin {
     }
 }
 
-fn spawn_player(mut commands: Commands, textures: Res<TextureAssets>) {
+fn spawn_player(
+    mut commands: Commands,
+    textures: Res<TextureAssets>,
+    settings: Res<PlayerSettings>,
+) {
+    // Spectators look around with the spectator camera instead.
+    if settings.spectator {
+        return;
+    }
     commands
         .spawn(SpriteBundle {
             texture: textures.texture_bevy.clone(),
@@ -0,0 +1,218 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::game_mode::{GameModes, SyncStrategy};
+use crate::late_join::{ProvideSnapshot, SnapshotWanted};
+use crate::lobby::Readiness;
+use crate::network::{
+    AsyncNetworkTasks, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent,
+};
+use crate::tick_negotiation::TickNegotiation;
+
+/// Keeps [`SessionRng`] seeded from the host so every peer draws the same numbers.
+pub struct SessionRngPlugin;
+
+impl Plugin for SessionRngPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SessionRng>()
+            .add_event::<RequestStartGame>()
+            .add_event::<RequestNewRound>()
+            .add_systems(
+                Update,
+                (
+                    send_host_seeds,
+                    reseed_from_network::<()>,
+                    share_with_late_joiners,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Host only: start the game with a freshly generated seed, `countdown` from now.
+/// Ignored until every player is [ready](crate::lobby::Readiness::all_ready).
+#[derive(Event, Debug, Clone, Copy)]
+pub struct RequestStartGame {
+    pub countdown: Duration,
+}
+
+impl Default for RequestStartGame {
+    fn default() -> Self {
+        Self {
+            countdown: Duration::from_secs(3),
+        }
+    }
+}
+
+/// Host only: start the next round with a freshly generated seed.
+#[derive(Event, Debug, Clone, Copy, Default)]
+pub struct RequestNewRound;
+
+/// Deterministic randomness shared by every peer in the session.
+///
+/// The seed comes from the host in the `StartGame` message and is replaced every
+/// round, so systems must draw from it in the same order on every peer.
+#[derive(Resource, Debug, Clone)]
+pub struct SessionRng {
+    seed: u64,
+    round: u32,
+    rng: ChaCha8Rng,
+}
+
+impl Default for SessionRng {
+    fn default() -> Self {
+        Self::new(0, 0)
+    }
+}
+
+impl SessionRng {
+    fn new(seed: u64, round: u32) -> Self {
+        Self {
+            seed,
+            round,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn round(&self) -> u32 {
+        self.round
+    }
+
+    /// The shared generator. Every draw advances it for all systems.
+    pub fn rng(&mut self) -> &mut ChaCha8Rng {
+        &mut self.rng
+    }
+
+    /// An independent generator for `stream`, so a system can draw without caring
+    /// what other systems did with [`Self::rng`] this round.
+    pub fn stream(&self, stream: u64) -> ChaCha8Rng {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(stream);
+        rng
+    }
+
+    /// Everything a late joiner needs to draw the same numbers from here on.
+    fn to_snapshot(&self) -> Vec<u8> {
+        bincode::serialize(&(self.seed, self.round, self.rng.get_word_pos()))
+            .expect("Integers always serialize")
+    }
+
+    fn from_snapshot(state: &[u8]) -> bincode::Result<Self> {
+        let (seed, round, word_pos): (u64, u32, u128) = bincode::deserialize(state)?;
+        let mut rng = Self::new(seed, round);
+        rng.rng.set_word_pos(word_pos);
+        Ok(rng)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    pub fn gen_range(&mut self, range: std::ops::Range<f32>) -> f32 {
+        self.rng.gen_range(range)
+    }
+}
+
+fn send_host_seeds(
+    rng: Res<SessionRng>,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    readiness: Option<Res<Readiness>>,
+    modes: Option<Res<GameModes>>,
+    negotiation: Option<Res<TickNegotiation>>,
+    mut start_requests: EventReader<RequestStartGame>,
+    mut round_requests: EventReader<RequestNewRound>,
+) {
+    for request in start_requests.iter() {
+        // Without the lobby nobody tracks readiness, so there's nobody to wait for.
+        if let Some(readiness) = readiness.as_ref().filter(|r| !r.all_ready()) {
+            log::warn!(
+                "Not starting: waiting for {} players to get ready",
+                readiness.waiting_for()
+            );
+            continue;
+        }
+        let players = readiness.as_ref().map_or(1, |r| r.players() + 1);
+        if let Some(mode) = modes
+            .as_ref()
+            .and_then(|modes| modes.selected())
+            .filter(|mode| players < mode.min_players)
+        {
+            log::warn!(
+                "Not starting: {} needs {} players, the room has {}",
+                mode.name,
+                mode.min_players,
+                players
+            );
+            continue;
+        }
+        let seed = rand::random();
+        let tick = modes
+            .as_ref()
+            .and_then(|modes| modes.selected())
+            .filter(|mode| mode.sync == SyncStrategy::Lockstep)
+            .zip(negotiation.as_ref())
+            .map(|(mode, negotiation)| negotiation.pick(mode.tick_rate));
+        tasks.send(GameEvent::Admin(GameAdminEvent::StartGame {
+            seed,
+            countdown: request.countdown,
+            tick,
+        }));
+    }
+    for _ in round_requests.iter() {
+        let event = GameAdminEvent::NewRound {
+            round: rng.round() + 1,
+            seed: rand::random(),
+        };
+        tasks.send(GameEvent::Admin(event));
+    }
+}
+
+fn reseed_from_network<ToGame>(
+    mut rng: ResMut<SessionRng>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        match event {
+            NetworkEvent::Admin(NetworkAdminEvent::GameStarted { seed, .. }) => {
+                log::info!("Game started with seed {}", seed);
+                *rng = SessionRng::new(*seed, 0);
+            }
+            NetworkEvent::Admin(NetworkAdminEvent::RoundStarted { round, seed }) => {
+                log::info!("Round {} started with seed {}", round, seed);
+                *rng = SessionRng::new(*seed, *round);
+            }
+            NetworkEvent::Admin(NetworkAdminEvent::SnapshotReceived(Some(snapshot))) => {
+                match SessionRng::from_snapshot(&snapshot.state) {
+                    Ok(caught_up) => *rng = caught_up,
+                    Err(e) => log::error!("Host sent a malformed snapshot: {}", e),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The demo's only shared state is the session RNG, so that is its whole snapshot.
+/// Rounds stand in for ticks.
+fn share_with_late_joiners(
+    rng: Res<SessionRng>,
+    mut wanted: EventReader<SnapshotWanted>,
+    mut snapshots: EventWriter<ProvideSnapshot>,
+) {
+    for SnapshotWanted { peer_id } in wanted.iter() {
+        snapshots.send(ProvideSnapshot {
+            peer_id: *peer_id,
+            tick: rng.round() as u64,
+            state: rng.to_snapshot(),
+        });
+    }
+}
@@ -0,0 +1,286 @@
+//! Room codes: six characters from an alphabet without look-alikes, plus a check
+//! character so typos are caught before we go looking for the room.
+//!
+//! Six characters are few enough to scan for, so private matches use an
+//! [`InviteToken`] instead, written with the same alphabet.
+
+use std::fmt;
+
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+/// Digits and capitals without 0/O and 1/I, which are easy to mix up.
+pub const ROOM_CODE_ALPHABET: &[u8; 32] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+const RANDOM_CHARS: usize = 6;
+/// 128 bits at 5 bits a character.
+const TOKEN_CHARS: usize = 26;
+const TOKEN_GROUP: usize = 9;
+const PRIVATE_ROOM_LABEL: &[u8] = b"bevy-p2p-demo private room v1";
+/// URI scheme of clickable invites. The Windows installer registers it to launch the
+/// game with the link as its argument.
+pub const INVITE_SCHEME: &str = "bevy-p2p";
+
+/// Why a typed room code was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidRoomCode {
+    Length,
+    Character(char),
+    Checksum,
+}
+
+impl fmt::Display for InvalidRoomCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Length => write!(
+                f,
+                "a code has {} characters and an invite {}",
+                RANDOM_CHARS + 1,
+                TOKEN_CHARS + 1
+            ),
+            Self::Character(c) => write!(f, "'{}' never appears in a code", c),
+            Self::Checksum => write!(f, "there's a typo somewhere"),
+        }
+    }
+}
+
+impl std::error::Error for InvalidRoomCode {}
+
+/// Odd weights are invertible mod 32, so any single wrong character changes the sum.
+fn check_char(values: &[u8]) -> u8 {
+    let sum: usize = values
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| (2 * i + 1) * v as usize)
+        .sum();
+    ROOM_CODE_ALPHABET[sum % ROOM_CODE_ALPHABET.len()]
+}
+
+fn format_code(chars: &[u8]) -> String {
+    let (first, second) = chars.split_at(3);
+    format!(
+        "{}-{}",
+        String::from_utf8_lossy(first),
+        String::from_utf8_lossy(second)
+    )
+}
+
+/// A fresh code like `K7P-XQ2M`.
+pub fn generate_room_code() -> String {
+    let mut rng = rand::thread_rng();
+    let values: Vec<u8> = (0..RANDOM_CHARS)
+        .map(|_| rng.gen_range(0..ROOM_CODE_ALPHABET.len() as u8))
+        .collect();
+    let mut chars: Vec<u8> = values
+        .iter()
+        .map(|&v| ROOM_CODE_ALPHABET[v as usize])
+        .collect();
+    chars.push(check_char(&values));
+    format_code(&chars)
+}
+
+/// Alphabet positions of what the player typed, ignoring case, dashes and spaces.
+fn parse_values(input: &str) -> Result<Vec<u8>, InvalidRoomCode> {
+    input
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .map(|c| {
+            let c = c.to_ascii_uppercase();
+            ROOM_CODE_ALPHABET
+                .iter()
+                .position(|&a| a as char == c)
+                .map(|v| v as u8)
+                .ok_or(InvalidRoomCode::Character(c))
+        })
+        .collect()
+}
+
+/// Normalizes what the player typed (case, dashes, spaces) and checks it, returning
+/// the code in the form the host advertises.
+pub fn parse_room_code(input: &str) -> Result<String, InvalidRoomCode> {
+    let values = parse_values(input)?;
+    if values.len() != RANDOM_CHARS + 1 {
+        return Err(InvalidRoomCode::Length);
+    }
+    let chars: Vec<u8> = values
+        .iter()
+        .map(|&v| ROOM_CODE_ALPHABET[v as usize])
+        .collect();
+    if check_char(&values[..RANDOM_CHARS]) != chars[RANDOM_CHARS] {
+        return Err(InvalidRoomCode::Checksum);
+    }
+    Ok(format_code(&chars))
+}
+
+/// A link that opens the game straight into joining `code`, a room code or invite
+/// token, like `bevy-p2p://join/K7P-XQ2M`.
+pub fn invite_uri(code: &str) -> String {
+    format!("{}://join/{}", INVITE_SCHEME, code)
+}
+
+/// The code in an [`invite_uri`] link, not checked yet. Chat apps sometimes add a
+/// trailing slash.
+pub fn parse_invite_uri(uri: &str) -> Option<&str> {
+    let code = uri
+        .strip_prefix(INVITE_SCHEME)?
+        .strip_prefix("://join/")?
+        .trim_end_matches('/');
+    (!code.is_empty()).then_some(code)
+}
+
+/// A private match invite: 128 random bits, far too many to guess.
+///
+/// The room is announced under [`room_id`](Self::room_id), a hash of the token, and
+/// joiners prove they hold the token itself in the join challenge.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct InviteToken([u8; 16]);
+
+impl InviteToken {
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    /// What the room is announced and looked up under. Seeing it in the DHT doesn't
+    /// help anyone join.
+    pub fn room_id(&self) -> String {
+        let digest = Sha256::new()
+            .chain_update(PRIVATE_ROOM_LABEL)
+            .chain_update(self.0)
+            .finalize();
+        let hex: String = digest[..10].iter().map(|b| format!("{:02x}", b)).collect();
+        format!("private-{}", hex)
+    }
+
+    /// Alphabet positions, 5 bits each; the last character holds the final 3 bits.
+    fn values(&self) -> Vec<u8> {
+        let bits = u128::from_be_bytes(self.0);
+        (0..TOKEN_CHARS)
+            .map(|i| {
+                let end = 5 * (i + 1);
+                let value = if end <= 128 {
+                    bits >> (128 - end)
+                } else {
+                    bits << (end - 128)
+                };
+                (value & 0x1f) as u8
+            })
+            .collect()
+    }
+}
+
+/// Shown as three groups like `K7PXQ2M4A-...`, check character last.
+impl fmt::Display for InviteToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut values = self.values();
+        values.push(
+            ROOM_CODE_ALPHABET
+                .iter()
+                .position(|&c| c == check_char(&values))
+                .expect("Check characters come from the alphabet") as u8,
+        );
+        let chars: Vec<String> = values
+            .chunks(TOKEN_GROUP)
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|&v| ROOM_CODE_ALPHABET[v as usize] as char)
+                    .collect()
+            })
+            .collect();
+        write!(f, "{}", chars.join("-"))
+    }
+}
+
+/// Never print the secret by accident.
+impl fmt::Debug for InviteToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InviteToken({})", self.room_id())
+    }
+}
+
+/// Like [`parse_room_code`], for invites.
+pub fn parse_invite_token(input: &str) -> Result<InviteToken, InvalidRoomCode> {
+    let values = parse_values(input)?;
+    if values.len() != TOKEN_CHARS + 1 {
+        return Err(InvalidRoomCode::Length);
+    }
+    let (values, check) = values.split_at(TOKEN_CHARS);
+    // The low two bits of the last character are padding and always zero.
+    if ROOM_CODE_ALPHABET[check[0] as usize] != check_char(values)
+        || values[TOKEN_CHARS - 1] & 0b11 != 0
+    {
+        return Err(InvalidRoomCode::Checksum);
+    }
+    let bits = values[..TOKEN_CHARS - 1]
+        .iter()
+        .fold(0u128, |bits, &v| (bits << 5) | v as u128);
+    let bits = (bits << 3) | (values[TOKEN_CHARS - 1] >> 2) as u128;
+    Ok(InviteToken(bits.to_be_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_codes_parse() {
+        for _ in 0..100 {
+            let code = generate_room_code();
+            assert_eq!(parse_room_code(&code), Ok(code.clone()));
+            assert_eq!(
+                parse_room_code(&code.to_lowercase().replace('-', " ")),
+                Ok(code)
+            );
+        }
+    }
+
+    #[test]
+    fn invite_tokens_round_trip() {
+        for _ in 0..100 {
+            let token = InviteToken::generate();
+            let typed = token.to_string();
+            assert_eq!(parse_invite_token(&typed), Ok(token));
+            assert_eq!(
+                parse_invite_token(&typed.to_lowercase().replace('-', "")),
+                Ok(token)
+            );
+            assert!(parse_room_code(&typed).is_err());
+        }
+    }
+
+    #[test]
+    fn catches_single_typos() {
+        let code = generate_room_code();
+        for (i, original) in code.char_indices().filter(|(_, c)| *c != '-') {
+            for &replacement in ROOM_CODE_ALPHABET.iter() {
+                if replacement as char == original {
+                    continue;
+                }
+                let mut typo = code.clone();
+                typo.replace_range(i..i + 1, &(replacement as char).to_string());
+                assert_eq!(parse_room_code(&typo), Err(InvalidRoomCode::Checksum));
+            }
+        }
+        assert_eq!(
+            parse_room_code("ABC-DEF"),
+            Err(InvalidRoomCode::Length),
+            "missing the check character"
+        );
+        assert_eq!(
+            parse_room_code("AB0-DEFG"),
+            Err(InvalidRoomCode::Character('0'))
+        );
+    }
+
+    #[test]
+    fn invite_uris_round_trip() {
+        let code = generate_room_code();
+        assert_eq!(parse_invite_uri(&invite_uri(&code)), Some(code.as_str()));
+        assert_eq!(
+            parse_invite_uri("bevy-p2p://join/K7P-XQ2M/"),
+            Some("K7P-XQ2M")
+        );
+        assert_eq!(parse_invite_uri("bevy-p2p://join/"), None);
+        assert_eq!(parse_invite_uri("https://join/K7P-XQ2M"), None);
+    }
+}
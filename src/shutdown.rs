@@ -0,0 +1,131 @@
+//! Leaving the room cleanly when the game ends. Closing the window or sending
+//! [`AppExit`] asks the network thread to quit, and the window only closes, or the
+//! app only exits, once it said goodbye to our peers or [`SHUTDOWN_TIMEOUT_SECS`]
+//! passed. Nothing blocks meanwhile: the app keeps running frames until then.
+
+use bevy::{app::AppExit, prelude::*, window::WindowCloseRequested};
+
+use crate::network::{GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent, NetworkManager};
+
+/// How long we wait for the network before closing anyway.
+const SHUTDOWN_TIMEOUT_SECS: f32 = 3.0;
+
+pub struct ShutdownPlugin;
+
+impl Plugin for ShutdownPlugin {
+    fn build(&self, app: &mut App) {
+        // Headless apps have no window plugin to add it.
+        app.add_event::<WindowCloseRequested>()
+            .init_resource::<Shutdown>()
+            .add_systems(Update, (request_shutdown, finish_shutdown).chain())
+            // After everything else, so the runner never sees an exit we hold back.
+            .add_systems(Last, hold_app_exit);
+    }
+}
+
+/// Where we are in closing the game.
+#[derive(Resource, Debug, Default)]
+pub enum Shutdown {
+    #[default]
+    Running,
+    /// Quit was sent; on `ShutdownComplete` or once `timeout` ends, `window` closes,
+    /// or the app exits if it's `None`.
+    Requested {
+        window: Option<Entity>,
+        timeout: Timer,
+    },
+    /// The window is being closed, or the app is exiting.
+    Done,
+}
+
+impl Shutdown {
+    /// Asks the network, if there is one still running, to quit, and starts waiting
+    /// for it.
+    fn request(manager: Option<&NetworkManager<(), ()>>, window: Option<Entity>) -> Self {
+        // Without a network, e.g. because it failed to start, there's nobody to wait for.
+        let Some(manager) = manager.filter(|manager| !manager.stopped()) else {
+            return Self::Requested {
+                window,
+                timeout: Timer::from_seconds(0.0, TimerMode::Once),
+            };
+        };
+        if manager
+            .try_send_to_network(GameEvent::Admin(GameAdminEvent::Quit))
+            .is_err()
+        {
+            log::warn!("Network thread is already gone");
+        }
+        Self::Requested {
+            window,
+            timeout: Timer::from_seconds(SHUTDOWN_TIMEOUT_SECS, TimerMode::Once),
+        }
+    }
+}
+
+fn request_shutdown(
+    mut shutdown: ResMut<Shutdown>,
+    manager: Option<Res<NetworkManager<(), ()>>>,
+    mut events: EventReader<WindowCloseRequested>,
+) {
+    for event in events.iter() {
+        if !matches!(*shutdown, Shutdown::Running) {
+            continue;
+        }
+        log::info!("Window closing, leaving the network");
+        *shutdown = Shutdown::request(manager.as_deref(), Some(event.window));
+    }
+}
+
+/// Takes back any [`AppExit`] sent before the network said goodbye, and sends it
+/// again from [`finish_shutdown`] once it has.
+fn hold_app_exit(
+    mut shutdown: ResMut<Shutdown>,
+    manager: Option<Res<NetworkManager<(), ()>>>,
+    mut exits: ResMut<Events<AppExit>>,
+) {
+    if exits.is_empty() {
+        return;
+    }
+    match shutdown.as_mut() {
+        Shutdown::Done => return,
+        Shutdown::Running => {
+            if manager.as_ref().map_or(true, |manager| manager.stopped()) {
+                return;
+            }
+            log::info!("App exiting, leaving the network");
+            *shutdown = Shutdown::request(manager.as_deref(), None);
+        }
+        // Exiting takes everything down with it, not just the window.
+        Shutdown::Requested { window, .. } => *window = None,
+    }
+    exits.clear();
+}
+
+fn finish_shutdown(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut shutdown: ResMut<Shutdown>,
+    mut network_events: EventReader<NetworkEvent<()>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let completed = network_events.iter().any(|event| {
+        matches!(
+            event,
+            NetworkEvent::Admin(NetworkAdminEvent::ShutdownComplete)
+        )
+    });
+    let Shutdown::Requested { window, timeout } = shutdown.as_mut() else {
+        return;
+    };
+    if !completed && !timeout.tick(time.delta()).finished() {
+        return;
+    }
+    if !completed {
+        log::warn!("Network did not confirm shutdown in time, closing anyway");
+    }
+    match window {
+        Some(window) => commands.entity(*window).despawn_recursive(),
+        None => exit.send(AppExit),
+    }
+    *shutdown = Shutdown::Done;
+}
@@ -0,0 +1,150 @@
+//! Hiding corrections to the local player. When the host's state says we mispredicted,
+//! the simulation has to snap to it, but the player shouldn't see a teleport: the
+//! snap goes into a [`RenderOffset`] that keeps drawing the entity where it was, and
+//! that offset fades out over [`ErrorSmoothing::window`].
+//!
+//! Send a [`SnapCorrection`] instead of writing the corrected `Transform` yourself.
+//! The `Transform` stays exact for the simulation; only what's drawn lags behind.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+pub struct ErrorSmoothingPlugin;
+
+impl Plugin for ErrorSmoothingPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<RenderOffset>()
+            .init_resource::<ErrorSmoothing>()
+            .add_event::<SnapCorrection>()
+            .add_systems(Update, (apply_corrections, decay_offsets).chain())
+            .add_systems(
+                PostUpdate,
+                (
+                    repropagate_offset.before(TransformSystem::TransformPropagate),
+                    offset_rendering.after(TransformSystem::TransformPropagate),
+                ),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct ErrorSmoothing {
+    /// How long a correction takes to fade out. Zero snaps straight away.
+    pub window: Duration,
+    /// Corrections longer than this are shown as the teleport they are, e.g. a respawn.
+    pub max_distance: f32,
+}
+
+impl Default for ErrorSmoothing {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_millis(150),
+            max_distance: 200.0,
+        }
+    }
+}
+
+/// Where an entity is drawn relative to its `Transform`. Added and kept up to date by
+/// [`SnapCorrection`]s.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default, Reflect)]
+pub struct RenderOffset(pub Vec3);
+
+/// The host says `entity` is really at `translation`.
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct SnapCorrection {
+    pub entity: Entity,
+    pub translation: Vec3,
+}
+
+/// Share of an offset left after `window`. Not zero, so the fade keeps its shape; the
+/// rest is dropped once it's too small to see.
+const REMAINING_AFTER_WINDOW: f32 = 0.01;
+const NEGLIGIBLE: f32 = 0.01;
+
+/// `offset` after `elapsed` more of its fade over `window`.
+pub fn decay(offset: Vec3, elapsed: Duration, window: Duration) -> Vec3 {
+    if window.is_zero() {
+        return Vec3::ZERO;
+    }
+    let remaining =
+        offset * REMAINING_AFTER_WINDOW.powf(elapsed.as_secs_f32() / window.as_secs_f32());
+    if remaining.length() < NEGLIGIBLE {
+        Vec3::ZERO
+    } else {
+        remaining
+    }
+}
+
+fn apply_corrections(
+    mut commands: Commands,
+    config: Res<ErrorSmoothing>,
+    mut corrections: EventReader<SnapCorrection>,
+    mut entities: Query<(&mut Transform, Option<&mut RenderOffset>)>,
+) {
+    for correction in corrections.iter() {
+        let Ok((mut transform, offset)) = entities.get_mut(correction.entity) else {
+            continue;
+        };
+        let shown = transform.translation + offset.as_deref().map_or(Vec3::ZERO, |offset| offset.0);
+        transform.translation = correction.translation;
+        let error = shown - correction.translation;
+        let error = if error.length() > config.max_distance {
+            Vec3::ZERO
+        } else {
+            error
+        };
+        match offset {
+            Some(mut offset) => offset.0 = error,
+            None => {
+                commands
+                    .entity(correction.entity)
+                    .insert(RenderOffset(error));
+            }
+        }
+    }
+}
+
+fn decay_offsets(
+    time: Res<Time>,
+    config: Res<ErrorSmoothing>,
+    mut offsets: Query<&mut RenderOffset>,
+) {
+    for mut offset in offsets.iter_mut() {
+        if offset.0 != Vec3::ZERO {
+            offset.0 = decay(offset.0, time.delta(), config.window);
+        }
+    }
+}
+
+/// The `GlobalTransform` is only recomputed for changed transforms, and would keep last
+/// frame's offset otherwise.
+fn repropagate_offset(mut entities: Query<&mut Transform, Changed<RenderOffset>>) {
+    for mut transform in entities.iter_mut() {
+        transform.set_changed();
+    }
+}
+
+fn offset_rendering(mut entities: Query<(&mut GlobalTransform, &RenderOffset)>) {
+    for (mut global, offset) in entities.iter_mut() {
+        if offset.0 != Vec3::ZERO {
+            *global = GlobalTransform::from_translation(offset.0) * *global;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_fade_over_the_window() {
+        let window = Duration::from_millis(100);
+        let offset = Vec3::new(10.0, 0.0, 0.0);
+        let halfway = decay(offset, window / 2, window);
+        assert!((halfway.x - 1.0).abs() < 1e-4);
+        assert_eq!(decay(offset, window * 2, window), Vec3::ZERO);
+        assert_eq!(decay(offset, Duration::ZERO, Duration::ZERO), Vec3::ZERO);
+    }
+}
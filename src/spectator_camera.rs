@@ -0,0 +1,128 @@
+//! The camera for spectators, see [`PlayerSettings::spectator`]. It flies freely with
+//! the movement keys, and Tab cycles through following each [`RemotePlayer`] and back
+//! to flying.
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::lobby::PlayerSettings;
+use crate::peer::RemotePlayer;
+use crate::GameState;
+
+/// Pixels per second the free camera flies at.
+const FLY_SPEED: f32 = 400.0;
+/// How quickly the camera catches up with the player it follows, as a fraction per
+/// second.
+const FOLLOW_SPEED: f32 = 8.0;
+
+pub struct SpectatorCameraPlugin;
+
+impl Plugin for SpectatorCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpectatorCamera>().add_systems(
+            Update,
+            (switch_target, fly_camera, follow_target)
+                .chain()
+                .run_if(in_state(GameState::Playing).and_then(spectating)),
+        );
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SpectatorCamera {
+    #[default]
+    FreeFly,
+    Follow(PeerId),
+}
+
+fn spectating(settings: Res<PlayerSettings>) -> bool {
+    settings.spectator
+}
+
+/// The player after `current` in a fixed order, or `None` after the last one.
+fn next_target(current: SpectatorCamera, players: &mut [PeerId]) -> Option<PeerId> {
+    players.sort();
+    match current {
+        SpectatorCamera::FreeFly => players.first().copied(),
+        SpectatorCamera::Follow(current) => players.iter().copied().find(|peer| *peer > current),
+    }
+}
+
+fn switch_target(
+    keys: Res<Input<KeyCode>>,
+    mut camera: ResMut<SpectatorCamera>,
+    players: Query<&RemotePlayer>,
+) {
+    if let SpectatorCamera::Follow(target) = *camera {
+        if !players.iter().any(|player| player.0 == target) {
+            *camera = SpectatorCamera::FreeFly;
+        }
+    }
+    if keys.just_pressed(KeyCode::Tab) {
+        let mut players: Vec<_> = players.iter().map(|player| player.0).collect();
+        *camera = next_target(*camera, &mut players)
+            .map_or(SpectatorCamera::FreeFly, SpectatorCamera::Follow);
+    }
+}
+
+fn fly_camera(
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    mut spectator: ResMut<SpectatorCamera>,
+    mut cameras: Query<&mut Transform, With<Camera2d>>,
+) {
+    let pressed = |keys_for: [KeyCode; 2]| keys_for.iter().any(|key| keys.pressed(*key)) as i8;
+    let direction = Vec2::new(
+        (pressed([KeyCode::D, KeyCode::Right]) - pressed([KeyCode::A, KeyCode::Left])) as f32,
+        (pressed([KeyCode::W, KeyCode::Up]) - pressed([KeyCode::S, KeyCode::Down])) as f32,
+    );
+    if direction == Vec2::ZERO {
+        return;
+    }
+    // Moving takes the camera off whoever it followed.
+    if *spectator != SpectatorCamera::FreeFly {
+        *spectator = SpectatorCamera::FreeFly;
+    }
+    let step = direction.normalize() * FLY_SPEED * time.delta_seconds();
+    for mut transform in cameras.iter_mut() {
+        transform.translation += step.extend(0.0);
+    }
+}
+
+fn follow_target(
+    time: Res<Time>,
+    spectator: Res<SpectatorCamera>,
+    players: Query<(&RemotePlayer, &GlobalTransform)>,
+    mut cameras: Query<&mut Transform, With<Camera2d>>,
+) {
+    let SpectatorCamera::Follow(target) = *spectator else {
+        return;
+    };
+    let Some((_, target)) = players.iter().find(|(player, _)| player.0 == target) else {
+        return;
+    };
+    let catch_up = (FOLLOW_SPEED * time.delta_seconds()).min(1.0);
+    for mut transform in cameras.iter_mut() {
+        let goal = target.translation().truncate();
+        let position = transform.translation.truncate().lerp(goal, catch_up);
+        transform.translation = position.extend(transform.translation.z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tab_cycles_through_players_and_back_to_flying() {
+        let mut players = vec![PeerId::random(), PeerId::random()];
+        let first = next_target(SpectatorCamera::FreeFly, &mut players).unwrap();
+        let second = next_target(SpectatorCamera::Follow(first), &mut players).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(
+            next_target(SpectatorCamera::Follow(second), &mut players),
+            None
+        );
+        assert_eq!(next_target(SpectatorCamera::FreeFly, &mut []), None);
+    }
+}
@@ -0,0 +1,121 @@
+//! Teams in the room we are in, e.g. red and blue. Each team has a channel only its
+//! members can read: send to it with [`GameEvent::Team`], and read what others sent
+//! from [`NetworkEvent::Team`]. The host sets teams up with
+//! [`GameAdminEvent::SetTeam`] and moves players between them with [`MoveToTeam`].
+//! Peer entities carry their team as a [`PeerTeam`], so systems can use
+//! `Changed<PeerTeam>` to react to moves.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::network::{
+    AsyncNetworkTasks, GameAdminEvent, GameEvent, NetworkAdminEvent, NetworkEvent,
+};
+use crate::peer::{PeerConnected, PeerIdComp, PeerTeam};
+
+pub struct TeamsPlugin;
+
+impl Plugin for TeamsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Teams>()
+            .add_event::<MoveToTeam>()
+            .add_systems(
+                Update,
+                (track_teams::<()>, send_team_moves)
+                    .chain()
+                    .run_if(resource_exists::<AsyncNetworkTasks<()>>()),
+            )
+            .add_systems(Update, tag_peer_teams.after(track_teams::<()>));
+    }
+}
+
+/// Who is on which team, as far as we've heard from the host.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Teams(HashMap<String, Vec<PeerId>>);
+
+impl Teams {
+    pub fn members(&self, team: &str) -> &[PeerId] {
+        self.0.get(team).map_or(&[], Vec::as_slice)
+    }
+
+    /// The team `peer_id` is on, if any.
+    pub fn team_of(&self, peer_id: &PeerId) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(_, members)| members.contains(peer_id))
+            .map(|(team, _)| team.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[PeerId])> {
+        self.0
+            .iter()
+            .map(|(team, members)| (team.as_str(), members.as_slice()))
+    }
+}
+
+/// Host only: put `peer_id` on `team`, or on no team. The teams it leaves and joins
+/// get new keys.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct MoveToTeam {
+    pub peer_id: PeerId,
+    pub team: Option<String>,
+}
+
+fn track_teams<ToGame>(
+    mut teams: ResMut<Teams>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        match event {
+            NetworkAdminEvent::TeamChanged { team, members } if members.is_empty() => {
+                teams.0.remove(team);
+            }
+            NetworkAdminEvent::TeamChanged { team, members } => {
+                teams.0.insert(team.clone(), members.clone());
+            }
+            NetworkAdminEvent::HostingStarted { .. }
+            | NetworkAdminEvent::JoinAccepted { .. }
+            | NetworkAdminEvent::LeftRoom => teams.0.clear(),
+            _ => {}
+        }
+    }
+}
+
+/// Keeps every peer entity's [`PeerTeam`] in line with [`Teams`], touching only those
+/// whose team changed.
+fn tag_peer_teams(
+    mut commands: Commands,
+    teams: Res<Teams>,
+    peers: Query<(Entity, &PeerIdComp, Option<&PeerTeam>)>,
+    connected: Query<(), Added<PeerConnected>>,
+) {
+    if !teams.is_changed() && connected.is_empty() {
+        return;
+    }
+    for (entity, peer_id, current) in &peers {
+        let team = teams.team_of(&peer_id.0);
+        if team == current.map(|current| current.0.as_str()) {
+            continue;
+        }
+        match team {
+            Some(team) => commands.entity(entity).insert(PeerTeam(team.to_string())),
+            None => commands.entity(entity).remove::<PeerTeam>(),
+        };
+    }
+}
+
+fn send_team_moves(mut moves: EventReader<MoveToTeam>, mut tasks: ResMut<AsyncNetworkTasks<()>>) {
+    for MoveToTeam { peer_id, team } in moves.iter() {
+        tasks.send(GameEvent::Admin(GameAdminEvent::MoveToTeam {
+            peer_id: *peer_id,
+            team: team.clone(),
+        }));
+    }
+}
@@ -0,0 +1,98 @@
+//! A second player in the same process, for trying host and joiner side by side
+//! without a second machine. It runs as an [`ExtraSessionPlugin`] session with its own
+//! identity, and an egui window joins it to the room we host and shows what it sees.
+
+use bevy::prelude::*;
+use bevy_inspector_egui::{bevy_egui::EguiContexts, egui};
+use serde::{Deserialize, Serialize};
+
+use crate::network::{
+    AsyncNetworkTasks, CurrentRoom, ExtraSessionPlugin, GameAdminEvent, GameEvent,
+    NetworkAdminEvent, NetworkEvent, SessionState,
+};
+
+/// Log lines shown in the window.
+const LOG_LINES: usize = 12;
+
+/// Marks the test client's session. Encodes like `()`, so it plays with the main one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestClient;
+
+/// Runs the test client once a `NetworkManager<TestClient, TestClient>` is inserted.
+pub struct TestClientPlugin;
+
+impl Plugin for TestClientPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ExtraSessionPlugin::<TestClient>::default())
+            .add_systems(
+                Update,
+                (follow_host, show_test_client)
+                    .run_if(resource_exists::<AsyncNetworkTasks<TestClient>>()),
+            );
+    }
+}
+
+/// Joins the test client to every room we start hosting.
+fn follow_host(
+    mut network_events: EventReader<NetworkEvent<()>>,
+    mut tasks: ResMut<AsyncNetworkTasks<TestClient>>,
+) {
+    for event in network_events.iter() {
+        if let NetworkEvent::Admin(NetworkAdminEvent::HostingStarted { room_code }) = event {
+            join(&mut tasks, room_code);
+        }
+    }
+}
+
+fn join(tasks: &mut AsyncNetworkTasks<TestClient>, room_code: &str) {
+    tasks.send(GameEvent::Admin(GameAdminEvent::Join {
+        room_code: room_code.to_string(),
+        name: "Test client".to_string(),
+    }));
+}
+
+fn show_test_client(
+    mut contexts: EguiContexts,
+    mut tasks: ResMut<AsyncNetworkTasks<TestClient>>,
+    state: Res<SessionState<TestClient>>,
+    hosted: Option<Res<CurrentRoom>>,
+    mut events: EventReader<NetworkEvent<TestClient>>,
+    mut last_event: Local<Option<String>>,
+) {
+    for event in events.iter() {
+        if let NetworkEvent::Admin(event) = event {
+            *last_event = Some(format!("{:?}", event));
+        }
+    }
+    egui::Window::new("Test client").show(contexts.ctx_mut(), |ui| {
+        if let Some(local) = state.local_peer {
+            ui.label(format!("Peer id {}", local.peer_id));
+        }
+        match &state.room {
+            Some(room) => {
+                ui.label(format!("In room {}", room.room_code));
+                if ui.button("Leave").clicked() {
+                    tasks.send(GameEvent::Admin(GameAdminEvent::Leave));
+                }
+            }
+            None => {
+                ui.label("Not in a room");
+                let room_code = hosted
+                    .filter(|room| room.hosting)
+                    .map(|room| room.room_code.clone());
+                if let Some(room_code) = room_code {
+                    if ui.button(format!("Join {}", room_code)).clicked() {
+                        join(&mut tasks, &room_code);
+                    }
+                }
+            }
+        }
+        if let Some(event) = last_event.as_ref() {
+            ui.label(format!("Last event: {}", event));
+        }
+        ui.separator();
+        for entry in state.log.iter().rev().take(LOG_LINES).rev() {
+            ui.label(format!("[{}] {}", entry.target, entry.message));
+        }
+    });
+}
@@ -0,0 +1,232 @@
+//! A single line text field for Bevy UI, which has none built in.
+//!
+//! Spawn a [`TextInput`] with [`spawn_text_input`]. Clicking it takes focus, typing edits
+//! [`TextInput::value`] and Enter sends a [`TextInputSubmitted`] event.
+
+use bevy::prelude::*;
+
+pub struct TextInputPlugin;
+
+impl Plugin for TextInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FocusedInput>()
+            .init_resource::<CursorBlink>()
+            .add_event::<TextInputSubmitted>()
+            .add_systems(
+                Update,
+                (
+                    focus_text_input,
+                    type_into_focused,
+                    blink_cursor,
+                    show_text_input,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct TextInput {
+    pub value: String,
+    pub max_len: usize,
+    /// Characters the field accepts. Anything else is ignored.
+    pub filter: fn(char) -> bool,
+    /// Shown while the field is empty and unfocused.
+    pub placeholder: String,
+}
+
+impl TextInput {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            value: String::new(),
+            max_len,
+            filter: |_| true,
+            placeholder: String::new(),
+        }
+    }
+
+    pub fn with_filter(mut self, filter: fn(char) -> bool) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_placeholder(mut self, placeholder: impl Into<String>) -> Self {
+        self.placeholder = placeholder.into();
+        self
+    }
+
+    pub fn with_value(mut self, value: impl Into<String>) -> Self {
+        self.value = value.into();
+        self
+    }
+
+    fn push(&mut self, c: char) -> bool {
+        if c.is_control() || !(self.filter)(c) || self.value.chars().count() >= self.max_len {
+            return false;
+        }
+        self.value.push(c);
+        true
+    }
+}
+
+/// Sent when Enter is pressed in a focused [`TextInput`].
+#[derive(Event, Debug, Clone)]
+pub struct TextInputSubmitted {
+    pub entity: Entity,
+}
+
+/// The input receiving keyboard events, if any.
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusedInput(pub Option<Entity>);
+
+#[derive(Resource)]
+struct CursorBlink {
+    timer: Timer,
+    visible: bool,
+}
+
+impl Default for CursorBlink {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(0.5, TimerMode::Repeating),
+            visible: true,
+        }
+    }
+}
+
+/// Spawns `input` as a button sized for `style`'s font, with `marker` on the button.
+pub fn spawn_text_input(
+    parent: &mut ChildBuilder,
+    input: TextInput,
+    style: TextStyle,
+    background: Color,
+    marker: impl Bundle,
+) -> Entity {
+    let width = style.font_size * 0.6 * (input.max_len + 1) as f32;
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(width.max(120.0)),
+                    height: Val::Px(style.font_size * 1.4),
+                    padding: UiRect::horizontal(Val::Px(8.0)),
+                    justify_content: JustifyContent::FlexStart,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: background.into(),
+                ..Default::default()
+            },
+            input,
+            marker,
+        ))
+        .with_children(|field| {
+            field.spawn(TextBundle::from_sections([
+                TextSection::new("", style.clone()),
+                TextSection::new("", style),
+            ]));
+        })
+        .id()
+}
+
+fn focus_text_input(
+    mut focused: ResMut<FocusedInput>,
+    mut blink: ResMut<CursorBlink>,
+    mouse: Res<Input<MouseButton>>,
+    inputs: Query<(Entity, &Interaction), With<TextInput>>,
+) {
+    if let Some(entity) = focused.0 {
+        if inputs.get(entity).is_err() {
+            focused.0 = None;
+        }
+    }
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    // A click anywhere else drops focus.
+    let clicked = inputs
+        .iter()
+        .find(|(_, interaction)| **interaction == Interaction::Pressed)
+        .map(|(entity, _)| entity);
+    if focused.0 != clicked {
+        focused.0 = clicked;
+        blink.visible = true;
+        blink.timer.reset();
+    }
+}
+
+fn type_into_focused(
+    mut focused: ResMut<FocusedInput>,
+    mut blink: ResMut<CursorBlink>,
+    mut characters: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    mut inputs: Query<&mut TextInput>,
+    mut submitted: EventWriter<TextInputSubmitted>,
+) {
+    let Some(mut input) = focused.0.and_then(|entity| inputs.get_mut(entity).ok()) else {
+        characters.clear();
+        return;
+    };
+    let mut typed = false;
+    for event in characters.iter() {
+        typed |= input.push(event.char);
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        typed |= input.value.pop().is_some();
+    }
+    if typed {
+        blink.visible = true;
+        blink.timer.reset();
+    }
+    if keys.just_pressed(KeyCode::Return) {
+        submitted.send(TextInputSubmitted {
+            entity: focused.0.expect("Focused input was just looked up"),
+        });
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        focused.0 = None;
+    }
+}
+
+fn blink_cursor(time: Res<Time>, mut blink: ResMut<CursorBlink>) {
+    // Only flag a change when the cursor toggles, so inputs aren't redrawn every frame.
+    let toggle = blink
+        .bypass_change_detection()
+        .timer
+        .tick(time.delta())
+        .just_finished();
+    if toggle {
+        blink.visible = !blink.visible;
+    }
+}
+
+fn show_text_input(
+    focused: Res<FocusedInput>,
+    blink: Res<CursorBlink>,
+    inputs: Query<(Entity, Ref<TextInput>, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    let redraw_all = focused.is_changed() || blink.is_changed();
+    for (entity, input, children) in &inputs {
+        if !redraw_all && !input.is_changed() {
+            continue;
+        }
+        let Some(mut text) = children
+            .first()
+            .and_then(|&child| texts.get_mut(child).ok())
+        else {
+            continue;
+        };
+        let has_focus = focused.0 == Some(entity);
+        text.sections[0].value = if input.value.is_empty() && !has_focus {
+            input.placeholder.clone()
+        } else {
+            input.value.clone()
+        };
+        text.sections[1].value = if has_focus && blink.visible {
+            "|".to_string()
+        } else {
+            String::new()
+        };
+    }
+}
@@ -0,0 +1,300 @@
+//! Picks a lockstep tick rate and input delay that suit the slowest link and machine
+//! in the room.
+//!
+//! Every peer measures its [`LinkConditions`] and reports them to the host while the
+//! room waits to start. The host picks [`TickSettings`] from them for the
+//! [`StartGame`](GameAdminEvent::StartGame) of a
+//! [lockstep](crate::game_mode::SyncStrategy::Lockstep) mode, and switches to slower
+//! ones while the game runs if the links get worse. Games read the settings in play
+//! from [`RoomTick`]; the tick rate is applied to Bevy's [`FixedTime`] already.
+
+use std::{collections::HashMap, time::Duration};
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::game_mode::GameModes;
+use crate::network::{
+    AsyncNetworkTasks, CurrentRoom, GameAdminEvent, GameEvent, LinkConditions, NetworkAdminEvent,
+    NetworkEvent, TickSettings,
+};
+use crate::peer::PeerStats;
+
+pub struct TickNegotiationPlugin;
+
+impl Plugin for TickNegotiationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TickNegotiation>()
+            .init_resource::<RoomTick>()
+            .add_systems(
+                Update,
+                (track_conditions::<()>, measure_conditions)
+                    .chain()
+                    .run_if(resource_exists::<AsyncNetworkTasks<()>>()),
+            )
+            .add_systems(Update, apply_room_tick);
+    }
+}
+
+/// How tick settings are picked, and what everyone measured.
+#[derive(Resource, Debug, Clone)]
+pub struct TickNegotiation {
+    /// The tick rates to pick from. The fastest whose input delay stays within
+    /// `max_input_delay` wins, else the slowest.
+    pub tick_rates: Vec<u32>,
+    /// Ticks of input delay beyond which a slower tick rate is picked.
+    pub max_input_delay: u32,
+    /// How often conditions are measured, reported to the host and, by the host,
+    /// checked against the settings in play.
+    pub report_every: Duration,
+    /// What we measured last.
+    local: Option<LinkConditions>,
+    /// Host only: what every peer in the room reported last.
+    reports: HashMap<PeerId, LinkConditions>,
+}
+
+impl Default for TickNegotiation {
+    fn default() -> Self {
+        Self {
+            tick_rates: vec![60, 30, 20, 15],
+            max_input_delay: 4,
+            report_every: Duration::from_secs(2),
+            local: None,
+            reports: HashMap::new(),
+        }
+    }
+}
+
+impl TickNegotiation {
+    /// What we measured last, once we did.
+    pub fn local(&self) -> Option<LinkConditions> {
+        self.local
+    }
+
+    /// Host only: what `peer_id` reported last.
+    pub fn report(&self, peer_id: &PeerId) -> Option<LinkConditions> {
+        self.reports.get(peer_id).copied()
+    }
+
+    /// The settings for the worst link and slowest machine anyone reported, running no
+    /// faster than `max_tick_rate`.
+    pub fn pick(&self, max_tick_rate: u32) -> TickSettings {
+        let conditions = self.local.iter().chain(self.reports.values());
+        let worst_rtt_ms = conditions
+            .clone()
+            .map(|conditions| conditions.worst_rtt_ms)
+            .max()
+            .unwrap_or(0);
+        let max_tick_rate = conditions
+            .map(|conditions| conditions.max_tick_rate)
+            .fold(max_tick_rate, u32::min)
+            .max(1);
+        let mut rates: Vec<u32> = self
+            .tick_rates
+            .iter()
+            .copied()
+            .filter(|rate| (1..=max_tick_rate).contains(rate))
+            .collect();
+        if rates.is_empty() {
+            rates.push(max_tick_rate);
+        }
+        rates.sort_unstable_by(|a, b| b.cmp(a));
+        let settings = |tick_rate| TickSettings {
+            tick_rate,
+            input_delay: input_delay(worst_rtt_ms, tick_rate),
+        };
+        rates
+            .iter()
+            .map(|rate| settings(*rate))
+            .find(|tick| tick.input_delay <= self.max_input_delay)
+            .unwrap_or_else(|| settings(rates[rates.len() - 1]))
+    }
+}
+
+/// Ticks an input needs to reach the peer farthest away: half the round trip, rounded
+/// up, and one more for the tick it's sampled in.
+fn input_delay(rtt_ms: u32, tick_rate: u32) -> u32 {
+    let one_way = u64::from(rtt_ms) * u64::from(tick_rate);
+    (one_way.saturating_add(1999) / 2000) as u32 + 1
+}
+
+/// The [`TickSettings`] of the game in progress, or `None` before it started or if the
+/// host didn't pick any.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RoomTick(pub Option<TickSettings>);
+
+fn track_conditions<ToGame>(
+    mut negotiation: ResMut<TickNegotiation>,
+    mut room_tick: ResMut<RoomTick>,
+    mut network_events: EventReader<NetworkEvent<ToGame>>,
+) where
+    ToGame: Send + Sync + 'static,
+{
+    for event in network_events.iter() {
+        let NetworkEvent::Admin(event) = event else {
+            continue;
+        };
+        match event {
+            NetworkAdminEvent::ConditionsReported {
+                peer_id,
+                conditions,
+            } => {
+                negotiation.reports.insert(*peer_id, *conditions);
+            }
+            NetworkAdminEvent::PlayerLeft(peer_id) | NetworkAdminEvent::Disconnected(peer_id) => {
+                negotiation.reports.remove(peer_id);
+            }
+            NetworkAdminEvent::GameStarted { tick, .. } => room_tick.0 = *tick,
+            NetworkAdminEvent::TickSettingsChanged(tick) => {
+                log::info!(
+                    "Now at {} ticks per second with {} ticks of input delay",
+                    tick.tick_rate,
+                    tick.input_delay
+                );
+                room_tick.0 = Some(*tick);
+            }
+            NetworkAdminEvent::HostingStarted { .. }
+            | NetworkAdminEvent::JoinAccepted { .. }
+            | NetworkAdminEvent::LeftRoom => {
+                negotiation.reports.clear();
+                room_tick.0 = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The room we are in, if any, and the settings it plays by.
+#[derive(SystemParam)]
+struct RoomState<'w> {
+    current: Option<Res<'w, CurrentRoom>>,
+    tick: Res<'w, RoomTick>,
+    modes: Option<Res<'w, GameModes>>,
+}
+
+/// Measures our links and frame rate every [`TickNegotiation::report_every`]. Joiners
+/// report them to the host; the host switches to slower settings if the game in
+/// progress can't keep up anymore.
+fn measure_conditions(
+    time: Res<Time>,
+    mut negotiation: ResMut<TickNegotiation>,
+    room: RoomState,
+    mut tasks: ResMut<AsyncNetworkTasks<()>>,
+    peers: Query<&PeerStats>,
+    mut since_report: Local<(Duration, u32)>,
+) {
+    let (elapsed, frames) = &mut *since_report;
+    *elapsed += time.delta();
+    *frames += 1;
+    if *elapsed < negotiation.report_every {
+        return;
+    }
+    let conditions = LinkConditions {
+        worst_rtt_ms: peers
+            .iter()
+            .filter_map(|stats| stats.rtt.p95())
+            .max()
+            .map_or(0, |rtt| rtt.as_millis() as u32),
+        max_tick_rate: (f64::from(*frames) / elapsed.as_secs_f64()) as u32,
+    };
+    *since_report = (Duration::ZERO, 0);
+    negotiation.local = Some(conditions);
+
+    let Some(current_room) = room.current else {
+        return;
+    };
+    if !current_room.hosting {
+        tasks.send(GameEvent::Admin(GameAdminEvent::ReportConditions(
+            conditions,
+        )));
+        return;
+    }
+    let Some(current) = room.tick.0 else {
+        return;
+    };
+    let max_tick_rate = room
+        .modes
+        .as_ref()
+        .and_then(|modes| modes.selected())
+        .map_or(current.tick_rate, |mode| mode.tick_rate);
+    let picked = negotiation.pick(max_tick_rate);
+    // Only ever slower, so a link that recovers now and then doesn't flip the
+    // settings back and forth mid-game.
+    if picked.tick_rate < current.tick_rate || picked.input_delay > current.input_delay {
+        tasks.send(GameEvent::Admin(GameAdminEvent::ChangeTickSettings(picked)));
+    }
+}
+
+fn apply_room_tick(room_tick: Res<RoomTick>, fixed_time: Option<ResMut<FixedTime>>) {
+    let (Some(tick), Some(mut fixed_time)) = (room_tick.0, fixed_time) else {
+        return;
+    };
+    let period = Duration::from_secs_f64(1.0 / f64::from(tick.tick_rate.max(1)));
+    if fixed_time.period != period {
+        fixed_time.period = period;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_for_the_worst_link_and_machine() {
+        let mut negotiation = TickNegotiation::default();
+        assert_eq!(
+            negotiation.pick(60),
+            TickSettings {
+                tick_rate: 60,
+                input_delay: 1,
+            }
+        );
+
+        negotiation.local = Some(LinkConditions {
+            worst_rtt_ms: 40,
+            max_tick_rate: 144,
+        });
+        assert_eq!(negotiation.pick(60).input_delay, 3);
+
+        // 250 ms round trips need 9 ticks at 60 and 5 at 30, but only 4 at 20.
+        negotiation.reports.insert(
+            PeerId::random(),
+            LinkConditions {
+                worst_rtt_ms: 250,
+                max_tick_rate: 60,
+            },
+        );
+        assert_eq!(
+            negotiation.pick(60),
+            TickSettings {
+                tick_rate: 20,
+                input_delay: 4,
+            }
+        );
+
+        // A slow machine caps the rate even when the links are fine.
+        negotiation.reports.clear();
+        negotiation.reports.insert(
+            PeerId::random(),
+            LinkConditions {
+                worst_rtt_ms: 10,
+                max_tick_rate: 25,
+            },
+        );
+        assert_eq!(negotiation.pick(60).tick_rate, 20);
+
+        // Nothing fits: the slowest rate, with the delay it takes.
+        negotiation.local = Some(LinkConditions {
+            worst_rtt_ms: 2000,
+            max_tick_rate: 60,
+        });
+        assert_eq!(
+            negotiation.pick(60),
+            TickSettings {
+                tick_rate: 15,
+                input_delay: 16,
+            }
+        );
+    }
+}
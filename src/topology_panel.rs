@@ -0,0 +1,159 @@
+//! An egui window drawing how we reach every peer: straight, or through which relay,
+//! with the round trip on each link. Shows at a glance who is stuck on a relay and,
+//! from the last hole punch, why.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    f32::consts::TAU,
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::{bevy_egui::EguiContexts, egui};
+use libp2p::PeerId;
+
+use crate::network::{ConnectionPath, LocalPeerInfo};
+use crate::peer::{HolePunch, Latency, PeerIdComp, PeerStats, RelayedVia};
+
+/// Width and height of the drawing.
+const SIZE: f32 = 360.0;
+const NODE_RADIUS: f32 = 7.0;
+
+pub struct TopologyPanelPlugin;
+
+impl Plugin for TopologyPanelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, show_topology_panel);
+    }
+}
+
+fn short(peer_id: &PeerId) -> String {
+    let peer = peer_id.to_base58();
+    peer[peer.len().saturating_sub(6)..].to_string()
+}
+
+/// Where the `i`th of `n` nodes goes on a ring of `radius` around `center`.
+fn on_ring(center: egui::Pos2, radius: f32, i: usize, n: usize) -> egui::Pos2 {
+    let angle = TAU * i as f32 / n.max(1) as f32 - TAU / 4.0;
+    center + radius * egui::vec2(angle.cos(), angle.sin())
+}
+
+fn show_topology_panel(
+    mut contexts: EguiContexts,
+    local: Option<Res<LocalPeerInfo>>,
+    peers: Query<(
+        &PeerIdComp,
+        Option<&ConnectionPath>,
+        Option<&RelayedVia>,
+        Option<&Latency>,
+        &PeerStats,
+        Option<&HolePunch>,
+    )>,
+) {
+    let mut peers: Vec<_> = peers.iter().collect();
+    peers.sort_by_key(|(peer_id, ..)| peer_id.0);
+    let relays: BTreeSet<PeerId> = peers
+        .iter()
+        .filter_map(|(_, _, relay, ..)| relay.map(|relay| relay.0))
+        .collect();
+
+    egui::Window::new("Network topology")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let (response, painter) =
+                ui.allocate_painter(egui::vec2(SIZE, SIZE), egui::Sense::hover());
+            let center = response.rect.center();
+            let font = egui::FontId::proportional(11.0);
+            let relay_at: BTreeMap<PeerId, egui::Pos2> = relays
+                .iter()
+                .enumerate()
+                .map(|(i, relay)| (*relay, on_ring(center, SIZE * 0.2, i, relays.len())))
+                .collect();
+
+            for &at in relay_at.values() {
+                painter.extend(egui::Shape::dashed_line(
+                    &[center, at],
+                    egui::Stroke::new(1.5, egui::Color32::GRAY),
+                    6.0,
+                    4.0,
+                ));
+            }
+            for (i, &(peer_id, path, relay, latency, stats, hole_punch)) in peers.iter().enumerate()
+            {
+                let at = on_ring(center, SIZE * 0.4, i, peers.len());
+                let failed = matches!(hole_punch, Some(HolePunch::Failed { .. }));
+                let (color, label) = match path {
+                    Some(ConnectionPath::Direct) => (egui::Color32::GREEN, "direct"),
+                    Some(ConnectionPath::Relayed) if failed => {
+                        (egui::Color32::RED, "relayed, punch failed")
+                    }
+                    Some(ConnectionPath::Relayed) => (egui::Color32::YELLOW, "relayed"),
+                    None => (egui::Color32::GRAY, "connecting"),
+                };
+                let stroke = egui::Stroke::new(1.5, color);
+                let from = match relay.and_then(|relay| relay_at.get(&relay.0)) {
+                    Some(&relay) => {
+                        painter.extend(egui::Shape::dashed_line(&[relay, at], stroke, 6.0, 4.0));
+                        relay
+                    }
+                    None => {
+                        painter.line_segment([center, at], stroke);
+                        center
+                    }
+                };
+                let rtt = latency.map_or("-".to_string(), |latency| {
+                    format!("{} ms", latency.0.as_millis())
+                });
+                painter.text(
+                    from + (at - from) * 0.5,
+                    egui::Align2::CENTER_CENTER,
+                    format!("{} {}", label, rtt),
+                    font.clone(),
+                    color,
+                );
+                painter.circle_filled(at, NODE_RADIUS, color);
+                painter.text(
+                    at + egui::vec2(0.0, NODE_RADIUS),
+                    egui::Align2::CENTER_TOP,
+                    format!("{} {:.0}% lost", short(&peer_id.0), stats.loss() * 100.0),
+                    font.clone(),
+                    egui::Color32::LIGHT_GRAY,
+                );
+            }
+            for (relay, &at) in &relay_at {
+                painter.circle_filled(at, NODE_RADIUS, egui::Color32::GRAY);
+                painter.text(
+                    at + egui::vec2(0.0, NODE_RADIUS),
+                    egui::Align2::CENTER_TOP,
+                    format!("relay {}", short(relay)),
+                    font.clone(),
+                    egui::Color32::LIGHT_GRAY,
+                );
+            }
+            painter.circle_filled(center, NODE_RADIUS, egui::Color32::LIGHT_BLUE);
+            painter.text(
+                center + egui::vec2(0.0, NODE_RADIUS),
+                egui::Align2::CENTER_TOP,
+                local.map_or("us".to_string(), |local| {
+                    format!("us {}", short(&local.peer_id))
+                }),
+                font,
+                egui::Color32::LIGHT_GRAY,
+            );
+
+            let stuck: Vec<_> = peers
+                .iter()
+                .filter(|(_, path, ..)| *path == Some(&ConnectionPath::Relayed))
+                .filter_map(|(peer_id, .., hole_punch)| match hole_punch {
+                    Some(HolePunch::Failed { reason }) => Some((peer_id.0, reason)),
+                    _ => None,
+                })
+                .collect();
+            if !stuck.is_empty() {
+                ui.separator();
+                ui.heading("Stuck on a relay");
+                for (peer_id, reason) in stuck {
+                    ui.label(format!("{}: {}", short(&peer_id), reason));
+                }
+            }
+        });
+}
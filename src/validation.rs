@@ -0,0 +1,130 @@
+//! Checking remote inputs on the host before they reach the simulation. The host's
+//! game is the one that counts, so a peer sending impossible inputs, like moving
+//! faster than anyone can or acting out of turn, must not get them into it.
+//!
+//! The game implements [`InputValidator`] for its input type and reads
+//! [`ValidInput`]s instead of [`NetworkEvent::Game`]. Only the host checks anything;
+//! everyone else passes inputs straight through, as the host corrects them anyway.
+//! Every rejected input costs its sender reputation, see
+//! [`Misbehaviour::IllegalInput`], and [`ValidationConfig::kick_after`] disconnects
+//! repeat offenders outright.
+
+use std::{collections::HashMap, marker::PhantomData};
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use libp2p::PeerId;
+
+use crate::authority::Authorities;
+use crate::network::{AsyncNetworkTasks, GameAdminEvent, GameEvent, NetworkEvent};
+use crate::peer::{Misbehaviour, PeerMisbehaved};
+
+/// Checks one input from a remote peer. A resource, so it can remember what it needs
+/// across inputs, e.g. each peer's last position for a speed limit.
+pub trait InputValidator: Resource {
+    type Input: Clone + Send + Sync + 'static;
+
+    /// `Err` says what's wrong with `input`, for the log.
+    fn validate(&mut self, peer_id: PeerId, input: &Self::Input) -> Result<(), String>;
+}
+
+/// Validates the inputs of the [`NetworkEvent`]s carrying `V::Input` with the `V`
+/// resource, which has to be inserted as well.
+pub struct InputValidationPlugin<V>(PhantomData<V>);
+
+impl<V> Default for InputValidationPlugin<V> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<V: InputValidator> Plugin for InputValidationPlugin<V> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ValidationConfig>()
+            .add_event::<ValidInput<V::Input>>()
+            .add_event::<InputRejected>()
+            .add_systems(
+                Update,
+                validate_inputs::<V>.run_if(resource_exists::<AsyncNetworkTasks<()>>()),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ValidationConfig {
+    /// Disconnect a peer once this many of its inputs were rejected, without waiting
+    /// for its reputation to run out. `None` leaves it to the reputation alone.
+    pub kick_after: Option<u32>,
+}
+
+/// An input from `source` that passed validation, or came in while we aren't the host.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct ValidInput<T> {
+    pub source: PeerId,
+    pub input: T,
+}
+
+/// Host only: an input from `peer_id` was dropped because of `reason`.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct InputRejected {
+    pub peer_id: PeerId,
+    pub reason: String,
+}
+
+/// Everything that follows from rejecting an input: the events, the sender's tally
+/// and, past [`ValidationConfig::kick_after`], its disconnection.
+#[derive(SystemParam)]
+struct Rejections<'w, 's> {
+    config: Res<'w, ValidationConfig>,
+    tasks: ResMut<'w, AsyncNetworkTasks<()>>,
+    rejected: EventWriter<'w, InputRejected>,
+    misbehaved: EventWriter<'w, PeerMisbehaved>,
+    violations: Local<'s, HashMap<PeerId, u32>>,
+}
+
+impl Rejections<'_, '_> {
+    fn reject(&mut self, source: PeerId, reason: String) {
+        log::warn!("Rejected an input from {}: {}", source, reason);
+        self.misbehaved.send(PeerMisbehaved {
+            peer_id: source,
+            kind: Misbehaviour::IllegalInput,
+        });
+        self.rejected.send(InputRejected {
+            peer_id: source,
+            reason,
+        });
+        let count = self.violations.entry(source).or_default();
+        *count += 1;
+        if self.config.kick_after == Some(*count) {
+            log::warn!("Disconnecting {} after {} illegal inputs", source, count);
+            self.tasks
+                .send(GameEvent::Admin(GameAdminEvent::Disconnect(source)));
+        }
+    }
+}
+
+fn validate_inputs<V: InputValidator>(
+    authorities: Res<Authorities>,
+    mut validator: ResMut<V>,
+    mut network_events: EventReader<NetworkEvent<V::Input>>,
+    mut valid: EventWriter<ValidInput<V::Input>>,
+    mut rejections: Rejections,
+) {
+    for event in network_events.iter() {
+        let NetworkEvent::Game { source, event } = event else {
+            continue;
+        };
+        let verdict = if authorities.is_host() {
+            validator.validate(*source, event)
+        } else {
+            Ok(())
+        };
+        match verdict {
+            Ok(()) => valid.send(ValidInput {
+                source: *source,
+                input: event.clone(),
+            }),
+            Err(reason) => rejections.reject(*source, reason),
+        }
+    }
+}